@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use chrono::Local;
 use tokio::sync::Mutex;
 use {
+    super::config,
     anyhow::Result,
     axum::{
         extract::Query,
@@ -17,6 +18,7 @@ use {
         utils::Uuid,
     },
     tokio::{self, sync::oneshot},
+    xrtsp::relay::pull_client::{PullClientConfig, RtspPullManager},
 };
 
 #[derive(serde::Serialize)]
@@ -45,9 +47,23 @@ struct QueryStream {
     uuid: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct StartRtspPull {
+    url: String,
+    stream_path: String,
+    // tcp(default) or udp
+    transport: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopRtspPull {
+    stream_path: String,
+}
+
 #[derive(Clone)]
 struct ApiService {
     channel_event_producer: StreamHubEventSender,
+    rtsp_pull_manager: Option<RtspPullManager>,
 }
 
 impl ApiService {
@@ -57,7 +73,10 @@ impl ApiService {
                 ./query_whole_streams(get) query whole streams' information or top streams' information.
                 ./query_stream(post) query stream information by identifier and uuid.
                 ./kick_off_client(post) kick off client by publish/subscribe id.
-                ./gen_nonce(post) generation nonce for publish/subscribe client.\n",
+                ./gen_nonce(post) generation nonce for publish/subscribe client.
+                ./start_rtsp_pull(post) pull a stream from rtsp url(IP camera) to stream path.
+                ./stop_rtsp_pull(post) stop pulling the rtsp stream by stream path.
+                ./query_rtsp_pulls(get) query the stream paths being pulled.\n",
         )
     }
 
@@ -150,6 +169,66 @@ impl ApiService {
         Ok(String::from("ok"))
     }
 
+    fn gen_response(result: Result<Value, String>) -> Json<ApiResponse<Value>> {
+        let api_response = match result {
+            Ok(data) => ApiResponse {
+                error_code: 0,
+                desp: String::from("succ"),
+                data,
+            },
+            Err(err) => ApiResponse {
+                error_code: -1,
+                desp: String::from("failed"),
+                data: serde_json::json!(err),
+            },
+        };
+        Json(api_response)
+    }
+
+    async fn start_rtsp_pull(&self, params: StartRtspPull) -> Json<ApiResponse<Value>> {
+        let manager = match &self.rtsp_pull_manager {
+            Some(manager) => manager,
+            None => return Self::gen_response(Err(String::from("rtsp is not enabled"))),
+        };
+
+        let pull_config = PullClientConfig {
+            url: params.url,
+            stream_path: params.stream_path,
+            protocol_type: config::parse_rtsp_transport(&params.transport),
+        };
+        Self::gen_response(
+            manager
+                .start(pull_config)
+                .await
+                .map(|_| Value::Null)
+                .map_err(|err| err.to_string()),
+        )
+    }
+
+    async fn stop_rtsp_pull(&self, params: StopRtspPull) -> Json<ApiResponse<Value>> {
+        let manager = match &self.rtsp_pull_manager {
+            Some(manager) => manager,
+            None => return Self::gen_response(Err(String::from("rtsp is not enabled"))),
+        };
+
+        if manager.stop(&params.stream_path).await {
+            Self::gen_response(Ok(Value::Null))
+        } else {
+            Self::gen_response(Err(format!(
+                "the stream path is not being pulled: {}",
+                params.stream_path
+            )))
+        }
+    }
+
+    async fn query_rtsp_pulls(&self) -> Json<ApiResponse<Value>> {
+        let stream_paths = match &self.rtsp_pull_manager {
+            Some(manager) => manager.get_stream_paths().await,
+            None => Vec::new(),
+        };
+        Self::gen_response(Ok(serde_json::json!(stream_paths)))
+    }
+
     async fn gen_nonce(&self, nonce_map: &Arc<Mutex<HashMap<String, i64>>>) -> String {
         let nonce = uuid::Uuid::new_v4().to_string();
         nonce_map.lock().await.insert(nonce.clone(), Local::now().timestamp_millis() + (10 * 60 * 1000));
@@ -157,9 +236,15 @@ impl ApiService {
     }
 }
 
-pub async fn run(producer: StreamHubEventSender, port: usize, nonce_map: Arc<Mutex<HashMap<String, i64>>>) {
+pub async fn run(
+    producer: StreamHubEventSender,
+    port: usize,
+    nonce_map: Arc<Mutex<HashMap<String, i64>>>,
+    rtsp_pull_manager: Option<RtspPullManager>,
+) {
     let api = Arc::new(ApiService {
         channel_event_producer: producer,
+        rtsp_pull_manager,
     });
 
     let api_root = api.clone();
@@ -184,12 +269,28 @@ pub async fn run(producer: StreamHubEventSender, port: usize, nonce_map: Arc<Mut
     let nonce_map_clone = Arc::clone(&nonce_map);
     let gen_nonce = move || async move { gen_nonce_api.gen_nonce(&nonce_map_clone).await };
 
+    let api_start_rtsp_pull = api.clone();
+    let start_rtsp_pull = move |Json(params): Json<StartRtspPull>| async move {
+        api_start_rtsp_pull.start_rtsp_pull(params).await
+    };
+
+    let api_stop_rtsp_pull = api.clone();
+    let stop_rtsp_pull = move |Json(params): Json<StopRtspPull>| async move {
+        api_stop_rtsp_pull.stop_rtsp_pull(params).await
+    };
+
+    let api_query_rtsp_pulls = api.clone();
+    let query_rtsp_pulls = move || async move { api_query_rtsp_pulls.query_rtsp_pulls().await };
+
     let app = Router::new()
         .route("/", get(root))
         .route("/query_whole_streams", get(query_streams))
         .route("/query_stream", post(query_stream))
         .route("/kick_off_client", post(kick_off))
-        .route("/gen_nonce", post(gen_nonce));
+        .route("/gen_nonce", post(gen_nonce))
+        .route("/start_rtsp_pull", post(start_rtsp_pull))
+        .route("/stop_rtsp_pull", post(stop_rtsp_pull))
+        .route("/query_rtsp_pulls", get(query_rtsp_pulls));
 
     log::info!("Http api server listening on http://0.0.0.0:{}", port);
     axum::Server::bind(&([0, 0, 0, 0], port as u16).into())
@@ -7,7 +7,15 @@ use tokio::sync::Mutex;
 use tokio::time::interval;
 use rtmp::remuxer::RtmpRemuxer;
 
-use crate::config::{AuthConfig, AuthSecretConfig};
+use crate::config::{self, AuthConfig, AuthSecretConfig};
+use xrtsp::global_trait::Unmarshal;
+use xrtsp::relay::pull_client::{PullClientConfig, RtspPullManager};
+use xrtsp::relay::push_client::RtspPushClient;
+use xrtsp::session::client_session::RtspUrl;
+use xrtsp::session::define::SESSION_TIMEOUT;
+use xrtsp::session::multicast::{MulticastConfig, MulticastManager};
+use xrtsp::rtsp_tls::TlsConfig;
+use xrtsp::session::playback::PlaybackConfig;
 
 use {
     super::api,
@@ -19,22 +27,31 @@ use {
     hls::server as hls_server,
     httpflv::server as httpflv_server,
     rtmp::{
+        cache::gop::{GopCacheConfig, GopLimit},
         relay::{pull_client::PullClient, push_client::PushClient},
         rtmp::RtmpServer,
     },
-    streamhub::{notify::Notifier, StreamsHub},
+    streamhub::{notify::Notifier, queue::QueueConfig, StreamsHub},
     tokio,
     xrtsp::rtsp::RtspServer,
+    xwebrtc::ice::{IceConfig, IceServerConfig},
+    xwebrtc::relay::{pull_client::WhepPullClient, push_client::WhipPushClient},
+    xwebrtc::remuxer::WebRTCRemuxer,
+    xwebrtc::tls::TlsConfig as WebRTCTlsConfig,
     xwebrtc::webrtc::WebRTCServer,
 };
 
 pub struct Service {
     cfg: Config,
+    rtsp_pull_manager: Option<RtspPullManager>,
 }
 
 impl Service {
     pub fn new(cfg: Config) -> Self {
-        Service { cfg }
+        Service {
+            cfg,
+            rtsp_pull_manager: None,
+        }
     }
 
     fn gen_auth(auth_config: &Option<AuthConfig>, authsecret: &AuthSecretConfig) -> Option<Auth> {
@@ -66,6 +83,28 @@ impl Service {
         }
     }
 
+    fn gen_ice_config(ice_config: &Option<config::WebRTCIceConfig>) -> Result<IceConfig> {
+        let mut config = IceConfig::default();
+        if let Some(cfg) = ice_config {
+            if let Some(servers) = &cfg.servers {
+                config.ice_servers = servers
+                    .iter()
+                    .map(|server| IceServerConfig {
+                        urls: server.urls.clone(),
+                        username: server.username.clone().unwrap_or_default(),
+                        credential: server.credential.clone().unwrap_or_default(),
+                    })
+                    .collect();
+            }
+            config.nat_1to1_ips = cfg.nat_1to1_ips.clone().unwrap_or_default();
+            config
+                .set_udp_port_range(cfg.udp_port_min, cfg.udp_port_max)
+                .map_err(|err| anyhow::anyhow!("webrtc ice config error: {}", err))?;
+            config.udp_mux_port = cfg.udp_mux_port;
+        }
+        Ok(config)
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         let notifier = if let Some(httpnotifier) = &self.cfg.httpnotify {
             if !httpnotifier.enabled {
@@ -108,6 +147,17 @@ impl Service {
 
         let mut stream_hub = StreamsHub::new(notifier, nonce_map);
 
+        if let Some(streamhub_cfg) = &self.cfg.streamhub {
+            let default_config = QueueConfig::default();
+            stream_hub.set_subscriber_queue_config(QueueConfig {
+                size: streamhub_cfg.queue_size.unwrap_or(default_config.size),
+                drop_policy: streamhub_cfg.drop_policy.clone().unwrap_or_default(),
+                disconnect_threshold: streamhub_cfg
+                    .disconnect_threshold
+                    .unwrap_or(default_config.disconnect_threshold),
+            });
+        }
+
         self.start_httpflv(&mut stream_hub).await?;
         self.start_hls(&mut stream_hub).await?;
         self.start_rtmp(&mut stream_hub).await?;
@@ -115,6 +165,7 @@ impl Service {
         self.start_webrtc(&mut stream_hub).await?;
         self.start_http_api_server(&mut stream_hub).await?;
         self.start_rtmp_remuxer(&mut stream_hub).await?;
+        self.start_webrtc_remuxer(&mut stream_hub).await?;
 
         tokio::spawn(async move {
             stream_hub.run().await;
@@ -133,9 +184,10 @@ impl Service {
         };
 
         let nonce_map = stream_hub.get_nonce_map();
+        let rtsp_pull_manager = self.rtsp_pull_manager.clone();
 
         tokio::spawn(async move {
-            api::run(producer, http_api_port, nonce_map).await;
+            api::run(producer, http_api_port, nonce_map, rtsp_pull_manager).await;
         });
         Ok(())
     }
@@ -153,6 +205,23 @@ impl Service {
             } else {
                 1
             };
+            let default_limit = GopLimit {
+                gop_num,
+                max_duration: rtmp_cfg_value.gop_max_duration,
+                max_bytes: rtmp_cfg_value.gop_max_bytes,
+            };
+            let mut gop_cache_config = GopCacheConfig::new(default_limit.clone());
+            /*the app level configurations override the rtmp level ones*/
+            if let Some(app_cfg_values) = &rtmp_cfg_value.apps {
+                for app_value in app_cfg_values {
+                    let limit = GopLimit {
+                        gop_num: app_value.gop_num.unwrap_or(default_limit.gop_num),
+                        max_duration: app_value.gop_max_duration.or(default_limit.max_duration),
+                        max_bytes: app_value.gop_max_bytes.or(default_limit.max_bytes),
+                    };
+                    gop_cache_config.app_limits.insert(app_value.name.clone(), limit);
+                }
+            }
 
             let producer = stream_hub.get_hub_event_sender();
 
@@ -216,7 +285,10 @@ impl Service {
             let nonce_map = stream_hub.get_nonce_map();
 
             let auth = Self::gen_auth(&rtmp_cfg_value.auth, &self.cfg.authsecret);
-            let mut rtmp_server = RtmpServer::new(address, producer, gop_num, auth, publish_token, subscribe_token, enabled_nonce, nonce_map);
+            let mut rtmp_server = RtmpServer::new(address, producer, gop_cache_config, auth, publish_token, subscribe_token, enabled_nonce, nonce_map);
+            if let Some(threshold) = rtmp_cfg_value.timestamp_jump_threshold {
+                rtmp_server.set_timestamp_jump_threshold(threshold);
+            }
             tokio::spawn(async move {
                 if let Err(err) = rtmp_server.run().await {
                     log::error!("rtmp server error: {}", err);
@@ -270,6 +342,28 @@ impl Service {
         Ok(())
     }
 
+    async fn start_webrtc_remuxer(&mut self, stream_hub: &mut StreamsHub) -> Result<()> {
+        //The remuxer is used for rtmp2webrtc(the rtsp streams are remuxed to rtmp first),
+        //so both rtmp/webrtc cfg need to be enabled.
+        let rtmp_enabled = self.cfg.rtmp.as_ref().is_some_and(|cfg| cfg.enabled);
+        let webrtc_enabled = self.cfg.webrtc.as_ref().is_some_and(|cfg| cfg.enabled);
+        if !rtmp_enabled || !webrtc_enabled {
+            return Ok(());
+        }
+
+        let event_producer = stream_hub.get_hub_event_sender();
+        let broadcast_event_receiver = stream_hub.get_client_event_consumer();
+        let mut remuxer = WebRTCRemuxer::new(broadcast_event_receiver, event_producer);
+        stream_hub.set_webrtc_remuxer_enabled(true);
+
+        tokio::spawn(async move {
+            if let Err(err) = remuxer.run().await {
+                log::error!("webrtc remuxer server error: {}", err);
+            }
+        });
+        Ok(())
+    }
+
     async fn start_rtsp(&mut self, stream_hub: &mut StreamsHub) -> Result<()> {
         let rtsp_cfg = &self.cfg.rtsp;
 
@@ -284,12 +378,137 @@ impl Service {
             let address = format!("0.0.0.0:{listen_port}");
 
             let auth = Self::gen_auth(&rtsp_cfg_value.auth, &self.cfg.authsecret);
-            let mut rtsp_server = RtspServer::new(address, producer, auth);
+            let multicast_manager = match &rtsp_cfg_value.multicast {
+                Some(multicast_cfg) if multicast_cfg.enabled => {
+                    let start_address = multicast_cfg.start_address.parse()?;
+                    Some(MulticastManager::new(MulticastConfig {
+                        start_address,
+                        address_count: multicast_cfg.address_count,
+                        start_port: multicast_cfg.start_port,
+                        ttl: multicast_cfg.ttl,
+                    }))
+                }
+                _ => None,
+            };
+            let playback_config = match &rtsp_cfg_value.playback {
+                Some(playback_cfg) if playback_cfg.enabled => Some(PlaybackConfig {
+                    path_prefix: playback_cfg.path_prefix.clone(),
+                    record_path: playback_cfg.record_path.clone(),
+                }),
+                _ => None,
+            };
+            let session_timeout = match rtsp_cfg_value.session_timeout {
+                Some(0) => {
+                    log::warn!(
+                        "rtsp session_timeout must be greater than 0, use {SESSION_TIMEOUT}"
+                    );
+                    SESSION_TIMEOUT
+                }
+                Some(session_timeout) => session_timeout,
+                None => SESSION_TIMEOUT,
+            };
+            if let Some(tunnel_port) = rtsp_cfg_value.tunnel_port {
+                let mut rtsp_tunnel_server = RtspServer::new(
+                    format!("0.0.0.0:{tunnel_port}"),
+                    producer.clone(),
+                    auth.clone(),
+                    multicast_manager.clone(),
+                    playback_config.clone(),
+                    session_timeout,
+                    None,
+                );
+                tokio::spawn(async move {
+                    if let Err(err) = rtsp_tunnel_server.run().await {
+                        log::error!("rtsp tunnel server error: {}", err);
+                    }
+                });
+            }
+            if let Some(tls_cfg) = rtsp_cfg_value.tls.as_ref().filter(|cfg| cfg.enabled) {
+                let tls_config = TlsConfig {
+                    cert_path: tls_cfg.cert_path.clone(),
+                    key_path: tls_cfg.key_path.clone(),
+                    enable_srtp: tls_cfg.srtp,
+                };
+                let mut rtsps_server = RtspServer::new(
+                    format!("0.0.0.0:{}", tls_cfg.port),
+                    producer.clone(),
+                    auth.clone(),
+                    multicast_manager.clone(),
+                    playback_config.clone(),
+                    session_timeout,
+                    Some(tls_config),
+                );
+                tokio::spawn(async move {
+                    if let Err(err) = rtsps_server.run().await {
+                        log::error!("rtsps server error: {}", err);
+                    }
+                });
+            }
+            let mut rtsp_server = RtspServer::new(
+                address,
+                producer.clone(),
+                auth,
+                multicast_manager,
+                playback_config,
+                session_timeout,
+                None,
+            );
             tokio::spawn(async move {
                 if let Err(err) = rtsp_server.run().await {
                     log::error!("rtsp server error: {}", err);
                 }
             });
+
+            /*pull streams from the IP cameras or other rtsp servers*/
+            let rtsp_pull_manager = RtspPullManager::new(producer.clone());
+            if let Some(pull_cfg_values) = &rtsp_cfg_value.pull {
+                for pull_cfg_value in pull_cfg_values {
+                    if !pull_cfg_value.enabled {
+                        continue;
+                    }
+                    let pull_config = PullClientConfig {
+                        url: pull_cfg_value.url.clone(),
+                        stream_path: pull_cfg_value.stream_path.clone(),
+                        protocol_type: config::parse_rtsp_transport(&pull_cfg_value.transport),
+                    };
+                    if let Err(err) = rtsp_pull_manager.start(pull_config).await {
+                        log::error!("start rtsp pull client error: {}", err);
+                    }
+                }
+            }
+            self.rtsp_pull_manager = Some(rtsp_pull_manager);
+
+            /*push the rtsp streams to other rtsp servers(e.g. NVR)*/
+            if let Some(push_cfg_values) = &rtsp_cfg_value.push {
+                for push_cfg_value in push_cfg_values {
+                    if !push_cfg_value.enabled {
+                        continue;
+                    }
+                    let url = match RtspUrl::unmarshal(&push_cfg_value.url) {
+                        Some(url) => url,
+                        None => {
+                            log::error!("invalid rtsp push url: {}", push_cfg_value.url);
+                            continue;
+                        }
+                    };
+                    log::info!("start rtsp push client to {}", push_cfg_value.url);
+
+                    let mut push_client = RtspPushClient::new(
+                        url,
+                        config::parse_rtsp_transport(&push_cfg_value.transport),
+                        config::parse_rtsp_push_stream_type(&push_cfg_value.stream_type),
+                        stream_hub.get_client_event_consumer(),
+                        producer.clone(),
+                    );
+                    tokio::spawn(async move {
+                        if let Err(err) = push_client.run().await {
+                            log::error!("rtsp push client error {}", err);
+                        }
+                    });
+
+                    stream_hub.set_rtsp_push_enabled(true);
+                }
+            }
         }
 
         Ok(())
@@ -309,12 +528,73 @@ impl Service {
             let address = format!("0.0.0.0:{listen_port}");
 
             let auth = Self::gen_auth(&webrtc_cfg_value.auth, &self.cfg.authsecret);
-            let mut webrtc_server = WebRTCServer::new(address, producer, auth);
+            let ice_config = Self::gen_ice_config(&webrtc_cfg_value.ice)?;
+            let tls_config = webrtc_cfg_value
+                .tls
+                .as_ref()
+                .filter(|cfg| cfg.enabled)
+                .map(|tls_cfg| WebRTCTlsConfig {
+                    address: format!("0.0.0.0:{}", tls_cfg.port),
+                    cert_path: tls_cfg.cert_path.clone(),
+                    key_path: tls_cfg.key_path.clone(),
+                });
+            let mut webrtc_server = WebRTCServer::new(
+                address,
+                producer.clone(),
+                stream_hub.get_client_event_consumer(),
+                auth,
+                ice_config.clone(),
+                tls_config,
+            );
             tokio::spawn(async move {
                 if let Err(err) = webrtc_server.run().await {
                     log::error!("webrtc server error: {}", err);
                 }
             });
+
+            /*push the webrtc streams to the remote whip endpoints*/
+            if let Some(push_cfg_values) = &webrtc_cfg_value.push {
+                for push_cfg_value in push_cfg_values {
+                    if !push_cfg_value.enabled {
+                        continue;
+                    }
+                    log::info!("start whip push client to {}", push_cfg_value.url);
+
+                    let mut push_client = WhipPushClient::new(
+                        push_cfg_value.url.clone(),
+                        push_cfg_value.token.clone(),
+                        ice_config.clone(),
+                        stream_hub.get_client_event_consumer(),
+                        producer.clone(),
+                    );
+                    tokio::spawn(async move {
+                        if let Err(err) = push_client.run().await {
+                            log::error!("whip push client error {}", err);
+                        }
+                    });
+                    stream_hub.set_webrtc_push_enabled(true);
+                }
+            }
+            /*pull the subscribed webrtc streams from the remote whep endpoint*/
+            if let Some(pull_cfg_value) = &webrtc_cfg_value.pull {
+                if pull_cfg_value.enabled {
+                    log::info!("start whep pull client from {}", pull_cfg_value.url);
+
+                    let mut pull_client = WhepPullClient::new(
+                        pull_cfg_value.url.clone(),
+                        pull_cfg_value.token.clone(),
+                        ice_config,
+                        stream_hub.get_client_event_consumer(),
+                        producer,
+                    );
+                    tokio::spawn(async move {
+                        if let Err(err) = pull_client.run().await {
+                            log::error!("whep pull client error {}", err);
+                        }
+                    });
+                    stream_hub.set_webrtc_pull_enabled(true);
+                }
+            }
         }
 
         Ok(())
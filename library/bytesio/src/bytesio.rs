@@ -33,22 +33,32 @@ pub struct UdpIO {
 
 impl UdpIO {
     pub async fn new(remote_domain: String, remote_port: u16, local_port: u16) -> Option<Self> {
-        let remote_address = format!("{remote_domain}:{remote_port}");
-        log::info!("remote address: {}", remote_address);
         let local_address = format!("0.0.0.0:{local_port}");
         if let Ok(local_socket) = UdpSocket::bind(local_address).await {
-            if let Ok(remote_socket_addr) = remote_address.parse::<SocketAddr>() {
-                if let Err(err) = local_socket.connect(remote_socket_addr).await {
-                    log::info!("connect to remote udp socket error: {}", err);
-                }
-            }
-            return Some(Self {
-                socket: local_socket,
-            });
+            return Some(Self::new_with_socket(local_socket, remote_domain, remote_port).await);
         }
 
         None
     }
+
+    //use a socket which is already bound, e.g. one of the rtp/rtcp port pair.
+    pub async fn new_with_socket(
+        local_socket: UdpSocket,
+        remote_domain: String,
+        remote_port: u16,
+    ) -> Self {
+        let remote_address = format!("{remote_domain}:{remote_port}");
+        log::info!("remote address: {}", remote_address);
+        if let Ok(remote_socket_addr) = remote_address.parse::<SocketAddr>() {
+            if let Err(err) = local_socket.connect(remote_socket_addr).await {
+                log::info!("connect to remote udp socket error: {}", err);
+            }
+        }
+        Self {
+            socket: local_socket,
+        }
+    }
+
     pub fn get_local_port(&self) -> Option<u16> {
         if let Ok(local_addr) = self.socket.local_addr() {
             log::info!("local address: {}", local_addr);
@@ -57,6 +67,11 @@ impl UdpIO {
 
         None
     }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> Result<(), BytesIOError> {
+        self.socket.set_multicast_ttl_v4(ttl)?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -49,6 +49,45 @@ impl Auth {
         stream_name: &String,
         query: &Option<String>,
         is_pull: bool,
+    ) -> Result<(), AuthError> {
+        self.authenticate_token(stream_name, Self::get_query_token(query), is_pull)
+    }
+
+    //The token is carried by the Authorization header(Bearer <token>) or the query(token=xxx),
+    //the header is preferred if both of them exist.
+    pub fn authenticate_bearer(
+        &self,
+        stream_name: &String,
+        authorization: Option<&String>,
+        query: &Option<String>,
+        is_pull: bool,
+    ) -> Result<(), AuthError> {
+        let token = authorization
+            .and_then(|value| value.trim().strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string())
+            .or_else(|| Self::get_query_token(query));
+        self.authenticate_token(stream_name, token, is_pull)
+    }
+
+    fn get_query_token(query: &Option<String>) -> Option<String> {
+        let query_val = query.as_ref()?;
+        let mut query_pairs = IndexMap::new();
+        let pars_array: Vec<&str> = query_val.split('&').collect();
+        for ele in pars_array {
+            let (k, v) = scanf!(ele, '=', String, String);
+            if k.is_none() || v.is_none() {
+                continue;
+            }
+            query_pairs.insert(k.unwrap(), v.unwrap());
+        }
+        query_pairs.get("token").cloned()
+    }
+
+    fn authenticate_token(
+        &self,
+        stream_name: &String,
+        token: Option<String>,
+        is_pull: bool,
     ) -> Result<(), AuthError> {
         if self.auth_type == AuthType::Both
             || is_pull && (self.auth_type == AuthType::Pull)
@@ -58,24 +97,12 @@ impl Auth {
             let mut err: AuthErrorValue = AuthErrorValue::NoTokenFound;
 
             /*Here we should do auth and it must be successful. */
-            if let Some(query_val) = query {
-                let mut query_pairs = IndexMap::new();
-                let pars_array: Vec<&str> = query_val.split('&').collect();
-                for ele in pars_array {
-                    let (k, v) = scanf!(ele, '=', String, String);
-                    if k.is_none() || v.is_none() {
-                        continue;
-                    }
-                    query_pairs.insert(k.unwrap(), v.unwrap());
-                }
-
-                if let Some(token) = query_pairs.get("token") {
-                    if self.check(stream_name, token) {
-                        return Ok(());
-                    }
-                    auth_err_reason = format!("token is not correct: {}", token);
-                    err = AuthErrorValue::TokenIsNotCorrect;
+            if let Some(token) = token {
+                if self.check(stream_name, &token) {
+                    return Ok(());
                 }
+                auth_err_reason = format!("token is not correct: {}", token);
+                err = AuthErrorValue::TokenIsNotCorrect;
             }
 
             log::error!(
@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::Bytes;
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize, Default)]
@@ -159,7 +159,7 @@ pub fn u8_2_avc_level(profile: u8) -> AvcLevel {
 }
 
 pub enum FlvData {
-    Video { timestamp: u32, data: BytesMut },
-    Audio { timestamp: u32, data: BytesMut },
-    MetaData { timestamp: u32, data: BytesMut },
+    Video { timestamp: u32, data: Bytes },
+    Audio { timestamp: u32, data: Bytes },
+    MetaData { timestamp: u32, data: Bytes },
 }
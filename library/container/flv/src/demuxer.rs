@@ -11,7 +11,7 @@ use {
         mpeg4_avc::Mpeg4AvcProcessor,
     },
     byteorder::BigEndian,
-    bytes::BytesMut,
+    bytes::{Bytes, BytesMut},
     bytesio::bytes_reader::BytesReader,
 };
 
@@ -90,6 +90,21 @@ impl FlvDemuxerAudioData {
         }
     }
 }
+//The longest audio/video tag header is 5 bytes, only these bytes are copied for
+//parsing the header, the tag data after it is borrowed from the shared frame.
+const MAX_TAG_HEADER_LEN: usize = 5;
+
+fn read_tag_header<T>(data: &[u8]) -> Result<(T, &[u8]), FlvDemuxerError>
+where
+    T: for<'a> Unmarshal<&'a mut BytesReader, Result<T, FlvDemuxerError>>,
+{
+    let header_data = &data[..data.len().min(MAX_TAG_HEADER_LEN)];
+    let mut reader = BytesReader::new(BytesMut::from(header_data));
+    let tag_header = T::unmarshal(&mut reader)?;
+    let header_len = header_data.len() - reader.len();
+    Ok((tag_header, &data[header_len..]))
+}
+
 #[derive(Default)]
 pub struct FlvDemuxerVideoData {
     pub frame_type: u8,
@@ -125,21 +140,20 @@ impl FlvVideoTagDemuxer {
     pub fn demux(
         &mut self,
         timestamp: u32,
-        data: BytesMut,
+        data: Bytes,
     ) -> Result<Option<FlvDemuxerVideoData>, FlvDemuxerError> {
-        let mut reader = BytesReader::new(data);
-
-        let tag_header = VideoTagHeader::unmarshal(&mut reader)?;
+        let (tag_header, payload) = read_tag_header::<VideoTagHeader>(&data)?;
         if tag_header.codec_id == AvcCodecId::H264 as u8 {
             match tag_header.avc_packet_type {
                 avc_packet_type::AVC_SEQHDR => {
+                    let mut reader = BytesReader::new(BytesMut::from(payload));
                     self.avc_processor
                         .decoder_configuration_record_load(&mut reader)?;
 
                     return Ok(None);
                 }
                 avc_packet_type::AVC_NALU => {
-                    let data = self.avc_processor.h264_mp4toannexb(&mut reader)?;
+                    let data = self.avc_processor.h264_mp4toannexb(payload)?;
 
                     let video_data = FlvDemuxerVideoData {
                         codec_id: AvcCodecId::H264 as u8,
@@ -174,13 +188,10 @@ impl FlvAudioTagDemuxer {
     pub fn demux(
         &mut self,
         timestamp: u32,
-        data: BytesMut,
+        data: Bytes,
     ) -> Result<FlvDemuxerAudioData, FlvDemuxerError> {
-        let mut reader = BytesReader::new(data);
-
-        let tag_header = AudioTagHeader::unmarshal(&mut reader)?;
-        self.aac_processor
-            .extend_data(reader.extract_remaining_bytes());
+        let (tag_header, payload) = read_tag_header::<AudioTagHeader>(&data)?;
+        self.aac_processor.extend_data(payload);
 
         if tag_header.sound_format == SoundFormat::AAC as u8 {
             match tag_header.aac_packet_type {
@@ -250,13 +261,13 @@ impl FlvDemuxer {
             tag_type::VIDEO => {
                 return Ok(Some(FlvData::Video {
                     timestamp: dts,
-                    data: body,
+                    data: body.freeze(),
                 }));
             }
             tag_type::AUDIO => {
                 return Ok(Some(FlvData::Audio {
                     timestamp: dts,
-                    data: body,
+                    data: body.freeze(),
                 }));
             }
 
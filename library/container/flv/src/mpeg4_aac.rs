@@ -98,8 +98,8 @@ impl Mpeg4AacProcessor {
         }
     }
 
-    pub fn extend_data(&mut self, data: BytesMut) -> &mut Self {
-        self.bytes_reader.extend_from_slice(&data[..]);
+    pub fn extend_data(&mut self, data: &[u8]) -> &mut Self {
+        self.bytes_reader.extend_from_slice(data);
         self
     }
 
@@ -1,8 +1,12 @@
 use {
     super::{define::h264_nal_type, errors::Mpeg4AvcHevcError},
     byteorder::BigEndian,
-    bytes::BytesMut,
-    bytesio::{bytes_reader::BytesReader, bytes_writer::BytesWriter},
+    bytes::{Bytes, BytesMut},
+    bytesio::{
+        bytes_errors::{BytesReadError, BytesReadErrorValue},
+        bytes_reader::BytesReader,
+        bytes_writer::BytesWriter,
+    },
     std::vec::Vec,
 };
 
@@ -80,6 +84,12 @@ pub struct Mpeg4Avc {
     // off: i32,
 }
 
+fn not_enough_bytes() -> Mpeg4AvcHevcError {
+    Mpeg4AvcHevcError::from(BytesReadError {
+        value: BytesReadErrorValue::NotEnoughBytes,
+    })
+}
+
 pub fn print(data: BytesMut) {
     println!("==========={}", data.len());
     let mut idx = 0;
@@ -225,16 +235,17 @@ impl Mpeg4AvcProcessor {
         Ok(self)
     }
     //https://stackoverflow.com/questions/28678615/efficiently-insert-or-replace-multiple-elements-in-the-middle-or-at-the-beginnin
-    pub fn h264_mp4toannexb(
-        &mut self,
-        bytes_reader: &mut BytesReader,
-    ) -> Result<BytesMut, Mpeg4AvcHevcError> {
+    //the nalus are read from the shared frame data without copying it first
+    pub fn h264_mp4toannexb(&mut self, mut data: &[u8]) -> Result<BytesMut, Mpeg4AvcHevcError> {
         let mut bytes_writer = BytesWriter::new();
 
         let mut sps_pps_flag = false;
-        while !bytes_reader.is_empty() {
-            let size = self.read_nalu_size(bytes_reader)?;
-            let nalu_type = bytes_reader.advance_u8()? & 0x1f;
+        while !data.is_empty() {
+            let size = self.read_nalu_size(&mut data)? as usize;
+            let nalu_type = match data.first() {
+                Some(byte) if data.len() >= size => byte & 0x1f,
+                _ => return Err(not_enough_bytes()),
+            };
 
             match nalu_type {
                 h264_nal_type::H264_NAL_PPS | h264_nal_type::H264_NAL_SPS => {
@@ -254,19 +265,24 @@ impl Mpeg4AvcProcessor {
             }
 
             bytes_writer.write(&H264_START_CODE)?;
-            let data = bytes_reader.read_bytes(size as usize)?;
-            bytes_writer.write(&data[..])?;
+            bytes_writer.write(&data[..size])?;
+            data = &data[size..];
         }
 
         Ok(bytes_writer.extract_current_bytes())
     }
 
-    pub fn read_nalu_size(&mut self, bytes_reader: &mut BytesReader) -> Result<u32, Mpeg4AvcHevcError> {
-        let mut size: u32 = 0;
+    pub fn read_nalu_size(&mut self, data: &mut &[u8]) -> Result<u32, Mpeg4AvcHevcError> {
+        let nalu_length = self.mpeg4_avc.nalu_length as usize;
+        if data.len() < nalu_length {
+            return Err(not_enough_bytes());
+        }
 
-        for _ in 0..self.mpeg4_avc.nalu_length {
-            size = bytes_reader.read_u8()? as u32 + (size << 8);
+        let mut size: u32 = 0;
+        for byte in &data[..nalu_length] {
+            size = *byte as u32 + (size << 8);
         }
+        *data = &data[nalu_length..];
         Ok(size)
     }
 
@@ -284,7 +300,7 @@ impl Mpeg4AvcProcessor {
         Ok(())
     }
 
-    pub fn nalus_to_mpeg4avc(&mut self, nalus: Vec<BytesMut>) -> Result<BytesMut, Mpeg4AvcHevcError> {
+    pub fn nalus_to_mpeg4avc(&mut self, nalus: Vec<Bytes>) -> Result<BytesMut, Mpeg4AvcHevcError> {
         let mut bytes_writer = BytesWriter::new();
 
         for nalu in nalus {
@@ -335,6 +351,7 @@ impl Mpeg4AvcProcessor {
 
 #[cfg(test)]
 mod tests {
+    use super::{Mpeg4Avc, Mpeg4AvcProcessor};
     use bytes::BytesMut;
     use bytesio::{bytes_reader::BytesReader, bytes_writer::BytesWriter};
 
@@ -363,4 +380,21 @@ mod tests {
         }
         println!("num: {:?}", bytes_writer.extract_current_bytes());
     }
+
+    #[test]
+    fn test_h264_mp4toannexb() {
+        let mut processor = Mpeg4AvcProcessor {
+            mpeg4_avc: Mpeg4Avc {
+                nalu_length: 4,
+                ..Default::default()
+            },
+        };
+
+        let data = [0, 0, 0, 2, 0x41, 0xAA, 0, 0, 0, 1, 0x01];
+        let annexb = processor.h264_mp4toannexb(&data).unwrap();
+        assert_eq!(&annexb[..], &[0, 0, 0, 1, 0x41, 0xAA, 0, 0, 0, 1, 0x01]);
+
+        //the nalu is shorter than its size
+        assert!(processor.h264_mp4toannexb(&data[..5]).is_err());
+    }
 }
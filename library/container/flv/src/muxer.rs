@@ -1,6 +1,5 @@
 use {
-    super::errors::FlvMuxerError, byteorder::BigEndian, bytes::BytesMut,
-    bytesio::bytes_writer::BytesWriter,
+    super::errors::FlvMuxerError, byteorder::BigEndian, bytesio::bytes_writer::BytesWriter,
 };
 
 const FLV_HEADER: [u8; 9] = [
@@ -72,8 +71,8 @@ impl FlvMuxer {
         Ok(())
     }
 
-    pub fn write_flv_tag_body(&mut self, body: BytesMut) -> Result<(), FlvMuxerError> {
-        self.writer.write(&body[..])?;
+    pub fn write_flv_tag_body(&mut self, body: &[u8]) -> Result<(), FlvMuxerError> {
+        self.writer.write(body)?;
         Ok(())
     }
 
@@ -0,0 +1,269 @@
+use {
+    super::{
+        define,
+        define::epat_pid,
+        errors::{MpegTsError, MpegTsErrorValue},
+    },
+    byteorder::BigEndian,
+    bytes::BytesMut,
+    bytesio::bytes_reader::BytesReader,
+    std::collections::HashMap,
+};
+
+//The elementary stream data of one pes packet.
+#[derive(Debug, Clone)]
+pub struct TsFrame {
+    pub pid: u16,
+    pub stream_type: u8,
+    //90kHz
+    pub pts: i64,
+    pub dts: i64,
+    pub data: BytesMut,
+}
+
+struct PesBuffer {
+    stream_type: u8,
+    pts: i64,
+    dts: i64,
+    data: BytesMut,
+    //the first packet(payload unit start) of the pes is received
+    is_started: bool,
+}
+
+#[derive(Default)]
+pub struct TsDemuxer {
+    pmt_pids: Vec<u16>,
+    //key: elementary pid
+    streams: HashMap<u16, PesBuffer>,
+}
+
+impl TsDemuxer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Demux the ts packets, the pes packets are returned when the next pes of the same pid begins.
+    pub fn demux(&mut self, data: &[u8]) -> Result<Vec<TsFrame>, MpegTsError> {
+        let mut frames = Vec::new();
+        for packet in data.chunks(define::TS_PACKET_SIZE) {
+            if packet.len() < define::TS_PACKET_SIZE {
+                break;
+            }
+            if let Some(frame) = self.demux_packet(packet)? {
+                frames.push(frame);
+            }
+        }
+        Ok(frames)
+    }
+
+    //Return the buffered pes packets at the end of the file.
+    pub fn flush(&mut self) -> Vec<TsFrame> {
+        let mut frames = Vec::new();
+        for (pid, stream) in self.streams.iter_mut() {
+            if let Some(frame) = Self::take_frame(*pid, stream) {
+                frames.push(frame);
+            }
+        }
+        frames.sort_by_key(|frame| frame.dts);
+        frames
+    }
+
+    fn demux_packet(&mut self, packet: &[u8]) -> Result<Option<TsFrame>, MpegTsError> {
+        let mut reader = BytesReader::new(BytesMut::from(packet));
+
+        /*sync byte*/
+        if reader.read_u8()? != 0x47 {
+            return Err(MpegTsError {
+                value: MpegTsErrorValue::SyncByteNotFound,
+            });
+        }
+        let pid_data = reader.read_u16::<BigEndian>()?;
+        let payload_unit_start =
+            (pid_data >> 8) as u8 & define::TS_PAYLOAD_UNIT_START_INDICATOR > 0;
+        let pid = pid_data & 0x1FFF;
+
+        let adaptation_field_control = (reader.read_u8()? >> 4) & 0x03;
+        if adaptation_field_control & 0x02 > 0 {
+            let adaptation_field_length = reader.read_u8()? as usize;
+            reader.read_bytes(adaptation_field_length)?;
+        }
+        if adaptation_field_control & 0x01 == 0 || reader.is_empty() {
+            return Ok(None);
+        }
+
+        if pid == epat_pid::PAT_TID_PAS {
+            self.parse_pat(&mut reader)?;
+        } else if self.pmt_pids.contains(&pid) {
+            self.parse_pmt(&mut reader)?;
+        } else if let Some(stream) = self.streams.get_mut(&pid) {
+            let mut frame = None;
+            if payload_unit_start {
+                frame = Self::take_frame(pid, stream);
+                Self::parse_pes_header(&mut reader, stream)?;
+                stream.is_started = true;
+            }
+            if stream.is_started {
+                stream
+                    .data
+                    .extend_from_slice(&reader.extract_remaining_bytes()[..]);
+            }
+            return Ok(frame);
+        }
+
+        Ok(None)
+    }
+
+    fn take_frame(pid: u16, stream: &mut PesBuffer) -> Option<TsFrame> {
+        if !stream.is_started || stream.data.is_empty() {
+            return None;
+        }
+        stream.is_started = false;
+
+        Some(TsFrame {
+            pid,
+            stream_type: stream.stream_type,
+            pts: stream.pts,
+            dts: stream.dts,
+            data: stream.data.split(),
+        })
+    }
+
+    fn read_section_header(reader: &mut BytesReader) -> Result<usize, MpegTsError> {
+        /*pointer field*/
+        let pointer_field = reader.read_u8()? as usize;
+        reader.read_bytes(pointer_field)?;
+        /*table id*/
+        reader.read_u8()?;
+        /*section length*/
+        let section_length = (reader.read_u16::<BigEndian>()? & 0x0FFF) as usize;
+        Ok(section_length)
+    }
+
+    fn parse_pat(&mut self, reader: &mut BytesReader) -> Result<(), MpegTsError> {
+        let section_length = Self::read_section_header(reader)?;
+        /*transport_stream_id + version + section_number + last_section_number*/
+        reader.read_bytes(5)?;
+
+        //the left data except crc32
+        let mut left_length = section_length.saturating_sub(5 + 4);
+        while left_length >= 4 {
+            let program_number = reader.read_u16::<BigEndian>()?;
+            let pid = reader.read_u16::<BigEndian>()? & 0x1FFF;
+            //program number 0 is the network pid
+            if program_number != 0 && !self.pmt_pids.contains(&pid) {
+                self.pmt_pids.push(pid);
+            }
+            left_length -= 4;
+        }
+        Ok(())
+    }
+
+    fn parse_pmt(&mut self, reader: &mut BytesReader) -> Result<(), MpegTsError> {
+        let section_length = Self::read_section_header(reader)?;
+        /*program_number + version + section_number + last_section_number + pcr pid*/
+        reader.read_bytes(7)?;
+        let program_info_length = (reader.read_u16::<BigEndian>()? & 0x0FFF) as usize;
+        reader.read_bytes(program_info_length)?;
+
+        let mut left_length = section_length.saturating_sub(9 + program_info_length + 4);
+        while left_length >= 5 {
+            let stream_type = reader.read_u8()?;
+            let pid = reader.read_u16::<BigEndian>()? & 0x1FFF;
+            let es_info_length = (reader.read_u16::<BigEndian>()? & 0x0FFF) as usize;
+            reader.read_bytes(es_info_length)?;
+            left_length = left_length.saturating_sub(5 + es_info_length);
+
+            self.streams.entry(pid).or_insert_with(|| PesBuffer {
+                stream_type,
+                pts: define::PTS_NO_VALUE,
+                dts: define::PTS_NO_VALUE,
+                data: BytesMut::new(),
+                is_started: false,
+            });
+        }
+        Ok(())
+    }
+
+    //http://dvdnav.mplayerhq.hu/dvdinfo/pes-hdr.html
+    fn parse_pes_header(
+        reader: &mut BytesReader,
+        stream: &mut PesBuffer,
+    ) -> Result<(), MpegTsError> {
+        /*start code(3 bytes) + stream id(1 byte) + pes packet length(2 bytes)*/
+        reader.read_bytes(define::PES_HEADER_LEN as usize)?;
+        /*first flag*/
+        reader.read_u8()?;
+        /*second flag*/
+        let flags = reader.read_u8()?;
+        let header_data_length = reader.read_u8()? as usize;
+        let mut header_reader = BytesReader::new(reader.read_bytes(header_data_length)?);
+
+        stream.pts = define::PTS_NO_VALUE;
+        if flags & 0x80 > 0 {
+            stream.pts = Self::read_timestamp(&mut header_reader)?;
+        }
+        stream.dts = stream.pts;
+        if flags & 0x40 > 0 {
+            stream.dts = Self::read_timestamp(&mut header_reader)?;
+        }
+        Ok(())
+    }
+
+    fn read_timestamp(reader: &mut BytesReader) -> Result<i64, MpegTsError> {
+        let data = reader.read_bytes(5)?;
+        Ok((((data[0] >> 1) & 0x07) as i64) << 30
+            | (data[1] as i64) << 22
+            | ((data[2] >> 1) as i64) << 15
+            | (data[3] as i64) << 7
+            | (data[4] >> 1) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define::epsi_stream_type;
+    use crate::ts::TsMuxer;
+
+    #[test]
+    fn test_demux_muxed_data() {
+        let mut muxer = TsMuxer::new();
+        let video_pid = muxer
+            .add_stream(epsi_stream_type::PSI_STREAM_H264, BytesMut::new())
+            .unwrap();
+        let audio_pid = muxer
+            .add_stream(epsi_stream_type::PSI_STREAM_AAC, BytesMut::new())
+            .unwrap();
+
+        let video_data = BytesMut::from(&[0x00, 0x00, 0x00, 0x01, 0x65, 0x88][..]);
+        let audio_data = BytesMut::from(&[0xAA; 400][..]);
+        muxer
+            .write(
+                video_pid,
+                9000,
+                3000,
+                define::MPEG_FLAG_IDR_FRAME,
+                video_data,
+            )
+            .unwrap();
+        muxer.write(audio_pid, 4000, 4000, 0, audio_data).unwrap();
+        muxer
+            .write(audio_pid, 5000, 5000, 0, BytesMut::from(&[0xBB; 10][..]))
+            .unwrap();
+
+        let mut demuxer = TsDemuxer::new();
+        let mut frames = demuxer.demux(&muxer.get_data()[..]).unwrap();
+        frames.extend(demuxer.flush());
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].pid, audio_pid);
+        assert_eq!(frames[0].pts, 4000);
+        assert_eq!(frames[0].data.len(), 400);
+
+        let video = frames.iter().find(|frame| frame.pid == video_pid).unwrap();
+        assert_eq!(video.stream_type, epsi_stream_type::PSI_STREAM_H264);
+        assert_eq!((video.pts, video.dts), (9000, 3000));
+        //the aud is inserted by the muxer
+        assert!(video.data.ends_with(&[0x00, 0x00, 0x00, 0x01, 0x65, 0x88]));
+    }
+}
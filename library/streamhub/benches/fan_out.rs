@@ -0,0 +1,73 @@
+//Compare the frame fan-out of copying the frame data for every subscriber with
+//sharing one reference-counted Bytes, run with: cargo bench -p streamhub
+use {
+    bytes::Bytes,
+    criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput},
+    streamhub::{
+        define::FrameData,
+        queue::{QueueConfig, SubscriberQueue},
+        utils::Uuid,
+    },
+    tokio::sync::mpsc,
+};
+
+//a 6 Mbps stream at 30 fps
+const FRAME_SIZE: usize = 6 * 1024 * 1024 / 8 / 30;
+const SUBSCRIBER_COUNTS: [usize; 3] = [1, 50, 500];
+
+type Subscribers = (
+    Vec<SubscriberQueue<FrameData>>,
+    Vec<mpsc::Receiver<FrameData>>,
+);
+
+fn new_subscribers(count: usize) -> Subscribers {
+    (0..count)
+        .map(|_| {
+            let (sender, receiver) = mpsc::channel(1);
+            let queue =
+                SubscriberQueue::new(Uuid::default(), sender, QueueConfig::default(), false);
+            (queue, receiver)
+        })
+        .unzip()
+}
+
+//push one frame into every subscriber queue and receive it, the frame data of each
+//subscriber is made by the given function.
+fn fan_out(subscribers: &mut Subscribers, data: &Bytes, make_data: fn(&Bytes) -> Bytes) {
+    let (queues, receivers) = subscribers;
+    for queue in queues.iter_mut() {
+        queue.push(FrameData::Video {
+            timestamp: 0,
+            data: make_data(data),
+        });
+    }
+    for receiver in receivers.iter_mut() {
+        let _ = receiver.try_recv();
+    }
+}
+
+fn bench_fan_out(c: &mut Criterion) {
+    //a key frame, so that the queues do not drop it while waiting for one
+    let mut frame = vec![0x27; FRAME_SIZE];
+    frame[0] = 0x17;
+    let data = Bytes::from(frame);
+
+    let mut group = c.benchmark_group("fan_out");
+    for count in SUBSCRIBER_COUNTS {
+        group.throughput(Throughput::Bytes((FRAME_SIZE * count) as u64));
+
+        let mut subscribers = new_subscribers(count);
+        group.bench_with_input(BenchmarkId::new("copied", count), &data, |b, data| {
+            b.iter(|| fan_out(&mut subscribers, data, |data| Bytes::copy_from_slice(data)))
+        });
+
+        let mut subscribers = new_subscribers(count);
+        group.bench_with_input(BenchmarkId::new("shared", count), &data, |b, data| {
+            b.iter(|| fan_out(&mut subscribers, data, Bytes::clone))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fan_out);
+criterion_main!(benches);
@@ -6,10 +6,13 @@ use crate::utils;
 
 use {
     super::errors::StreamHubError,
-    crate::statistics::StatisticsStream,
+    crate::statistics::{
+        RtpReceiveStatistics, RtpSendStatistics, StatisticsStream, WebRTCStatistics,
+    },
     crate::stream::StreamIdentifier,
+    crate::timestamp::TimestampCorrection,
     async_trait::async_trait,
-    bytes::BytesMut,
+    bytes::Bytes,
     serde::ser::SerializeStruct,
     serde::Serialize,
     serde::Serializer,
@@ -37,6 +40,12 @@ pub enum SubscribeType {
     /* Local client *subscribe* from local rtmp session
     and *publish* (relay push) the stream to remote server.*/
     PublisherRtmp,
+    /* Local client *subscribe* from local rtsp session
+    and *publish* (relay push) the stream to remote server.*/
+    PublisherRtsp,
+    /* Local client *subscribe* the raw rtp stream from local session
+    and *publish* (whip) the stream to remote webrtc server.*/
+    PublisherWebrtc,
 }
 
 //session publish type
@@ -52,6 +61,9 @@ pub enum PublishType {
     RelayRtsp,
     /* Receive webrtc stream from remote push client(whip),  */
     PushWebRTC,
+    /* Local client *publish* the webrtc stream to local session,
+    the stream is *subscribed* (whep) from remote webrtc server.*/
+    RelayWebRTC,
     /* It used for publishing raw rtp data of rtsp/whbrtc(whip) */
     PushRtp,
 }
@@ -62,12 +74,42 @@ pub struct NotifyInfo {
     pub remote_addr: String,
 }
 
+//Decide which cached data is sent to a subscriber before the live data,
+//it can be set by the "start_mode" query parameter of the play url.
+#[derive(Debug, Serialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SubscribeStartMode {
+    /* Send all the cached gops, the player can start fast.*/
+    #[default]
+    FullGop,
+    /* Only send the latest gop which begins with the latest key frame, it has lower latency.*/
+    LatestKeyFrame,
+    /* Do not send the cached gops, the player waits for the next key frame.*/
+    NoCache,
+}
+
+impl SubscribeStartMode {
+    pub fn from_query(query: &str) -> Self {
+        for param in query.split('&') {
+            if let Some(("start_mode", value)) = param.split_once('=') {
+                match value {
+                    "full_gop" => return Self::FullGop,
+                    "key_frame" => return Self::LatestKeyFrame,
+                    "no_cache" => return Self::NoCache,
+                    _ => log::warn!("unknown start_mode: {}", value),
+                }
+            }
+        }
+        Self::default()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SubscriberInfo {
     pub id: Uuid,
     pub sub_type: SubscribeType,
     pub notify_info: NotifyInfo,
     pub sub_data_type: SubDataType,
+    pub start_mode: SubscribeStartMode,
 }
 
 impl Serialize for SubscriberInfo {
@@ -108,10 +150,24 @@ impl Serialize for PublisherInfo {
     }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub enum VideoCodecType {
     H264,
     H265,
+    VP8,
+    VP9,
+    AV1,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum AudioCodecType {
+    AAC,
+    //G.711 A-law
+    G711A,
+    //G.711 µ-law
+    G711U,
+    G726,
+    Opus,
 }
 
 #[derive(Clone)]
@@ -119,27 +175,35 @@ pub struct MediaInfo {
     pub audio_clock_rate: u32,
     pub video_clock_rate: u32,
     pub vcodec: VideoCodecType,
+    pub acodec: AudioCodecType,
 }
 
+//The a/v data is reference-counted, so the fan-out to subscribers and
+//the gop cache replay share one allocation instead of copying it.
 #[derive(Clone)]
 pub enum FrameData {
-    Video { timestamp: u32, data: BytesMut },
-    Audio { timestamp: u32, data: BytesMut },
-    MetaData { timestamp: u32, data: BytesMut },
+    Video { timestamp: u32, data: Bytes },
+    Audio { timestamp: u32, data: Bytes },
+    MetaData { timestamp: u32, data: Bytes },
     MediaInfo { media_info: MediaInfo },
+    //a text or binary message of the webrtc data channel, e.g. the cues, captions and chats
+    DataMessage { timestamp: u32, data: Bytes },
 }
 
 //Used to pass rtp raw data.
 #[derive(Clone)]
 pub enum PacketData {
-    Video { timestamp: u32, data: BytesMut },
-    Audio { timestamp: u32, data: BytesMut },
+    Video { timestamp: u32, data: Bytes },
+    Audio { timestamp: u32, data: Bytes },
+    DataMessage { timestamp: u32, data: Bytes },
 }
 
 //used to save data which needs to be transferred between client/server sessions
 #[derive(Clone)]
 pub enum Information {
     Sdp { data: String },
+    //the negotiated video codec of a webrtc publisher
+    VideoCodec { codec: VideoCodecType },
 }
 
 //used to transfer a/v frame between different protocols(rtmp/rtsp/webrtc/http-flv/hls)
@@ -153,6 +217,13 @@ pub type FrameDataReceiver = mpsc::UnboundedReceiver<FrameData>;
 pub type PacketDataSender = mpsc::UnboundedSender<PacketData>;
 pub type PacketDataReceiver = mpsc::UnboundedReceiver<PacketData>;
 
+//used to send a/v frame/packet data from stream hub to subscribers, the channels
+//are bounded so that a slow subscriber cannot make the memory grow unlimitedly.
+pub type SubFrameDataSender = mpsc::Sender<FrameData>;
+pub type SubFrameDataReceiver = mpsc::Receiver<FrameData>;
+pub type SubPacketDataSender = mpsc::Sender<PacketData>;
+pub type SubPacketDataReceiver = mpsc::Receiver<PacketData>;
+
 pub type InformationSender = mpsc::UnboundedSender<Information>;
 pub type InformationReceiver = mpsc::UnboundedReceiver<Information>;
 
@@ -175,7 +246,7 @@ pub type StatisticApiResultSender = oneshot::Sender<Value>;
 pub type StatisticApiResultReceiver = oneshot::Receiver<Value>;
 
 pub type SubEventExecuteResultSender =
-    oneshot::Sender<Result<(DataReceiver, Option<StatisticDataSender>), StreamHubError>>;
+    oneshot::Sender<Result<(SubDataReceiver, Option<StatisticDataSender>), StreamHubError>>;
 pub type PubEventExecuteResultSender = oneshot::Sender<
     Result<
         (
@@ -194,9 +265,13 @@ pub trait TStreamHandler: Send + Sync {
         &self,
         sender: DataSender,
         sub_type: SubscribeType,
+        start_mode: SubscribeStartMode,
     ) -> Result<(), StreamHubError>;
     async fn get_statistic_data(&self) -> Option<StatisticsStream>;
     async fn send_information(&self, sender: InformationSender);
+    //a subscriber lost the reference frames(e.g. a PLI/FIR from a webrtc viewer), the
+    //sender is the subscriber's, if the stream handler replays the cached data to it.
+    async fn request_key_frame(&self, _sender: Option<DataSender>) {}
 }
 
 //A publisher can publish one or two kinds of av stream at a time.
@@ -205,11 +280,16 @@ pub struct DataReceiver {
     pub packet_receiver: Option<PacketDataReceiver>,
 }
 
+pub struct SubDataReceiver {
+    pub frame_receiver: Option<SubFrameDataReceiver>,
+    pub packet_receiver: Option<SubPacketDataReceiver>,
+}
+
 //A subscriber only needs to subscribe to one type of stream at a time
 #[derive(Debug, Clone)]
 pub enum DataSender {
-    Frame { sender: FrameDataSender },
-    Packet { sender: PacketDataSender },
+    Frame { sender: SubFrameDataSender },
+    Packet { sender: SubPacketDataSender },
 }
 //we can only sub one kind of stream.
 #[derive(Debug, Clone, Serialize)]
@@ -264,6 +344,12 @@ pub enum StreamHubEvent {
         identifier: StreamIdentifier,
         sender: InformationSender,
     },
+    //the subscriber asks the publisher of the stream for a key frame
+    #[serde(skip_serializing)]
+    RequestKeyFrame {
+        identifier: StreamIdentifier,
+        id: Uuid,
+    },
 }
 
 #[derive(Debug)]
@@ -285,6 +371,9 @@ pub enum TransceiverEvent {
     Request {
         sender: InformationSender,
     },
+    RequestKeyFrame {
+        id: Uuid,
+    },
 }
 
 impl fmt::Display for TransceiverEvent {
@@ -301,6 +390,8 @@ pub enum BroadcastEvent {
     /*Need subscribe(pull) a stream from other rtmp server*/
     Subscribe { identifier: StreamIdentifier },
     UnSubscribe { identifier: StreamIdentifier },
+    /*Need close the connection of a kicked off client(e.g. a webrtc peer connection)*/
+    KickOffClient { id: Uuid },
 }
 
 pub enum StatisticData {
@@ -341,4 +432,28 @@ pub enum StatisticData {
         sub_type: SubscribeType,
         start_time: DateTime<Local>,
     },
+    //the subscriber's queue is full and the frame/packet is dropped.
+    SubscriberDrop {
+        id: Uuid,
+        is_video: bool,
+    },
+    //the publisher's timestamp is corrected by the normalizer.
+    TimestampCorrection {
+        correction: TimestampCorrection,
+    },
+    //the packet loss/jitter/recovery counters of the rtp publisher.
+    RtpReceive {
+        statistics: RtpReceiveStatistics,
+    },
+    //the rtt/loss/bandwidth of the webrtc subscriber.
+    RtpSend {
+        id: Uuid,
+        statistics: RtpSendStatistics,
+    },
+    //the peer connection state of the webrtc publisher or subscriber.
+    WebRTC {
+        id: Uuid,
+        remote_addr: String,
+        statistics: WebRTCStatistics,
+    },
 }
@@ -1,9 +1,10 @@
 use define::{
-    FrameDataReceiver, PacketDataReceiver, PacketDataSender, StatisticData, StatisticDataReceiver,
-    StatisticDataSender,
+    FrameDataReceiver, PacketDataReceiver, StatisticData, StatisticDataReceiver,
+    StatisticDataSender, SubDataReceiver,
 };
+use queue::{MediaKind, PushResult, QueueConfig, SubscriberQueue, TQueueData};
 use serde_json::{json, Value};
-use statistics::{StatisticSubscriber, StatisticsStream};
+use statistics::{RtpSendStatistics, StatisticSubscriber, StatisticsStream};
 use tokio::sync::oneshot;
 use xflv::define::aac_packet_type;
 
@@ -12,17 +13,19 @@ use crate::define::PacketData;
 pub mod define;
 pub mod errors;
 pub mod notify;
+pub mod queue;
 pub mod statistics;
 pub mod stream;
+pub mod timestamp;
 pub mod utils;
 
 use {
     crate::notify::Notifier,
     define::{
         BroadcastEvent, BroadcastEventReceiver, BroadcastEventSender, DataReceiver, DataSender,
-        FrameData, FrameDataSender, Information, StreamHubEvent, StreamHubEventReceiver,
-        StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler, TransceiverEvent,
-        TransceiverEventReceiver, TransceiverEventSender,
+        FrameData, Information, StreamHubEvent, StreamHubEventReceiver, StreamHubEventSender,
+        SubscribeStartMode, SubscribeType, SubscriberInfo, TStreamHandler, TransceiverEvent,
+        TransceiverEventReceiver, TransceiverEventSender, VideoCodecType,
     },
     errors::{StreamHubError, StreamHubErrorValue},
     std::collections::HashMap,
@@ -40,9 +43,9 @@ pub struct StreamDataTransceiver {
     //used for receiving event
     event_receiver: TransceiverEventReceiver,
     //used for sending audio/video frame data to players/subscribers
-    id_to_frame_sender: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+    id_to_frame_sender: Arc<Mutex<HashMap<Uuid, SubscriberQueue<FrameData>>>>,
     //used for sending audio/video packet data to players/subscribers
-    id_to_packet_sender: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
+    id_to_packet_sender: Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
     //publisher and subscribers use this sender to submit statistical data
     statistic_data_sender: StatisticDataSender,
     //used for receiving statistical data from publishers and subscribers
@@ -51,6 +54,10 @@ pub struct StreamDataTransceiver {
     statistic_data: Arc<Mutex<StatisticsStream>>,
     //a hander implement by protocols, such as rtmp, webrtc, http-flv, hls
     stream_handler: Arc<dyn TStreamHandler>,
+    //the queue size and drop policy of the subscribers
+    queue_config: QueueConfig,
+    //the video codec in the media info of the publisher
+    video_codec: Arc<Mutex<VideoCodecType>>,
 }
 
 impl StreamDataTransceiver {
@@ -59,6 +66,7 @@ impl StreamDataTransceiver {
         event_receiver: UnboundedReceiver<TransceiverEvent>,
         identifier: StreamIdentifier,
         h: Arc<dyn TStreamHandler>,
+        queue_config: QueueConfig,
     ) -> Self {
         let (statistic_data_sender, statistic_data_receiver) = mpsc::unbounded_channel();
         Self {
@@ -70,74 +78,91 @@ impl StreamDataTransceiver {
             id_to_packet_sender: Arc::new(Mutex::new(HashMap::new())),
             stream_handler: h,
             statistic_data: Arc::new(Mutex::new(StatisticsStream::new(identifier))),
+            queue_config,
+            video_codec: Arc::new(Mutex::new(VideoCodecType::H264)),
         }
     }
 
-    async fn receive_frame_data(
-        data: Option<FrameData>,
-        frame_senders: &Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+    //push the data into all the subscribers' queues, the slow subscribers'
+    //data is dropped according to the drop policy.
+    async fn dispatch_to_subscribers<T: TQueueData>(
+        data: T,
+        subscribers: &Arc<Mutex<HashMap<Uuid, SubscriberQueue<T>>>>,
+        statistic_sender: &StatisticDataSender,
     ) {
-        if let Some(val) = data {
-            match val {
-                FrameData::MetaData {
-                    timestamp: _,
-                    data: _,
-                } => {}
-                FrameData::Audio { timestamp, data } => {
-                    let data = FrameData::Audio {
-                        timestamp,
-                        data: data.clone(),
+        let mut subscribers = subscribers.lock().await;
+        let mut disconnected_ids = Vec::new();
+
+        for (id, queue) in subscribers.iter_mut() {
+            match queue.push(data.clone()) {
+                PushResult::Sent => {}
+                PushResult::Dropped { kind } => {
+                    //only the dropped audio and video frames are counted
+                    let is_video = match kind {
+                        MediaKind::Video { .. } => true,
+                        MediaKind::Audio => false,
+                        MediaKind::Other => continue,
                     };
-
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(audio_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendAudioError,
-                        }) {
-                            log::error!("Transmiter send error: {}", audio_err);
-                        }
+                    if let Err(err) =
+                        statistic_sender.send(StatisticData::SubscriberDrop { id: *id, is_video })
+                    {
+                        log::error!("Transmiter send statistic data error: {}", err);
                     }
                 }
-                FrameData::Video { timestamp, data } => {
-                    let data = FrameData::Video {
-                        timestamp,
-                        data: data.clone(),
-                    };
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(video_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", video_err);
-                        }
-                    }
+                PushResult::Disconnect => {
+                    disconnected_ids.push(*id);
                 }
-                FrameData::MediaInfo {
-                    media_info: info_value,
-                } => {
-                    let data = FrameData::MediaInfo {
-                        media_info: info_value,
-                    };
-                    for (_, v) in frame_senders.lock().await.iter() {
-                        if let Err(media_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", media_err);
-                        }
-                    }
+            }
+        }
+
+        //the receiver of the subscriber will get None after the sender is dropped.
+        for id in disconnected_ids {
+            log::warn!("Transmiter remove slow or closed subscriber: {}", id);
+            subscribers.remove(&id);
+        }
+    }
+
+    async fn receive_frame_data(
+        data: Option<FrameData>,
+        frame_senders: &Arc<Mutex<HashMap<Uuid, SubscriberQueue<FrameData>>>>,
+        packet_senders: &Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
+        video_codec: &Arc<Mutex<VideoCodecType>>,
+        statistic_sender: &StatisticDataSender,
+    ) {
+        if let Some(val) = data {
+            //the packet subscribers detect the key frames by the video codec
+            if let FrameData::MediaInfo { media_info } = &val {
+                *video_codec.lock().await = media_info.vcodec.clone();
+                for queue in packet_senders.lock().await.values_mut() {
+                    queue.set_video_codec(media_info.vcodec.clone());
                 }
             }
+            //the metadata and the other amf data messages are forwarded to the rtmp
+            //and http-flv subscribers as well
+            Self::dispatch_to_subscribers(val, frame_senders, statistic_sender).await;
         }
     }
 
     async fn receive_frame_data_loop(
         mut exit: broadcast::Receiver<()>,
         mut receiver: FrameDataReceiver,
-        frame_senders: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+        frame_senders: Arc<Mutex<HashMap<Uuid, SubscriberQueue<FrameData>>>>,
+        packet_senders: Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
+        video_codec: Arc<Mutex<VideoCodecType>>,
+        statistic_sender: StatisticDataSender,
     ) {
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     data = receiver.recv() => {
-                       Self::receive_frame_data(data, &frame_senders).await;
+                       Self::receive_frame_data(
+                           data,
+                           &frame_senders,
+                           &packet_senders,
+                           &video_codec,
+                           &statistic_sender,
+                       )
+                       .await;
                     }
                     _ = exit.recv()=>{
                         break;
@@ -149,51 +174,25 @@ impl StreamDataTransceiver {
 
     async fn receive_packet_data(
         data: Option<PacketData>,
-        packet_senders: &Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
+        packet_senders: &Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
+        statistic_sender: &StatisticDataSender,
     ) {
         if let Some(val) = data {
-            match val {
-                PacketData::Audio { timestamp, data } => {
-                    let data = PacketData::Audio {
-                        timestamp,
-                        data: data.clone(),
-                    };
-
-                    for (_, v) in packet_senders.lock().await.iter() {
-                        if let Err(audio_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendAudioError,
-                        }) {
-                            log::error!("Transmiter send error: {}", audio_err);
-                        }
-                    }
-                }
-                PacketData::Video { timestamp, data } => {
-                    let data = PacketData::Video {
-                        timestamp,
-                        data: data.clone(),
-                    };
-                    for (_, v) in packet_senders.lock().await.iter() {
-                        if let Err(video_err) = v.send(data.clone()).map_err(|_| StreamHubError {
-                            value: StreamHubErrorValue::SendVideoError,
-                        }) {
-                            log::error!("Transmiter send error: {}", video_err);
-                        }
-                    }
-                }
-            }
+            Self::dispatch_to_subscribers(val, packet_senders, statistic_sender).await;
         }
     }
 
     async fn receive_packet_data_loop(
         mut exit: broadcast::Receiver<()>,
         mut receiver: PacketDataReceiver,
-        packet_senders: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
+        packet_senders: Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
+        statistic_sender: StatisticDataSender,
     ) {
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     data = receiver.recv() => {
-                       Self::receive_packet_data(data, &packet_senders).await;
+                       Self::receive_packet_data(data, &packet_senders, &statistic_sender).await;
                     }
                     _ = exit.recv()=>{
                         break;
@@ -325,9 +324,54 @@ impl StreamDataTransceiver {
                         send_bitrate: 0,
                         send_bytes: 0,
                         total_send_bytes: 0,
+                        dropped_video_count: 0,
+                        dropped_audio_count: 0,
+                        rtp_send: RtpSendStatistics::default(),
+                        webrtc: None,
                     };
                     subscriber.insert(id, sub);
                 }
+                StatisticData::SubscriberDrop { id, is_video } => {
+                    let subscriber = &mut statistics_data.lock().await.subscribers;
+                    if let Some(sub) = subscriber.get_mut(&id) {
+                        if is_video {
+                            sub.dropped_video_count += 1;
+                        } else {
+                            sub.dropped_audio_count += 1;
+                        }
+                    }
+                }
+                StatisticData::TimestampCorrection { correction } => {
+                    statistics_data
+                        .lock()
+                        .await
+                        .publisher
+                        .timestamp_corrections
+                        .add(correction);
+                }
+                StatisticData::RtpReceive { statistics } => {
+                    statistics_data.lock().await.publisher.rtp_receive = statistics;
+                }
+                StatisticData::RtpSend { id, statistics } => {
+                    let subscriber = &mut statistics_data.lock().await.subscribers;
+                    if let Some(sub) = subscriber.get_mut(&id) {
+                        sub.rtp_send = statistics;
+                    }
+                }
+                StatisticData::WebRTC {
+                    id,
+                    remote_addr,
+                    statistics,
+                } => {
+                    let stat_data = &mut statistics_data.lock().await;
+                    if stat_data.publisher.id == id {
+                        stat_data.publisher.remote_address = remote_addr;
+                        stat_data.publisher.webrtc = Some(statistics);
+                    } else if let Some(sub) = stat_data.subscribers.get_mut(&id) {
+                        sub.remote_address = remote_addr;
+                        sub.webrtc = Some(statistics);
+                    }
+                }
             }
         }
     }
@@ -357,14 +401,17 @@ impl StreamDataTransceiver {
         });
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn receive_event_loop(
         stream_handler: Arc<dyn TStreamHandler>,
         exit: broadcast::Sender<()>,
         mut receiver: TransceiverEventReceiver,
-        packet_senders: Arc<Mutex<HashMap<Uuid, PacketDataSender>>>,
-        frame_senders: Arc<Mutex<HashMap<Uuid, FrameDataSender>>>,
+        packet_senders: Arc<Mutex<HashMap<Uuid, SubscriberQueue<PacketData>>>>,
+        frame_senders: Arc<Mutex<HashMap<Uuid, SubscriberQueue<FrameData>>>>,
         statistic_sender: StatisticDataSender,
         statistics_data: Arc<Mutex<StatisticsStream>>,
+        queue_config: QueueConfig,
+        video_codec: Arc<Mutex<VideoCodecType>>,
     ) {
         tokio::spawn(async move {
             loop {
@@ -376,21 +423,36 @@ impl StreamDataTransceiver {
                             result_sender,
                         } => {
                             if let Err(err) = stream_handler
-                                .send_prior_data(sender.clone(), info.sub_type)
+                                .send_prior_data(sender.clone(), info.sub_type, info.start_mode)
                                 .await
                             {
                                 log::error!("receive_event_loop send_prior_data err: {}", err);
                             }
+                            //no cached gop is sent, the subscriber starts from the next key frame
+                            let wait_key_frame = info.start_mode == SubscribeStartMode::NoCache;
                             match sender {
                                 DataSender::Frame {
                                     sender: frame_sender,
                                 } => {
-                                    frame_senders.lock().await.insert(info.id, frame_sender);
+                                    let queue = SubscriberQueue::new(
+                                        info.id,
+                                        frame_sender,
+                                        queue_config.clone(),
+                                        wait_key_frame,
+                                    );
+                                    frame_senders.lock().await.insert(info.id, queue);
                                 }
                                 DataSender::Packet {
                                     sender: packet_sender,
                                 } => {
-                                    packet_senders.lock().await.insert(info.id, packet_sender);
+                                    let mut queue = SubscriberQueue::new(
+                                        info.id,
+                                        packet_sender,
+                                        queue_config.clone(),
+                                        wait_key_frame,
+                                    );
+                                    queue.set_video_codec(video_codec.lock().await.clone());
+                                    packet_senders.lock().await.insert(info.id, queue);
                                 }
                             }
 
@@ -406,7 +468,9 @@ impl StreamDataTransceiver {
                         }
                         TransceiverEvent::UnSubscribe { info } => {
                             match info.sub_type {
-                                SubscribeType::PlayerRtp | SubscribeType::PlayerWebrtc => {
+                                SubscribeType::PlayerRtp
+                                | SubscribeType::PlayerWebrtc
+                                | SubscribeType::PublisherWebrtc => {
                                     packet_senders.lock().await.remove(&info.id);
                                 }
                                 _ => {
@@ -441,6 +505,21 @@ impl StreamDataTransceiver {
                         TransceiverEvent::Request { sender } => {
                             stream_handler.send_information(sender).await;
                         }
+                        TransceiverEvent::RequestKeyFrame { id } => {
+                            let mut sender = packet_senders.lock().await.get(&id).map(|queue| {
+                                DataSender::Packet {
+                                    sender: queue.sender(),
+                                }
+                            });
+                            if sender.is_none() {
+                                sender = frame_senders.lock().await.get(&id).map(|queue| {
+                                    DataSender::Frame {
+                                        sender: queue.sender(),
+                                    }
+                                });
+                            }
+                            stream_handler.request_key_frame(sender).await;
+                        }
                     }
                 }
             }
@@ -455,6 +534,9 @@ impl StreamDataTransceiver {
                 tx.subscribe(),
                 receiver,
                 self.id_to_frame_sender.clone(),
+                self.id_to_packet_sender.clone(),
+                self.video_codec.clone(),
+                self.statistic_data_sender.clone(),
             )
             .await;
         }
@@ -464,6 +546,7 @@ impl StreamDataTransceiver {
                 tx.subscribe(),
                 receiver,
                 self.id_to_packet_sender.clone(),
+                self.statistic_data_sender.clone(),
             )
             .await;
         }
@@ -484,6 +567,8 @@ impl StreamDataTransceiver {
             self.id_to_frame_sender,
             self.statistic_data_sender,
             self.statistic_data.clone(),
+            self.queue_config,
+            self.video_codec,
         )
         .await;
 
@@ -509,15 +594,21 @@ pub struct StreamsHub {
     //The rtmp static push/pull and the hls transfer is triggered actively,
     //add a control switches separately.
     rtmp_push_enabled: bool,
+    rtsp_push_enabled: bool,
+    webrtc_push_enabled: bool,
     rtmp_remuxer_enabled: bool,
+    webrtc_remuxer_enabled: bool,
     //enable rtmp pull
     rtmp_pull_enabled: bool,
+    webrtc_pull_enabled: bool,
     //enable hls
     hls_enabled: bool,
     //http notifier on sub/pub event
     notifier: Option<Notifier>,
     //nonce map
     nonce_map: Arc<Mutex<HashMap<String, i64>>>,
+    //the queue size and drop policy of the subscribers
+    subscriber_queue_config: QueueConfig,
 }
 
 impl StreamsHub {
@@ -532,11 +623,16 @@ impl StreamsHub {
             hub_event_sender: event_producer,
             client_event_sender: client_producer,
             rtmp_push_enabled: false,
+            rtsp_push_enabled: false,
+            webrtc_push_enabled: false,
             rtmp_pull_enabled: false,
+            webrtc_pull_enabled: false,
             rtmp_remuxer_enabled: false,
+            webrtc_remuxer_enabled: false,
             hls_enabled: false,
             notifier,
             nonce_map,
+            subscriber_queue_config: QueueConfig::default(),
         }
     }
     pub async fn run(&mut self) {
@@ -547,18 +643,43 @@ impl StreamsHub {
         self.rtmp_push_enabled = enabled;
     }
 
+    pub fn set_rtsp_push_enabled(&mut self, enabled: bool) {
+        self.rtsp_push_enabled = enabled;
+    }
+
+    pub fn set_webrtc_push_enabled(&mut self, enabled: bool) {
+        self.webrtc_push_enabled = enabled;
+    }
+
     pub fn set_rtmp_pull_enabled(&mut self, enabled: bool) {
         self.rtmp_pull_enabled = enabled;
     }
 
+    pub fn set_webrtc_pull_enabled(&mut self, enabled: bool) {
+        self.webrtc_pull_enabled = enabled;
+    }
+
     pub fn set_rtmp_remuxer_enabled(&mut self, enabled: bool) {
         self.rtmp_remuxer_enabled = enabled;
     }
 
+    pub fn set_webrtc_remuxer_enabled(&mut self, enabled: bool) {
+        self.webrtc_remuxer_enabled = enabled;
+    }
+
     pub fn set_hls_enabled(&mut self, enabled: bool) {
         self.hls_enabled = enabled;
     }
 
+    pub fn set_subscriber_queue_config(&mut self, mut config: QueueConfig) {
+        //the channel of a subscriber cannot be created with 0 capacity
+        if config.size == 0 {
+            log::warn!("the subscriber queue size cannot be 0, use 1 instead");
+            config.size = 1;
+        }
+        self.subscriber_queue_config = config;
+    }
+
     pub fn get_hub_event_sender(&mut self) -> StreamHubEventSender {
         self.hub_event_sender.clone()
     }
@@ -678,24 +799,26 @@ impl StreamsHub {
                     //new chan for Frame/Packet sender and receiver
                     let (sender, receiver) = match info.sub_data_type {
                         define::SubDataType::Frame => {
-                            let (sender_chan, receiver_chan) = mpsc::unbounded_channel();
+                            let (sender_chan, receiver_chan) =
+                                mpsc::channel(self.subscriber_queue_config.size);
                             (
                                 DataSender::Frame {
                                     sender: sender_chan,
                                 },
-                                DataReceiver {
+                                SubDataReceiver {
                                     frame_receiver: Some(receiver_chan),
                                     packet_receiver: None,
                                 },
                             )
                         }
                         define::SubDataType::Packet => {
-                            let (sender_chan, receiver_chan) = mpsc::unbounded_channel();
+                            let (sender_chan, receiver_chan) =
+                                mpsc::channel(self.subscriber_queue_config.size);
                             (
                                 DataSender::Packet {
                                     sender: sender_chan,
                                 },
-                                DataReceiver {
+                                SubDataReceiver {
                                     frame_receiver: None,
                                     packet_receiver: Some(receiver_chan),
                                 },
@@ -760,6 +883,16 @@ impl StreamsHub {
                         log::error!("event_loop request error: {}", err);
                     }
                 }
+                StreamHubEvent::RequestKeyFrame { identifier, id } => {
+                    if let Some(producer) = self.streams.get(&identifier) {
+                        if producer
+                            .send(TransceiverEvent::RequestKeyFrame { id })
+                            .is_err()
+                        {
+                            log::error!("event_loop request key frame error: {}", identifier);
+                        }
+                    }
+                }
             }
         }
     }
@@ -873,6 +1006,10 @@ impl StreamsHub {
                 }
                 _ => {}
             }
+            //the webrtc server closes the peer connection, it is ok if nobody receives it
+            let _ = self
+                .client_event_sender
+                .send(BroadcastEvent::KickOffClient { id: uid });
         } else {
             log::warn!("cannot find uid: {}", uid);
         };
@@ -902,7 +1039,7 @@ impl StreamsHub {
             return Ok(result_receiver.await?);
         }
 
-        if self.rtmp_pull_enabled {
+        if self.rtmp_pull_enabled || self.webrtc_pull_enabled {
             log::info!("subscribe: try to pull stream, identifier: {}", identifer);
 
             let client_event = BroadcastEvent::Subscribe {
@@ -960,8 +1097,13 @@ impl StreamsHub {
         }
 
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
-        let transceiver =
-            StreamDataTransceiver::new(receiver, event_receiver, identifier.clone(), handler);
+        let transceiver = StreamDataTransceiver::new(
+            receiver,
+            event_receiver,
+            identifier.clone(),
+            handler,
+            self.subscriber_queue_config.clone(),
+        );
 
         let statistic_data_sender = transceiver.get_statistics_data_sender();
         let identifier_clone = identifier.clone();
@@ -978,7 +1120,13 @@ impl StreamsHub {
 
         self.streams.insert(identifier.clone(), event_sender);
 
-        if self.rtmp_push_enabled || self.hls_enabled || self.rtmp_remuxer_enabled {
+        if self.rtmp_push_enabled
+            || self.rtsp_push_enabled
+            || self.webrtc_push_enabled
+            || self.hls_enabled
+            || self.rtmp_remuxer_enabled
+            || self.webrtc_remuxer_enabled
+        {
             let client_event = BroadcastEvent::Publish { identifier };
 
             //send publish info to push clients
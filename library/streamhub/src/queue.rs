@@ -0,0 +1,575 @@
+use {
+    crate::{
+        define::{FrameData, PacketData, VideoCodecType},
+        utils::Uuid,
+    },
+    serde::Deserialize,
+    tokio::sync::mpsc::{self, error::TrySendError},
+};
+
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+pub const DEFAULT_DISCONNECT_THRESHOLD: usize = 1024;
+
+//What to do when the queue of a slow subscriber is full.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub enum DropPolicy {
+    /*Drop the non-key video frames until the next key frame, the audio frames
+    are still sent if there is room in the queue.*/
+    #[default]
+    #[serde(rename = "drop_non_key_frames")]
+    DropNonKeyFrames,
+    /*Drop the whole remaining GOP (audio and video) until the next key frame.*/
+    #[serde(rename = "drop_gop")]
+    DropGop,
+    /*Same as DropNonKeyFrames, but the subscriber is disconnected when the
+    dropped frame count exceeds the disconnect threshold.*/
+    #[serde(rename = "disconnect")]
+    Disconnect,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    //the max frame/packet count buffered for one subscriber
+    pub size: usize,
+    pub drop_policy: DropPolicy,
+    //only used by DropPolicy::Disconnect, the count of continuous dropped frames.
+    pub disconnect_threshold: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_QUEUE_SIZE,
+            drop_policy: DropPolicy::default(),
+            disconnect_threshold: DEFAULT_DISCONNECT_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MediaKind {
+    Video { is_key_frame: bool },
+    Audio,
+    Other,
+}
+
+//The data type which can be pushed into a subscriber queue.
+pub trait TQueueData: Clone {
+    fn media_kind(&self, vcodec: &VideoCodecType) -> MediaKind;
+}
+
+impl TQueueData for FrameData {
+    fn media_kind(&self, _vcodec: &VideoCodecType) -> MediaKind {
+        match self {
+            FrameData::Video { timestamp: _, data } => MediaKind::Video {
+                is_key_frame: is_key_frame(&data[..]),
+            },
+            FrameData::Audio { .. } => MediaKind::Audio,
+            _ => MediaKind::Other,
+        }
+    }
+}
+
+impl TQueueData for PacketData {
+    fn media_kind(&self, vcodec: &VideoCodecType) -> MediaKind {
+        match self {
+            PacketData::Video { timestamp: _, data } => MediaKind::Video {
+                is_key_frame: is_rtp_key_frame(vcodec, &data[..]),
+            },
+            PacketData::Audio { .. } => MediaKind::Audio,
+            PacketData::DataMessage { .. } => MediaKind::Other,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PushResult {
+    Sent,
+    Dropped { kind: MediaKind },
+    //the subscriber should be removed from the stream.
+    Disconnect,
+}
+
+//A bounded queue from the stream hub to one subscriber.
+pub struct SubscriberQueue<T: TQueueData> {
+    pub id: Uuid,
+    sender: mpsc::Sender<T>,
+    config: QueueConfig,
+    //the queue overflowed or no cached gop was sent, and we are waiting for the next key frame.
+    waiting_key_frame: bool,
+    //continuous dropped frame count, reset when a frame is sent.
+    dropped_count: usize,
+    //the key frames of the rtp packets are detected by the codec
+    vcodec: VideoCodecType,
+}
+
+impl<T: TQueueData> SubscriberQueue<T> {
+    //A subscriber which does not receive the cached gops(the no_cache start mode)
+    //must start from a key frame, the non-key frames before it cannot be decoded.
+    pub fn new(
+        id: Uuid,
+        sender: mpsc::Sender<T>,
+        config: QueueConfig,
+        wait_key_frame: bool,
+    ) -> Self {
+        //the prior data(gop cache) may have already filled the queue.
+        let waiting_key_frame = wait_key_frame || sender.capacity() == 0;
+        Self {
+            id,
+            sender,
+            config,
+            waiting_key_frame,
+            dropped_count: 0,
+            vcodec: VideoCodecType::H264,
+        }
+    }
+
+    pub fn set_video_codec(&mut self, vcodec: VideoCodecType) {
+        self.vcodec = vcodec;
+    }
+
+    //send data to the subscriber bypassing the drop policy, e.g. the replayed gop
+    pub fn sender(&self) -> mpsc::Sender<T> {
+        self.sender.clone()
+    }
+
+    pub fn push(&mut self, data: T) -> PushResult {
+        let kind = data.media_kind(&self.vcodec);
+
+        if self.waiting_key_frame {
+            let can_send = match kind {
+                MediaKind::Video { is_key_frame } => is_key_frame,
+                MediaKind::Audio => self.config.drop_policy != DropPolicy::DropGop,
+                MediaKind::Other => true,
+            };
+            if !can_send {
+                return self.on_dropped(kind);
+            }
+        }
+
+        match self.sender.try_send(data) {
+            Ok(()) => {
+                if let MediaKind::Video { is_key_frame: true } = kind {
+                    self.waiting_key_frame = false;
+                }
+                self.dropped_count = 0;
+                PushResult::Sent
+            }
+            Err(TrySendError::Full(_)) => {
+                if let MediaKind::Video { .. } = kind {
+                    self.waiting_key_frame = true;
+                }
+                self.on_dropped(kind)
+            }
+            Err(TrySendError::Closed(_)) => PushResult::Disconnect,
+        }
+    }
+
+    fn on_dropped(&mut self, kind: MediaKind) -> PushResult {
+        self.dropped_count += 1;
+        if self.config.drop_policy == DropPolicy::Disconnect
+            && self.dropped_count > self.config.disconnect_threshold
+        {
+            return PushResult::Disconnect;
+        }
+        PushResult::Dropped { kind }
+    }
+}
+
+fn is_h264_key_nalu(header: u8) -> bool {
+    //IDR or SPS
+    matches!(header & 0x1F, 5 | 7)
+}
+
+//H.265 nal header is two bytes, the second byte is 0x01 for the base layer,
+//checking it avoids treating a H.264 nal unit as a H.265 one.
+fn is_h265_key_nalu(header: &[u8]) -> bool {
+    header.len() >= 2 && header[1] == 0x01 && matches!((header[0] >> 1) & 0x3F, 16..=21 | 32)
+}
+
+//The video frame data in stream hub is a FLV video tag(published by RTMP)
+//or H.264/H.265 Annex-B nal units(published by RTSP/WebRTC), an Annex-B
+//frame always begins with a start code whose first byte is 0.
+pub fn is_key_frame(data: &[u8]) -> bool {
+    if data.is_empty() {
+        return false;
+    }
+    if data[0] != 0 {
+        return (data[0] >> 4) & 0x07 == xflv::define::frame_type::KEY_FRAME;
+    }
+
+    let mut i = 0;
+    while i + 3 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            let nalu = &data[i + 3..];
+            if is_h264_key_nalu(nalu[0]) || is_h265_key_nalu(nalu) {
+                return true;
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    false
+}
+
+//the payload of a rtp packet, it has at least 3 bytes
+fn get_rtp_payload(packet: &[u8]) -> Option<&[u8]> {
+    if packet.len() < 12 {
+        return None;
+    }
+    let csrc_count = (packet[0] & 0x0F) as usize;
+    let mut offset = 12 + csrc_count * 4;
+    if packet[0] & 0x10 != 0 {
+        if packet.len() < offset + 4 {
+            return None;
+        }
+        let extension_length = u16::from_be_bytes([packet[offset + 2], packet[offset + 3]]);
+        offset += 4 + extension_length as usize * 4;
+    }
+    if packet.len() < offset + 3 {
+        return None;
+    }
+    Some(&packet[offset..])
+}
+
+//the payload of a VP8 rtp packet after the payload descriptor(https://www.rfc-editor.org/rfc/rfc7741#section-4.2)
+fn get_vp8_payload(payload: &[u8]) -> Option<&[u8]> {
+    let mut offset = 1;
+    if payload[0] & 0x80 != 0 {
+        let extension = payload[1];
+        offset += 1;
+        //the picture id is 7 or 15 bits
+        if extension & 0x80 != 0 {
+            offset += if payload.get(offset)? & 0x80 != 0 {
+                2
+            } else {
+                1
+            };
+        }
+        //TL0PICIDX
+        if extension & 0x40 != 0 {
+            offset += 1;
+        }
+        //TID/KEYIDX
+        if extension & 0x30 != 0 {
+            offset += 1;
+        }
+    }
+    payload.get(offset..).filter(|payload| !payload.is_empty())
+}
+
+//the payload may be truncated, so the bytes after the payload header are read by get
+fn is_h264_rtp_key_frame(payload: &[u8]) -> bool {
+    match payload.first().map(|header| header & 0x1F) {
+        //STAP-A
+        Some(24) => payload
+            .get(3)
+            .is_some_and(|header| is_h264_key_nalu(*header)),
+        //FU-A with start bit
+        Some(28) => payload
+            .get(1)
+            .is_some_and(|header| header & 0x80 != 0 && is_h264_key_nalu(*header)),
+        Some(header) => is_h264_key_nalu(header),
+        None => false,
+    }
+}
+
+//the H.265 payload header is two bytes(https://www.rfc-editor.org/rfc/rfc7798#section-4.4)
+fn is_h265_rtp_key_frame(payload: &[u8]) -> bool {
+    match payload.first().map(|header| (header >> 1) & 0x3F) {
+        //aggregation packet, the first nal unit follows its 2 bytes size
+        Some(48) => payload.get(4..).is_some_and(is_h265_key_nalu),
+        //fragmentation unit with start bit
+        Some(49) => payload.get(2).is_some_and(|header| {
+            header & 0x80 != 0 && is_h265_key_nalu(&[(header & 0x3F) << 1, 1])
+        }),
+        Some(_) => is_h265_key_nalu(payload),
+        None => false,
+    }
+}
+
+//the nal_ref_idc is 0, the FU-A indicator carries the one of the nal unit
+fn is_h264_rtp_non_reference_frame(payload: &[u8]) -> bool {
+    let nal_type = match payload.first().map(|header| header & 0x1F) {
+        Some(28) => payload.get(1).map(|header| header & 0x1F),
+        nal_type => nal_type,
+    };
+    payload[0] & 0x60 == 0 && nal_type == Some(1)
+}
+
+fn is_h265_rtp_non_reference_frame(payload: &[u8]) -> bool {
+    let nal_type = match payload.first().map(|header| (header >> 1) & 0x3F) {
+        Some(49) => payload.get(2).map(|header| header & 0x3F),
+        nal_type => nal_type,
+    };
+    //TRAIL_N, TSA_N, STSA_N, RADL_N and RASL_N
+    matches!(nal_type, Some(0 | 2 | 4 | 6 | 8))
+}
+
+//Check if a video rtp packet contains (or starts) a key frame.
+pub fn is_rtp_key_frame(codec: &VideoCodecType, packet: &[u8]) -> bool {
+    let payload = match get_rtp_payload(packet) {
+        Some(payload) => payload,
+        None => return false,
+    };
+
+    match codec {
+        VideoCodecType::H264 => is_h264_rtp_key_frame(payload),
+        VideoCodecType::H265 => is_h265_rtp_key_frame(payload),
+        //the start of partition 0 and the inverse key frame flag of the payload header is 0
+        VideoCodecType::VP8 => {
+            payload[0] & 0x17 == 0x10
+                && get_vp8_payload(payload).is_some_and(|vp8_payload| vp8_payload[0] & 0x01 == 0)
+        }
+        //the start of a frame which is not inter-picture predicted
+        VideoCodecType::VP9 => payload[0] & 0x48 == 0x08,
+        //the first packet of a coded video sequence
+        VideoCodecType::AV1 => payload[0] & 0x08 != 0,
+    }
+}
+
+//Check if a video rtp packet belongs to a non-reference frame, which can be
+//dropped without breaking the decoding of the other frames.
+pub fn is_rtp_non_reference_frame(codec: &VideoCodecType, packet: &[u8]) -> bool {
+    let payload = match get_rtp_payload(packet) {
+        Some(payload) => payload,
+        None => return false,
+    };
+
+    match codec {
+        VideoCodecType::H264 => is_h264_rtp_non_reference_frame(payload),
+        VideoCodecType::H265 => is_h265_rtp_non_reference_frame(payload),
+        //the N bit of the payload descriptor
+        VideoCodecType::VP8 => payload[0] & 0x20 != 0,
+        //the reference of the VP9 and AV1 frames is unknown in the rtp packets
+        VideoCodecType::VP9 | VideoCodecType::AV1 => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn video(key: bool) -> FrameData {
+        let first = if key { 0x17 } else { 0x27 };
+        FrameData::Video {
+            timestamp: 0,
+            data: Bytes::copy_from_slice(&[first, 0x01, 0x00]),
+        }
+    }
+
+    fn audio() -> FrameData {
+        FrameData::Audio {
+            timestamp: 0,
+            data: Bytes::from_static(&[0xaf, 0x01]),
+        }
+    }
+
+    #[test]
+    fn test_is_key_frame() {
+        assert!(is_key_frame(&[0x17, 0x01]));
+        assert!(!is_key_frame(&[0x27, 0x01]));
+        assert!(is_key_frame(&[0x00, 0x00, 0x00, 0x01, 0x65, 0x88]));
+        assert!(!is_key_frame(&[0x00, 0x00, 0x00, 0x01, 0x41, 0x9a]));
+        assert!(is_key_frame(&[0x00, 0x00, 0x00, 0x01, 0x26, 0x01, 0xaf]));
+        assert!(!is_key_frame(&[0x00, 0x00, 0x00, 0x01, 0x02, 0x01, 0xd0]));
+    }
+
+    #[test]
+    fn test_is_rtp_key_frame() {
+        let mut packet = vec![0x80, 0x60, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1];
+        let mut fu_a_start = packet.clone();
+        fu_a_start.extend_from_slice(&[0x7c, 0x85, 0x88]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H264, &fu_a_start));
+
+        let mut fu_a_middle = packet.clone();
+        fu_a_middle.extend_from_slice(&[0x7c, 0x05, 0x88]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::H264, &fu_a_middle));
+
+        let mut stap_a = packet.clone();
+        stap_a.extend_from_slice(&[0x18, 0x00, 0x05, 0x67]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H264, &stap_a));
+        //a truncated STAP-A packet
+        stap_a.truncate(15);
+        assert!(!is_rtp_key_frame(&VideoCodecType::H264, &stap_a));
+        assert!(!is_rtp_key_frame(&VideoCodecType::H265, &stap_a));
+
+        packet.extend_from_slice(&[0x41, 0x9a, 0x00]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::H264, &packet));
+    }
+
+    #[test]
+    fn test_is_h264_h265_rtp_key_frame() {
+        let packet = vec![0x80, 0x60, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1];
+        let gen_packet = |payload: &[u8]| [&packet[..], payload].concat();
+
+        //the H.264 P slices are not read as the H.265 nal units
+        for header in [0x41, 0x21, 0x61] {
+            let p_slice = gen_packet(&[header, 0x01, 0x9a]);
+            assert!(!is_rtp_key_frame(&VideoCodecType::H264, &p_slice));
+            assert!(!is_rtp_non_reference_frame(&VideoCodecType::H264, &p_slice));
+        }
+        //a H.264 IDR whose header looks like a H.265 aggregation packet
+        let idr = gen_packet(&[0x65, 0x01, 0x9a]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H264, &idr));
+
+        //H.265 IDR_W_RADL, the start of a fragmented CRA and an aggregation packet with a VPS
+        let idr = gen_packet(&[0x26, 0x01, 0xaf]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H265, &idr));
+        let fu_start = gen_packet(&[0x62, 0x01, 0x95]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H265, &fu_start));
+        let ap = gen_packet(&[0x60, 0x01, 0x00, 0x18, 0x40, 0x01]);
+        assert!(is_rtp_key_frame(&VideoCodecType::H265, &ap));
+
+        //H.265 TRAIL_R and TRAIL_N
+        let trail_r = gen_packet(&[0x02, 0x01, 0xd0]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::H265, &trail_r));
+        assert!(!is_rtp_non_reference_frame(&VideoCodecType::H265, &trail_r));
+        let trail_n = gen_packet(&[0x00, 0x01, 0xd0]);
+        assert!(is_rtp_non_reference_frame(&VideoCodecType::H265, &trail_n));
+    }
+
+    #[test]
+    fn test_is_rtp_non_reference_frame() {
+        let packet = vec![0x80, 0x60, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1];
+        let mut non_reference = packet.clone();
+        non_reference.extend_from_slice(&[0x01, 0x9a, 0x00]);
+        assert!(is_rtp_non_reference_frame(
+            &VideoCodecType::H264,
+            &non_reference
+        ));
+
+        let mut fu_a = packet.clone();
+        fu_a.extend_from_slice(&[0x1c, 0x81, 0x9a]);
+        assert!(is_rtp_non_reference_frame(&VideoCodecType::H264, &fu_a));
+
+        let mut reference = packet.clone();
+        reference.extend_from_slice(&[0x41, 0x9a, 0x00]);
+        assert!(!is_rtp_non_reference_frame(
+            &VideoCodecType::H264,
+            &reference
+        ));
+
+        let mut idr = packet;
+        idr.extend_from_slice(&[0x65, 0x88, 0x84]);
+        assert!(!is_rtp_non_reference_frame(&VideoCodecType::H264, &idr));
+    }
+
+    #[test]
+    fn test_is_vpx_av1_rtp_key_frame() {
+        let packet = vec![0x80, 0x60, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1];
+        //VP8 with a 15 bits picture id
+        let mut vp8_key = packet.clone();
+        vp8_key.extend_from_slice(&[0x90, 0x80, 0x81, 0x02, 0x50, 0x01]);
+        assert!(is_rtp_key_frame(&VideoCodecType::VP8, &vp8_key));
+        let mut vp8_delta = packet.clone();
+        vp8_delta.extend_from_slice(&[0x10, 0x51, 0x02, 0x00]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::VP8, &vp8_delta));
+        let mut vp8_non_reference = packet.clone();
+        vp8_non_reference.extend_from_slice(&[0x30, 0x51, 0x02, 0x00]);
+        assert!(is_rtp_non_reference_frame(
+            &VideoCodecType::VP8,
+            &vp8_non_reference
+        ));
+
+        let mut vp9_key = packet.clone();
+        vp9_key.extend_from_slice(&[0x8c, 0x01, 0x00]);
+        assert!(is_rtp_key_frame(&VideoCodecType::VP9, &vp9_key));
+        let mut vp9_delta = packet.clone();
+        vp9_delta.extend_from_slice(&[0xcc, 0x01, 0x00]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::VP9, &vp9_delta));
+
+        let mut av1_key = packet.clone();
+        av1_key.extend_from_slice(&[0x18, 0x0a, 0x0b]);
+        assert!(is_rtp_key_frame(&VideoCodecType::AV1, &av1_key));
+        let mut av1_delta = packet;
+        av1_delta.extend_from_slice(&[0x10, 0x32, 0x0b]);
+        assert!(!is_rtp_key_frame(&VideoCodecType::AV1, &av1_delta));
+    }
+
+    #[test]
+    fn test_drop_non_key_frames() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let mut queue =
+            SubscriberQueue::new(Uuid::default(), sender, QueueConfig::default(), false);
+
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+        assert_eq!(queue.push(video(false)), PushResult::Sent);
+        assert_eq!(
+            queue.push(video(false)),
+            PushResult::Dropped {
+                kind: MediaKind::Video {
+                    is_key_frame: false
+                }
+            }
+        );
+
+        receiver.try_recv().unwrap();
+        receiver.try_recv().unwrap();
+        //still waiting for the key frame, non-key frames are dropped
+        assert_ne!(queue.push(video(false)), PushResult::Sent);
+        //audio can be sent
+        assert_eq!(queue.push(audio()), PushResult::Sent);
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+        receiver.try_recv().unwrap();
+        assert_eq!(queue.push(video(false)), PushResult::Sent);
+    }
+
+    #[test]
+    fn test_wait_key_frame() {
+        let (sender, mut receiver) = mpsc::channel(8);
+        let mut queue = SubscriberQueue::new(Uuid::default(), sender, QueueConfig::default(), true);
+
+        assert_ne!(queue.push(video(false)), PushResult::Sent);
+        assert_ne!(queue.push(video(false)), PushResult::Sent);
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+        assert_eq!(queue.push(video(false)), PushResult::Sent);
+
+        //only the key frame and the frames after it are received
+        let is_key_frames: Vec<bool> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|data| {
+                data.media_kind(&VideoCodecType::H264) == MediaKind::Video { is_key_frame: true }
+            })
+            .collect();
+        assert_eq!(is_key_frames, vec![true, false]);
+    }
+
+    #[test]
+    fn test_drop_gop() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let config = QueueConfig {
+            drop_policy: DropPolicy::DropGop,
+            ..Default::default()
+        };
+        let mut queue = SubscriberQueue::new(Uuid::default(), sender, config, false);
+
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+        assert_ne!(queue.push(video(false)), PushResult::Sent);
+        receiver.try_recv().unwrap();
+        assert_eq!(
+            queue.push(audio()),
+            PushResult::Dropped {
+                kind: MediaKind::Audio
+            }
+        );
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (sender, _receiver) = mpsc::channel(1);
+        let config = QueueConfig {
+            drop_policy: DropPolicy::Disconnect,
+            disconnect_threshold: 2,
+            ..Default::default()
+        };
+        let mut queue = SubscriberQueue::new(Uuid::default(), sender, config, false);
+
+        assert_eq!(queue.push(video(true)), PushResult::Sent);
+        assert_ne!(queue.push(video(false)), PushResult::Sent);
+        assert_ne!(queue.push(video(false)), PushResult::Disconnect);
+        assert_eq!(queue.push(video(false)), PushResult::Disconnect);
+    }
+}
@@ -1,6 +1,10 @@
 use {
     super::stream::StreamIdentifier,
-    crate::{define::SubscribeType, utils::Uuid},
+    crate::{
+        define::{SubscribeType, VideoCodecType},
+        timestamp::TimestampCorrections,
+        utils::Uuid,
+    },
     chrono::{DateTime, Local},
     serde::Serialize,
     std::{collections::HashMap, sync::Arc, time::Duration},
@@ -44,6 +48,52 @@ pub struct AudioInfo {
     #[serde(rename = "bitrate(kbits/s)")]
     pub bitrate: usize,
 }
+//the rtp receiving state of the publishers over udp(rtsp)
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct RtpReceiveStatistics {
+    /*the packets still missing after waiting in the jitter buffer*/
+    pub lost_packets: usize,
+    /*the missing packets received later, reordered or retransmitted*/
+    pub recovered_packets: usize,
+    /*how many NACKs are sent to request the retransmission*/
+    pub nack_count: usize,
+    /*the video frames dropped while waiting for the next key frame*/
+    pub dropped_frames: usize,
+    #[serde(rename = "jitter(ms)")]
+    pub jitter: u32,
+}
+//the rtp sending state of the subscribers over webrtc, from the rtcp feedbacks
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct RtpSendStatistics {
+    #[serde(rename = "rtt(ms)")]
+    pub rtt: u32,
+    /*the packet loss reported by the receiver reports*/
+    #[serde(rename = "packet_loss(%)")]
+    pub packet_loss: f64,
+    /*the bandwidth estimated from the REMB/TWCC feedbacks*/
+    #[serde(rename = "estimated_bitrate(kbits/s)")]
+    pub estimated_bitrate: usize,
+    /*how many key frames are requested by PLI/FIR*/
+    pub key_frame_requests: usize,
+    /*the non-reference video packets dropped when the subscriber is congested*/
+    pub dropped_packets: usize,
+}
+//the peer connection state of the webrtc publishers and subscribers, from the rtc stats
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct WebRTCStatistics {
+    /*the selected ice candidate pair, e.g. "host 192.168.0.10:50000"*/
+    pub local_candidate: String,
+    pub remote_candidate: String,
+    pub video_codec: Option<VideoCodecType>,
+    /*the rtp bitrate received from the publisher or sent to the subscriber*/
+    #[serde(rename = "bitrate(kbits/s)")]
+    pub bitrate: usize,
+    #[serde(rename = "packet_loss(%)")]
+    pub packet_loss: f64,
+    /*how long the peer connection lasts*/
+    #[serde(rename = "duration(s)")]
+    pub duration: u64,
+}
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct StatisticsStream {
     /*publisher infomation */
@@ -71,6 +121,10 @@ pub struct StatisticPublisher {
     /*the bitrate at which the server receives streaming data*/
     #[serde(rename = "recv_bitrate(kbits/s)")]
     pub recv_bitrate: usize,
+    /*how many times the timestamps are corrected*/
+    pub timestamp_corrections: TimestampCorrections,
+    pub rtp_receive: RtpReceiveStatistics,
+    pub webrtc: Option<WebRTCStatistics>,
 }
 
 impl StatisticPublisher {
@@ -95,6 +149,11 @@ pub struct StatisticSubscriber {
     pub send_bitrate: usize,
     #[serde(rename = "total_send_bytes(kbits/s)")]
     pub total_send_bytes: usize,
+    /*the frames/packets dropped because the subscriber is too slow*/
+    pub dropped_video_count: usize,
+    pub dropped_audio_count: usize,
+    pub rtp_send: RtpSendStatistics,
+    pub webrtc: Option<WebRTCStatistics>,
 }
 
 impl StatisticsStream {
@@ -0,0 +1,161 @@
+use serde::Serialize;
+
+//the max timestamp gap(ms) between two continuous frames which is not a jump.
+pub const DEFAULT_JUMP_THRESHOLD: u32 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampCorrection {
+    //the 32-bit timestamp wraps around to 0
+    Rollover,
+    //the timestamp goes back more than the threshold, e.g. the encoder restarts
+    BackwardJump,
+    //the gap is larger than the threshold
+    ForwardJump,
+}
+
+//The corrections of a publisher's timestamps, shown in the statistics.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimestampCorrections {
+    pub rollover_count: usize,
+    pub backward_jump_count: usize,
+    pub forward_jump_count: usize,
+}
+
+impl TimestampCorrections {
+    pub fn add(&mut self, correction: TimestampCorrection) {
+        match correction {
+            TimestampCorrection::Rollover => self.rollover_count += 1,
+            TimestampCorrection::BackwardJump => self.backward_jump_count += 1,
+            TimestampCorrection::ForwardJump => self.forward_jump_count += 1,
+        }
+    }
+}
+
+/*Normalize the timestamps of a published stream: the first timestamp is rebased
+to 0, and the jumps/rollovers are corrected, so the outputs keep increasing
+continuously. Audio and video share one normalizer to keep them in sync.*/
+pub struct TimestampNormalizer {
+    jump_threshold: u32,
+    last_input: Option<u32>,
+    last_output: i64,
+    //the last normal gap, used as the gap of a corrected jump
+    last_delta: i64,
+}
+
+impl Default for TimestampNormalizer {
+    fn default() -> Self {
+        Self::new(DEFAULT_JUMP_THRESHOLD)
+    }
+}
+
+impl TimestampNormalizer {
+    pub fn new(jump_threshold: u32) -> Self {
+        Self {
+            jump_threshold,
+            last_input: None,
+            last_output: 0,
+            last_delta: 0,
+        }
+    }
+
+    pub fn normalize(&mut self, timestamp: u32) -> (u32, Option<TimestampCorrection>) {
+        let last_input = match self.last_input {
+            Some(last_input) => last_input,
+            None => {
+                self.last_input = Some(timestamp);
+                return (0, None);
+            }
+        };
+        self.last_input = Some(timestamp);
+
+        //the wrapping difference is small when the timestamp rolls over
+        let delta = timestamp.wrapping_sub(last_input) as i32 as i64;
+        let threshold = self.jump_threshold as i64;
+
+        let (delta, correction) = if delta > threshold {
+            (self.last_delta, Some(TimestampCorrection::ForwardJump))
+        } else if delta < -threshold {
+            (self.last_delta, Some(TimestampCorrection::BackwardJump))
+        } else if delta > 0 && timestamp < last_input {
+            (delta, Some(TimestampCorrection::Rollover))
+        } else {
+            (delta, None)
+        };
+
+        if delta > 0 && correction.is_none() {
+            self.last_delta = delta;
+        }
+        //a small backward step(e.g. audio is a little behind video) is kept.
+        self.last_output = (self.last_output + delta).max(0);
+
+        (self.last_output as u32, correction)
+    }
+
+    /*Convert the timestamp of the other messages(e.g. metadata) by the current offset
+    of the a/v timestamps. The state is not changed, so a metadata timestamp(usually 0)
+    is not taken as a jump of the a/v timestamps.*/
+    pub fn convert(&self, timestamp: u32) -> u32 {
+        let last_input = match self.last_input {
+            Some(last_input) => last_input,
+            None => return 0,
+        };
+
+        let delta = timestamp.wrapping_sub(last_input) as i32 as i64;
+        if delta.abs() > self.jump_threshold as i64 {
+            return self.last_output as u32;
+        }
+        (self.last_output + delta).max(0) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_and_jump() {
+        let mut normalizer = TimestampNormalizer::default();
+        assert_eq!(normalizer.normalize(100_000), (0, None));
+        assert_eq!(normalizer.normalize(100_040), (40, None));
+        //audio is a little behind video
+        assert_eq!(normalizer.normalize(100_020), (20, None));
+        assert_eq!(normalizer.normalize(100_080), (80, None));
+
+        //the encoder restarts
+        assert_eq!(
+            normalizer.normalize(0),
+            (140, Some(TimestampCorrection::BackwardJump))
+        );
+        assert_eq!(normalizer.normalize(40), (180, None));
+
+        assert_eq!(
+            normalizer.normalize(3_600_000),
+            (220, Some(TimestampCorrection::ForwardJump))
+        );
+        assert_eq!(normalizer.normalize(3_600_040), (260, None));
+    }
+
+    #[test]
+    fn test_convert() {
+        let mut normalizer = TimestampNormalizer::default();
+        assert_eq!(normalizer.convert(0), 0);
+        assert_eq!(normalizer.normalize(100_000), (0, None));
+        //the metadata timestamp is 0 while the a/v ones are not
+        assert_eq!(normalizer.convert(0), 0);
+        assert_eq!(normalizer.normalize(100_040), (40, None));
+        assert_eq!(normalizer.convert(100_060), 60);
+        assert_eq!(normalizer.convert(0), 40);
+        assert_eq!(normalizer.normalize(100_080), (80, None));
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut normalizer = TimestampNormalizer::default();
+        assert_eq!(normalizer.normalize(u32::MAX - 39), (0, None));
+        assert_eq!(
+            normalizer.normalize(0),
+            (40, Some(TimestampCorrection::Rollover))
+        );
+        assert_eq!(normalizer.normalize(40), (80, None));
+    }
+}
@@ -8,7 +8,7 @@ use {
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
             SubscribeType, SubscriberInfo,
         },
         stream::StreamIdentifier,
@@ -24,7 +24,7 @@ pub struct FlvDataReceiver {
     app_name: String,
     stream_name: String,
     event_producer: StreamHubEventSender,
-    data_consumer: FrameDataReceiver,
+    data_consumer: SubFrameDataReceiver,
     media_processor: Flv2HlsRemuxer,
     subscriber_id: Uuid,
 }
@@ -37,7 +37,7 @@ impl FlvDataReceiver {
         duration: i64,
         need_record: bool,
     ) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
         let subscriber_id = Uuid::new(RandomDigitCount::Four);
 
         Self {
@@ -102,6 +102,7 @@ impl FlvDataReceiver {
             id: self.subscriber_id,
             sub_type: SubscribeType::GenerateHls,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -140,6 +141,7 @@ impl FlvDataReceiver {
             id: self.subscriber_id,
             sub_type: SubscribeType::PlayerHls,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -191,6 +191,17 @@ impl M3u8 {
         if segment.discontinuity {
             self.vod_m3u8_content += "#EXT-X-DISCONTINUITY\n";
         }
+        //the segment is closed just now, it is used for seeking the record by the absolute time.
+        //the closing time is used if the duration is out of range.
+        let now = chrono::Utc::now();
+        let program_date_time = chrono::TimeDelta::try_milliseconds(segment.duration)
+            .and_then(|duration| now.checked_sub_signed(duration))
+            .unwrap_or(now);
+        self.vod_m3u8_content += format!(
+            "#EXT-X-PROGRAM-DATE-TIME:{}\n",
+            program_date_time.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        )
+        .as_str();
         self.vod_m3u8_content += format!(
             "#EXTINF:{:.3}\n{}\n",
             segment.duration as f64 / 1000.0,
@@ -2,7 +2,7 @@
 mod tests {
     use crate::errors::MediaError;
     use crate::flv2hls::Flv2HlsRemuxer;
-    use bytes::BytesMut;
+    use bytes::{Bytes, BytesMut};
     use xflv::define::FlvData;
 
     use xflv::demuxer::FlvDemuxer;
@@ -12,7 +12,7 @@ mod tests {
     use std::time::Instant;
 
     #[allow(dead_code)]
-    pub fn print(data: BytesMut) {
+    pub fn print(data: Bytes) {
         println!("==========={}", data.len());
         let mut idx = 0;
         for i in data {
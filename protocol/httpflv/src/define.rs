@@ -1,5 +1,5 @@
 use {
-    futures::channel::mpsc::{UnboundedReceiver, UnboundedSender},
+    futures::channel::mpsc::{Receiver, Sender},
     {bytes::BytesMut, std::io},
 };
 pub mod tag_type {
@@ -7,5 +7,8 @@ pub mod tag_type {
     pub const VIDEO: u8 = 9;
     pub const SCRIPT_DATA_AMF: u8 = 18;
 }
-pub type HttpResponseDataProducer = UnboundedSender<io::Result<BytesMut>>;
-pub type HttpResponseDataConsumer = UnboundedReceiver<io::Result<BytesMut>>;
+//The flv data buffered for a http response. When the viewer is too slow, the frames
+//are dropped by its subscriber queue in the stream hub instead of piling up here.
+pub const HTTP_RESPONSE_QUEUE_SIZE: usize = 8;
+pub type HttpResponseDataProducer = Sender<io::Result<BytesMut>>;
+pub type HttpResponseDataConsumer = Receiver<io::Result<BytesMut>>;
@@ -16,11 +16,12 @@ use {
         define::{tag_type, HttpResponseDataProducer},
         errors::{HttpFLvError, HttpFLvErrorValue},
     },
-    bytes::BytesMut,
+    bytes::Bytes,
+    futures::SinkExt,
     std::net::SocketAddr,
     streamhub::define::{
-        FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
-        SubDataType, SubscribeType, SubscriberInfo,
+        FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubDataType,
+        SubFrameDataReceiver, SubscribeStartMode, SubscribeType, SubscriberInfo,
     },
     streamhub::{
         stream::StreamIdentifier,
@@ -38,7 +39,7 @@ pub struct HttpFlv {
     muxer: FlvMuxer,
 
     event_producer: StreamHubEventSender,
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     /* now used for subscriber session */
     statistic_data_sender: Option<StatisticDataSender>,
     http_response_data_producer: HttpResponseDataProducer,
@@ -68,7 +69,7 @@ impl HttpFlv {
         subscribe_token: Option<String>,
         nonce_map: Arc<Mutex<HashMap<String, i64>>>,
     ) -> Self {
-        let (_, data_receiver) = mpsc::unbounded_channel();
+        let (_, data_receiver) = mpsc::channel(1);
         let subscriber_id = Uuid::new(RandomDigitCount::Four);
 
         Self {
@@ -153,13 +154,13 @@ impl HttpFlv {
         self.muxer.write_flv_header()?;
         self.muxer.write_previous_tag_size(0)?;
 
-        self.flush_response_data()?;
+        self.flush_response_data().await?;
 
         let mut retry_count = 0;
         //write flv body
         loop {
             if let Some(data) = self.data_receiver.recv().await {
-                if let Err(err) = self.write_flv_tag(data) {
+                if let Err(err) = self.write_flv_tag(data).await {
                     if let HttpFLvErrorValue::MpscSendError(err_in) = &err.value {
                         if err_in.is_disconnected() {
                             log::info!("write_flv_tag: {}", err_in);
@@ -198,7 +199,7 @@ impl HttpFlv {
 
     //used for the http-flv protocol
 
-    pub fn write_flv_tag(&mut self, channel_data: FrameData) -> Result<(), HttpFLvError> {
+    pub async fn write_flv_tag(&mut self, channel_data: FrameData) -> Result<(), HttpFLvError> {
         let (common_data, common_timestamp, tag_type) = match channel_data {
             FrameData::Audio { timestamp, data } => {
                 if let Some(sender) = &self.statistic_data_sender {
@@ -232,16 +233,21 @@ impl HttpFlv {
                 (data, timestamp, tag_type::VIDEO)
             }
             FrameData::MetaData { timestamp, data } => {
-                //remove @setDataFrame from RTMP's metadata
+                //remove @setDataFrame from RTMP's metadata, the other data messages do not have it
                 let mut amf_writer: Amf0Writer = Amf0Writer::new();
                 amf_writer.write_string(&String::from("@setDataFrame"))?;
-                let (_, right) = data.split_at(amf_writer.len());
+                let set_data_frame = amf_writer.extract_current_bytes();
+                let right = if data.starts_with(&set_data_frame) {
+                    data.slice(set_data_frame.len()..)
+                } else {
+                    data
+                };
 
-                (BytesMut::from(right), timestamp, tag_type::SCRIPT_DATA_AMF)
+                (right, timestamp, tag_type::SCRIPT_DATA_AMF)
             }
             _ => {
                 log::error!("should not be here!!!");
-                (BytesMut::new(), 0, 0)
+                (Bytes::new(), 0, 0)
             }
         };
 
@@ -258,23 +264,24 @@ impl HttpFlv {
 
         self.muxer
             .write_flv_tag_header(tag_type, common_data_len, common_timestamp)?;
-        self.muxer.write_flv_tag_body(common_data)?;
+        self.muxer.write_flv_tag_body(&common_data[..])?;
         self.muxer
             .write_previous_tag_size(common_data_len + HEADER_LENGTH)?;
 
-        self.flush_response_data()?;
+        self.flush_response_data().await?;
 
         Ok(())
     }
 
-    pub fn flush_response_data(&mut self) -> Result<(), HttpFLvError> {
+    //wait until the viewer receives the data, so that a stalled viewer fills its subscriber queue
+    pub async fn flush_response_data(&mut self) -> Result<(), HttpFLvError> {
         let data = self.muxer.writer.extract_current_bytes();
 
         if let Some(file_handler) = &mut self.file_handler {
             file_handler.write_all(data.as_ref())?;
         }
 
-        self.http_response_data_producer.start_send(Ok(data))?;
+        self.http_response_data_producer.send(Ok(data)).await?;
 
         Ok(())
     }
@@ -284,6 +291,7 @@ impl HttpFlv {
             id: self.subscriber_id,
             sub_type: SubscribeType::PlayerHttpFlv,
             sub_data_type: SubDataType::Frame,
+            start_mode: SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr: self.remote_addr.to_string(),
@@ -311,6 +319,7 @@ impl HttpFlv {
             id: self.subscriber_id,
             sub_type: SubscribeType::PlayerHttpFlv,
             sub_data_type: SubDataType::Frame,
+            start_mode: SubscribeStartMode::from_query(self.req.uri().query().unwrap_or_default()),
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr: self.remote_addr.to_string(),
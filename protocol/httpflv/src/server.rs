@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use {
-    super::httpflv::HttpFlv,
+    super::{define::HTTP_RESPONSE_QUEUE_SIZE, httpflv::HttpFlv},
     axum::{
         body::Body,
         extract::{ConnectInfo, Request, State},
@@ -11,7 +11,7 @@ use {
         response::Response,
     },
     commonlib::auth::Auth,
-    futures::channel::mpsc::unbounded,
+    futures::channel::mpsc::channel,
     std::net::SocketAddr,
     streamhub::define::StreamHubEventSender,
     tokio::net::TcpListener,
@@ -50,7 +50,8 @@ async fn handle_connection(
                 }
             }
 
-            let (http_response_data_producer, http_response_data_consumer) = unbounded();
+            let (http_response_data_producer, http_response_data_consumer) =
+                channel(HTTP_RESPONSE_QUEUE_SIZE);
 
             let mut flv_hanlder = HttpFlv::new(
                 app_name,
@@ -1,7 +1,62 @@
-use {std::collections::VecDeque, streamhub::define::FrameData};
+use {
+    std::collections::{HashMap, VecDeque},
+    streamhub::define::FrameData,
+};
+
+//Limit the cached gops of a stream, the oldest gops are removed when any limit is exceeded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GopLimit {
+    /*the max gop count, 0 disables the gop cache as before*/
+    pub gop_num: usize,
+    /*the max duration(ms) of the cached gops, only used when gop_num is not 0*/
+    pub max_duration: Option<u32>,
+    /*the max bytes of the cached gops, only used when gop_num is not 0*/
+    pub max_bytes: Option<usize>,
+}
+
+impl GopLimit {
+    pub fn new(gop_num: usize) -> Self {
+        Self {
+            gop_num,
+            ..Default::default()
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.gop_num != 0
+    }
+}
+
+//The gop limits of all the rtmp applications.
+#[derive(Debug, Clone, Default)]
+pub struct GopCacheConfig {
+    pub default_limit: GopLimit,
+    /*key: app name*/
+    pub app_limits: HashMap<String, GopLimit>,
+}
+
+impl GopCacheConfig {
+    pub fn new(default_limit: GopLimit) -> Self {
+        Self {
+            default_limit,
+            app_limits: HashMap::new(),
+        }
+    }
+
+    pub fn get_limit(&self, app_name: &str) -> GopLimit {
+        self.app_limits
+            .get(app_name)
+            .unwrap_or(&self.default_limit)
+            .clone()
+    }
+}
+
 #[derive(Clone)]
 pub struct Gop {
     datas: Vec<FrameData>,
+    bytes: usize,
+    first_timestamp: Option<u32>,
+    last_timestamp: u32,
 }
 
 impl Default for Gop {
@@ -12,15 +67,32 @@ impl Default for Gop {
 
 impl Gop {
     pub fn new() -> Self {
-        Self { datas: Vec::new() }
+        Self {
+            datas: Vec::new(),
+            bytes: 0,
+            first_timestamp: None,
+            last_timestamp: 0,
+        }
     }
 
     fn save_frame_data(&mut self, data: FrameData) {
+        match &data {
+            FrameData::Video { timestamp, data }
+            | FrameData::Audio { timestamp, data }
+            | FrameData::MetaData { timestamp, data } => {
+                self.bytes += data.len();
+                if self.first_timestamp.is_none() {
+                    self.first_timestamp = Some(*timestamp);
+                }
+                self.last_timestamp = *timestamp;
+            }
+            FrameData::MediaInfo { .. } | FrameData::DataMessage { .. } => {}
+        }
         self.datas.push(data);
     }
 
-    pub fn get_frame_data(self) -> Vec<FrameData> {
-        self.datas
+    pub fn get_frame_data(&self) -> &[FrameData] {
+        &self.datas
     }
 
     pub fn len(&self) -> usize {
@@ -35,48 +107,169 @@ impl Gop {
 #[derive(Clone)]
 pub struct Gops {
     gops: VecDeque<Gop>,
-    size: usize,
+    limit: GopLimit,
+    bytes: usize,
+    /*the only gop is larger than max_bytes, so it is dropped and
+    the frames are not cached until the next key frame.*/
+    waiting_key_frame: bool,
 }
 
 impl Default for Gops {
     fn default() -> Self {
-        Self::new(1)
+        Self::new(GopLimit::new(1))
     }
 }
 
 impl Gops {
-    pub fn new(size: usize) -> Self {
+    pub fn new(limit: GopLimit) -> Self {
         Self {
             gops: VecDeque::from([Gop::new()]),
-            size,
+            limit,
+            bytes: 0,
+            waiting_key_frame: false,
         }
     }
 
     pub fn save_frame_data(&mut self, data: FrameData, is_key_frame: bool) {
-        if self.size == 0 {
+        if !self.limit.enabled() {
             return;
         }
 
         if is_key_frame {
-            //todo It may be possible to optimize here
-            if self.gops.len() == self.size {
-                self.gops.pop_front();
+            if self.gops.len() >= self.limit.gop_num {
+                self.pop_front();
             }
             self.gops.push_back(Gop::new());
+            self.waiting_key_frame = false;
+        } else if self.waiting_key_frame {
+            return;
         }
 
         if let Some(gop) = self.gops.back_mut() {
+            let bytes = gop.bytes;
             gop.save_frame_data(data);
+            self.bytes += gop.bytes - bytes;
         } else {
             log::error!("should not be here!");
         }
+
+        //always keep the latest gop, a player can only start from a key frame.
+        while self.gops.len() > 1 && (self.exceed_duration() || self.exceed_bytes()) {
+            self.pop_front();
+        }
+
+        if self.exceed_bytes() {
+            log::warn!("the gop is larger than the max bytes, drop it");
+            self.gops.clear();
+            self.bytes = 0;
+            self.waiting_key_frame = true;
+        }
+    }
+
+    fn pop_front(&mut self) {
+        if let Some(gop) = self.gops.pop_front() {
+            self.bytes -= gop.bytes;
+        }
+    }
+
+    fn duration(&self) -> u32 {
+        let first = self.gops.iter().find_map(|gop| gop.first_timestamp);
+        match (first, self.gops.back()) {
+            (Some(first), Some(last)) => last.last_timestamp.saturating_sub(first),
+            _ => 0,
+        }
+    }
+
+    fn exceed_duration(&self) -> bool {
+        match self.limit.max_duration {
+            Some(max_duration) => self.duration() > max_duration,
+            None => false,
+        }
+    }
+
+    fn exceed_bytes(&self) -> bool {
+        match self.limit.max_bytes {
+            Some(max_bytes) => self.bytes > max_bytes,
+            None => false,
+        }
     }
 
     pub fn setted(&self) -> bool {
-        self.size != 0
+        self.limit.enabled()
+    }
+
+    pub fn get_gops(&self) -> &VecDeque<Gop> {
+        &self.gops
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, bytes::Bytes};
+
+    fn video(timestamp: u32, size: usize) -> FrameData {
+        FrameData::Video {
+            timestamp,
+            data: Bytes::from(vec![0; size]),
+        }
+    }
+
+    fn save_gops(gops: &mut Gops, gop_count: u32) {
+        //one gop per second, 10 frames per gop
+        for i in 0..gop_count * 10 {
+            gops.save_frame_data(video(i * 100, 100), i % 10 == 0);
+        }
+    }
+
+    #[test]
+    fn test_gop_num_limit() {
+        let mut gops = Gops::new(GopLimit::new(2));
+        save_gops(&mut gops, 5);
+        assert_eq!(gops.get_gops().len(), 2);
+
+        //0 disables the gop cache even if the other limits are set
+        let mut gops = Gops::new(GopLimit {
+            gop_num: 0,
+            max_duration: Some(2500),
+            max_bytes: Some(2500),
+        });
+        save_gops(&mut gops, 5);
+        assert!(!gops.setted());
+    }
+
+    #[test]
+    fn test_duration_limit() {
+        let mut gops = Gops::new(GopLimit {
+            gop_num: 10,
+            max_duration: Some(2500),
+            ..Default::default()
+        });
+        save_gops(&mut gops, 5);
+        //only the latest 2 gops are kept(4900 - 3000 <= 2500)
+        assert_eq!(gops.get_gops().len(), 2);
+        assert!(gops.duration() <= 2500);
+    }
+
+    #[test]
+    fn test_bytes_limit() {
+        let mut gops = Gops::new(GopLimit {
+            gop_num: 10,
+            max_bytes: Some(2500),
+            ..Default::default()
+        });
+        save_gops(&mut gops, 5);
+        assert_eq!(gops.get_gops().len(), 2);
+        assert_eq!(gops.bytes, 2000);
 
-    pub fn get_gops(&self) -> VecDeque<Gop> {
-        self.gops.clone()
+        //a gop larger than max bytes is not cached
+        let mut gops = Gops::new(GopLimit {
+            gop_num: 10,
+            max_bytes: Some(500),
+            ..Default::default()
+        });
+        save_gops(&mut gops, 2);
+        assert_eq!(gops.bytes, 0);
+        gops.save_frame_data(video(2000, 100), true);
+        assert_eq!(gops.bytes, 100);
     }
 }
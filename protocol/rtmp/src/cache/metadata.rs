@@ -1,11 +1,11 @@
 use {
-    bytes::BytesMut,
+    bytes::{Bytes, BytesMut},
     bytesio::bytes_reader::BytesReader,
     xflv::amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
 };
 #[derive(Clone)]
 pub struct MetaData {
-    chunk_body: BytesMut,
+    chunk_body: Bytes,
     // values: Vec<Amf0ValueType>,
 }
 
@@ -18,13 +18,13 @@ impl Default for MetaData {
 impl MetaData {
     pub fn new() -> Self {
         Self {
-            chunk_body: BytesMut::new(),
+            chunk_body: Bytes::new(),
             //values: Vec::new(),
         }
     }
     //, values: Vec<Amf0ValueType>
-    pub fn save(&mut self, body: &BytesMut) {
-        if self.is_metadata(body.clone()) {
+    pub fn save(&mut self, body: &Bytes) {
+        if self.is_metadata(BytesMut::from(&body[..])) {
             self.chunk_body = body.clone();
         }
     }
@@ -80,7 +80,7 @@ impl MetaData {
         is_metadata
     }
 
-    pub fn get_chunk_body(&self) -> BytesMut {
+    pub fn get_chunk_body(&self) -> Bytes {
         self.chunk_body.clone()
     }
 }
@@ -3,8 +3,8 @@ pub mod gop;
 pub mod metadata;
 
 use {
-    self::gop::Gops,
-    bytes::BytesMut,
+    self::gop::{GopLimit, Gops},
+    bytes::{Bytes, BytesMut},
     bytesio::bytes_reader::BytesReader,
     errors::CacheError,
     gop::Gop,
@@ -19,35 +19,39 @@ use {
     },
 };
 
+//the tag header length of AAC audio and AVC video, the remaining data is
+//parsed only for the sequence headers.
+const AUDIO_TAG_HEADER_LEN: usize = 2;
+const VIDEO_TAG_HEADER_LEN: usize = 5;
+
 // #[derive(Clone)]
 pub struct Cache {
     metadata: metadata::MetaData,
     metadata_timestamp: u32,
-    video_seq: BytesMut,
+    video_seq: Bytes,
     video_timestamp: u32,
-    audio_seq: BytesMut,
+    audio_seq: Bytes,
     audio_timestamp: u32,
     gops: Gops,
     statistic_data_sender: Option<StatisticDataSender>,
 }
 
 impl Cache {
-    pub fn new(gop_num: usize, statistic_data_sender: Option<StatisticDataSender>) -> Self {
-        
+    pub fn new(gop_limit: GopLimit, statistic_data_sender: Option<StatisticDataSender>) -> Self {
         Cache {
             metadata: metadata::MetaData::new(),
             metadata_timestamp: 0,
-            video_seq: BytesMut::new(),
+            video_seq: Bytes::new(),
             video_timestamp: 0,
-            audio_seq: BytesMut::new(),
+            audio_seq: Bytes::new(),
             audio_timestamp: 0,
-            gops: Gops::new(gop_num),
+            gops: Gops::new(gop_limit),
             statistic_data_sender,
         }
     }
 
     //, values: Vec<Amf0ValueType>
-    pub fn save_metadata(&mut self, chunk_body: &BytesMut, timestamp: u32) {
+    pub fn save_metadata(&mut self, chunk_body: &Bytes, timestamp: u32) {
         self.metadata.save(chunk_body);
         self.metadata_timestamp = timestamp;
     }
@@ -66,7 +70,7 @@ impl Cache {
     //save audio gops and sequence header information
     pub async fn save_audio_data(
         &mut self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         let channel_data = FrameData::Audio {
@@ -75,7 +79,9 @@ impl Cache {
         };
         self.gops.save_frame_data(channel_data, false);
 
-        let mut reader = BytesReader::new(chunk_body.clone());
+        let mut reader = BytesReader::new(BytesMut::from(
+            &chunk_body[..chunk_body.len().min(AUDIO_TAG_HEADER_LEN)],
+        ));
         let tag_header = AudioTagHeader::unmarshal(&mut reader)?;
 
         if tag_header.sound_format == define::SoundFormat::AAC as u8
@@ -88,7 +94,7 @@ impl Cache {
                 let mut aac_processor = Mpeg4AacProcessor::default();
 
                 let aac = aac_processor
-                    .extend_data(reader.extract_remaining_bytes())
+                    .extend_data(&chunk_body[AUDIO_TAG_HEADER_LEN..])
                     .audio_specific_config_load()?;
 
                 let statistic_audio_codec = StatisticData::AudioCodec {
@@ -140,7 +146,7 @@ impl Cache {
     //save video gops and sequence header information
     pub async fn save_video_data(
         &mut self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         let channel_data = FrameData::Video {
@@ -148,7 +154,9 @@ impl Cache {
             data: chunk_body.clone(),
         };
 
-        let mut reader = BytesReader::new(chunk_body.clone());
+        let mut reader = BytesReader::new(BytesMut::from(
+            &chunk_body[..chunk_body.len().min(VIDEO_TAG_HEADER_LEN)],
+        ));
         let tag_header = VideoTagHeader::unmarshal(&mut reader)?;
 
         let is_key_frame = tag_header.frame_type == define::frame_type::KEY_FRAME;
@@ -160,6 +168,8 @@ impl Cache {
 
             if let Some(statistic_data_sender) = &self.statistic_data_sender {
                 let mut avc_processor = Mpeg4AvcProcessor::default();
+                let mut reader =
+                    BytesReader::new(BytesMut::from(&chunk_body[VIDEO_TAG_HEADER_LEN..]));
                 avc_processor.decoder_configuration_record_load(&mut reader)?;
 
                 let statistic_video_codec = StatisticData::VideoCodec {
@@ -191,7 +201,7 @@ impl Cache {
         Ok(())
     }
 
-    pub fn get_gops_data(&self) -> Option<VecDeque<Gop>> {
+    pub fn get_gops_data(&self) -> Option<&VecDeque<Gop>> {
         if self.gops.setted() {
             Some(self.gops.get_gops())
         } else {
@@ -167,6 +167,18 @@ impl ChunkPacketizer {
     }
 
     pub async fn write_chunk(&mut self, chunk_info: &mut ChunkInfo) -> Result<(), PackError> {
+        let payload = std::mem::take(&mut chunk_info.payload);
+        self.write_chunk_with_payload(chunk_info, &payload[..])
+            .await
+    }
+
+    /*write the chunk with a payload outside the chunk info, it is used to send
+    the shared a/v data to players without copying it into the chunk info.*/
+    pub async fn write_chunk_with_payload(
+        &mut self,
+        chunk_info: &mut ChunkInfo,
+        payload: &[u8],
+    ) -> Result<(), PackError> {
         self.zip_chunk_header(chunk_info)?;
 
         log::trace!(
@@ -174,7 +186,7 @@ impl ChunkPacketizer {
             chunk_info.message_header.timestamp,
         );
 
-        let mut whole_payload_size = chunk_info.payload.len();
+        let mut whole_payload_size = payload.len();
 
         self.write_basic_header(
             chunk_info.basic_header.format,
@@ -188,6 +200,7 @@ impl ChunkPacketizer {
         }
 
         let mut cur_payload_size: usize;
+        let mut payload_offset: usize = 0;
         while whole_payload_size > 0 {
             cur_payload_size = if whole_payload_size > self.max_chunk_size {
                 self.max_chunk_size
@@ -195,9 +208,10 @@ impl ChunkPacketizer {
                 whole_payload_size
             };
 
-            let payload_bytes = chunk_info.payload.split_to(cur_payload_size);
-            self.writer.write(&payload_bytes[0..])?;
+            self.writer
+                .write(&payload[payload_offset..payload_offset + cur_payload_size])?;
 
+            payload_offset += cur_payload_size;
             whole_payload_size -= cur_payload_size;
 
             if whole_payload_size > 0 {
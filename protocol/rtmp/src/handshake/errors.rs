@@ -18,6 +18,8 @@ pub enum HandshakeErrorValue {
     DigestNotFound,
     #[fail(display = "s0 version not correct error")]
     S0VersionNotCorrect,
+    #[fail(display = "s2 digest not correct error")]
+    S2DigestNotCorrect,
     #[fail(display = "io error")]
     IOError(Error),
 }
@@ -1,11 +1,18 @@
 use {
     super::{
-        define, define::ClientHandshakeState, errors::HandshakeError,
-        handshake_trait::THandshakeClient, utils,
+        define,
+        define::ClientHandshakeState,
+        digest::DigestProcessor,
+        errors::{HandshakeError, HandshakeErrorValue},
+        handshake_trait::THandshakeClient,
+        utils,
     },
     byteorder::BigEndian,
     bytes::BytesMut,
-    bytesio::{bytes_reader::BytesReader, bytes_writer::AsyncBytesWriter, bytesio::TNetIO},
+    bytesio::{
+        bytes_reader::BytesReader, bytes_writer::AsyncBytesWriter, bytes_writer::BytesWriter,
+        bytesio::TNetIO,
+    },
     std::sync::Arc,
     tokio::sync::Mutex,
 };
@@ -14,43 +21,68 @@ use {
 // use super::utils;
 // use super::{define::ClientHandshakeState, handshake_trait::THandshakeClient};
 
-pub struct SimpleHandshakeClient {
+/*The complex(digest) handshake client, some servers only accept it. If the
+server does not return a valid S1 digest, it falls back to the simple
+handshake by echoing S1 as C2. If the server rejects the complex C1, the
+session reconnects and uses the simple handshake only.*/
+pub struct ComplexHandshakeClient {
     reader: BytesReader,
     writer: AsyncBytesWriter,
     s1_bytes: BytesMut,
+    /*the digest of our C1, used to validate S2*/
+    c1_digest: BytesMut,
+    /*none if the server only supports the simple handshake*/
+    s1_digest: Option<BytesMut>,
+    /*send a simple C1 without the digest*/
+    simple_only: bool,
     pub state: ClientHandshakeState,
 }
 
-impl SimpleHandshakeClient {
+impl ComplexHandshakeClient {
     pub fn new(io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) -> Self {
         Self {
             reader: BytesReader::new(BytesMut::new()),
             writer: AsyncBytesWriter::new(io),
             s1_bytes: BytesMut::new(),
+            c1_digest: BytesMut::new(),
+            s1_digest: None,
+            simple_only: false,
             state: ClientHandshakeState::WriteC0C1,
         }
     }
 
+    pub fn new_simple(io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) -> Self {
+        let mut client = Self::new(io);
+        client.simple_only = true;
+        client
+    }
+
     pub fn extend_data(&mut self, data: &[u8]) {
         self.reader.extend_from_slice(data);
     }
-    pub async fn flush(&mut self) -> Result<(), HandshakeError> {
-        self.writer.flush().await?;
-        Ok(())
+
+    pub fn is_complex(&self) -> bool {
+        self.s1_digest.is_some()
+    }
+
+    pub fn is_simple_only(&self) -> bool {
+        self.simple_only
     }
 
     pub async fn handshake(&mut self) -> Result<(), HandshakeError> {
         loop {
             match self.state {
                 ClientHandshakeState::WriteC0C1 => {
+                    log::info!("[ C->S ] [complex handshake] write C0C1");
                     self.write_c0()?;
                     self.write_c1()?;
-                    self.flush().await?;
+                    self.writer.flush().await?;
                     self.state = ClientHandshakeState::ReadS0S1S2;
                     break;
                 }
 
                 ClientHandshakeState::ReadS0S1S2 => {
+                    log::info!("[ C<-S ] [complex handshake] read S0S1S2");
                     self.read_s0()?;
                     self.read_s1()?;
                     self.read_s2()?;
@@ -58,8 +90,9 @@ impl SimpleHandshakeClient {
                 }
 
                 ClientHandshakeState::WriteC2 => {
+                    log::info!("[ C->S ] [complex handshake] write C2");
                     self.write_c2()?;
-                    self.flush().await?;
+                    self.writer.flush().await?;
                     self.state = ClientHandshakeState::Finish;
                 }
 
@@ -73,21 +106,93 @@ impl SimpleHandshakeClient {
     }
 }
 
-impl THandshakeClient for SimpleHandshakeClient {
+/*C1: time(4 bytes) + version(4 bytes) + random data with the digest(schema 0),
+returns C1 and its digest*/
+fn generate_complex_c1() -> Result<(Vec<u8>, BytesMut), HandshakeError> {
+    let mut writer = BytesWriter::new();
+    writer.write_u32::<BigEndian>(utils::current_time())?;
+    writer.write(&define::RTMP_CLIENT_VERSION)?;
+    writer.write_random_bytes(define::RTMP_HANDSHAKE_SIZE as u32 - 8)?;
+
+    let mut key = BytesMut::new();
+    key.extend_from_slice(define::RTMP_CLIENT_KEY_FIRST_HALF.as_bytes());
+
+    let mut digest_processor = DigestProcessor::new(writer.extract_current_bytes(), key.clone());
+    let c1 = digest_processor.generate_and_fill_digest()?;
+
+    let (c1_digest, _) = DigestProcessor::new(BytesMut::from(&c1[..]), key).read_digest()?;
+    Ok((c1, c1_digest))
+}
+
+/*C2: random data(1504 bytes) + the digest of the random data, the digest key
+is generated from the S1 digest.*/
+fn generate_complex_c2(s1_digest: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+    let mut key = BytesMut::new();
+    key.extend_from_slice(&define::RTMP_CLIENT_KEY);
+
+    let mut digest_processor = DigestProcessor::new(BytesMut::new(), key);
+    let tmp_key = digest_processor.make_digest(Vec::from(s1_digest))?;
+
+    let mut writer = BytesWriter::new();
+    writer.write_random_bytes((define::RTMP_HANDSHAKE_SIZE - define::RTMP_DIGEST_LENGTH) as u32)?;
+    let data = writer.extract_current_bytes();
+
+    let mut digest_processor_2 = DigestProcessor::new(BytesMut::new(), tmp_key);
+    let digest = digest_processor_2.make_digest(Vec::from(&data[..]))?;
+
+    Ok([data, digest].concat())
+}
+
+/*S2: random data(1504 bytes) + the digest of the random data, the digest key
+is generated from the C1 digest, see ComplexHandshakeServer::write_s2.*/
+fn validate_complex_s2(s2: &[u8], c1_digest: &[u8]) -> Result<(), HandshakeError> {
+    let mut key = BytesMut::new();
+    key.extend_from_slice(&define::RTMP_SERVER_KEY);
+
+    let mut digest_processor = DigestProcessor::new(BytesMut::new(), key);
+    let tmp_key = digest_processor.make_digest(Vec::from(c1_digest))?;
+
+    let data_len = define::RTMP_HANDSHAKE_SIZE - define::RTMP_DIGEST_LENGTH;
+    let mut digest_processor_2 = DigestProcessor::new(BytesMut::new(), tmp_key);
+    let digest = digest_processor_2.make_digest(Vec::from(&s2[..data_len]))?;
+
+    if digest[..] != s2[data_len..] {
+        return Err(HandshakeError {
+            value: HandshakeErrorValue::S2DigestNotCorrect,
+        });
+    }
+    Ok(())
+}
+
+impl THandshakeClient for ComplexHandshakeClient {
     fn write_c0(&mut self) -> Result<(), HandshakeError> {
         self.writer.write_u8(define::RTMP_VERSION as u8)?;
         Ok(())
     }
     fn write_c1(&mut self) -> Result<(), HandshakeError> {
-        self.writer.write_u32::<BigEndian>(utils::current_time())?;
-        self.writer.write_u32::<BigEndian>(0)?;
+        if self.simple_only {
+            self.writer.write_u32::<BigEndian>(utils::current_time())?;
+            self.writer.write_u32::<BigEndian>(0)?;
+            self.writer
+                .write_random_bytes((define::RTMP_HANDSHAKE_SIZE - 8) as u32)?;
+            return Ok(());
+        }
 
-        self.writer
-            .write_random_bytes((define::RTMP_HANDSHAKE_SIZE - 8) as u32)?;
+        let (c1, c1_digest) = generate_complex_c1()?;
+        self.writer.write(&c1[..])?;
+        self.c1_digest = c1_digest;
         Ok(())
     }
     fn write_c2(&mut self) -> Result<(), HandshakeError> {
-        self.writer.write(&self.s1_bytes[0..])?;
+        match &self.s1_digest {
+            Some(s1_digest) => {
+                let c2 = generate_complex_c2(&s1_digest[..])?;
+                self.writer.write(&c2[..])?;
+            }
+            None => {
+                self.writer.write(&self.s1_bytes[0..])?;
+            }
+        }
         Ok(())
     }
 
@@ -97,10 +202,108 @@ impl THandshakeClient for SimpleHandshakeClient {
     }
     fn read_s1(&mut self) -> Result<(), HandshakeError> {
         self.s1_bytes = self.reader.read_bytes(define::RTMP_HANDSHAKE_SIZE)?;
+        if self.simple_only {
+            return Ok(());
+        }
+
+        let mut key = BytesMut::new();
+        key.extend_from_slice(define::RTMP_SERVER_KEY_FIRST_HALF.as_bytes());
+
+        let mut digest_processor = DigestProcessor::new(self.s1_bytes.clone(), key);
+        match digest_processor.read_digest() {
+            Ok((digest, _)) => {
+                self.s1_digest = Some(digest);
+            }
+            Err(err) => {
+                log::warn!(
+                    "complex handshake: read s1 digest err: {}, fall back to simple handshake",
+                    err
+                );
+                self.s1_digest = None;
+            }
+        }
         Ok(())
     }
     fn read_s2(&mut self) -> Result<(), HandshakeError> {
-        let _ = self.reader.read_bytes(define::RTMP_HANDSHAKE_SIZE)?;
+        let s2_bytes = self.reader.read_bytes(define::RTMP_HANDSHAKE_SIZE)?;
+        /*a server replying the simple S1 echoes C1 as S2, nothing to validate*/
+        if self.s1_digest.is_some() {
+            validate_complex_s2(&s2_bytes[..], &self.c1_digest[..])?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        hmac::{Hmac, Mac, NewMac},
+        sha2::Sha256,
+    };
+
+    fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /*a fixed S1 generated like ComplexHandshakeServer::write_s1*/
+    fn known_s1() -> Vec<u8> {
+        let mut s1 = vec![0x5a; define::RTMP_HANDSHAKE_SIZE];
+        s1[4..8].copy_from_slice(&define::RTMP_SERVER_VERSION);
+
+        let mut key = BytesMut::new();
+        key.extend_from_slice(define::RTMP_SERVER_KEY_FIRST_HALF.as_bytes());
+        DigestProcessor::new(BytesMut::from(&s1[..]), key)
+            .generate_and_fill_digest()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_complex_c1_c2() {
+        /*the server validates C1 like ComplexHandshakeServer::read_c1*/
+        let (c1, c1_digest) = generate_complex_c1().unwrap();
+        assert_eq!(c1.len(), define::RTMP_HANDSHAKE_SIZE);
+
+        let mut key = BytesMut::new();
+        key.extend_from_slice(define::RTMP_CLIENT_KEY_FIRST_HALF.as_bytes());
+        let mut digest_processor = DigestProcessor::new(BytesMut::from(&c1[..]), key);
+        let (digest, _) = digest_processor.read_digest().unwrap();
+        assert_eq!(digest, c1_digest);
+
+        let s1 = known_s1();
+        let mut key = BytesMut::new();
+        key.extend_from_slice(define::RTMP_SERVER_KEY_FIRST_HALF.as_bytes());
+        let (s1_digest, _) = DigestProcessor::new(BytesMut::from(&s1[..]), key)
+            .read_digest()
+            .unwrap();
+
+        let c2 = generate_complex_c2(&s1_digest[..]).unwrap();
+        assert_eq!(c2.len(), define::RTMP_HANDSHAKE_SIZE);
+
+        let data_len = define::RTMP_HANDSHAKE_SIZE - define::RTMP_DIGEST_LENGTH;
+        let tmp_key = hmac_sha256(&define::RTMP_CLIENT_KEY, &s1_digest[..]);
+        assert_eq!(c2[data_len..], hmac_sha256(&tmp_key, &c2[..data_len])[..]);
+
+        /*a C2 generated from another S1 digest does not match*/
+        let c2 = generate_complex_c2(&[0; define::RTMP_DIGEST_LENGTH]).unwrap();
+        assert_ne!(c2[data_len..], hmac_sha256(&tmp_key, &c2[..data_len])[..]);
+    }
+
+    #[test]
+    fn test_validate_complex_s2() {
+        let (_, c1_digest) = generate_complex_c1().unwrap();
+
+        /*S2 generated like ComplexHandshakeServer::write_s2*/
+        let data_len = define::RTMP_HANDSHAKE_SIZE - define::RTMP_DIGEST_LENGTH;
+        let data = vec![0x3c; data_len];
+        let tmp_key = hmac_sha256(&define::RTMP_SERVER_KEY, &c1_digest[..]);
+        let mut s2 = [data.clone(), hmac_sha256(&tmp_key, &data)].concat();
+        assert!(validate_complex_s2(&s2, &c1_digest[..]).is_ok());
+
+        s2[0] ^= 0xff;
+        let err = validate_complex_s2(&s2, &c1_digest[..]).unwrap_err();
+        assert!(matches!(err.value, HandshakeErrorValue::S2DigestNotCorrect));
+    }
+}
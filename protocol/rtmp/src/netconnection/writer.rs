@@ -257,6 +257,14 @@ impl NetConnection {
         self.write_chunk().await
     }
 
+    pub async fn write_check_bw(&mut self, transaction_id: &f64) -> Result<(), NetConnectionError> {
+        self.amf0_writer.write_string(&String::from("_checkbw"))?;
+        self.amf0_writer.write_number(transaction_id)?;
+        self.amf0_writer.write_null()?;
+
+        self.write_chunk().await
+    }
+
     pub async fn write_get_stream_length(
         &mut self,
         transaction_id: &f64,
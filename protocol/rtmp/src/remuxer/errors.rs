@@ -2,6 +2,7 @@ use {
     crate::{cache::errors::MetadataError, session::errors::SessionError},
     bytesio::bytes_errors::{BytesReadError, BytesWriteError},
     failure::Fail,
+    fdk_aac::enc::EncoderError as AacEncoderError,
     std::fmt,
     streamhub::errors::StreamHubError,
     tokio::sync::broadcast::error::RecvError,
@@ -9,6 +10,7 @@ use {
     xflv::amf0::errors::Amf0WriteError,
     xflv::errors::FlvMuxerError,
     xflv::errors::Mpeg4AvcHevcError,
+    xflv::errors::MpegAacError,
 };
 
 pub struct RtmpRemuxerError {
@@ -43,6 +45,8 @@ pub enum RtmpRemuxerErrorValue {
     OneshotRecvError(#[cause] OneshotRecvError),
     #[fail(display = "Channel receive error")]
     ChannelRecvError,
+    #[fail(display = "g711 to aac transcode error: {:?}", _0)]
+    G7112AacError(G7112AacError),
 }
 impl From<RecvError> for RtmpRemuxerError {
     fn from(error: RecvError) -> Self {
@@ -124,8 +128,43 @@ impl From<OneshotRecvError> for RtmpRemuxerError {
     }
 }
 
+impl From<G7112AacError> for RtmpRemuxerError {
+    fn from(error: G7112AacError) -> Self {
+        RtmpRemuxerError {
+            value: RtmpRemuxerErrorValue::G7112AacError(error),
+        }
+    }
+}
+
 impl fmt::Display for RtmpRemuxerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.value, f)
     }
 }
+
+#[derive(Debug)]
+pub struct G7112AacError {
+    pub value: G7112AacErrorValue,
+}
+
+#[derive(Debug)]
+pub enum G7112AacErrorValue {
+    AacEncoderError(AacEncoderError),
+    MpegAacError(MpegAacError),
+}
+
+impl From<AacEncoderError> for G7112AacError {
+    fn from(error: AacEncoderError) -> Self {
+        G7112AacError {
+            value: G7112AacErrorValue::AacEncoderError(error),
+        }
+    }
+}
+
+impl From<MpegAacError> for G7112AacError {
+    fn from(error: MpegAacError) -> Self {
+        G7112AacError {
+            value: G7112AacErrorValue::MpegAacError(error),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::errors::G7112AacError;
+use bytes::BytesMut;
+use fdk_aac::enc::{Encoder as AacEncoder, EncoderParams};
+use streamhub::define::AudioCodecType;
+use xflv::mpeg4_aac::Mpeg4Aac;
+
+//the pcm samples of one aac frame
+const AAC_FRAME_SAMPLES: usize = 1024;
+
+//https://www.itu.int/rec/T-REC-G.711
+fn alaw_to_linear(alaw: u8) -> i16 {
+    let alaw = alaw ^ 0x55;
+    let exponent = (alaw >> 4) & 0x07;
+    let mut sample = (((alaw & 0x0F) as i16) << 4) + 8;
+    if exponent != 0 {
+        sample = (sample + 0x100) << (exponent - 1);
+    }
+    if alaw & 0x80 != 0 {
+        sample
+    } else {
+        -sample
+    }
+}
+
+fn ulaw_to_linear(ulaw: u8) -> i16 {
+    let ulaw = !ulaw;
+    let exponent = (ulaw >> 4) & 0x07;
+    let sample = (((((ulaw & 0x0F) as i16) << 3) + 0x84) << exponent) - 0x84;
+    if ulaw & 0x80 != 0 {
+        -sample
+    } else {
+        sample
+    }
+}
+
+//Decode the G.711(PCMA/PCMU) frames to pcm and encode them to mono aac-lc,
+//the cameras send G.711 which can not be carried in RTMP/HTTP-FLV/HLS.
+pub struct G7112AacTranscoder {
+    acodec: AudioCodecType,
+    sample_rate: u32,
+    encoder: AacEncoder,
+    pcm_data: Vec<i16>,
+    //the rtp timestamp of the first sample in pcm_data
+    pcm_timestamp: u32,
+}
+
+impl G7112AacTranscoder {
+    pub fn new(acodec: AudioCodecType, sample_rate: u32) -> Result<Self, G7112AacError> {
+        let encoder = AacEncoder::new(EncoderParams {
+            bit_rate: fdk_aac::enc::BitRate::Cbr(32000),
+            transport: fdk_aac::enc::Transport::Raw,
+            channels: fdk_aac::enc::ChannelMode::Mono,
+            sample_rate,
+        })?;
+
+        Ok(Self {
+            acodec,
+            sample_rate,
+            encoder,
+            pcm_data: Vec::new(),
+            pcm_timestamp: 0,
+        })
+    }
+
+    pub fn gen_audio_specific_config(&self) -> Result<BytesMut, G7112AacError> {
+        //aac-lc mono
+        let aac = Mpeg4Aac::new(2, self.sample_rate, 1)?;
+        Ok(aac.gen_audio_specific_config()?)
+    }
+
+    //returns the aac frames with their rtp timestamps
+    pub fn transcode(
+        &mut self,
+        timestamp: u32,
+        input: &[u8],
+    ) -> Result<Vec<(u32, Vec<u8>)>, G7112AacError> {
+        if self.pcm_data.is_empty() {
+            self.pcm_timestamp = timestamp;
+        }
+
+        match self.acodec {
+            AudioCodecType::G711A => self
+                .pcm_data
+                .extend(input.iter().map(|sample| alaw_to_linear(*sample))),
+            _ => self
+                .pcm_data
+                .extend(input.iter().map(|sample| ulaw_to_linear(*sample))),
+        }
+
+        let mut aac_output: Vec<u8> = vec![0; 2048];
+        let mut result = Vec::new();
+        while self.pcm_data.len() >= AAC_FRAME_SAMPLES {
+            let pcm = self.pcm_data.split_off(AAC_FRAME_SAMPLES);
+            let encoder_info = self.encoder.encode(&self.pcm_data, &mut aac_output)?;
+            self.pcm_data = pcm;
+            if encoder_info.output_size > 0 {
+                result.push((
+                    self.pcm_timestamp,
+                    aac_output[..encoder_info.output_size].to_vec(),
+                ));
+            }
+            self.pcm_timestamp = self.pcm_timestamp.wrapping_add(AAC_FRAME_SAMPLES as u32);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alaw_to_linear, ulaw_to_linear};
+
+    #[test]
+    fn test_g711_to_linear() {
+        assert_eq!(ulaw_to_linear(0xFF), 0);
+        assert_eq!(ulaw_to_linear(0x00), -32124);
+        assert_eq!(ulaw_to_linear(0x80), 32124);
+        assert_eq!(alaw_to_linear(0xD5), 8);
+        assert_eq!(alaw_to_linear(0x55), -8);
+        assert_eq!(alaw_to_linear(0xAA), 32256);
+    }
+}
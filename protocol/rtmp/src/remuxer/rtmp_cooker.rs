@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_writer::BytesWriter;
 use indexmap::IndexMap;
 
@@ -68,7 +68,7 @@ impl RtmpCooker {
 
     pub fn gen_video_frame_data(
         &self,
-        nalus: Vec<BytesMut>,
+        nalus: Vec<Bytes>,
         contains_idr: bool,
     ) -> Result<BytesMut, RtmpRemuxerError> {
         let frame_type = if contains_idr { 1 } else { 2 };
@@ -95,10 +95,7 @@ impl RtmpCooker {
         Ok(writer.extract_current_bytes())
     }
     //generate audio rtmp frame (including seq header and common frame)
-    pub fn gen_audio_frame_data(
-        &self,
-        audio_data: &BytesMut,
-    ) -> Result<BytesMut, RtmpRemuxerError> {
+    pub fn gen_audio_frame_data(&self, audio_data: &[u8]) -> Result<BytesMut, RtmpRemuxerError> {
         let mut aac_packet_type: u8 = 0;
 
         if audio_data.len() > 5 {
@@ -1,7 +1,7 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
 use h264_decoder::sps::SpsParser;
-use streamhub::define::VideoCodecType;
+use streamhub::define::{AudioCodecType, VideoCodecType};
 use tokio::sync::oneshot;
 use xflv::define::h264_nal_type::{H264_NAL_IDR, H264_NAL_PPS, H264_NAL_SPS};
 
@@ -9,15 +9,16 @@ use crate::session::define::SessionType;
 
 use super::{
     errors::{RtmpRemuxerError, RtmpRemuxerErrorValue},
+    g7112aac::G7112AacTranscoder,
     rtmp_cooker::RtmpCooker,
 };
 
 use {
-    crate::session::common::Common,
+    crate::{cache::gop::GopLimit, session::common::Common},
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
             SubscribeType, SubscriberInfo,
         },
         stream::StreamIdentifier,
@@ -32,18 +33,26 @@ pub struct Rtsp2RtmpRemuxerSession {
     stream_name: String,
 
     //RTSP
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     stream_path: String,
     subscribe_id: Uuid,
     video_clock_rate: u32,
     audio_clock_rate: u32,
-    base_video_timestamp: u32,
-    base_audio_timestamp: u32,
+    acodec: AudioCodecType,
+    //transcode the G.711 audio to aac
+    g7112aac_transcoder: Option<G7112AacTranscoder>,
 
     rtmp_handler: Common,
     rtmp_cooker: RtmpCooker,
 }
 
+//The rtsp timestamps of the audio and video tracks start from a common clock(aligned
+//by the rtcp sender reports), so they are converted to milliseconds without per
+//track offsets, otherwise the a/v offset of the start would be lost.
+fn rtp_timestamp_to_millis(timestamp: u32, clock_rate: u32) -> u32 {
+    (timestamp as u64 * 1000 / clock_rate.max(1) as u64) as u32
+}
+
 pub fn find_start_code(nalus: &[u8]) -> Option<usize> {
     let pattern = [0x00, 0x00, 0x01];
     nalus.windows(pattern.len()).position(|w| w == pattern)
@@ -51,7 +60,7 @@ pub fn find_start_code(nalus: &[u8]) -> Option<usize> {
 
 impl Rtsp2RtmpRemuxerSession {
     pub fn new(stream_path: String, event_producer: StreamHubEventSender) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
 
         let eles: Vec<&str> = stream_path.splitn(2, '/').collect();
         let (app_name, stream_name) = if eles.len() < 2 {
@@ -75,8 +84,8 @@ impl Rtsp2RtmpRemuxerSession {
 
             video_clock_rate: 1000,
             audio_clock_rate: 1000,
-            base_audio_timestamp: 0,
-            base_video_timestamp: 0,
+            acodec: AudioCodecType::AAC,
+            g7112aac_transcoder: None,
             rtmp_handler: Common::new(None, event_producer, SessionType::Server, None),
             rtmp_cooker: RtmpCooker::default(),
         }
@@ -92,7 +101,7 @@ impl Rtsp2RtmpRemuxerSession {
 
     pub async fn publish_rtmp(&mut self) -> Result<(), RtmpRemuxerError> {
         self.rtmp_handler
-            .publish_to_channels(self.app_name.clone(), self.stream_name.clone(), 0)
+            .publish_to_channels(self.app_name.clone(), self.stream_name.clone(), GopLimit::new(0))
             .await?;
         Ok(())
     }
@@ -110,6 +119,7 @@ impl Rtsp2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::PlayerRtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -140,6 +150,7 @@ impl Rtsp2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::PlayerRtsp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -165,14 +176,15 @@ impl Rtsp2RtmpRemuxerSession {
         loop {
             if let Some(data) = self.data_receiver.recv().await {
                 match data {
-                    FrameData::Audio { timestamp, data } => {
-                        self.on_rtsp_audio(&data, timestamp).await?
-                    }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        self.on_rtsp_video(&mut data, timestamp).await?;
+                    FrameData::Audio { timestamp, data } => match self.acodec {
+                        AudioCodecType::AAC => self.on_rtsp_audio(&data, timestamp).await?,
+                        AudioCodecType::G711A | AudioCodecType::G711U => {
+                            self.on_rtsp_g711_audio(&data[..], timestamp).await?
+                        }
+                        _ => {}
+                    },
+                    FrameData::Video { timestamp, data } => {
+                        self.on_rtsp_video(data, timestamp).await?;
                     }
                     FrameData::MediaInfo { media_info } => {
                         self.video_clock_rate = media_info.video_clock_rate;
@@ -183,6 +195,25 @@ impl Rtsp2RtmpRemuxerSession {
                             self.video_clock_rate
                         );
 
+                        self.acodec = media_info.acodec;
+                        match self.acodec {
+                            AudioCodecType::AAC => {}
+                            AudioCodecType::G711A | AudioCodecType::G711U => {
+                                let transcoder = G7112AacTranscoder::new(
+                                    self.acodec.clone(),
+                                    self.audio_clock_rate,
+                                )?;
+                                let asc = transcoder.gen_audio_specific_config()?;
+                                self.on_rtsp_audio(&asc, 0).await?;
+                                self.g7112aac_transcoder = Some(transcoder);
+                            }
+                            _ => {
+                                log::warn!(
+                                    "only aac and G.711 rtsp audio can be remuxed to rtmp, the audio is dropped"
+                                );
+                            }
+                        }
+
                         if media_info.vcodec == VideoCodecType::H265 {
                             log::warn!(
                                 "h265 rtsp to rtmp is not supported now!!! will come soon!!"
@@ -209,17 +240,12 @@ impl Rtsp2RtmpRemuxerSession {
 
     async fn on_rtsp_audio(
         &mut self,
-        audio_data: &BytesMut,
+        audio_data: &[u8],
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
-        if self.base_audio_timestamp == 0 {
-            self.base_audio_timestamp = timestamp;
-        }
-
         let mut audio_frame = self.rtmp_cooker.gen_audio_frame_data(audio_data)?;
 
-        let timestamp_adjust =
-            (timestamp - self.base_audio_timestamp) / (self.audio_clock_rate / 1000);
+        let timestamp_adjust = rtp_timestamp_to_millis(timestamp, self.audio_clock_rate);
         self.rtmp_handler
             .on_audio_data(&mut audio_frame, &timestamp_adjust)
             .await?;
@@ -227,14 +253,29 @@ impl Rtsp2RtmpRemuxerSession {
         Ok(())
     }
 
-    async fn on_rtsp_video(
+    async fn on_rtsp_g711_audio(
         &mut self,
-        nalus: &mut BytesMut,
+        audio_data: &[u8],
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
-        if self.base_video_timestamp == 0 {
-            self.base_video_timestamp = timestamp;
+        let aac_frames = if let Some(transcoder) = &mut self.g7112aac_transcoder {
+            transcoder.transcode(timestamp, audio_data)?
+        } else {
+            return Ok(());
+        };
+
+        for (timestamp, aac_frame) in aac_frames {
+            self.on_rtsp_audio(&aac_frame, timestamp).await?;
         }
+
+        Ok(())
+    }
+
+    async fn on_rtsp_video(
+        &mut self,
+        mut nalus: Bytes,
+        timestamp: u32,
+    ) -> Result<(), RtmpRemuxerError> {
         let mut nalu_vec = Vec::new();
         while !nalus.is_empty() {
             if let Some(first_pos) = find_start_code(&nalus[..]) {
@@ -250,7 +291,9 @@ impl Rtsp2RtmpRemuxerSession {
                     };
 
                 let nalu = nalu_with_start_code.split_off(first_pos + 3);
-                nalu_vec.push(nalu);
+                if !nalu.is_empty() {
+                    nalu_vec.push(nalu);
+                }
             } else {
                 break;
             }
@@ -265,11 +308,10 @@ impl Rtsp2RtmpRemuxerSession {
         let mut contains_idr = false;
 
         for nalu in &nalu_vec {
-            let mut nalu_reader = BytesReader::new(nalu.clone());
-
-            let nalu_type = nalu_reader.read_u8()?;
-            match nalu_type & 0x1F {
+            match nalu[0] & 0x1F {
                 H264_NAL_SPS => {
+                    //only the small parameter sets are copied
+                    let nalu_reader = BytesReader::new(BytesMut::from(&nalu[1..]));
                     let mut sps_parser = SpsParser::new(nalu_reader);
                     (width, height) = if let Ok((width, height)) = sps_parser.parse() {
                         (width, height)
@@ -281,9 +323,9 @@ impl Rtsp2RtmpRemuxerSession {
                     level = sps_parser.sps.level_idc;
                     profile = sps_parser.sps.profile_idc;
 
-                    sps = Some(nalu.clone());
+                    sps = Some(BytesMut::from(&nalu[..]));
                 }
-                H264_NAL_PPS => pps = Some(nalu.clone()),
+                H264_NAL_PPS => pps = Some(BytesMut::from(&nalu[..])),
                 H264_NAL_IDR => {
                     contains_idr = true;
                 }
@@ -307,8 +349,7 @@ impl Rtsp2RtmpRemuxerSession {
                 .rtmp_cooker
                 .gen_video_frame_data(nalu_vec, contains_idr)?;
 
-            let timestamp_adjust =
-                (timestamp - self.base_video_timestamp) / (self.video_clock_rate / 1000);
+            let timestamp_adjust = rtp_timestamp_to_millis(timestamp, self.video_clock_rate);
             self.rtmp_handler
                 .on_video_data(&mut frame_data, &timestamp_adjust)
                 .await?;
@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_reader::BytesReader;
 use h264_decoder::sps::SpsParser;
 use streamhub::define::VideoCodecType;
@@ -13,11 +13,11 @@ use super::{
 };
 
 use {
-    crate::session::common::Common,
+    crate::{cache::gop::GopLimit, session::common::Common},
     std::time::Duration,
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, NotifyInfo, StreamHubEvent, StreamHubEventSender,
+            FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver,
             SubscribeType, SubscriberInfo,
         },
         stream::StreamIdentifier,
@@ -32,7 +32,7 @@ pub struct Whip2RtmpRemuxerSession {
     stream_name: String,
 
     //WHIP
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
 
     subscribe_id: Uuid,
     video_clock_rate: u32,
@@ -74,7 +74,7 @@ impl Whip2RtmpRemuxerSession {
         stream_name: String,
         event_producer: StreamHubEventSender,
     ) -> Self {
-        let (_, data_consumer) = mpsc::unbounded_channel();
+        let (_, data_consumer) = mpsc::channel(1);
 
         Self {
             app_name,
@@ -105,7 +105,7 @@ impl Whip2RtmpRemuxerSession {
 
     pub async fn publish_rtmp(&mut self) -> Result<(), RtmpRemuxerError> {
         self.rtmp_handler
-            .publish_to_channels(self.app_name.clone(), self.stream_name.clone(), 1)
+            .publish_to_channels(self.app_name.clone(), self.stream_name.clone(), GopLimit::new(1))
             .await?;
         Ok(())
     }
@@ -124,6 +124,7 @@ impl Whip2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::PlayerRtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -155,6 +156,7 @@ impl Whip2RtmpRemuxerSession {
             id: self.subscribe_id,
             sub_type: SubscribeType::PlayerRtmp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -184,11 +186,8 @@ impl Whip2RtmpRemuxerSession {
                     FrameData::Audio { timestamp, data } => {
                         self.on_whip_audio(&data, timestamp).await?
                     }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        self.on_whip_video(&mut data, timestamp).await?;
+                    FrameData::Video { timestamp, data } => {
+                        self.on_whip_video(data, timestamp).await?;
                     }
                     FrameData::MediaInfo { media_info } => {
                         self.video_clock_rate = media_info.video_clock_rate;
@@ -199,10 +198,9 @@ impl Whip2RtmpRemuxerSession {
                             self.video_clock_rate
                         );
 
-                        if media_info.vcodec == VideoCodecType::H265 {
-                            log::warn!(
-                                "h265 rtsp to rtmp is not supported now!!! will come soon!!"
-                            );
+                        //only the h264 webrtc video can be remuxed to flv
+                        if media_info.vcodec != VideoCodecType::H264 {
+                            log::warn!("{:?} whip to rtmp is not supported now", media_info.vcodec);
                             break;
                         }
                     }
@@ -225,7 +223,7 @@ impl Whip2RtmpRemuxerSession {
 
     async fn on_whip_audio(
         &mut self,
-        audio_data: &BytesMut,
+        audio_data: &[u8],
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
         if self.base_audio_timestamp == 0 {
@@ -246,7 +244,7 @@ impl Whip2RtmpRemuxerSession {
 
     async fn on_whip_video(
         &mut self,
-        nalus: &mut BytesMut,
+        mut nalus: Bytes,
         timestamp: u32,
     ) -> Result<(), RtmpRemuxerError> {
         if self.base_video_timestamp == 0 {
@@ -267,7 +265,9 @@ impl Whip2RtmpRemuxerSession {
                     };
 
                 let nalu = nalu_with_start_code.split_off(first_pos + 3);
-                nalu_vec.push(nalu);
+                if !nalu.is_empty() {
+                    nalu_vec.push(nalu);
+                }
             } else {
                 break;
             }
@@ -281,11 +281,10 @@ impl Whip2RtmpRemuxerSession {
         let mut contains_idr = false;
 
         for nalu in &nalu_vec {
-            let mut nalu_reader = BytesReader::new(nalu.clone());
-
-            let nalu_type = nalu_reader.read_u8()?;
-            match nalu_type & 0x1F {
+            match nalu[0] & 0x1F {
                 H264_NAL_SPS => {
+                    //only the small parameter sets are copied
+                    let nalu_reader = BytesReader::new(BytesMut::from(&nalu[1..]));
                     let mut sps_parser = SpsParser::new(nalu_reader);
                     (width, height) = if let Ok((width, height)) = sps_parser.parse() {
                         (width, height)
@@ -296,9 +295,9 @@ impl Whip2RtmpRemuxerSession {
                     level = sps_parser.sps.level_idc;
                     profile = sps_parser.sps.profile_idc;
 
-                    self.sps = Some(nalu.clone());
+                    self.sps = Some(BytesMut::from(&nalu[..]));
                 }
-                H264_NAL_PPS => self.pps = Some(nalu.clone()),
+                H264_NAL_PPS => self.pps = Some(BytesMut::from(&nalu[..])),
                 H264_NAL_IDR => {
                     contains_idr = true;
                 }
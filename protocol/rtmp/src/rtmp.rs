@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use streamhub::define::StreamHubEventSender;
+use streamhub::timestamp::DEFAULT_JUMP_THRESHOLD;
 
+use super::cache::gop::GopCacheConfig;
 use super::session::server_session;
 use commonlib::auth::Auth;
 use std::net::SocketAddr;
@@ -12,19 +14,20 @@ use tokio::sync::Mutex;
 pub struct RtmpServer {
     address: String,
     event_producer: StreamHubEventSender,
-    gop_num: usize,
+    gop_cache_config: GopCacheConfig,
     auth: Option<Auth>,
     enabled_nonce: bool,
     publish_token: Option<String>,
     subscribe_token: Option<String>,
     nonce_map: Arc<Mutex<HashMap<String, i64>>>,
+    timestamp_jump_threshold: u32,
 }
 
 impl RtmpServer {
     pub fn new(
         address: String,
         event_producer: StreamHubEventSender,
-        gop_num: usize,
+        gop_cache_config: GopCacheConfig,
         auth: Option<Auth>,
         publish_token: Option<String>,
         subscribe_token: Option<String>,
@@ -34,15 +37,21 @@ impl RtmpServer {
         Self {
             address,
             event_producer,
-            gop_num,
+            gop_cache_config,
             auth,
             publish_token,
             subscribe_token,
             enabled_nonce,
             nonce_map,
+            timestamp_jump_threshold: DEFAULT_JUMP_THRESHOLD,
         }
     }
 
+    //the max timestamp gap(ms) of two continuous frames from a publisher, a larger one is corrected as a jump
+    pub fn set_timestamp_jump_threshold(&mut self, threshold: u32) {
+        self.timestamp_jump_threshold = threshold;
+    }
+
     pub async fn run(&mut self) -> Result<(), Error> {
         let socket_addr: &SocketAddr = &self.address.parse().unwrap();
         let listener = TcpListener::bind(socket_addr).await?;
@@ -55,13 +64,16 @@ impl RtmpServer {
             let mut session = server_session::ServerSession::new(
                 tcp_stream,
                 self.event_producer.clone(),
-                self.gop_num,
+                self.gop_cache_config.clone(),
                 self.auth.clone(),
                 self.publish_token.clone(),
                 self.subscribe_token.clone(),
                 self.enabled_nonce,
                 Arc::clone(&self.nonce_map),
             );
+            session
+                .common
+                .set_timestamp_jump_threshold(self.timestamp_jump_threshold);
             tokio::spawn(async move {
                 if let Err(err) = session.run().await {
                     log::info!(
@@ -8,12 +8,16 @@ use {
         errors::{SessionError, SessionErrorValue},
     },
     crate::{
+        cache::gop::GopLimit,
         chunk::{
             define::CHUNK_SIZE,
             unpacketizer::{ChunkUnpacketizer, UnpackResult},
         },
         handshake,
-        handshake::{define::ClientHandshakeState, handshake_client::SimpleHandshakeClient},
+        handshake::{
+            define::ClientHandshakeState, errors::HandshakeError,
+            handshake_client::ComplexHandshakeClient,
+        },
         messages::{define::RtmpMessageData, parser::MessageParser},
         netconnection::writer::{ConnectProperties, NetConnection},
         netstream::writer::NetStreamWriter,
@@ -68,7 +72,7 @@ pub enum ClientType {
 pub struct ClientSession {
     io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
     common: Common,
-    handshaker: SimpleHandshakeClient,
+    handshaker: ComplexHandshakeClient,
     unpacketizer: ChunkUnpacketizer,
     //domain name with port
     raw_domain_name: String,
@@ -116,7 +120,7 @@ impl ClientSession {
         Self {
             io: Arc::clone(&net_io),
             common,
-            handshaker: SimpleHandshakeClient::new(Arc::clone(&net_io)),
+            handshaker: ComplexHandshakeClient::new(Arc::clone(&net_io)),
             unpacketizer: ChunkUnpacketizer::new(),
             raw_domain_name,
             app_name,
@@ -196,10 +200,38 @@ impl ClientSession {
     }
 
     async fn handshake(&mut self) -> Result<(), SessionError> {
+        if let Err(err) = self.do_handshake().await {
+            if self.handshaker.is_simple_only() {
+                return Err(err);
+            }
+            /*some servers close the connection when they do not accept the
+            complex C1, reconnect and try the simple handshake.*/
+            log::warn!(
+                "complex handshake err: {}, reconnect with simple handshake",
+                err
+            );
+            let stream = TcpStream::connect(self.raw_domain_name.clone())
+                .await
+                .map_err(HandshakeError::from)?;
+            *self.io.lock().await = Box::new(TcpIO::new(stream));
+
+            self.handshaker = ComplexHandshakeClient::new_simple(Arc::clone(&self.io));
+            self.do_handshake().await?;
+        }
+
+        self.state = ClientSessionState::Connect;
+
+        Ok(())
+    }
+
+    async fn do_handshake(&mut self) -> Result<(), SessionError> {
         loop {
             self.handshaker.handshake().await?;
             if self.handshaker.state == ClientHandshakeState::Finish {
-                log::info!("handshake finish");
+                log::info!(
+                    "handshake finish, complex handshake: {}",
+                    self.handshaker.is_complex()
+                );
                 break;
             }
 
@@ -211,8 +243,6 @@ impl ClientSession {
             }
         }
 
-        self.state = ClientSessionState::Connect;
-
         Ok(())
     }
 
@@ -252,13 +282,16 @@ impl ClientSession {
                 self.on_stream_is_recorded(stream_id)?;
             }
             RtmpMessageData::AudioData { data } => {
-                self.common.on_audio_data(data, timestamp).await?
+                let timestamp = self.common.normalize_timestamp(*timestamp);
+                self.common.on_audio_data(data, &timestamp).await?;
             }
             RtmpMessageData::VideoData { data } => {
-                self.common.on_video_data(data, timestamp).await?
+                let timestamp = self.common.normalize_timestamp(*timestamp);
+                self.common.on_video_data(data, &timestamp).await?;
             }
             RtmpMessageData::AmfData { raw_data } => {
-                self.common.on_meta_data(raw_data, timestamp).await?;
+                let timestamp = self.common.normalize_data_timestamp(*timestamp);
+                self.common.on_meta_data(raw_data, &timestamp).await?;
             }
 
             _ => {}
@@ -302,11 +335,27 @@ impl ClientSession {
                     log::info!("[C <- S] on_result_create_stream...");
                     self.on_result_create_stream()?;
                 }
+                define::TRANSACTION_ID_RELEASE_STREAM => {
+                    log::info!("[C <- S] on_result_release_stream...");
+                }
+                define::TRANSACTION_ID_FCPUBLISH => {
+                    log::info!("[C <- S] on_result_fcpublish...");
+                }
+                define::TRANSACTION_ID_CHECK_BW => {
+                    log::info!("[C <- S] on_result_check_bw...");
+                }
                 _ => {}
             },
             "_error" => {
                 self.on_error()?;
             }
+            "onBWDone" => {
+                log::info!("[C <- S] on_bw_done...");
+                self.on_bw_done().await?;
+            }
+            "onFCPublish" => {
+                log::info!("[C <- S] on_fcpublish...");
+            }
             "onStatus" => {
                 match others.remove(0) {
                     Amf0ValueType::Object(obj) => self.on_status(&obj).await?,
@@ -454,13 +503,23 @@ impl ClientSession {
             ProtocolControlMessagesWriter::new(AsyncBytesWriter::new(self.io.clone()));
         controlmessage.write_acknowledgement(3107).await?;
 
-        let mut netstream = NetStreamWriter::new(Arc::clone(&self.io));
-        netstream
-            .write_release_stream(&(define::TRANSACTION_ID_CONNECT as f64), &self.stream_name)
-            .await?;
-        netstream
-            .write_fcpublish(&(define::TRANSACTION_ID_CONNECT as f64), &self.stream_name)
-            .await?;
+        /*the encoders(FMLE, OBS, FFmpeg) send releaseStream and FCPublish before
+        creating the stream, some ingest servers require them.*/
+        if self.client_type == ClientType::Publish {
+            let mut netstream = NetStreamWriter::new(Arc::clone(&self.io));
+            netstream
+                .write_release_stream(
+                    &(define::TRANSACTION_ID_RELEASE_STREAM as f64),
+                    &self.raw_stream_name,
+                )
+                .await?;
+            netstream
+                .write_fcpublish(
+                    &(define::TRANSACTION_ID_FCPUBLISH as f64),
+                    &self.raw_stream_name,
+                )
+                .await?;
+        }
 
         self.state = ClientSessionState::CreateStream;
 
@@ -501,6 +560,16 @@ impl ClientSession {
         Ok(())
     }
 
+    /*the server finishes the bandwidth detection, answer it with _checkbw.*/
+    pub async fn on_bw_done(&mut self) -> Result<(), SessionError> {
+        let mut netconnection = NetConnection::new(Arc::clone(&self.io));
+        netconnection
+            .write_check_bw(&(define::TRANSACTION_ID_CHECK_BW as f64))
+            .await?;
+
+        Ok(())
+    }
+
     pub fn on_error(&mut self) -> Result<(), SessionError> {
         Ok(())
     }
@@ -536,7 +605,7 @@ impl ClientSession {
                         .publish_to_channels(
                             self.app_name.clone(),
                             self.stream_name.clone(),
-                            self.gop_num,
+                            GopLimit::new(self.gop_num),
                         )
                         .await?
                 }
@@ -3,12 +3,13 @@ use tokio::sync::oneshot;
 
 use {
     super::{
+        data_message::amf_data_to_message,
         define::SessionType,
         errors::{SessionError, SessionErrorValue},
     },
     crate::{
         cache::errors::CacheError,
-        cache::Cache,
+        cache::{gop::GopLimit, Cache},
         chunk::{
             define::{chunk_type, csid_type},
             packetizer::ChunkPacketizer,
@@ -17,21 +18,22 @@ use {
         messages::define::msg_type_id,
     },
     async_trait::async_trait,
-    bytes::BytesMut,
+    bytes::{Bytes, BytesMut},
     std::fmt,
     std::{net::SocketAddr, sync::Arc},
     streamhub::{
         define::{
-            FrameData, FrameDataReceiver, FrameDataSender, InformationSender, NotifyInfo,
-            PublishType, PublisherInfo, StreamHubEvent, StreamHubEventSender, SubscribeType,
-            SubscriberInfo, TStreamHandler,
+            FrameData, FrameDataSender, InformationSender, NotifyInfo, PublishType, PublisherInfo,
+            StreamHubEvent, StreamHubEventSender, SubFrameDataReceiver, SubscribeStartMode,
+            SubscribeType, SubscriberInfo, TStreamHandler,
         },
         errors::{StreamHubError, StreamHubErrorValue},
         statistics::StatisticsStream,
         stream::StreamIdentifier,
+        timestamp::{TimestampNormalizer, DEFAULT_JUMP_THRESHOLD},
         utils::Uuid,
     },
-    tokio::sync::{mpsc, Mutex},
+    tokio::sync::{mpsc, mpsc::error::TrySendError, Mutex},
 };
 
 pub struct Common {
@@ -42,7 +44,7 @@ pub struct Common {
     //only Server Subscriber or Client Publisher needs to send out trunck data.
     packetizer: Option<ChunkPacketizer>,
 
-    data_receiver: FrameDataReceiver,
+    data_receiver: SubFrameDataReceiver,
     data_sender: FrameDataSender,
 
     event_producer: StreamHubEventSender,
@@ -52,9 +54,14 @@ pub struct Common {
     remote_addr: Option<SocketAddr>,
     /*request URL from client*/
     pub request_url: String,
+    /*which cached data is sent to the player before the live data*/
+    pub start_mode: SubscribeStartMode,
     pub stream_handler: Arc<RtmpStreamHandler>,
-    /* now used for subscriber session */
+    /* used for both the subscriber and publisher session */
     statistic_data_sender: Option<StatisticDataSender>,
+    /* correct the timestamps from the remote publisher */
+    timestamp_normalizer: TimestampNormalizer,
+    timestamp_jump_threshold: u32,
 }
 
 impl Common {
@@ -65,7 +72,8 @@ impl Common {
         remote_addr: Option<SocketAddr>,
     ) -> Self {
         //only used for init,since I don't found a better way to deal with this.
-        let (init_producer, init_consumer) = mpsc::unbounded_channel();
+        let (init_producer, _) = mpsc::unbounded_channel();
+        let (_, init_consumer) = mpsc::channel(1);
 
         Self {
             session_id: Uuid::new(streamhub::utils::RandomDigitCount::Four),
@@ -78,8 +86,11 @@ impl Common {
             session_type,
             remote_addr,
             request_url: String::default(),
+            start_mode: SubscribeStartMode::default(),
             stream_handler: Arc::new(RtmpStreamHandler::new()),
             statistic_data_sender: None,
+            timestamp_normalizer: TimestampNormalizer::default(),
+            timestamp_jump_threshold: DEFAULT_JUMP_THRESHOLD,
             //cache: None,
         }
     }
@@ -142,7 +153,7 @@ impl Common {
         }
     }
 
-    pub async fn send_audio(&mut self, data: BytesMut, timestamp: u32) -> Result<(), SessionError> {
+    pub async fn send_audio(&mut self, data: Bytes, timestamp: u32) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
             csid_type::AUDIO,
             chunk_type::TYPE_0,
@@ -150,17 +161,19 @@ impl Common {
             data.len() as u32,
             msg_type_id::AUDIO,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_chunk_with_payload(&mut chunk_info, &data[..])
+                .await?;
         }
 
         Ok(())
     }
 
-    pub async fn send_video(&mut self, data: BytesMut, timestamp: u32) -> Result<(), SessionError> {
+    pub async fn send_video(&mut self, data: Bytes, timestamp: u32) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
             csid_type::VIDEO,
             chunk_type::TYPE_0,
@@ -168,11 +181,13 @@ impl Common {
             data.len() as u32,
             msg_type_id::VIDEO,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_chunk_with_payload(&mut chunk_info, &data[..])
+                .await?;
         }
 
         Ok(())
@@ -180,7 +195,7 @@ impl Common {
 
     pub async fn send_metadata(
         &mut self,
-        data: BytesMut,
+        data: Bytes,
         timestamp: u32,
     ) -> Result<(), SessionError> {
         let mut chunk_info = ChunkInfo::new(
@@ -190,21 +205,58 @@ impl Common {
             data.len() as u32,
             msg_type_id::DATA_AMF0,
             0,
-            data,
+            BytesMut::new(),
         );
 
         if let Some(packetizer) = &mut self.packetizer {
-            packetizer.write_chunk(&mut chunk_info).await?;
+            packetizer
+                .write_chunk_with_payload(&mut chunk_info, &data[..])
+                .await?;
         }
 
         Ok(())
     }
 
+    /*Rebase the a/v timestamp of the remote publisher and correct the jumps,
+    the a/v data generated locally(e.g. remuxed) does not need it.*/
+    pub fn normalize_timestamp(&mut self, timestamp: u32) -> u32 {
+        let (normalized, correction) = self.timestamp_normalizer.normalize(timestamp);
+
+        if let Some(correction) = correction {
+            log::warn!(
+                "timestamp corrected: {:?}, {} -> {}",
+                correction,
+                timestamp,
+                normalized
+            );
+            if let Some(sender) = &self.statistic_data_sender {
+                if let Err(err) = sender.send(StatisticData::TimestampCorrection { correction }) {
+                    log::error!("send statistic_data err: {}", err);
+                }
+            }
+        }
+
+        normalized
+    }
+
+    /*The timestamp of the metadata and the data messages follows the a/v timestamps.*/
+    pub fn normalize_data_timestamp(&self, timestamp: u32) -> u32 {
+        self.timestamp_normalizer.convert(timestamp)
+    }
+
+    /*the max timestamp gap(ms) of two continuous frames, a larger one is corrected as a jump*/
+    pub fn set_timestamp_jump_threshold(&mut self, threshold: u32) {
+        self.timestamp_jump_threshold = threshold;
+        self.timestamp_normalizer = TimestampNormalizer::new(threshold);
+    }
+
     pub async fn on_video_data(
         &mut self,
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        //freeze the data once, the stream hub and the cache share it.
+        let data = data.split().freeze();
         let channel_data = FrameData::Video {
             timestamp: *timestamp,
             data: data.clone(),
@@ -221,7 +273,7 @@ impl Common {
         }
 
         self.stream_handler
-            .save_video_data(data, *timestamp)
+            .save_video_data(&data, *timestamp)
             .await?;
 
         Ok(())
@@ -232,6 +284,7 @@ impl Common {
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        let data = data.split().freeze();
         let channel_data = FrameData::Audio {
             timestamp: *timestamp,
             data: data.clone(),
@@ -248,7 +301,7 @@ impl Common {
         }
 
         self.stream_handler
-            .save_audio_data(data, *timestamp)
+            .save_audio_data(&data, *timestamp)
             .await?;
 
         Ok(())
@@ -259,6 +312,22 @@ impl Common {
         data: &mut BytesMut,
         timestamp: &u32,
     ) -> Result<(), SessionError> {
+        let data = data.split().freeze();
+        //the other data messages(e.g. onTextData) are also sent to the webrtc data channels,
+        //they are not cached as the metadata.
+        let data_message = amf_data_to_message(&data);
+        if let Some(message) = &data_message {
+            let channel_data = FrameData::DataMessage {
+                timestamp: *timestamp,
+                data: message.clone(),
+            };
+            if self.data_sender.send(channel_data).is_err() {
+                return Err(SessionError {
+                    value: SessionErrorValue::SendFrameDataErr,
+                });
+            }
+        }
+
         let channel_data = FrameData::MetaData {
             timestamp: *timestamp,
             data: data.clone(),
@@ -273,7 +342,9 @@ impl Common {
             }
         }
 
-        self.stream_handler.save_metadata(data, *timestamp).await;
+        if data_message.is_none() {
+            self.stream_handler.save_metadata(&data, *timestamp).await;
+        }
 
         Ok(())
     }
@@ -296,6 +367,7 @@ impl Common {
             and publish(relay) the rtmp steam to remote RTMP server*/
             sub_type,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: self.start_mode,
             notify_info: NotifyInfo {
                 request_url: self.request_url.clone(),
                 remote_addr,
@@ -407,7 +479,7 @@ impl Common {
         &mut self,
         app_name: String,
         stream_name: String,
-        gop_num: usize,
+        gop_limit: GopLimit,
     ) -> Result<(), SessionError> {
         let (event_result_sender, event_result_receiver) = oneshot::channel();
         let info = self.get_publisher_info();
@@ -433,6 +505,8 @@ impl Common {
         self.data_sender = result.0.unwrap();
 
         let statistic_data_sender: Option<StatisticDataSender> = result.2;
+        self.statistic_data_sender = statistic_data_sender.clone();
+        self.timestamp_normalizer = TimestampNormalizer::new(self.timestamp_jump_threshold);
 
         if let Some(sender) = &statistic_data_sender {
             let statistic_publisher = StatisticData::Publisher {
@@ -446,7 +520,7 @@ impl Common {
         }
 
         self.stream_handler
-            .set_cache(Cache::new(gop_num, statistic_data_sender))
+            .set_cache(Cache::new(gop_limit, statistic_data_sender))
             .await;
         Ok(())
     }
@@ -515,7 +589,7 @@ impl RtmpStreamHandler {
 
     pub async fn save_video_data(
         &self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         if let Some(cache) = &mut *self.cache.lock().await {
@@ -526,7 +600,7 @@ impl RtmpStreamHandler {
 
     pub async fn save_audio_data(
         &self,
-        chunk_body: &BytesMut,
+        chunk_body: &Bytes,
         timestamp: u32,
     ) -> Result<(), CacheError> {
         if let Some(cache) = &mut *self.cache.lock().await {
@@ -535,7 +609,7 @@ impl RtmpStreamHandler {
         Ok(())
     }
 
-    pub async fn save_metadata(&self, chunk_body: &BytesMut, timestamp: u32) {
+    pub async fn save_metadata(&self, chunk_body: &Bytes, timestamp: u32) {
         if let Some(cache) = &mut *self.cache.lock().await {
             cache.save_metadata(chunk_body, timestamp);
         }
@@ -548,6 +622,7 @@ impl TStreamHandler for RtmpStreamHandler {
         &self,
         data_sender: DataSender,
         sub_type: SubscribeType,
+        start_mode: SubscribeStartMode,
     ) -> Result<(), StreamHubError> {
         let sender = match data_sender {
             DataSender::Frame { sender } => sender,
@@ -560,21 +635,27 @@ impl TStreamHandler for RtmpStreamHandler {
         if let Some(cache) = &mut *self.cache.lock().await {
             if let Some(meta_body_data) = cache.get_metadata() {
                 log::info!("send_prior_data: meta_body_data: ");
-                sender.send(meta_body_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                sender
+                    .try_send(meta_body_data)
+                    .map_err(|_| StreamHubError {
+                        value: StreamHubErrorValue::SendError,
+                    })?;
             }
             if let Some(audio_seq_data) = cache.get_audio_seq() {
                 log::info!("send_prior_data: audio_seq_data: ",);
-                sender.send(audio_seq_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                sender
+                    .try_send(audio_seq_data)
+                    .map_err(|_| StreamHubError {
+                        value: StreamHubErrorValue::SendError,
+                    })?;
             }
             if let Some(video_seq_data) = cache.get_video_seq() {
                 log::info!("send_prior_data: video_seq_data:");
-                sender.send(video_seq_data).map_err(|_| StreamHubError {
-                    value: StreamHubErrorValue::SendError,
-                })?;
+                sender
+                    .try_send(video_seq_data)
+                    .map_err(|_| StreamHubError {
+                        value: StreamHubErrorValue::SendError,
+                    })?;
             }
             match sub_type {
                 SubscribeType::PlayerRtmp
@@ -582,11 +663,26 @@ impl TStreamHandler for RtmpStreamHandler {
                 | SubscribeType::PlayerHls
                 | SubscribeType::GenerateHls => {
                     if let Some(gops_data) = cache.get_gops_data() {
-                        for gop in gops_data {
+                        let skip_count = match start_mode {
+                            SubscribeStartMode::FullGop => 0,
+                            SubscribeStartMode::LatestKeyFrame => gops_data.len().saturating_sub(1),
+                            SubscribeStartMode::NoCache => gops_data.len(),
+                        };
+                        'gops: for gop in gops_data.iter().skip(skip_count) {
                             for channel_data in gop.get_frame_data() {
-                                sender.send(channel_data).map_err(|_| StreamHubError {
-                                    value: StreamHubErrorValue::SendError,
-                                })?;
+                                match sender.try_send(channel_data.clone()) {
+                                    Ok(()) => {}
+                                    //the left frames will be dropped until the next key frame.
+                                    Err(TrySendError::Full(_)) => {
+                                        log::warn!("send_prior_data: subscriber queue is full");
+                                        break 'gops;
+                                    }
+                                    Err(TrySendError::Closed(_)) => {
+                                        return Err(StreamHubError {
+                                            value: StreamHubErrorValue::SendError,
+                                        });
+                                    }
+                                }
                             }
                         }
                     }
@@ -613,3 +709,79 @@ impl fmt::Debug for Common {
         write!(fmt, "S2 {{ member: {:?} }}", self.request_url)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytesio::bytesio::{TNetIO, TcpIO};
+    use indexmap::IndexMap;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use xflv::amf0::{amf0_writer::Amf0Writer, Amf0ValueType};
+
+    #[tokio::test]
+    async fn test_data_message_to_rtmp_subscriber() {
+        let (event_producer, _event_consumer) = mpsc::unbounded_channel();
+
+        //the onTextData of the publisher is sent as a data message and the amf data
+        let mut properties = IndexMap::new();
+        properties.insert(
+            String::from("text"),
+            Amf0ValueType::UTF8String(String::from("hello")),
+        );
+        let mut amf_writer = Amf0Writer::new();
+        amf_writer
+            .write_string(&String::from("onTextData"))
+            .unwrap();
+        amf_writer.write_object(&properties).unwrap();
+        let amf_data = amf_writer.extract_current_bytes();
+
+        let mut publisher = Common::new(None, event_producer.clone(), SessionType::Server, None);
+        let (data_sender, mut data_receiver) = mpsc::unbounded_channel();
+        publisher.data_sender = data_sender;
+        *publisher.stream_handler.cache.lock().await = Some(Cache::new(GopLimit::new(1), None));
+        publisher
+            .on_meta_data(&mut amf_data.clone(), &1000)
+            .await
+            .unwrap();
+
+        match data_receiver.recv().await {
+            Some(FrameData::DataMessage { timestamp, data }) => {
+                assert_eq!(timestamp, 1000);
+                assert_eq!(&data[..], b"hello");
+            }
+            _ => panic!("the data message is not sent"),
+        }
+        let metadata = data_receiver.recv().await.unwrap();
+        match &metadata {
+            FrameData::MetaData { data, .. } => assert_eq!(&data[..], &amf_data[..]),
+            _ => panic!("the amf data is not sent"),
+        }
+        //the data message does not replace the cached metadata
+        if let Some(cache) = &*publisher.stream_handler.cache.lock().await {
+            assert!(cache.get_metadata().is_none());
+        }
+
+        //the rtmp subscriber writes the amf data out
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let io: Box<dyn TNetIO + Send + Sync> = Box::new(TcpIO::new(server));
+        let packetizer = ChunkPacketizer::new(Arc::new(Mutex::new(io)));
+        let mut subscriber =
+            Common::new(Some(packetizer), event_producer, SessionType::Server, None);
+        let (frame_sender, frame_receiver) = mpsc::channel(1);
+        subscriber.data_receiver = frame_receiver;
+        frame_sender.send(metadata).await.unwrap();
+        drop(frame_sender);
+        assert!(subscriber.send_channel_data().await.is_err());
+
+        let mut buffer = vec![0; 1024];
+        let len = client.read(&mut buffer).await.unwrap();
+        assert!(buffer[..len]
+            .windows(amf_data.len())
+            .any(|window| window == &amf_data[..]));
+    }
+}
@@ -0,0 +1,103 @@
+use {
+    bytes::{Bytes, BytesMut},
+    bytesio::bytes_reader::BytesReader,
+    indexmap::IndexMap,
+    serde_json::{Map, Number, Value},
+    xflv::amf0::{amf0_reader::Amf0Reader, Amf0ValueType},
+};
+
+//Convert an AMF data message of the rtmp publisher into a data channel message, it is the
+//text of onTextData, or the json array of the other ones, e.g. ["onCuePoint",{"time":1.5}].
+//None is returned for the metadata.
+pub fn amf_data_to_message(data: &Bytes) -> Option<Bytes> {
+    let reader = BytesReader::new(BytesMut::from(&data[..]));
+    let mut values = Amf0Reader::new(reader).read_all().ok()?;
+
+    if let Some(Amf0ValueType::UTF8String(name)) = values.first() {
+        if name == "@setDataFrame" {
+            values.remove(0);
+        }
+    }
+    let name = match values.first() {
+        Some(Amf0ValueType::UTF8String(name)) => name.clone(),
+        _ => return None,
+    };
+    if name == "onMetaData" {
+        return None;
+    }
+
+    if name == "onTextData" {
+        if let Some(Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties)) =
+            values.get(1)
+        {
+            if let Some(Amf0ValueType::UTF8String(text) | Amf0ValueType::LongUTF8String(text)) =
+                properties.get("text")
+            {
+                return Some(Bytes::from(text.clone()));
+            }
+        }
+    }
+
+    let values: Vec<Value> = values.iter().map(amf_to_json).collect();
+    Some(Bytes::from(Value::Array(values).to_string()))
+}
+
+fn amf_to_json(value: &Amf0ValueType) -> Value {
+    match value {
+        Amf0ValueType::Number(number) => Number::from_f64(*number)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        Amf0ValueType::Boolean(boolean) => Value::Bool(*boolean),
+        Amf0ValueType::UTF8String(string) | Amf0ValueType::LongUTF8String(string) => {
+            Value::String(string.clone())
+        }
+        Amf0ValueType::Object(properties) | Amf0ValueType::EcmaArray(properties) => {
+            Value::Object(properties_to_json(properties))
+        }
+        Amf0ValueType::Null | Amf0ValueType::END => Value::Null,
+    }
+}
+
+fn properties_to_json(properties: &IndexMap<String, Amf0ValueType>) -> Map<String, Value> {
+    properties
+        .iter()
+        .map(|(key, value)| (key.clone(), amf_to_json(value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::amf_data_to_message;
+    use indexmap::IndexMap;
+    use xflv::amf0::{amf0_writer::Amf0Writer, Amf0ValueType};
+
+    fn gen_amf_data(name: &str, properties: IndexMap<String, Amf0ValueType>) -> bytes::Bytes {
+        let mut amf_writer = Amf0Writer::new();
+        amf_writer.write_string(&String::from(name)).unwrap();
+        amf_writer.write_object(&properties).unwrap();
+        amf_writer.extract_current_bytes().freeze()
+    }
+
+    #[test]
+    fn test_amf_data_to_message() {
+        let mut properties = IndexMap::new();
+        properties.insert(
+            String::from("text"),
+            Amf0ValueType::UTF8String(String::from("hello")),
+        );
+        let message = amf_data_to_message(&gen_amf_data("onTextData", properties));
+        assert_eq!(message.as_deref(), Some(&b"hello"[..]));
+
+        let mut properties = IndexMap::new();
+        properties.insert(String::from("time"), Amf0ValueType::Number(1.5));
+        let message = amf_data_to_message(&gen_amf_data("onCuePoint", properties));
+        assert_eq!(
+            message.as_deref(),
+            Some(&br#"["onCuePoint",{"time":1.5}]"#[..])
+        );
+
+        let mut properties = IndexMap::new();
+        properties.insert(String::from("width"), Amf0ValueType::Number(1280.));
+        assert!(amf_data_to_message(&gen_amf_data("onMetaData", properties)).is_none());
+    }
+}
@@ -2,5 +2,6 @@
 pub mod define;
 pub mod errors;
 pub mod common;
+pub mod data_message;
 pub mod client_session;
 pub mod server_session;
@@ -4,6 +4,7 @@ use crate::chunk::{errors::UnpackErrorValue, packetizer::ChunkPacketizer};
 
 use {
     crate::{
+        cache::gop::GopCacheConfig,
         chunk::{
             define::CHUNK_SIZE,
             unpacketizer::{ChunkUnpacketizer, UnpackResult},
@@ -25,7 +26,7 @@ use {
     commonlib::auth::Auth,
     indexmap::IndexMap,
     std::{sync::Arc, time::Duration},
-    streamhub::define::StreamHubEventSender,
+    streamhub::define::{StreamHubEventSender, SubscribeStartMode},
     super::{
         common::Common,
         define,
@@ -58,8 +59,8 @@ pub struct ServerSession {
     has_remaing_data: bool,
     connect_properties: ConnectProperties,
     pub common: Common,
-    /*configure how many gops will be cached.*/
-    gop_num: usize,
+    /*configure the gop cache limits of every app.*/
+    gop_cache_config: GopCacheConfig,
     auth: Option<Auth>,
     publish_token: Option<String>,
     subscribe_token: Option<String>,
@@ -71,7 +72,7 @@ impl ServerSession {
     pub fn new(
         stream: TcpStream,
         event_producer: StreamHubEventSender,
-        gop_num: usize,
+        gop_cache_config: GopCacheConfig,
         auth: Option<Auth>,
         publish_token: Option<String>,
         subscribe_token: Option<String>,
@@ -106,7 +107,7 @@ impl ServerSession {
             bytesio_data: BytesMut::new(),
             has_remaing_data: false,
             connect_properties: ConnectProperties::default(),
-            gop_num,
+            gop_cache_config,
             auth,
             publish_token,
             subscribe_token,
@@ -267,13 +268,16 @@ impl ServerSession {
                 self.on_set_chunk_size(*chunk_size as usize)?;
             }
             RtmpMessageData::AudioData { data } => {
-                self.common.on_audio_data(data, timestamp).await?;
+                let timestamp = self.common.normalize_timestamp(*timestamp);
+                self.common.on_audio_data(data, &timestamp).await?;
             }
             RtmpMessageData::VideoData { data } => {
-                self.common.on_video_data(data, timestamp).await?;
+                let timestamp = self.common.normalize_timestamp(*timestamp);
+                self.common.on_video_data(data, &timestamp).await?;
             }
             RtmpMessageData::AmfData { raw_data } => {
-                self.common.on_meta_data(raw_data, timestamp).await?;
+                let timestamp = self.common.normalize_data_timestamp(*timestamp);
+                self.common.on_meta_data(raw_data, &timestamp).await?;
             }
 
             _ => {}
@@ -676,6 +680,10 @@ impl ServerSession {
             validate_nonce(&self.nonce_map, &nonce).await?;
         }
 
+        if let Some(query) = &self.query {
+            self.common.start_mode = SubscribeStartMode::from_query(query);
+        }
+
         /*Now it can update the request url*/
         self.common.request_url = self.get_request_url(raw_stream_name);
         self.common
@@ -781,7 +789,7 @@ impl ServerSession {
             .publish_to_channels(
                 self.app_name.clone(),
                 self.stream_name.clone(),
-                self.gop_num,
+                self.gop_cache_config.get_limit(&self.app_name),
             )
             .await?;
 
@@ -1,11 +1,16 @@
 pub mod global_trait;
 // pub mod http;
+pub mod relay;
 pub mod rtp;
 pub mod rtsp;
+pub mod rtsp_auth;
 pub mod rtsp_codec;
 pub mod rtsp_channel;
 pub mod rtsp_range;
+pub mod rtsp_sync;
+pub mod rtsp_tls;
 pub mod rtsp_track;
+pub mod rtsp_tunnel;
 pub mod rtsp_transport;
 pub mod rtsp_utils;
 pub mod sdp;
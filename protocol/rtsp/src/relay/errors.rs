@@ -0,0 +1,65 @@
+use {
+    crate::session::errors::SessionError,
+    failure::{Backtrace, Fail},
+    std::{fmt, io},
+    tokio::sync::broadcast::error::RecvError,
+};
+
+#[derive(Debug)]
+pub struct ClientError {
+    pub value: ClientErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum ClientErrorValue {
+    #[fail(display = "session error: {}", _0)]
+    SessionError(#[cause] SessionError),
+    #[fail(display = "invalid rtsp url: {}", _0)]
+    InvalidUrl(String),
+    #[fail(display = "the stream path is being pulled: {}", _0)]
+    StreamPathExists(String),
+    #[fail(display = "receive broadcast event error: {}", _0)]
+    ReceiveError(RecvError),
+    #[fail(display = "io error: {}", _0)]
+    IOError(#[cause] io::Error),
+}
+
+impl From<SessionError> for ClientError {
+    fn from(error: SessionError) -> Self {
+        ClientError {
+            value: ClientErrorValue::SessionError(error),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        ClientError {
+            value: ClientErrorValue::IOError(error),
+        }
+    }
+}
+
+impl From<RecvError> for ClientError {
+    fn from(error: RecvError) -> Self {
+        ClientError {
+            value: ClientErrorValue::ReceiveError(error),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for ClientError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod errors;
+pub mod pull_client;
+pub mod push_client;
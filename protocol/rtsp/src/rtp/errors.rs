@@ -95,3 +95,38 @@ impl Fail for UnPackerError {
         self.value.backtrace()
     }
 }
+
+#[derive(Debug)]
+pub struct SrtpError {
+    pub value: SrtpErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum SrtpErrorValue {
+    #[fail(display = "unsupported crypto suite: {}", _0)]
+    UnsupportedCryptoSuite(String),
+    #[fail(display = "invalid master key length")]
+    InvalidKeyLength,
+    #[fail(display = "the packet is too short")]
+    PacketTooShort,
+    #[fail(display = "authentication failed")]
+    AuthenticationFailed,
+    #[fail(display = "the packet is replayed")]
+    ReplayedPacket,
+}
+
+impl fmt::Display for SrtpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for SrtpError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
@@ -0,0 +1,254 @@
+use super::RtpPacket;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+//the time waiting for a missing packet(reordered or retransmitted) before it is treated as lost
+const MAX_DELAY: Duration = Duration::from_millis(200);
+//the missing packets are treated as lost if too many packets are buffered
+const MAX_BUFFERED_PACKETS: usize = 512;
+//the missing packets are treated as lost if they are too far behind the highest
+//sequence number(e.g. a large sequence jump), only the ones within it are requested
+const MAX_NACK_WINDOW: u64 = 512;
+//the interval of requesting a missing packet again
+const NACK_INTERVAL: Duration = Duration::from_millis(50);
+
+pub enum JitterBufferOutput {
+    Packet(RtpPacket),
+    //the number of the lost packets before the next output packet
+    Lost(u64),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JitterBufferStatistics {
+    pub lost_packets: usize,
+    //the missing packets which are received later(reordered or retransmitted)
+    pub recovered_packets: usize,
+}
+
+//Reorder the rtp packets received over udp by the sequence numbers, a missing
+//packet is waited for at most MAX_DELAY and requested by the NACKs meanwhile.
+pub struct JitterBuffer {
+    //key: the extended sequence number
+    packets: BTreeMap<u64, RtpPacket>,
+    //the extended sequence number of the next output packet
+    next_seq: Option<u64>,
+    highest_seq: u64,
+    //the time since the next output packet is missing
+    missing_since: Option<Instant>,
+    //the missing packets and the last time they are requested
+    nack_times: BTreeMap<u64, Instant>,
+    pub statistics: JitterBufferStatistics,
+}
+
+impl Default for JitterBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JitterBuffer {
+    pub fn new() -> Self {
+        Self {
+            packets: BTreeMap::new(),
+            next_seq: None,
+            highest_seq: 0,
+            missing_since: None,
+            nack_times: BTreeMap::new(),
+            statistics: JitterBufferStatistics::default(),
+        }
+    }
+
+    //extend the 16 bits sequence number to the one nearest to the highest sequence number
+    fn extend_seq(&self, seq: u16) -> u64 {
+        let mut extended = (self.highest_seq & !0xFFFF) | seq as u64;
+        if extended + 0x8000 < self.highest_seq {
+            extended += 0x10000;
+        } else if extended > self.highest_seq + 0x8000 && extended >= 0x10000 {
+            extended -= 0x10000;
+        }
+        extended
+    }
+
+    pub fn push(&mut self, packet: RtpPacket, now: Instant) -> Vec<JitterBufferOutput> {
+        let seq = match self.next_seq {
+            Some(next_seq) => {
+                let seq = self.extend_seq(packet.header.seq_number);
+                //too late, it has been treated as lost
+                if seq < next_seq || self.packets.contains_key(&seq) {
+                    return self.pop(now);
+                }
+                if seq < self.highest_seq {
+                    self.statistics.recovered_packets += 1;
+                }
+                seq
+            }
+            None => {
+                //start from the second cycle, so the extended sequence numbers never underflow
+                let seq = 0x10000 | packet.header.seq_number as u64;
+                self.next_seq = Some(seq);
+                seq
+            }
+        };
+
+        self.highest_seq = self.highest_seq.max(seq);
+        self.nack_times.remove(&seq);
+        self.packets.insert(seq, packet);
+
+        self.pop(now)
+    }
+
+    fn pop(&mut self, now: Instant) -> Vec<JitterBufferOutput> {
+        let mut outputs = Vec::new();
+
+        while let Some(next_seq) = self.next_seq {
+            if let Some(packet) = self.packets.remove(&next_seq) {
+                outputs.push(JitterBufferOutput::Packet(packet));
+                self.next_seq = Some(next_seq + 1);
+                self.missing_since = None;
+                continue;
+            }
+
+            let first_seq = match self.packets.keys().next() {
+                Some(seq) => *seq,
+                None => break,
+            };
+
+            let missing_since = *self.missing_since.get_or_insert(now);
+            if now.duration_since(missing_since) < MAX_DELAY
+                && self.packets.len() < MAX_BUFFERED_PACKETS
+                && self.highest_seq - next_seq < MAX_NACK_WINDOW
+            {
+                break;
+            }
+
+            let lost = first_seq - next_seq;
+            self.statistics.lost_packets += lost as usize;
+            outputs.push(JitterBufferOutput::Lost(lost));
+            self.next_seq = Some(first_seq);
+            self.missing_since = None;
+        }
+
+        if let Some(next_seq) = self.next_seq {
+            self.nack_times = self.nack_times.split_off(&next_seq);
+        }
+
+        outputs
+    }
+
+    //The missing sequence numbers which should be requested by the NACK.
+    pub fn get_nack_sequences(&mut self, now: Instant) -> Vec<u16> {
+        let mut sequences = Vec::new();
+
+        let next_seq = match self.next_seq {
+            Some(next_seq) => next_seq,
+            None => return sequences,
+        };
+
+        let first_seq = next_seq.max(self.highest_seq.saturating_sub(MAX_NACK_WINDOW));
+        for seq in first_seq..self.highest_seq {
+            if self.packets.contains_key(&seq) {
+                continue;
+            }
+            if let Some(nack_time) = self.nack_times.get(&seq) {
+                if now.duration_since(*nack_time) < NACK_INTERVAL {
+                    continue;
+                }
+            }
+            self.nack_times.insert(seq, now);
+            sequences.push(seq as u16);
+        }
+
+        sequences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JitterBuffer, JitterBufferOutput, MAX_DELAY, MAX_NACK_WINDOW};
+    use crate::rtp::rtp_header::RtpHeader;
+    use crate::rtp::RtpPacket;
+    use tokio::time::Instant;
+
+    fn new_packet(seq_number: u16) -> RtpPacket {
+        RtpPacket {
+            header: RtpHeader {
+                seq_number,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn output_seqs(outputs: Vec<JitterBufferOutput>) -> Vec<i64> {
+        outputs
+            .into_iter()
+            .map(|output| match output {
+                JitterBufferOutput::Packet(packet) => packet.header.seq_number as i64,
+                JitterBufferOutput::Lost(count) => -(count as i64),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_jitter_buffer() {
+        let mut jitter_buffer = JitterBuffer::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(65534), now)),
+            vec![65534]
+        );
+        //65535 is reordered and 0 is missing
+        assert!(jitter_buffer.push(new_packet(1), now).is_empty());
+        assert_eq!(jitter_buffer.get_nack_sequences(now), vec![65535, 0]);
+        assert!(jitter_buffer.get_nack_sequences(now).is_empty());
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(65535), now)),
+            vec![65535]
+        );
+
+        //0 is lost after waiting for MAX_DELAY
+        let later = now + MAX_DELAY;
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(2), later)),
+            vec![-1, 1, 2]
+        );
+        //the lost packet is too late
+        assert!(jitter_buffer.push(new_packet(0), later).is_empty());
+
+        assert_eq!(jitter_buffer.statistics.lost_packets, 1);
+        assert_eq!(jitter_buffer.statistics.recovered_packets, 1);
+    }
+
+    #[test]
+    fn test_jitter_buffer_sequence_jump() {
+        let mut jitter_buffer = JitterBuffer::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(100), now)),
+            vec![100]
+        );
+        assert!(jitter_buffer.push(new_packet(102), now).is_empty());
+        assert_eq!(jitter_buffer.get_nack_sequences(now), vec![101]);
+
+        //the gap is beyond the nack window, resync to the new sequence number
+        //without waiting for or requesting the missing packets
+        let seq = 102 + MAX_NACK_WINDOW as u16 + 1000;
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(seq), now)),
+            vec![-1, 102, -(MAX_NACK_WINDOW as i64 + 999), seq as i64]
+        );
+        assert!(jitter_buffer.get_nack_sequences(now).is_empty());
+
+        assert_eq!(
+            output_seqs(jitter_buffer.push(new_packet(seq + 1), now)),
+            vec![seq as i64 + 1]
+        );
+        assert_eq!(
+            jitter_buffer.statistics.lost_packets,
+            MAX_NACK_WINDOW as usize + 1000
+        );
+    }
+}
@@ -1,10 +1,13 @@
 pub mod define;
 pub mod errors;
+pub mod jitter_buffer;
 pub mod rtcp;
 pub mod rtp_aac;
+pub mod rtp_audio;
 pub mod rtp_h264;
 pub mod rtp_h265;
 pub mod rtp_header;
+pub mod srtp;
 pub mod utils;
 
 use byteorder::BigEndian;
@@ -3,6 +3,7 @@ pub mod rtcp_app;
 pub mod rtcp_bye;
 pub mod rtcp_context;
 pub mod rtcp_header;
+pub mod rtcp_nack;
 pub mod rtcp_rr;
 pub mod rtcp_sr;
 
@@ -11,3 +12,9 @@ pub const RTCP_RR: u8 = 201;
 pub const RTCP_SDES: u8 = 202;
 pub const RTCP_BYE: u8 = 203;
 pub const RTCP_APP: u8 = 204;
+//the transport layer feedback and its generic NACK format
+pub const RTCP_RTPFB: u8 = 205;
+pub const RTCP_RTPFB_NACK: u8 = 1;
+
+//the interval(seconds) of sending the sender reports
+pub const RTCP_SR_INTERVAL: u64 = 5;
@@ -8,7 +8,7 @@ use super::{
     rtcp_header::RtcpHeader,
     rtcp_rr::{ReportBlock, RtcpReceiverReport},
     rtcp_sr::RtcpSenderReport,
-    RTCP_RR,
+    RTCP_RR, RTCP_SR,
 };
 
 //For example: sequence numbers inserted are 65533, 65534, the new coming one is 2,
@@ -139,6 +139,41 @@ impl RtcpContext {
         }
     }
 
+    //the context of the rtp packets sent out, used to generate the sender reports
+    pub fn new_sender(ssrc: u32, sample_rate: u32) -> Self {
+        RtcpContext {
+            ssrc,
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    //the context of the received rtp packets, used to generate the receiver reports
+    pub fn new_receiver(ssrc: u32, sample_rate: u32) -> Self {
+        RtcpContext {
+            ssrc,
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    //the ssrc of the received rtp packets
+    pub fn get_sender_ssrc(&self) -> u32 {
+        self.sender_ssrc
+    }
+
+    //the interarrival jitter in milliseconds
+    pub fn get_jitter(&self) -> u32 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        (self.source.jitter * 1000. / self.sample_rate as f64) as u32
+    }
+
     pub fn generate_app(&self, name: String, data: BytesMut) -> RtcpApp {
         let mut buf = BytesMut::with_capacity(name.len());
         buf.extend_from_slice(name.as_bytes());
@@ -212,9 +247,39 @@ impl RtcpContext {
         }
     }
 
+    //https://www.rfc-editor.org/rfc/rfc3550#section-6.4.1
+    pub fn generate_sr(&self) -> Option<RtcpSenderReport> {
+        if self.send_packets == 0 {
+            return None;
+        }
+
+        //the rtp timestamp corresponding to the ntp time is extrapolated from
+        //the last rtp packet sent out.
+        let elapsed = utils::current_time().saturating_sub(self.last_rtp_clock);
+        let rtp_timestamp = self
+            .last_rtp_timestamp
+            .wrapping_add((elapsed * self.sample_rate as u64 / 1000000) as u32);
+
+        Some(RtcpSenderReport {
+            header: RtcpHeader {
+                payload_type: RTCP_SR,
+                version: 2,
+                length: (4 + 20) / 4,
+                ..Default::default()
+            },
+            ssrc: self.ssrc,
+            ntp: utils::current_ntp_time(),
+            rtp_timestamp,
+            sender_packet_count: self.send_packets as u32,
+            sender_octet_count: self.send_bytes as u32,
+            report_blocks: Vec::new(),
+        })
+    }
+
     pub fn send_rtp(&mut self, pkt: RtpPacket) {
         self.send_bytes += pkt.payload.len() as u64;
         self.send_packets += 1;
+        self.last_rtp_clock = utils::current_time();
         self.last_rtp_timestamp = pkt.header.timestamp;
     }
 
@@ -226,6 +291,7 @@ impl RtcpContext {
     }
 
     pub fn received_rtp(&mut self, pkt: RtpPacket) {
+        self.sender_ssrc = pkt.header.ssrc;
         if 0 == self.source.update_sequence(pkt.header.seq_number) {
             return;
         }
@@ -234,9 +300,9 @@ impl RtcpContext {
         if self.last_rtp_clock == 0 {
             self.source.jitter = 0.;
         } else {
-            let mut d = ((rtp_clock - self.last_rtp_clock) * self.sample_rate as u64 / 1000000)
-                as i64
-                - (pkt.header.timestamp - self.last_rtp_timestamp) as i64;
+            let mut d = (rtp_clock.saturating_sub(self.last_rtp_clock) * self.sample_rate as u64
+                / 1000000) as i64
+                - pkt.header.timestamp.wrapping_sub(self.last_rtp_timestamp) as i32 as i64;
 
             if d < 0 {
                 d = -d;
@@ -0,0 +1,138 @@
+use super::errors::RtcpError;
+use super::rtcp_header::RtcpHeader;
+use super::{RTCP_RTPFB, RTCP_RTPFB_NACK};
+use crate::rtp::utils::Marshal;
+use crate::rtp::utils::Unmarshal;
+use byteorder::BigEndian;
+use bytes::BytesMut;
+use bytesio::bytes_reader::BytesReader;
+use bytesio::bytes_writer::BytesWriter;
+
+//https://www.rfc-editor.org/rfc/rfc4585#section-6.2.1
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |V=2|P| FMT=1   |   PT=205      |          length               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                  SSRC of packet sender                        |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                  SSRC of media source                         |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |            PID                |             BLP               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// :                              ...                              :
+#[derive(Debug, Clone, Default)]
+pub struct RtcpNack {
+    pub header: RtcpHeader,
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    //the packet id and the bitmask of the following lost packets
+    pub nack_pairs: Vec<(u16, u16)>,
+}
+
+impl RtcpNack {
+    //the sequence numbers should be in ascending order
+    pub fn new(sender_ssrc: u32, media_ssrc: u32, lost_sequences: &[u16]) -> Self {
+        let mut nack_pairs: Vec<(u16, u16)> = Vec::new();
+
+        for seq in lost_sequences {
+            if let Some((pid, blp)) = nack_pairs.last_mut() {
+                let distance = seq.wrapping_sub(*pid);
+                if (1..=16).contains(&distance) {
+                    *blp |= 1 << (distance - 1);
+                    continue;
+                }
+            }
+            nack_pairs.push((*seq, 0));
+        }
+
+        RtcpNack {
+            header: RtcpHeader {
+                version: 2,
+                report_count: RTCP_RTPFB_NACK,
+                payload_type: RTCP_RTPFB,
+                length: 2 + nack_pairs.len() as u16,
+                ..Default::default()
+            },
+            sender_ssrc,
+            media_ssrc,
+            nack_pairs,
+        }
+    }
+
+    pub fn lost_sequences(&self) -> Vec<u16> {
+        let mut sequences = Vec::new();
+        for (pid, blp) in &self.nack_pairs {
+            sequences.push(*pid);
+            for i in 0..16 {
+                if blp & (1 << i) != 0 {
+                    sequences.push(pid.wrapping_add(i + 1));
+                }
+            }
+        }
+        sequences
+    }
+}
+
+impl Unmarshal<&mut BytesReader, Result<Self, RtcpError>> for RtcpNack {
+    fn unmarshal(reader: &mut BytesReader) -> Result<Self, RtcpError>
+    where
+        Self: Sized,
+    {
+        let mut rtcp_nack = RtcpNack {
+            header: RtcpHeader::unmarshal(reader)?,
+            sender_ssrc: reader.read_u32::<BigEndian>()?,
+            media_ssrc: reader.read_u32::<BigEndian>()?,
+            ..Default::default()
+        };
+
+        for _ in 2..rtcp_nack.header.length {
+            let pid = reader.read_u16::<BigEndian>()?;
+            let blp = reader.read_u16::<BigEndian>()?;
+            rtcp_nack.nack_pairs.push((pid, blp));
+        }
+
+        Ok(rtcp_nack)
+    }
+}
+
+impl Marshal<Result<BytesMut, RtcpError>> for RtcpNack {
+    fn marshal(&self) -> Result<BytesMut, RtcpError> {
+        let mut writer = BytesWriter::default();
+
+        let header_bytesmut = self.header.marshal()?;
+        writer.write(&header_bytesmut[..])?;
+
+        writer.write_u32::<BigEndian>(self.sender_ssrc)?;
+        writer.write_u32::<BigEndian>(self.media_ssrc)?;
+
+        for (pid, blp) in &self.nack_pairs {
+            writer.write_u16::<BigEndian>(*pid)?;
+            writer.write_u16::<BigEndian>(*blp)?;
+        }
+
+        Ok(writer.extract_current_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtcpNack;
+    use crate::rtp::utils::{Marshal, Unmarshal};
+    use bytesio::bytes_reader::BytesReader;
+
+    #[test]
+    fn test_marshal_unmarshal_nack() {
+        let lost_sequences = vec![65534, 65535, 0, 14, 15, 40];
+        let nack = RtcpNack::new(1, 2, &lost_sequences);
+        assert_eq!(nack.nack_pairs, vec![(65534, 0x8003), (15, 0), (40, 0)]);
+
+        let data = nack.marshal().unwrap();
+        assert_eq!(data.len(), 12 + 4 * 3);
+
+        let mut reader = BytesReader::new(data);
+        let nack = RtcpNack::unmarshal(&mut reader).unwrap();
+        assert_eq!(nack.media_ssrc, 2);
+        assert_eq!(nack.lost_sequences(), lost_sequences);
+    }
+}
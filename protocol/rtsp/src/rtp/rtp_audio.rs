@@ -0,0 +1,171 @@
+use super::errors::PackerError;
+use super::errors::UnPackerError;
+use super::utils::OnFrameFn;
+use super::utils::OnRtpPacketFn;
+use super::utils::OnRtpPacketFn2;
+use super::utils::TPacker;
+
+use super::utils::TRtpReceiverForRtcp;
+use super::utils::TUnPacker;
+use super::utils::Unmarshal;
+use super::RtpHeader;
+use super::RtpPacket;
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes};
+
+use bytesio::bytes_reader::BytesReader;
+use bytesio::bytesio::TNetIO;
+use std::sync::Arc;
+use streamhub::define::FrameData;
+use tokio::sync::Mutex;
+
+// The audio payloads without payload header, one frame is carried in one rtp packet:
+// PCMA/PCMU(https://www.rfc-editor.org/rfc/rfc3551#section-4.5.14),
+// G726(https://www.rfc-editor.org/rfc/rfc3551#section-4.5.4),
+// Opus(https://www.rfc-editor.org/rfc/rfc7587#section-4.2).
+pub struct RtpAudioPacker {
+    header: RtpHeader,
+    on_packet_handler: Option<OnRtpPacketFn>,
+    on_packet_for_rtcp_handler: Option<OnRtpPacketFn2>,
+    io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
+}
+
+impl RtpAudioPacker {
+    pub fn new(
+        payload_type: u8,
+        ssrc: u32,
+        init_seq: u16,
+        io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
+    ) -> Self {
+        RtpAudioPacker {
+            header: RtpHeader {
+                payload_type,
+                seq_number: init_seq,
+                ssrc,
+                version: 2,
+                ..Default::default()
+            },
+            io,
+            on_packet_handler: None,
+            on_packet_for_rtcp_handler: None,
+        }
+    }
+}
+
+#[async_trait]
+impl TPacker for RtpAudioPacker {
+    async fn pack(&mut self, data: Bytes, timestamp: u32) -> Result<(), PackerError> {
+        self.header.timestamp = timestamp;
+
+        let mut packet = RtpPacket::new(self.header.clone());
+        packet.payload.put(data);
+
+        if let Some(f) = &self.on_packet_for_rtcp_handler {
+            f(packet.clone()).await;
+        }
+
+        if let Some(f) = &self.on_packet_handler {
+            f(self.io.clone(), packet).await?;
+        }
+
+        self.header.seq_number = self.header.seq_number.wrapping_add(1);
+
+        Ok(())
+    }
+
+    fn on_packet_handler(&mut self, f: OnRtpPacketFn) {
+        self.on_packet_handler = Some(f);
+    }
+
+    fn get_seq_number(&self) -> u16 {
+        self.header.seq_number
+    }
+}
+
+impl TRtpReceiverForRtcp for RtpAudioPacker {
+    fn on_packet_for_rtcp_handler(&mut self, f: OnRtpPacketFn2) {
+        self.on_packet_for_rtcp_handler = Some(f);
+    }
+}
+
+#[derive(Default)]
+pub struct RtpAudioUnPacker {
+    on_frame_handler: Option<OnFrameFn>,
+    on_packet_for_rtcp_handler: Option<OnRtpPacketFn2>,
+}
+
+impl RtpAudioUnPacker {
+    pub fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl TUnPacker for RtpAudioUnPacker {
+    async fn unpack(&mut self, reader: &mut BytesReader) -> Result<(), UnPackerError> {
+        let rtp_packet = RtpPacket::unmarshal(reader)?;
+
+        if let Some(f) = &self.on_packet_for_rtcp_handler {
+            f(rtp_packet.clone()).await;
+        }
+
+        if rtp_packet.payload.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(f) = &self.on_frame_handler {
+            f(FrameData::Audio {
+                timestamp: rtp_packet.header.timestamp,
+                data: rtp_packet.payload.freeze(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn on_frame_handler(&mut self, f: OnFrameFn) {
+        self.on_frame_handler = Some(f);
+    }
+}
+
+impl TRtpReceiverForRtcp for RtpAudioUnPacker {
+    fn on_packet_for_rtcp_handler(&mut self, f: OnRtpPacketFn2) {
+        self.on_packet_for_rtcp_handler = Some(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::utils::Marshal;
+    use std::sync::Mutex as StdMutex;
+
+    #[tokio::test]
+    async fn test_unpack_pcma() {
+        let mut packet = RtpPacket::new(RtpHeader {
+            payload_type: 8,
+            seq_number: 1,
+            timestamp: 160,
+            ssrc: 1234,
+            version: 2,
+            ..Default::default()
+        });
+        packet.payload.put(&[0xD5; 160][..]);
+
+        let frames = Arc::new(StdMutex::new(Vec::new()));
+        let frames_in = frames.clone();
+        let mut unpacker = RtpAudioUnPacker::new();
+        unpacker.on_frame_handler(Box::new(move |frame: FrameData| {
+            if let FrameData::Audio { timestamp, data } = frame {
+                frames_in.lock().unwrap().push((timestamp, data.len()));
+            }
+            Ok(())
+        }));
+
+        let mut reader = BytesReader::new(packet.marshal().unwrap());
+        unpacker.unpack(&mut reader).await.unwrap();
+        assert_eq!(*frames.lock().unwrap(), vec![(160, 160)]);
+    }
+}
@@ -14,7 +14,8 @@ use super::RtpHeader;
 use super::RtpPacket;
 use async_trait::async_trait;
 use byteorder::BigEndian;
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytesio::bytes_errors::{BytesReadError, BytesReadErrorValue};
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
@@ -52,14 +53,18 @@ impl RtpH264Packer {
         }
     }
 
-    pub async fn pack_fu_a(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
-        let mut nalu_reader = BytesReader::new(nalu);
-        let byte_1st = nalu_reader.read_u8()?;
+    pub async fn pack_fu_a(&mut self, mut nalu: Bytes) -> Result<(), PackerError> {
+        if nalu.is_empty() {
+            return Err(PackerError::from(BytesReadError {
+                value: BytesReadErrorValue::NotEnoughBytes,
+            }));
+        }
+        let byte_1st = nalu.get_u8();
 
         let fu_indicator: u8 = (byte_1st & 0xE0) | define::FU_A;
         let mut fu_header: u8 = (byte_1st & 0x1F) | define::FU_START;
 
-        let mut left_nalu_bytes: usize = nalu_reader.len();
+        let mut left_nalu_bytes: usize = nalu.len();
         let mut fu_payload_len: usize;
 
         while left_nalu_bytes > 0 {
@@ -70,7 +75,7 @@ impl RtpH264Packer {
                 fu_payload_len = self.mtu - define::RTP_FIXED_HEADER_LEN - 2;
             }
 
-            let fu_payload = nalu_reader.read_bytes(fu_payload_len)?;
+            let fu_payload = nalu.split_to(fu_payload_len);
 
             let mut packet = RtpPacket::new(self.header.clone());
             packet.payload.put_u8(fu_indicator);
@@ -92,13 +97,13 @@ impl RtpH264Packer {
                 f(self.io.clone(), packet).await?;
             }
 
-            left_nalu_bytes = nalu_reader.len();
+            left_nalu_bytes = nalu.len();
             self.header.seq_number += 1;
         }
 
         Ok(())
     }
-    pub async fn pack_single(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    pub async fn pack_single(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         let mut packet = RtpPacket::new(self.header.clone());
         packet.header.marker = 1;
         packet.payload.put(nalu);
@@ -121,7 +126,7 @@ impl RtpH264Packer {
 #[async_trait]
 impl TPacker for RtpH264Packer {
     //pack annexb h264 data
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError> {
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         self.header.timestamp = timestamp; // ((timestamp as u64 * self.clock_rate as u64) / 1000) as u32;
         utils::split_annexb_and_process(nalus, self).await?;
         Ok(())
@@ -130,6 +135,10 @@ impl TPacker for RtpH264Packer {
     fn on_packet_handler(&mut self, f: OnRtpPacketFn) {
         self.on_packet_handler = Some(f);
     }
+
+    fn get_seq_number(&self) -> u16 {
+        self.header.seq_number
+    }
 }
 
 impl TRtpReceiverForRtcp for RtpH264Packer {
@@ -140,7 +149,7 @@ impl TRtpReceiverForRtcp for RtpH264Packer {
 
 #[async_trait]
 impl TVideoPacker for RtpH264Packer {
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         if nalu.len() + define::RTP_FIXED_HEADER_LEN <= self.mtu {
             self.pack_single(nalu).await?;
         } else {
@@ -195,6 +204,10 @@ impl TUnPacker for RtpH264UnPacker {
     fn on_frame_handler(&mut self, f: OnFrameFn) {
         self.on_frame_handler = Some(f);
     }
+
+    fn on_packet_lost(&mut self) {
+        self.fu_buffer.clear();
+    }
 }
 
 impl RtpH264UnPacker {
@@ -216,7 +229,7 @@ impl RtpH264UnPacker {
 
             f(FrameData::Video {
                 timestamp: self.timestamp,
-                data: annexb_payload,
+                data: annexb_payload.freeze(),
             })?;
         }
         Ok(())
@@ -276,7 +289,13 @@ impl RtpH264UnPacker {
             payload_reader.read_u16::<BigEndian>()?;
         }
 
+        //the start fragment is lost, drop the remaining ones
+        if !utils::is_fu_start(fu_header) && self.fu_buffer.is_empty() {
+            return Ok(());
+        }
+
         if utils::is_fu_start(fu_header) {
+            self.fu_buffer.clear();
             self.fu_buffer
                 .put_u8((fu_indicator & 0xE0) | (fu_header & 0x1F))
         }
@@ -291,7 +310,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -366,7 +385,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -460,7 +479,7 @@ impl RtpH264UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -14,7 +14,8 @@ use super::RtpHeader;
 use super::RtpPacket;
 use async_trait::async_trait;
 use byteorder::BigEndian;
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytesio::bytes_errors::{BytesReadError, BytesReadErrorValue};
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
@@ -52,8 +53,7 @@ impl RtpH265Packer {
         }
     }
 
-    pub async fn pack_fu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
-        let mut nalu_reader = BytesReader::new(nalu);
+    pub async fn pack_fu(&mut self, mut nalu: Bytes) -> Result<(), PackerError> {
         /* NALU header
         0               1
         0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5
@@ -66,8 +66,13 @@ impl RtpH265Packer {
         NUH layer ID(LayerId) : 6 bits
         NUH temporal ID plus 1 (TID) : 3 bits
         */
-        let nalu_header_1st_byte = nalu_reader.read_u8()?;
-        let nalu_header_2nd_byte = nalu_reader.read_u8()?;
+        if nalu.len() < 2 {
+            return Err(PackerError::from(BytesReadError {
+                value: BytesReadErrorValue::NotEnoughBytes,
+            }));
+        }
+        let nalu_header_1st_byte = nalu.get_u8();
+        let nalu_header_2nd_byte = nalu.get_u8();
 
         /* The PayloadHdr needs replace Type with the FU type value(49) */
         let payload_hdr: u16 = ((nalu_header_1st_byte as u16 & 0x81) | ((define::FU as u16) << 1))
@@ -83,7 +88,7 @@ impl RtpH265Packer {
         /*set FuType from NALU header's Type */
         let mut fu_header = (nalu_header_1st_byte >> 1) & 0x3F | define::FU_START;
 
-        let mut left_nalu_bytes: usize = nalu_reader.len();
+        let mut left_nalu_bytes: usize = nalu.len();
         let mut fu_payload_len: usize;
 
         while left_nalu_bytes > 0 {
@@ -95,7 +100,7 @@ impl RtpH265Packer {
                 fu_payload_len = self.mtu - define::RTP_FIXED_HEADER_LEN - 3;
             }
 
-            let fu_payload = nalu_reader.read_bytes(fu_payload_len)?;
+            let fu_payload = nalu.split_to(fu_payload_len);
 
             let mut packet = RtpPacket::new(self.header.clone());
             packet.payload.put_u16(payload_hdr);
@@ -114,13 +119,13 @@ impl RtpH265Packer {
             if let Some(f) = &self.on_packet_handler {
                 f(self.io.clone(), packet).await?;
             }
-            left_nalu_bytes = nalu_reader.len();
+            left_nalu_bytes = nalu.len();
             self.header.seq_number += 1;
         }
 
         Ok(())
     }
-    pub async fn pack_single(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    pub async fn pack_single(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         let mut packet = RtpPacket::new(self.header.clone());
         packet.header.marker = 1;
         packet.payload.put(nalu);
@@ -140,7 +145,7 @@ impl RtpH265Packer {
 
 #[async_trait]
 impl TPacker for RtpH265Packer {
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError> {
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         self.header.timestamp = timestamp;
         utils::split_annexb_and_process(nalus, self).await?;
         Ok(())
@@ -148,6 +153,10 @@ impl TPacker for RtpH265Packer {
     fn on_packet_handler(&mut self, f: OnRtpPacketFn) {
         self.on_packet_handler = Some(f);
     }
+
+    fn get_seq_number(&self) -> u16 {
+        self.header.seq_number
+    }
 }
 
 impl TRtpReceiverForRtcp for RtpH265Packer {
@@ -158,7 +167,7 @@ impl TRtpReceiverForRtcp for RtpH265Packer {
 
 #[async_trait]
 impl TVideoPacker for RtpH265Packer {
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError> {
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError> {
         if nalu.len() + define::RTP_FIXED_HEADER_LEN <= self.mtu {
             self.pack_single(nalu).await?;
         } else {
@@ -212,6 +221,10 @@ impl TUnPacker for RtpH265UnPacker {
     fn on_frame_handler(&mut self, f: OnFrameFn) {
         self.on_frame_handler = Some(f);
     }
+
+    fn on_packet_lost(&mut self) {
+        self.fu_buffer.clear();
+    }
 }
 
 impl RtpH265UnPacker {
@@ -227,7 +240,7 @@ impl RtpH265UnPacker {
         if let Some(f) = &self.on_frame_handler {
             f(FrameData::Video {
                 timestamp: self.timestamp,
-                data: annexb_payload,
+                data: annexb_payload.freeze(),
             })?;
         }
         Ok(())
@@ -279,7 +292,7 @@ impl RtpH265UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
@@ -329,7 +342,13 @@ impl RtpH265UnPacker {
             payload_reader.read_bytes(2)?;
         }
 
+        //the start fragment is lost, drop the remaining ones
+        if !utils::is_fu_start(fu_header) && self.fu_buffer.is_empty() {
+            return Ok(());
+        }
+
         if utils::is_fu_start(fu_header) {
+            self.fu_buffer.clear();
             /*set NAL UNIT type 2 bytes */
             //replace Type of PayloadHdr with the FuType of FU header
             let nal_1st_byte = (payload_header_1st_byte & 0x81) | ((fu_header & 0x3F) << 1);
@@ -348,7 +367,7 @@ impl RtpH265UnPacker {
             if let Some(f) = &self.on_frame_handler {
                 f(FrameData::Video {
                     timestamp: self.timestamp,
-                    data: payload,
+                    data: payload.freeze(),
                 })?;
             }
         }
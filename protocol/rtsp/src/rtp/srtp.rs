@@ -0,0 +1,598 @@
+use super::errors::{SrtpError, SrtpErrorValue};
+use aes::Aes128;
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use bytesio::bytesio::{NetType, TNetIO};
+use bytesio::bytesio_errors::{BytesIOError, BytesIOErrorValue};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::time::Duration;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+type HmacSha1 = Hmac<Sha1>;
+
+//https://www.rfc-editor.org/rfc/rfc4568#section-6.2
+pub const AES_CM_128_HMAC_SHA1_80: &str = "AES_CM_128_HMAC_SHA1_80";
+pub const AES_CM_128_HMAC_SHA1_32: &str = "AES_CM_128_HMAC_SHA1_32";
+
+pub const MASTER_KEY_LEN: usize = 16;
+pub const MASTER_SALT_LEN: usize = 14;
+const AUTH_KEY_LEN: usize = 20;
+//the srtcp auth tag is 80 bits for both suites
+const SRTCP_AUTH_TAG_LEN: usize = 10;
+const SRTCP_INDEX_LEN: usize = 4;
+const RTP_HEADER_LEN: usize = 12;
+const RTCP_HEADER_LEN: usize = 8;
+//https://www.rfc-editor.org/rfc/rfc3711#section-3.3.2
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+//https://www.rfc-editor.org/rfc/rfc3711#section-4.3.1
+const LABEL_RTP_ENCRYPTION: u8 = 0x00;
+const LABEL_RTP_AUTHENTICATION: u8 = 0x01;
+const LABEL_RTP_SALT: u8 = 0x02;
+const LABEL_RTCP_ENCRYPTION: u8 = 0x03;
+const LABEL_RTCP_AUTHENTICATION: u8 = 0x04;
+const LABEL_RTCP_SALT: u8 = 0x05;
+
+struct SessionKeys {
+    cipher_key: Vec<u8>,
+    auth_key: Vec<u8>,
+    salt: Vec<u8>,
+}
+
+impl SessionKeys {
+    fn derive(master_key: &[u8], master_salt: &[u8], labels: [u8; 3]) -> Self {
+        Self {
+            cipher_key: derive_key(master_key, master_salt, labels[0], MASTER_KEY_LEN),
+            auth_key: derive_key(master_key, master_salt, labels[1], AUTH_KEY_LEN),
+            salt: derive_key(master_key, master_salt, labels[2], MASTER_SALT_LEN),
+        }
+    }
+}
+
+//the key derivation rate is 0, so the key id is the label only
+fn derive_key(master_key: &[u8], master_salt: &[u8], label: u8, length: usize) -> Vec<u8> {
+    let mut iv = [0_u8; 16];
+    iv[..MASTER_SALT_LEN].copy_from_slice(master_salt);
+    iv[7] ^= label;
+
+    let mut key = vec![0_u8; length];
+    Aes128Ctr::new(master_key.into(), &iv.into()).apply_keystream(&mut key);
+    key
+}
+
+//IV = (k_s * 2^16) XOR (SSRC * 2^64) XOR (i * 2^16)
+fn apply_keystream(keys: &SessionKeys, ssrc: u32, index: u64, data: &mut [u8]) {
+    let mut iv = [0_u8; 16];
+    iv[..MASTER_SALT_LEN].copy_from_slice(&keys.salt);
+    for (i, b) in ssrc.to_be_bytes().iter().enumerate() {
+        iv[4 + i] ^= b;
+    }
+    for (i, b) in index.to_be_bytes()[2..].iter().enumerate() {
+        iv[8 + i] ^= b;
+    }
+    Aes128Ctr::new(keys.cipher_key[..].into(), &iv.into()).apply_keystream(data);
+}
+
+fn new_auth_mac(keys: &SessionKeys, data: &[u8], roc: Option<u32>) -> HmacSha1 {
+    let mut mac = HmacSha1::new_from_slice(&keys.auth_key).unwrap();
+    mac.update(data);
+    if let Some(roc) = roc {
+        mac.update(&roc.to_be_bytes());
+    }
+    mac
+}
+
+fn gen_auth_tag(keys: &SessionKeys, data: &[u8], roc: Option<u32>) -> Vec<u8> {
+    new_auth_mac(keys, data, roc)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+//the truncated tag is compared in constant time
+fn verify_auth_tag(keys: &SessionKeys, data: &[u8], roc: Option<u32>, auth_tag: &[u8]) -> bool {
+    new_auth_mac(keys, data, roc)
+        .verify_truncated_left(auth_tag)
+        .is_ok()
+}
+
+fn rtp_header_length(packet: &[u8]) -> Result<usize, SrtpError> {
+    if packet.len() < RTP_HEADER_LEN {
+        return Err(SrtpError {
+            value: SrtpErrorValue::PacketTooShort,
+        });
+    }
+    let csrc_count = (packet[0] & 0x0F) as usize;
+    let mut length = RTP_HEADER_LEN + csrc_count * 4;
+    //header extension
+    if packet[0] & 0x10 > 0 {
+        if packet.len() < length + 4 {
+            return Err(SrtpError {
+                value: SrtpErrorValue::PacketTooShort,
+            });
+        }
+        let extension_length = u16::from_be_bytes([packet[length + 2], packet[length + 3]]);
+        length += 4 + extension_length as usize * 4;
+    }
+    if packet.len() < length {
+        return Err(SrtpError {
+            value: SrtpErrorValue::PacketTooShort,
+        });
+    }
+    Ok(length)
+}
+
+#[derive(Clone, Copy)]
+struct RolloverState {
+    roc: u32,
+    highest_seq: u16,
+}
+
+impl RolloverState {
+    //https://www.rfc-editor.org/rfc/rfc3711#section-3.3.1
+    fn estimate_roc(&self, seq: u16) -> u32 {
+        let (s_l, seq) = (self.highest_seq as i32, seq as i32);
+        if s_l < 0x8000 {
+            if seq - s_l > 0x8000 {
+                return self.roc.wrapping_sub(1);
+            }
+        } else if s_l - 0x8000 > seq {
+            return self.roc.wrapping_add(1);
+        }
+        self.roc
+    }
+
+    fn update(&mut self, roc: u32, seq: u16) {
+        if roc == self.roc.wrapping_add(1) || (roc == self.roc && seq > self.highest_seq) {
+            self.roc = roc;
+            self.highest_seq = seq;
+        }
+    }
+}
+
+//The received packet indexes of one SSRC, the bit i of the window is set if the packet
+//with the index of (highest_index - i) is received.
+#[derive(Default)]
+struct ReplayWindow {
+    highest_index: u64,
+    window: u64,
+}
+
+impl ReplayWindow {
+    fn is_replayed(&self, index: u64) -> bool {
+        if index > self.highest_index {
+            return false;
+        }
+        let delta = self.highest_index - index;
+        //the packets older than the window are treated as replayed
+        delta >= REPLAY_WINDOW_SIZE || self.window & (1 << delta) > 0
+    }
+
+    //called after the packet is authenticated
+    fn update(&mut self, index: u64) {
+        if index > self.highest_index || self.window == 0 {
+            let shift = index.saturating_sub(self.highest_index);
+            self.window = if shift < REPLAY_WINDOW_SIZE {
+                self.window << shift | 1
+            } else {
+                1
+            };
+            self.highest_index = index;
+        } else {
+            self.window |= 1 << (self.highest_index - index);
+        }
+    }
+}
+
+//The SDES(a=crypto) master key of a track.
+#[derive(Debug, Clone)]
+pub struct SrtpKey {
+    crypto_suite: String,
+    //the master key is followed by the master salt
+    master_key_salt: Vec<u8>,
+}
+
+impl SrtpKey {
+    pub fn new(crypto_suite: &str, master_key_salt: Vec<u8>) -> Result<Self, SrtpError> {
+        if get_rtp_auth_tag_len(crypto_suite).is_none() {
+            return Err(SrtpError {
+                value: SrtpErrorValue::UnsupportedCryptoSuite(crypto_suite.to_string()),
+            });
+        }
+        if master_key_salt.len() != MASTER_KEY_LEN + MASTER_SALT_LEN {
+            return Err(SrtpError {
+                value: SrtpErrorValue::InvalidKeyLength,
+            });
+        }
+        Ok(Self {
+            crypto_suite: crypto_suite.to_string(),
+            master_key_salt,
+        })
+    }
+
+    pub fn generate(crypto_suite: &str) -> Result<Self, SrtpError> {
+        let master_key_salt: [u8; MASTER_KEY_LEN + MASTER_SALT_LEN] = rand::thread_rng().gen();
+        Self::new(crypto_suite, master_key_salt.to_vec())
+    }
+
+    pub fn crypto_suite(&self) -> &str {
+        &self.crypto_suite
+    }
+
+    pub fn master_key_salt(&self) -> &[u8] {
+        &self.master_key_salt
+    }
+}
+
+fn get_rtp_auth_tag_len(crypto_suite: &str) -> Option<usize> {
+    match crypto_suite {
+        AES_CM_128_HMAC_SHA1_80 => Some(10),
+        AES_CM_128_HMAC_SHA1_32 => Some(4),
+        _ => None,
+    }
+}
+
+//The SRTP/SRTCP crypto context of one direction, the rollover counters and the
+//replay windows of the received packets are kept for each SSRC.
+pub struct SrtpContext {
+    rtp_keys: SessionKeys,
+    rtcp_keys: SessionKeys,
+    rtp_auth_tag_len: usize,
+    rollover_states: HashMap<u32, RolloverState>,
+    srtcp_index: u32,
+    rtp_replay_windows: HashMap<u32, ReplayWindow>,
+    rtcp_replay_windows: HashMap<u32, ReplayWindow>,
+}
+
+impl SrtpContext {
+    pub fn new(key: &SrtpKey) -> Self {
+        let (master_key, master_salt) = key.master_key_salt.split_at(MASTER_KEY_LEN);
+        Self {
+            rtp_keys: SessionKeys::derive(
+                master_key,
+                master_salt,
+                [
+                    LABEL_RTP_ENCRYPTION,
+                    LABEL_RTP_AUTHENTICATION,
+                    LABEL_RTP_SALT,
+                ],
+            ),
+            rtcp_keys: SessionKeys::derive(
+                master_key,
+                master_salt,
+                [
+                    LABEL_RTCP_ENCRYPTION,
+                    LABEL_RTCP_AUTHENTICATION,
+                    LABEL_RTCP_SALT,
+                ],
+            ),
+            rtp_auth_tag_len: get_rtp_auth_tag_len(&key.crypto_suite).unwrap_or(10),
+            rollover_states: HashMap::new(),
+            srtcp_index: 0,
+            rtp_replay_windows: HashMap::new(),
+            rtcp_replay_windows: HashMap::new(),
+        }
+    }
+
+    fn get_roc(&self, ssrc: u32, seq: u16) -> u32 {
+        match self.rollover_states.get(&ssrc) {
+            Some(state) => state.estimate_roc(seq),
+            None => 0,
+        }
+    }
+
+    fn update_roc(&mut self, ssrc: u32, roc: u32, seq: u16) {
+        self.rollover_states
+            .entry(ssrc)
+            .or_insert(RolloverState {
+                roc,
+                highest_seq: seq,
+            })
+            .update(roc, seq);
+    }
+
+    pub fn protect_rtp(&mut self, packet: &[u8]) -> Result<BytesMut, SrtpError> {
+        let header_length = rtp_header_length(packet)?;
+        let seq = u16::from_be_bytes([packet[2], packet[3]]);
+        let ssrc = u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]);
+        let roc = self.get_roc(ssrc, seq);
+        let index = (roc as u64) << 16 | seq as u64;
+
+        let mut data = BytesMut::from(packet);
+        apply_keystream(&self.rtp_keys, ssrc, index, &mut data[header_length..]);
+        let auth_tag = gen_auth_tag(&self.rtp_keys, &data, Some(roc));
+        data.put(&auth_tag[..self.rtp_auth_tag_len]);
+
+        self.update_roc(ssrc, roc, seq);
+        Ok(data)
+    }
+
+    pub fn unprotect_rtp(&mut self, packet: &[u8]) -> Result<BytesMut, SrtpError> {
+        if packet.len() < RTP_HEADER_LEN + self.rtp_auth_tag_len {
+            return Err(SrtpError {
+                value: SrtpErrorValue::PacketTooShort,
+            });
+        }
+        let (packet, auth_tag) = packet.split_at(packet.len() - self.rtp_auth_tag_len);
+        let header_length = rtp_header_length(packet)?;
+        let seq = u16::from_be_bytes([packet[2], packet[3]]);
+        let ssrc = u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]);
+        let roc = self.get_roc(ssrc, seq);
+        let index = (roc as u64) << 16 | seq as u64;
+
+        if self
+            .rtp_replay_windows
+            .get(&ssrc)
+            .is_some_and(|replay_window| replay_window.is_replayed(index))
+        {
+            return Err(SrtpError {
+                value: SrtpErrorValue::ReplayedPacket,
+            });
+        }
+        if !verify_auth_tag(&self.rtp_keys, packet, Some(roc), auth_tag) {
+            return Err(SrtpError {
+                value: SrtpErrorValue::AuthenticationFailed,
+            });
+        }
+
+        let mut data = BytesMut::from(packet);
+        apply_keystream(&self.rtp_keys, ssrc, index, &mut data[header_length..]);
+
+        self.update_roc(ssrc, roc, seq);
+        self.rtp_replay_windows
+            .entry(ssrc)
+            .or_default()
+            .update(index);
+        Ok(data)
+    }
+
+    //only the first rtcp packet header of the compound packet is not encrypted
+    pub fn protect_rtcp(&mut self, packet: &[u8]) -> Result<BytesMut, SrtpError> {
+        if packet.len() < RTCP_HEADER_LEN {
+            return Err(SrtpError {
+                value: SrtpErrorValue::PacketTooShort,
+            });
+        }
+        let ssrc = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        let index = self.srtcp_index;
+        self.srtcp_index = (self.srtcp_index + 1) & 0x7FFF_FFFF;
+
+        let mut data = BytesMut::from(packet);
+        apply_keystream(
+            &self.rtcp_keys,
+            ssrc,
+            index as u64,
+            &mut data[RTCP_HEADER_LEN..],
+        );
+        //the E flag is set
+        data.put_u32(0x8000_0000 | index);
+        let auth_tag = gen_auth_tag(&self.rtcp_keys, &data, None);
+        data.put(&auth_tag[..SRTCP_AUTH_TAG_LEN]);
+        Ok(data)
+    }
+
+    pub fn unprotect_rtcp(&mut self, packet: &[u8]) -> Result<BytesMut, SrtpError> {
+        if packet.len() < RTCP_HEADER_LEN + SRTCP_INDEX_LEN + SRTCP_AUTH_TAG_LEN {
+            return Err(SrtpError {
+                value: SrtpErrorValue::PacketTooShort,
+            });
+        }
+        let (packet, auth_tag) = packet.split_at(packet.len() - SRTCP_AUTH_TAG_LEN);
+        let (rtcp_packet, e_index) = packet.split_at(packet.len() - SRTCP_INDEX_LEN);
+        let e_index = u32::from_be_bytes([e_index[0], e_index[1], e_index[2], e_index[3]]);
+        let index = (e_index & 0x7FFF_FFFF) as u64;
+        let ssrc = u32::from_be_bytes([
+            rtcp_packet[4],
+            rtcp_packet[5],
+            rtcp_packet[6],
+            rtcp_packet[7],
+        ]);
+
+        if self
+            .rtcp_replay_windows
+            .get(&ssrc)
+            .is_some_and(|replay_window| replay_window.is_replayed(index))
+        {
+            return Err(SrtpError {
+                value: SrtpErrorValue::ReplayedPacket,
+            });
+        }
+        if !verify_auth_tag(&self.rtcp_keys, packet, None, auth_tag) {
+            return Err(SrtpError {
+                value: SrtpErrorValue::AuthenticationFailed,
+            });
+        }
+
+        let mut data = BytesMut::from(rtcp_packet);
+        if e_index & 0x8000_0000 > 0 {
+            apply_keystream(&self.rtcp_keys, ssrc, index, &mut data[RTCP_HEADER_LEN..]);
+        }
+        self.rtcp_replay_windows
+            .entry(ssrc)
+            .or_default()
+            .update(index);
+        Ok(data)
+    }
+}
+
+//Protect the rtp/rtcp packets written to the udp channel and unprotect the received ones,
+//each SDES peer protects the packets it sends with its own master key.
+pub struct SrtpIO {
+    io: Box<dyn TNetIO + Send + Sync>,
+    send_context: SrtpContext,
+    recv_context: SrtpContext,
+    is_rtcp: bool,
+}
+
+impl SrtpIO {
+    pub fn new(
+        io: Box<dyn TNetIO + Send + Sync>,
+        send_key: &SrtpKey,
+        recv_key: &SrtpKey,
+        is_rtcp: bool,
+    ) -> Self {
+        Self {
+            io,
+            send_context: SrtpContext::new(send_key),
+            recv_context: SrtpContext::new(recv_key),
+            is_rtcp,
+        }
+    }
+}
+
+#[async_trait]
+impl TNetIO for SrtpIO {
+    fn get_net_type(&self) -> NetType {
+        self.io.get_net_type()
+    }
+
+    async fn write(&mut self, bytes: Bytes) -> Result<(), BytesIOError> {
+        let result = if self.is_rtcp {
+            self.send_context.protect_rtcp(&bytes)
+        } else {
+            self.send_context.protect_rtp(&bytes)
+        };
+        match result {
+            Ok(data) => self.io.write(data.freeze()).await,
+            Err(err) => {
+                log::error!("srtp protect error: {}", err);
+                Ok(())
+            }
+        }
+    }
+
+    async fn read_timeout(&mut self, duration: Duration) -> Result<BytesMut, BytesIOError> {
+        match tokio::time::timeout(duration, self.read()).await {
+            Ok(data) => data,
+            Err(err) => Err(BytesIOError {
+                value: BytesIOErrorValue::TimeoutError(err),
+            }),
+        }
+    }
+
+    //the packets which fail to be authenticated are dropped
+    async fn read(&mut self) -> Result<BytesMut, BytesIOError> {
+        loop {
+            let data = self.io.read().await?;
+            let result = if self.is_rtcp {
+                self.recv_context.unprotect_rtcp(&data)
+            } else {
+                self.recv_context.unprotect_rtp(&data)
+            };
+            match result {
+                Ok(data) => return Ok(data),
+                Err(err) => log::warn!("srtp unprotect error: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(data: &str) -> Vec<u8> {
+        hex::decode(data).unwrap()
+    }
+
+    //https://www.rfc-editor.org/rfc/rfc3711#appendix-B.3
+    #[test]
+    fn test_derive_session_keys() {
+        let master_key = from_hex("E1F97A0D3E018BE0D64FA32C06DE4139");
+        let master_salt = from_hex("0EC675AD498AFEEBB6960B3AABE6");
+        let keys = SessionKeys::derive(
+            &master_key,
+            &master_salt,
+            [
+                LABEL_RTP_ENCRYPTION,
+                LABEL_RTP_AUTHENTICATION,
+                LABEL_RTP_SALT,
+            ],
+        );
+
+        assert_eq!(
+            keys.cipher_key,
+            from_hex("C61E7A93744F39EE10734AFE3FF7A087")
+        );
+        assert_eq!(keys.salt, from_hex("30CBBC08863D8C85D49DB34A9AE1"));
+        assert_eq!(
+            keys.auth_key,
+            from_hex("CEBE321F6FF7716B6FD4AB49AF256A156D38BAA4")
+        );
+    }
+
+    #[test]
+    fn test_protect_unprotect() {
+        let master_key_salt =
+            from_hex("E1F97A0D3E018BE0D64FA32C06DE41390EC675AD498AFEEBB6960B3AABE6");
+        let key = SrtpKey::new(AES_CM_128_HMAC_SHA1_80, master_key_salt).unwrap();
+        let mut sender = SrtpContext::new(&key);
+        let mut receiver = SrtpContext::new(&key);
+
+        //the sequence number rolls over
+        for seq in [0xFFFE_u16, 0xFFFF, 0x0000, 0x0001] {
+            let mut rtp = vec![0x80, 0x60];
+            rtp.extend_from_slice(&seq.to_be_bytes());
+            rtp.extend_from_slice(&[0, 0, 0x03, 0xE8, 0x12, 0x34, 0x56, 0x78]);
+            rtp.extend_from_slice(b"rtp payload");
+
+            let srtp = sender.protect_rtp(&rtp).unwrap();
+            assert_eq!(srtp.len(), rtp.len() + 10);
+            assert_eq!(srtp[..RTP_HEADER_LEN], rtp[..RTP_HEADER_LEN]);
+            assert_ne!(srtp[RTP_HEADER_LEN..rtp.len()], rtp[RTP_HEADER_LEN..]);
+            assert_eq!(receiver.unprotect_rtp(&srtp).unwrap()[..], rtp[..]);
+        }
+        assert_eq!(sender.rollover_states[&0x12345678].roc, 1);
+        assert_eq!(receiver.rollover_states[&0x12345678].roc, 1);
+
+        //receiver report
+        let rtcp = from_hex("81C90007123456780000000000000000000000000000000000000000");
+        let mut srtcp = sender.protect_rtcp(&rtcp).unwrap();
+        assert_eq!(receiver.unprotect_rtcp(&srtcp).unwrap()[..], rtcp[..]);
+
+        srtcp[10] ^= 0x01;
+        assert!(receiver.unprotect_rtcp(&srtcp).is_err());
+    }
+
+    #[test]
+    fn test_replay_protection() {
+        let master_key_salt =
+            from_hex("E1F97A0D3E018BE0D64FA32C06DE41390EC675AD498AFEEBB6960B3AABE6");
+        let key = SrtpKey::new(AES_CM_128_HMAC_SHA1_80, master_key_salt).unwrap();
+        let mut sender = SrtpContext::new(&key);
+        let mut receiver = SrtpContext::new(&key);
+
+        let srtp_packets: Vec<BytesMut> = (0..100_u16)
+            .map(|seq| {
+                let mut rtp = vec![0x80, 0x60];
+                rtp.extend_from_slice(&seq.to_be_bytes());
+                rtp.extend_from_slice(&[0, 0, 0x03, 0xE8, 0x12, 0x34, 0x56, 0x78]);
+                rtp.extend_from_slice(b"rtp payload");
+                sender.protect_rtp(&rtp).unwrap()
+            })
+            .collect();
+
+        assert!(receiver.unprotect_rtp(&srtp_packets[10]).is_ok());
+        assert!(receiver.unprotect_rtp(&srtp_packets[99]).is_ok());
+        //the reordered packet in the window is accepted once
+        assert!(receiver.unprotect_rtp(&srtp_packets[50]).is_ok());
+        for seq in [50, 99, 10] {
+            assert!(matches!(
+                receiver
+                    .unprotect_rtp(&srtp_packets[seq])
+                    .unwrap_err()
+                    .value,
+                SrtpErrorValue::ReplayedPacket
+            ));
+        }
+
+        let rtcp = from_hex("81C90007123456780000000000000000000000000000000000000000");
+        let srtcp = sender.protect_rtcp(&rtcp).unwrap();
+        assert!(receiver.unprotect_rtcp(&srtcp).is_ok());
+        assert!(receiver.unprotect_rtcp(&srtcp).is_err());
+    }
+}
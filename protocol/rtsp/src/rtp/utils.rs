@@ -3,7 +3,7 @@ use super::errors::PackerError;
 use super::errors::UnPackerError;
 use super::RtpPacket;
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::Bytes;
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::future::Future;
@@ -47,15 +47,17 @@ pub trait TRtpReceiverForRtcp {
 #[async_trait]
 pub trait TPacker: TRtpReceiverForRtcp + Send + Sync {
     /*Split frame to rtp packets and send out*/
-    async fn pack(&mut self, nalus: &mut BytesMut, timestamp: u32) -> Result<(), PackerError>;
+    async fn pack(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError>;
     /*Call back function used for processing a rtp packet.*/
     fn on_packet_handler(&mut self, f: OnRtpPacketFn);
+    /*The sequence number of the next rtp packet.*/
+    fn get_seq_number(&self) -> u16;
 }
 
 #[async_trait]
 pub trait TVideoPacker: TPacker {
     /*pack one nalu to rtp packets*/
-    async fn pack_nalu(&mut self, nalu: BytesMut) -> Result<(), PackerError>;
+    async fn pack_nalu(&mut self, nalu: Bytes) -> Result<(), PackerError>;
 }
 
 #[async_trait]
@@ -64,6 +66,8 @@ pub trait TUnPacker: TRtpReceiverForRtcp + Send + Sync {
     async fn unpack(&mut self, reader: &mut BytesReader) -> Result<(), UnPackerError>;
     /*Call back function used for processing a frame.*/
     fn on_frame_handler(&mut self, f: OnFrameFn);
+    /*Called when packets are lost before the next one, drop the incomplete fragments.*/
+    fn on_packet_lost(&mut self) {}
 }
 
 pub(super) fn is_fu_start(fu_header: u8) -> bool {
@@ -80,7 +84,7 @@ pub fn find_start_code(nalus: &[u8]) -> Option<usize> {
 }
 
 pub async fn split_annexb_and_process<T: TVideoPacker>(
-    nalus: &mut BytesMut,
+    mut nalus: Bytes,
     packer: &mut T,
 ) -> Result<(), PackerError> {
     while !nalus.is_empty() {
@@ -118,6 +122,17 @@ pub fn current_time() -> u64 {
     }
 }
 
+//the seconds between 1900(the ntp epoch) and 1970
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+//The 64 bits ntp timestamp, the high 32 bits are seconds and the low 32 bits are the fraction.
+pub fn current_ntp_time() -> u64 {
+    let micros = current_time();
+    let seconds = micros / 1_000_000 + NTP_UNIX_OFFSET;
+    let fraction = ((micros % 1_000_000) << 32) / 1_000_000;
+    (seconds << 32) | fraction
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1,23 +1,51 @@
 use streamhub::define::StreamHubEventSender;
 
+use super::session::multicast::MulticastManager;
+use super::session::playback::PlaybackConfig;
 use super::session::RtspServerSession;
+use crate::rtsp_tls::{TlsConfig, TlsIO};
+use crate::rtsp_tunnel::{self, ConnectionType, HttpTunnelManager, WebSocketIO};
+use bytesio::bytesio::{TNetIO, TcpIO};
 use commonlib::auth::Auth;
 use std::net::SocketAddr;
 use tokio::io::Error;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_native_tls::TlsAcceptor;
 
+#[derive(Clone)]
 pub struct RtspServer {
     address: String,
     event_producer: StreamHubEventSender,
     auth: Option<Auth>,
+    multicast_manager: Option<MulticastManager>,
+    playback_config: Option<PlaybackConfig>,
+    session_timeout: u64,
+    tunnel_manager: HttpTunnelManager,
+    //the rtsps server accepts the TLS connections only
+    tls_config: Option<TlsConfig>,
+    tls_acceptor: Option<TlsAcceptor>,
 }
 
 impl RtspServer {
-    pub fn new(address: String, event_producer: StreamHubEventSender, auth: Option<Auth>) -> Self {
+    pub fn new(
+        address: String,
+        event_producer: StreamHubEventSender,
+        auth: Option<Auth>,
+        multicast_manager: Option<MulticastManager>,
+        playback_config: Option<PlaybackConfig>,
+        session_timeout: u64,
+        tls_config: Option<TlsConfig>,
+    ) -> Self {
         Self {
             address,
             event_producer,
             auth,
+            multicast_manager,
+            playback_config,
+            session_timeout,
+            tunnel_manager: HttpTunnelManager::new(),
+            tls_config,
+            tls_acceptor: None,
         }
     }
 
@@ -25,16 +53,85 @@ impl RtspServer {
         let socket_addr: &SocketAddr = &self.address.parse().unwrap();
         let listener = TcpListener::bind(socket_addr).await?;
 
-        log::info!("Rtsp server listening on tcp://{}", socket_addr);
+        if let Some(tls_config) = &self.tls_config {
+            self.tls_acceptor = Some(tls_config.new_acceptor()?);
+            log::info!("Rtsps server listening on tcp://{}", socket_addr);
+        } else {
+            log::info!("Rtsp server listening on tcp://{}", socket_addr);
+        }
         loop {
             let (tcp_stream, _) = listener.accept().await?;
-            let mut session =
-                RtspServerSession::new(tcp_stream, self.event_producer.clone(), self.auth.clone());
+            let server = self.clone();
             tokio::spawn(async move {
-                if let Err(err) = session.run().await {
-                    log::error!("session run error, err: {}", err);
-                }
+                server.handle_connection(tcp_stream).await;
             });
         }
     }
+
+    //The rtsp over http tunnel and websocket connections share the port with the rtsp ones.
+    async fn accept_connection(
+        &self,
+        mut tcp_stream: TcpStream,
+    ) -> Option<Box<dyn TNetIO + Send + Sync>> {
+        match rtsp_tunnel::accept_connection(&mut tcp_stream).await {
+            Ok(ConnectionType::Rtsp) => Some(Box::new(TcpIO::new(tcp_stream))),
+            Ok(ConnectionType::WebSocket) => match WebSocketIO::accept(tcp_stream).await {
+                Ok(io) => Some(Box::new(io)),
+                Err(err) => {
+                    log::error!("websocket handshake error: {}", err);
+                    None
+                }
+            },
+            Ok(ConnectionType::HttpTunnelGet { cookie }) => {
+                if let Err(err) = self.tunnel_manager.on_get(cookie, tcp_stream).await {
+                    log::error!("http tunnel GET connection error: {}", err);
+                }
+                None
+            }
+            Ok(ConnectionType::HttpTunnelPost { cookie }) => {
+                let io = self
+                    .tunnel_manager
+                    .on_post(cookie, TcpIO::new(tcp_stream))?;
+                Some(Box::new(io))
+            }
+            Err(err) => {
+                log::error!("accept connection error: {}", err);
+                None
+            }
+        }
+    }
+
+    async fn handle_connection(&self, tcp_stream: TcpStream) {
+        let net_io: Box<dyn TNetIO + Send + Sync> = match &self.tls_acceptor {
+            Some(tls_acceptor) => match tls_acceptor.accept(tcp_stream).await {
+                Ok(tls_stream) => Box::new(TlsIO::new(tls_stream)),
+                Err(err) => {
+                    log::error!("tls handshake error: {}", err);
+                    return;
+                }
+            },
+            None => match self.accept_connection(tcp_stream).await {
+                Some(net_io) => net_io,
+                None => return,
+            },
+        };
+        let enable_srtp = self
+            .tls_config
+            .as_ref()
+            .is_some_and(|tls_config| tls_config.enable_srtp);
+
+        let mut session = RtspServerSession::new(
+            net_io,
+            self.event_producer.clone(),
+            self.auth.clone(),
+            self.multicast_manager.clone(),
+            self.playback_config.clone(),
+            enable_srtp,
+            self.session_timeout,
+        );
+        if let Err(err) = session.run().await {
+            log::error!("session run error, err: {}", err);
+        }
+        session.leave_stream_hub().await;
+    }
 }
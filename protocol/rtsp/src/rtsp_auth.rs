@@ -0,0 +1,181 @@
+use super::global_trait::Unmarshal;
+use base64::{engine::general_purpose, Engine as _};
+use indexmap::IndexMap;
+use rand::Rng;
+
+// The challenge in the WWW-Authenticate header of a 401 response:
+// WWW-Authenticate: Digest realm="IP Camera", nonce="6f5a0c1e9b", algorithm="MD5"
+// WWW-Authenticate: Basic realm="IP Camera"
+#[derive(Debug, Clone, PartialEq)]
+pub enum WwwAuthenticate {
+    Basic {
+        realm: String,
+    },
+    Digest {
+        realm: String,
+        nonce: String,
+        opaque: Option<String>,
+        qop: Option<String>,
+    },
+}
+
+impl Unmarshal for WwwAuthenticate {
+    fn unmarshal(raw_data: &str) -> Option<Self> {
+        let (scheme, params_data) = raw_data.trim().split_once(' ')?;
+
+        let mut params = IndexMap::new();
+        for param in params_data.split(',') {
+            if let Some((k, v)) = param.split_once('=') {
+                params.insert(
+                    k.trim().to_lowercase(),
+                    v.trim().trim_matches('"').to_string(),
+                );
+            }
+        }
+        let realm = params.get("realm").cloned().unwrap_or_default();
+
+        match scheme.to_lowercase().as_str() {
+            "basic" => Some(WwwAuthenticate::Basic { realm }),
+            "digest" => Some(WwwAuthenticate::Digest {
+                realm,
+                nonce: params.get("nonce")?.clone(),
+                opaque: params.get("opaque").cloned(),
+                //only qop=auth is supported
+                qop: params
+                    .get("qop")
+                    .filter(|qop| qop.split(',').any(|v| v.trim() == "auth"))
+                    .map(|_| String::from("auth")),
+            }),
+            _ => None,
+        }
+    }
+}
+
+//Generate the Authorization header of the requests sent by a rtsp client.
+#[derive(Debug, Clone)]
+pub struct ClientAuth {
+    username: String,
+    password: String,
+    challenge: Option<WwwAuthenticate>,
+    nonce_count: u32,
+}
+
+impl ClientAuth {
+    pub fn new(username: String, password: String) -> Self {
+        Self {
+            username,
+            password,
+            challenge: None,
+            nonce_count: 0,
+        }
+    }
+
+    pub fn set_challenge(&mut self, challenge: WwwAuthenticate) {
+        self.challenge = Some(challenge);
+        self.nonce_count = 0;
+    }
+
+    pub fn authorization(&mut self, method: &str, uri: &str) -> Option<String> {
+        match self.challenge.clone()? {
+            WwwAuthenticate::Basic { .. } => {
+                let credential = format!("{}:{}", self.username, self.password);
+                Some(format!(
+                    "Basic {}",
+                    general_purpose::STANDARD.encode(credential)
+                ))
+            }
+            WwwAuthenticate::Digest {
+                realm,
+                nonce,
+                opaque,
+                qop,
+            } => {
+                self.nonce_count += 1;
+                let nonce_count = format!("{:08x}", self.nonce_count);
+                let cnonce = format!("{:016x}", rand::thread_rng().gen::<u64>());
+
+                let response = self.digest_response(
+                    method,
+                    uri,
+                    &realm,
+                    &nonce,
+                    qop.as_ref()
+                        .map(|qop| (qop.as_str(), nonce_count.as_str(), cnonce.as_str())),
+                );
+
+                let mut authorization = format!(
+                    "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", response=\"{}\"",
+                    self.username, realm, nonce, uri, response
+                );
+                if let Some(opaque) = opaque {
+                    authorization += &format!(", opaque=\"{opaque}\"");
+                }
+                if let Some(qop) = qop {
+                    authorization += &format!(", qop={qop}, nc={nonce_count}, cnonce=\"{cnonce}\"");
+                }
+                Some(authorization)
+            }
+        }
+    }
+
+    // RFC 2617 3.2.2.1 Request-Digest
+    // qop_params: (qop, nonce_count, cnonce)
+    fn digest_response(
+        &self,
+        method: &str,
+        uri: &str,
+        realm: &str,
+        nonce: &str,
+        qop_params: Option<(&str, &str, &str)>,
+    ) -> String {
+        let ha1 = md5::compute(format!("{}:{}:{}", self.username, realm, self.password));
+        let ha2 = md5::compute(format!("{method}:{uri}"));
+
+        let data = match qop_params {
+            Some((qop, nonce_count, cnonce)) => {
+                format!("{ha1:x}:{nonce}:{nonce_count}:{cnonce}:{qop}:{ha2:x}")
+            }
+            None => format!("{ha1:x}:{nonce}:{ha2:x}"),
+        };
+        format!("{:x}", md5::compute(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_response() {
+        let challenge = WwwAuthenticate::unmarshal(
+            "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\"",
+        )
+        .unwrap();
+
+        let auth = ClientAuth::new(String::from("Mufasa"), String::from("Circle Of Life"));
+        //the example of RFC 2617
+        assert_eq!(
+            auth.digest_response(
+                "GET",
+                "/dir/index.html",
+                "testrealm@host.com",
+                "dcd98b7102dd2f0e8b11d0f600bfb0c093",
+                Some(("auth", "00000001", "0a4f113b"))
+            ),
+            "6629fae49393a05397450978507c4ef1"
+        );
+
+        let mut auth = auth;
+        auth.set_challenge(challenge);
+        let authorization = auth.authorization("GET", "/dir/index.html").unwrap();
+        assert!(authorization.starts_with("Digest username=\"Mufasa\""));
+        assert!(authorization.contains("qop=auth, nc=00000001"));
+
+        auth.set_challenge(WwwAuthenticate::unmarshal("Basic realm=\"camera\"").unwrap());
+        assert_eq!(
+            auth.authorization("OPTIONS", "rtsp://127.0.0.1/live")
+                .unwrap(),
+            "Basic TXVmYXNhOkNpcmNsZSBPZiBMaWZl"
+        );
+    }
+}
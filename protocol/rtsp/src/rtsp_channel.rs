@@ -1,6 +1,9 @@
 use crate::rtp::errors::PackerError;
 use crate::rtp::errors::UnPackerError;
+use crate::rtp::jitter_buffer::JitterBuffer;
+use crate::rtp::jitter_buffer::JitterBufferOutput;
 use crate::rtp::rtcp::rtcp_header::RtcpHeader;
+use crate::rtp::rtcp::rtcp_nack::RtcpNack;
 use crate::rtp::rtcp::RTCP_RR;
 use crate::rtp::rtcp::RTCP_SR;
 use crate::rtp::utils::OnFrameFn;
@@ -9,10 +12,12 @@ use crate::rtp::utils::OnRtpPacketFn2;
 use crate::rtp::RtpPacket;
 
 use super::rtp::rtp_aac::RtpAacPacker;
+use super::rtp::rtp_audio::RtpAudioPacker;
 use super::rtp::rtp_h264::RtpH264Packer;
 use super::rtp::rtp_h265::RtpH265Packer;
 
 use super::rtp::rtp_aac::RtpAacUnPacker;
+use super::rtp::rtp_audio::RtpAudioUnPacker;
 use super::rtp::rtp_h264::RtpH264UnPacker;
 use super::rtp::rtp_h265::RtpH265UnPacker;
 
@@ -22,17 +27,26 @@ use super::rtp::utils::TPacker;
 use super::rtp::utils::TUnPacker;
 use super::rtsp_codec::RtspCodecId;
 use super::rtsp_codec::RtspCodecInfo;
+use super::rtsp_sync::AvSynchronizer;
+use super::rtsp_track::TrackType;
+use crate::rtp::define::ANNEXB_NALU_START_CODE;
 use crate::rtp::utils::Marshal;
 use crate::rtp::utils::Unmarshal;
 use byteorder::BigEndian;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use bytesio::bytes_errors::BytesWriteError;
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytes_writer::AsyncBytesWriter;
 use bytesio::bytesio::TNetIO;
 use rand::Rng;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use streamhub::define::FrameData;
+use streamhub::statistics::RtpReceiveStatistics;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 pub trait TRtpFunc {
     fn create_packer(&mut self, writer: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>);
@@ -45,6 +59,13 @@ pub struct RtpChannel {
     pub rtp_unpacker: Option<Box<dyn TUnPacker>>,
     ssrc: u32,
     init_sequence: u16,
+    //reorder the rtp packets received over udp
+    jitter_buffer: Option<JitterBuffer>,
+    //send the sequence numbers of the missing packets to the rtcp channel
+    nack_sender: Option<UnboundedSender<Vec<u16>>>,
+    //drop the video frames until the next key frame after packets are lost
+    wait_for_key_frame: Arc<AtomicBool>,
+    dropped_frames: Arc<AtomicUsize>,
 }
 
 #[derive(Default)]
@@ -52,6 +73,10 @@ pub struct RtcpChannel {
     recv_ctx: RtcpContext,
     pub send_ctx: RtcpContext,
     channel_identifier: u8,
+    clock_rate: u32,
+    nack_count: usize,
+    //the received sender reports are used to synchronize the tracks of a publisher
+    av_sync: Option<(TrackType, Arc<StdMutex<AvSynchronizer>>)>,
 }
 
 impl RtpChannel {
@@ -63,25 +88,88 @@ impl RtpChannel {
             rtp_packer: None,
             rtp_unpacker: None,
             init_sequence: 0,
+            jitter_buffer: None,
+            nack_sender: None,
+            wait_for_key_frame: Arc::new(AtomicBool::new(false)),
+            dropped_frames: Arc::new(AtomicUsize::new(0)),
         };
         rtp_channel.create_unpacker();
         rtp_channel
     }
 
+    //The rtp packets over udp may be lost or reordered, reorder them before unpacking.
+    pub fn enable_jitter_buffer(&mut self) {
+        self.jitter_buffer = Some(JitterBuffer::new());
+    }
+
+    pub fn set_nack_sender(&mut self, sender: UnboundedSender<Vec<u16>>) {
+        self.nack_sender = Some(sender);
+    }
+
+    fn is_video(&self) -> bool {
+        matches!(
+            self.codec_info.codec_id,
+            RtspCodecId::H264 | RtspCodecId::H265
+        )
+    }
+
     //Receive av frame from network -> pack AV frame to RTP packet -> send to stream hub
     pub async fn on_packet(&mut self, reader: &mut BytesReader) -> Result<(), UnPackerError> {
-        if let Some(unpacker) = &mut self.rtp_unpacker {
-            unpacker.unpack(reader).await?;
+        let is_video = self.is_video();
+        let unpacker = match &mut self.rtp_unpacker {
+            Some(unpacker) => unpacker,
+            None => return Ok(()),
+        };
+        let jitter_buffer = match &mut self.jitter_buffer {
+            Some(jitter_buffer) => jitter_buffer,
+            None => return unpacker.unpack(reader).await,
+        };
+
+        let now = Instant::now();
+        let outputs = jitter_buffer.push(RtpPacket::unmarshal(reader)?, now);
+
+        let nack_sequences = jitter_buffer.get_nack_sequences(now);
+        if !nack_sequences.is_empty() {
+            if let Some(sender) = &self.nack_sender {
+                if let Err(err) = sender.send(nack_sequences) {
+                    log::error!("send nack sequences error: {}", err);
+                }
+            }
+        }
+
+        for output in outputs {
+            match output {
+                JitterBufferOutput::Packet(packet) => {
+                    let mut packet_reader = BytesReader::new(packet.marshal()?);
+                    if let Err(err) = unpacker.unpack(&mut packet_reader).await {
+                        log::error!("unpack rtp packet error: {}", err);
+                    }
+                }
+                JitterBufferOutput::Lost(_) => {
+                    unpacker.on_packet_lost();
+                    if is_video {
+                        self.wait_for_key_frame.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
         }
         Ok(())
     }
 
+    pub fn get_receive_statistics(&self) -> RtpReceiveStatistics {
+        let mut statistics = RtpReceiveStatistics {
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        if let Some(jitter_buffer) = &self.jitter_buffer {
+            statistics.lost_packets = jitter_buffer.statistics.lost_packets;
+            statistics.recovered_packets = jitter_buffer.statistics.recovered_packets;
+        }
+        statistics
+    }
+
     //Receive av frame from stream hub -> pack -> send out
-    pub async fn on_frame(
-        &mut self,
-        nalus: &mut BytesMut,
-        timestamp: u32,
-    ) -> Result<(), PackerError> {
+    pub async fn on_frame(&mut self, nalus: Bytes, timestamp: u32) -> Result<(), PackerError> {
         if let Some(packer) = &mut self.rtp_packer {
             return packer.pack(nalus, timestamp).await;
         }
@@ -91,8 +179,26 @@ impl RtpChannel {
     //Set handler for processing AV frame when unpack a whole AV frame
     //from rtp packets received from network.
     pub fn on_frame_handler(&mut self, f: OnFrameFn) {
+        let codec_id = self.codec_info.codec_id.clone();
+        let wait_for_key_frame = self.wait_for_key_frame.clone();
+        let dropped_frames = self.dropped_frames.clone();
+
+        //the incomplete frames after packets are lost will corrupt the decoders
+        let handler: OnFrameFn = Box::new(move |frame_data: FrameData| {
+            if let FrameData::Video { data, .. } = &frame_data {
+                if wait_for_key_frame.load(Ordering::Relaxed) {
+                    if !is_key_nalu(&codec_id, data) {
+                        dropped_frames.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    wait_for_key_frame.store(false, Ordering::Relaxed);
+                }
+            }
+            f(frame_data)
+        });
+
         if let Some(unpacker) = &mut self.rtp_unpacker {
-            unpacker.on_frame_handler(f);
+            unpacker.on_frame_handler(handler);
         }
     }
 
@@ -103,14 +209,48 @@ impl RtpChannel {
         }
     }
 
+    //The sequence number of the next rtp packet sent out, used for the RTP-Info header
+    pub fn get_seq_number(&self) -> Option<u16> {
+        self.rtp_packer
+            .as_ref()
+            .map(|packer| packer.get_seq_number())
+    }
+
+    pub fn get_ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
     //Set handler for processing received AV rtp packet from network
     pub fn on_packet_for_rtcp_handler(&mut self, f: OnRtpPacketFn2) {
+        if let Some(unpacker) = &mut self.rtp_unpacker {
+            unpacker.on_packet_for_rtcp_handler(f);
+        }
+    }
+
+    //Set handler for processing the rtp packet sent out
+    pub fn on_sent_packet_for_rtcp_handler(&mut self, f: OnRtpPacketFn2) {
         if let Some(packer) = &mut self.rtp_packer {
             packer.on_packet_for_rtcp_handler(f);
         }
     }
 }
 
+//the parameter sets and the IDR/IRAP NALUs start a decodable access unit,
+//the NALU is prefixed with the annexb start code.
+fn is_key_nalu(codec_id: &RtspCodecId, nalu: &[u8]) -> bool {
+    let nalu_header = match nalu.get(ANNEXB_NALU_START_CODE.len()) {
+        Some(nalu_header) => *nalu_header,
+        None => return false,
+    };
+    match codec_id {
+        //5: IDR 7: SPS
+        RtspCodecId::H264 => matches!(nalu_header & 0x1F, 5 | 7),
+        //16-21: IRAP 32: VPS
+        RtspCodecId::H265 => matches!((nalu_header >> 1) & 0x3F, 16..=21 | 32),
+        _ => true,
+    }
+}
+
 impl TRtpFunc for RtpChannel {
     fn create_unpacker(&mut self) {
         match self.codec_info.codec_id {
@@ -123,7 +263,9 @@ impl TRtpFunc for RtpChannel {
             RtspCodecId::AAC => {
                 self.rtp_unpacker = Some(Box::new(RtpAacUnPacker::new()));
             }
-            RtspCodecId::G711A => {}
+            RtspCodecId::G711A | RtspCodecId::G711U | RtspCodecId::G726 | RtspCodecId::Opus => {
+                self.rtp_unpacker = Some(Box::new(RtpAudioUnPacker::new()));
+            }
         }
     }
     fn create_packer(&mut self, io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) {
@@ -154,12 +296,36 @@ impl TRtpFunc for RtpChannel {
                     io,
                 )));
             }
-            RtspCodecId::G711A => {}
+            RtspCodecId::G711A | RtspCodecId::G711U | RtspCodecId::G726 | RtspCodecId::Opus => {
+                self.rtp_packer = Some(Box::new(RtpAudioPacker::new(
+                    self.codec_info.payload_type,
+                    self.ssrc,
+                    self.init_sequence,
+                    io,
+                )));
+            }
         }
     }
 }
 
 impl RtcpChannel {
+    pub fn new(ssrc: u32, clock_rate: u32) -> Self {
+        Self {
+            recv_ctx: RtcpContext::new_receiver(ssrc, clock_rate),
+            send_ctx: RtcpContext::new_sender(ssrc, clock_rate),
+            clock_rate,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_av_synchronizer(
+        &mut self,
+        track_type: TrackType,
+        av_sync: Arc<StdMutex<AvSynchronizer>>,
+    ) {
+        self.av_sync = Some((track_type, av_sync));
+    }
+
     pub fn set_channel_identifier(&mut self, channel_idendifier: u8) {
         self.channel_identifier = channel_idendifier;
     }
@@ -175,6 +341,16 @@ impl RtcpChannel {
                 RTCP_SR => {
                     if let Ok(sr) = RtcpSenderReport::unmarshal(reader) {
                         self.recv_ctx.received_sr(&sr);
+                        if let Some((track_type, av_sync)) = &self.av_sync {
+                            if let Ok(mut av_sync) = av_sync.lock() {
+                                av_sync.on_sender_report(
+                                    track_type.clone(),
+                                    self.clock_rate,
+                                    sr.ntp,
+                                    sr.rtp_timestamp,
+                                );
+                            }
+                        }
                         if let Err(err) = self.send_rr(rtcp_io).await {
                             log::error!("send rr error: {}", err);
                         }
@@ -190,26 +366,77 @@ impl RtcpChannel {
         self.recv_ctx.received_rtp(packet);
     }
 
+    pub fn on_sent_packet(&mut self, packet: RtpPacket) {
+        self.send_ctx.send_rtp(packet);
+    }
+
     pub async fn send_rr(
         &mut self,
         rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
     ) -> Result<(), BytesWriteError> {
         let rr = self.recv_ctx.generate_rr();
+        if let Ok(msg) = rr.marshal() {
+            self.send_rtcp(&msg, rtcp_io).await?;
+        }
+        Ok(())
+    }
+
+    //Request the retransmission of the missing packets by the generic NACK.
+    pub async fn send_nack(
+        &mut self,
+        lost_sequences: &[u16],
+        rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
+    ) -> Result<(), BytesWriteError> {
+        let nack = RtcpNack::new(
+            self.recv_ctx.get_ssrc(),
+            self.recv_ctx.get_sender_ssrc(),
+            lost_sequences,
+        );
+        if let Ok(msg) = nack.marshal() {
+            self.send_rtcp(&msg, rtcp_io).await?;
+            self.nack_count += 1;
+        }
+        Ok(())
+    }
 
+    pub fn get_nack_count(&self) -> usize {
+        self.nack_count
+    }
+
+    pub fn get_jitter(&self) -> u32 {
+        self.recv_ctx.get_jitter()
+    }
+
+    //Send the sender report if any rtp packet has been sent out.
+    pub async fn send_sr(
+        &mut self,
+        rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
+    ) -> Result<(), BytesWriteError> {
+        if let Some(sr) = self.send_ctx.generate_sr() {
+            if let Ok(msg) = sr.marshal() {
+                self.send_rtcp(&msg, rtcp_io).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_rtcp(
+        &self,
+        msg: &BytesMut,
+        rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
+    ) -> Result<(), BytesWriteError> {
         let net_type = rtcp_io.lock().await.get_net_type();
-        if let Ok(msg) = rr.marshal() {
-            let mut bytes_writer = AsyncBytesWriter::new(rtcp_io);
-            match net_type {
-                bytesio::bytesio::NetType::TCP => {
-                    bytes_writer.write_u8(0x24)?;
-                    bytes_writer.write_u8(self.channel_identifier)?;
-                    bytes_writer.write_u16::<BigEndian>(msg.len() as u16)?;
-                }
-                bytesio::bytesio::NetType::UDP => {}
+        let mut bytes_writer = AsyncBytesWriter::new(rtcp_io);
+        match net_type {
+            bytesio::bytesio::NetType::TCP => {
+                bytes_writer.write_u8(0x24)?;
+                bytes_writer.write_u8(self.channel_identifier)?;
+                bytes_writer.write_u16::<BigEndian>(msg.len() as u16)?;
             }
-            bytes_writer.write(&msg)?;
-            bytes_writer.flush().await?;
+            bytesio::bytesio::NetType::UDP => {}
         }
+        bytes_writer.write(msg)?;
+        bytes_writer.flush().await?;
         Ok(())
     }
 }
@@ -8,6 +8,10 @@ pub enum RtspCodecId {
     H265,
     AAC,
     G711A,
+    G711U,
+    //G.726 of all the bit rates(16/24/32/40 kbit/s)
+    G726,
+    Opus,
 }
 
 lazy_static! {
@@ -17,6 +21,9 @@ lazy_static! {
         m.insert(RtspCodecId::H265, "h265");
         m.insert(RtspCodecId::AAC, "mpeg4-generic");
         m.insert(RtspCodecId::G711A, "pcma");
+        m.insert(RtspCodecId::G711U, "pcmu");
+        m.insert(RtspCodecId::G726, "g726-32");
+        m.insert(RtspCodecId::Opus, "opus");
         m
     };
     pub static ref RTSP_CODEC_NAME_2_ID: HashMap<&'static str, RtspCodecId> = {
@@ -25,6 +32,12 @@ lazy_static! {
         m.insert("h265", RtspCodecId::H265);
         m.insert("mpeg4-generic", RtspCodecId::AAC);
         m.insert("pcma", RtspCodecId::G711A);
+        m.insert("pcmu", RtspCodecId::G711U);
+        m.insert("g726-16", RtspCodecId::G726);
+        m.insert("g726-24", RtspCodecId::G726);
+        m.insert("g726-32", RtspCodecId::G726);
+        m.insert("g726-40", RtspCodecId::G726);
+        m.insert("opus", RtspCodecId::Opus);
         m
     };
 }
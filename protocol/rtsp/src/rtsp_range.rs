@@ -1,7 +1,6 @@
 use crate::global_trait::Marshal;
 
 use super::global_trait::Unmarshal;
-use super::rtsp_utils;
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub enum RtspRangeType {
@@ -12,9 +11,53 @@ pub enum RtspRangeType {
 
 #[derive(Debug, Clone, Default)]
 pub struct RtspRange {
-    range_type: RtspRangeType,
-    begin: i64,
-    end: Option<i64>,
+    pub range_type: RtspRangeType,
+    //npt: the offset(ms) from the beginning of the presentation
+    //clock: the absolute utc time(ms)
+    pub begin: i64,
+    pub end: Option<i64>,
+}
+
+//npt-sec = 1*DIGIT [ "." *DIGIT ]
+//npt-hhmmss = npt-hh ":" npt-mm ":" npt-ss [ "." *DIGIT ]
+fn parse_npt_time(range_time: &str) -> Option<i64> {
+    let (time, fraction) = match range_time.split_once('.') {
+        Some((time, fraction)) => (time, fraction),
+        None => (range_time, ""),
+    };
+
+    let mut seconds: i64 = 0;
+    for value in time.split(':') {
+        seconds = seconds * 60 + value.parse::<i64>().ok()?;
+    }
+
+    let mut milliseconds: i64 = 0;
+    for (index, c) in fraction.chars().chain("000".chars()).take(3).enumerate() {
+        milliseconds += c.to_digit(10)? as i64 * 10_i64.pow(2 - index as u32);
+    }
+
+    Some(seconds * 1000 + milliseconds)
+}
+
+//utc-time = utc-date "T" utc-time "Z", e.g. 19961108T143720.25Z
+fn parse_clock_time(range_time: &str) -> Option<i64> {
+    match chrono::NaiveDateTime::parse_from_str(range_time, "%Y%m%dT%H%M%S%.fZ") {
+        Ok(datetime) => Some(datetime.and_utc().timestamp_millis()),
+        Err(err) => {
+            log::error!("parse clock time {} error: {}", range_time, err);
+            None
+        }
+    }
+}
+
+impl RtspRange {
+    pub fn new(range_type: RtspRangeType, begin: i64, end: Option<i64>) -> Self {
+        Self {
+            range_type,
+            begin,
+            end,
+        }
+    }
 }
 
 impl Unmarshal for RtspRange {
@@ -26,61 +69,31 @@ impl Unmarshal for RtspRange {
             return None;
         }
 
-        match kv[0] {
+        //the time parameter(;time=) is not supported
+        let ranges: Vec<&str> = kv[1].split(';').next()?.trim().split('-').collect();
+        let end = ranges.get(1).filter(|end| !end.is_empty());
+
+        match kv[0].trim() {
             "clock" => {
                 rtsp_range.range_type = RtspRangeType::CLOCK;
-                let ranges: Vec<&str> = kv[1].split('-').collect();
-
-                let get_clock_time = |range_time: &str| -> i64 {
-                    let datetime =
-                        match chrono::NaiveDateTime::parse_from_str(range_time, "%Y%m%dT%H%M%SZ") {
-                            Ok(dt) => dt,
-                            Err(err) => {
-                                println!("get_clock_time error: {err}");
-                                return -1;
-                            }
-                        };
-                    datetime.and_utc().timestamp()
-                };
-
-                rtsp_range.begin = get_clock_time(ranges[0]);
-                if ranges.len() > 1 {
-                    rtsp_range.end = Some(get_clock_time(ranges[1]));
+                rtsp_range.begin = parse_clock_time(ranges[0])?;
+                if let Some(end) = end {
+                    rtsp_range.end = Some(parse_clock_time(end)?);
                 }
             }
             "npt" => {
                 rtsp_range.range_type = RtspRangeType::NPT;
-                let ranges: Vec<&str> = kv[1].split('-').collect();
-
-                let get_npt_time = |range_time: &str| -> i64 {
-                    if let (Some(hour), Some(minute), Some(second), mill) =
-                        rtsp_utils::scanf!(range_time, |c| c == ':' || c == '.', i64, i64, i64, i64)
-                    {
-                        let mut result = (hour * 3600 + minute * 60 + second) * 1000;
-                        if let Some(m) = mill {
-                            result += m;
-                        }
-                        result
-                    } else {
-                        0
-                    }
+                rtsp_range.begin = match ranges[0] {
+                    "now" | "" => 0,
+                    begin => parse_npt_time(begin)?,
                 };
-
-                match ranges[0] {
-                    "now" => {
-                        rtsp_range.begin = 0;
-                    }
-                    _ => {
-                        rtsp_range.begin = get_npt_time(ranges[0]);
-                    }
-                }
-
-                if ranges.len() == 2 && !ranges[1].is_empty() {
-                    rtsp_range.end = Some(get_npt_time(ranges[1]));
+                if let Some(end) = end {
+                    rtsp_range.end = Some(parse_npt_time(end)?);
                 }
             }
             _ => {
                 log::info!("{} not parsed..", kv[0]);
+                return None;
             }
         }
 
@@ -90,7 +103,22 @@ impl Unmarshal for RtspRange {
 
 impl Marshal for RtspRange {
     fn marshal(&self) -> String {
-        String::default()
+        let format_time = |time: i64| -> String {
+            match self.range_type {
+                RtspRangeType::NPT => format!("{}.{:03}", time / 1000, time % 1000),
+                RtspRangeType::CLOCK => chrono::DateTime::from_timestamp_millis(time)
+                    .map(|datetime| datetime.format("%Y%m%dT%H%M%S%.3fZ").to_string())
+                    .unwrap_or_default(),
+            }
+        };
+
+        let range_type = match self.range_type {
+            RtspRangeType::NPT => "npt",
+            RtspRangeType::CLOCK => "clock",
+        };
+        let end = self.end.map(format_time).unwrap_or_default();
+
+        format!("{}={}-{}", range_type, format_time(self.begin), end)
     }
 }
 
@@ -98,7 +126,8 @@ impl Marshal for RtspRange {
 mod tests {
 
     use super::RtspRange;
-    use crate::global_trait::Unmarshal;
+    use super::RtspRangeType;
+    use crate::global_trait::{Marshal, Unmarshal};
 
     #[test]
     fn test_parse_transport() {
@@ -116,4 +145,21 @@ mod tests {
         let parser2 = RtspRange::unmarshal("npt=0-").unwrap();
         println!(" parser2: {:?}, {}", parser2, parser2.end.is_none());
     }
+
+    #[test]
+    fn test_parse_seek_range() {
+        let range = RtspRange::unmarshal("npt=10.5-").unwrap();
+        assert_eq!((range.begin, range.end), (10500, None));
+        assert_eq!(range.marshal(), "npt=10.500-");
+
+        let range = RtspRange::unmarshal("npt=0:01:02.25-0:02:00").unwrap();
+        assert_eq!((range.begin, range.end), (62250, Some(120000)));
+
+        let range = RtspRange::unmarshal("clock=19961108T143720.25Z-").unwrap();
+        assert_eq!(range.range_type, RtspRangeType::CLOCK);
+        assert_eq!(range.begin, 847463840250);
+        assert_eq!(range.marshal(), "clock=19961108T143720.250Z-");
+
+        assert!(RtspRange::unmarshal("npt=abc-").is_none());
+    }
 }
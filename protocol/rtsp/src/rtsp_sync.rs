@@ -0,0 +1,170 @@
+use super::rtsp_track::TrackType;
+use std::collections::HashMap;
+
+//the tracks are aligned again if their clocks drift more than 40ms, in the 32.32 ntp format
+const MAX_SYNC_ERROR: i128 = (40 << 32) / 1000;
+
+#[derive(Debug, Clone, Default)]
+struct TrackClock {
+    //the rtp timestamp of the last frame
+    last_rtp_timestamp: Option<u32>,
+    //the rtp timestamp of the last frame which is unwrapped and starts from 0
+    unwrapped_timestamp: i64,
+    //added to the unwrapped timestamps to align the tracks, it only moves forward
+    offset: i64,
+    //the ntp time and the rtp timestamp of the latest sender report
+    sender_report: Option<(u64, u32)>,
+    clock_rate: u32,
+}
+
+impl TrackClock {
+    //The difference between the output time and the wall clock of the last frame,
+    //the tracks are aligned if the differences are the same.
+    fn get_sync_error(&self) -> Option<i128> {
+        let (ntp_time, sr_rtp_timestamp) = self.sender_report?;
+        let last_rtp_timestamp = self.last_rtp_timestamp?;
+        if self.clock_rate == 0 {
+            return None;
+        }
+        //the ntp time is a 32.32 fixed point number
+        let rtp_offset = last_rtp_timestamp.wrapping_sub(sr_rtp_timestamp) as i32 as i128;
+        let wall_clock = ntp_time as i128 + (rtp_offset << 32) / self.clock_rate as i128;
+        let output_time =
+            (((self.unwrapped_timestamp + self.offset) as i128) << 32) / self.clock_rate as i128;
+        Some(output_time - wall_clock)
+    }
+}
+
+//Map the rtp timestamps of the audio and video tracks onto a common clock.
+//The rtp timestamps of the tracks have random offsets and the sender clocks of
+//them may drift, the ntp times of the received sender reports tell the wall clock
+//of the rtp timestamps. The timestamps are still in the clock rate of each track
+//and start from 0, they are continuous across the sender reports: the tracks are
+//aligned by moving the offset of the track which lags behind forward only.
+#[derive(Debug, Default)]
+pub struct AvSynchronizer {
+    tracks: HashMap<TrackType, TrackClock>,
+}
+
+impl AvSynchronizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_sender_report(
+        &mut self,
+        track_type: TrackType,
+        clock_rate: u32,
+        ntp_time: u64,
+        rtp_timestamp: u32,
+    ) {
+        if clock_rate == 0 {
+            return;
+        }
+
+        let track = self.tracks.entry(track_type).or_default();
+        track.sender_report = Some((ntp_time, rtp_timestamp));
+        track.clock_rate = clock_rate;
+        self.align_tracks();
+    }
+
+    pub fn sync_timestamp(&mut self, track_type: TrackType, rtp_timestamp: u32) -> u32 {
+        let track = self.tracks.entry(track_type.clone()).or_default();
+        let is_first_frame = track.last_rtp_timestamp.is_none();
+        if let Some(last_rtp_timestamp) = track.last_rtp_timestamp {
+            track.unwrapped_timestamp +=
+                rtp_timestamp.wrapping_sub(last_rtp_timestamp) as i32 as i64;
+        }
+        track.last_rtp_timestamp = Some(rtp_timestamp);
+
+        if is_first_frame {
+            self.align_tracks();
+        }
+        match self.tracks.get(&track_type) {
+            Some(track) => (track.unwrapped_timestamp + track.offset) as u32,
+            None => 0,
+        }
+    }
+
+    //The tracks lagging behind the most advanced one are moved forward, so that
+    //the output timestamps never go backward.
+    fn align_tracks(&mut self) {
+        let sync_errors: Vec<(TrackType, i128)> = self
+            .tracks
+            .iter()
+            .filter_map(|(track_type, track)| Some((track_type.clone(), track.get_sync_error()?)))
+            .collect();
+        if sync_errors.len() < 2 {
+            return;
+        }
+
+        let max_sync_error = sync_errors
+            .iter()
+            .map(|(_, error)| *error)
+            .max()
+            .unwrap_or(0);
+        for (track_type, sync_error) in sync_errors {
+            let lag = max_sync_error - sync_error;
+            if lag <= MAX_SYNC_ERROR {
+                continue;
+            }
+            if let Some(track) = self.tracks.get_mut(&track_type) {
+                track.offset += ((lag * track.clock_rate as i128) >> 32) as i64;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvSynchronizer;
+    use crate::rtsp_track::TrackType;
+
+    #[test]
+    fn test_sync_timestamp() {
+        let mut av_sync = AvSynchronizer::new();
+        assert_eq!(av_sync.sync_timestamp(TrackType::Video, 1000), 0);
+        assert_eq!(av_sync.sync_timestamp(TrackType::Video, 4000), 3000);
+
+        let ntp_time: u64 = 3_900_000_000 << 32;
+        //the timestamps keep continuous after the first sender report
+        av_sync.on_sender_report(TrackType::Video, 90000, ntp_time, 90000);
+        assert_eq!(av_sync.sync_timestamp(TrackType::Video, 90000), 89000);
+
+        //the audio sender report is 0.5 seconds later than the video one,
+        //the audio lags behind by 89000/90000 + 0.5 seconds and is moved forward
+        assert_eq!(av_sync.sync_timestamp(TrackType::Audio, u32::MAX - 100), 0);
+        av_sync.on_sender_report(TrackType::Audio, 8000, ntp_time + (1 << 31), u32::MAX - 100);
+        assert_eq!(av_sync.sync_timestamp(TrackType::Video, 225000), 224000);
+        //the rtp timestamp wraps around
+        assert_eq!(av_sync.sync_timestamp(TrackType::Audio, 7899), 19911);
+    }
+
+    #[test]
+    fn test_sync_timestamp_never_decrease() {
+        let ntp_time: u64 = 3_900_000_000 << 32;
+        let mut av_sync = AvSynchronizer::new();
+        let mut last_timestamps = (0, 0);
+
+        //the audio frames are 2 seconds earlier than the video ones by the wall clock,
+        //the video is moved forward instead of moving the audio backward
+        for i in 0..100_u32 {
+            if i == 20 {
+                av_sync.on_sender_report(TrackType::Video, 90000, ntp_time, 1_000_000);
+            }
+            if i == 50 {
+                av_sync.on_sender_report(TrackType::Audio, 8000, ntp_time - (2 << 32), 500);
+            }
+
+            let video_timestamp = av_sync.sync_timestamp(TrackType::Video, 1_000_000 + i * 3600);
+            let audio_timestamp = av_sync.sync_timestamp(TrackType::Audio, 500 + i * 320);
+            assert!(video_timestamp >= last_timestamps.0);
+            assert!(audio_timestamp >= last_timestamps.1);
+            last_timestamps = (video_timestamp, audio_timestamp);
+        }
+
+        let video_time = last_timestamps.0 as f64 / 90000.0;
+        let audio_time = last_timestamps.1 as f64 / 8000.0;
+        assert!((video_time - audio_time - 2.0).abs() < 0.01);
+    }
+}
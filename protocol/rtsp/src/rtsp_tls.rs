@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use bytesio::bytesio::{NetType, TNetIO};
+use bytesio::bytesio_errors::{BytesIOError, BytesIOErrorValue};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::{TlsAcceptor, TlsStream};
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    //the PEM encoded certificate chain
+    pub cert_path: String,
+    //the PEM encoded PKCS#8 private key
+    pub key_path: String,
+    //offer the SRTP keys(a=crypto) in the DESCRIBE response
+    pub enable_srtp: bool,
+}
+
+impl TlsConfig {
+    pub fn new_acceptor(&self) -> io::Result<TlsAcceptor> {
+        let cert = std::fs::read(&self.cert_path)?;
+        let key = std::fs::read(&self.key_path)?;
+        let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(io::Error::other)?;
+        let acceptor = native_tls::TlsAcceptor::new(identity).map_err(io::Error::other)?;
+        Ok(TlsAcceptor::from(acceptor))
+    }
+}
+
+//The rtsp signaling and interleaved data over the TLS connection(rtsps://).
+pub struct TlsIO {
+    stream: TlsStream<TcpStream>,
+}
+
+impl TlsIO {
+    pub fn new(stream: TlsStream<TcpStream>) -> Self {
+        Self { stream }
+    }
+}
+
+#[async_trait]
+impl TNetIO for TlsIO {
+    fn get_net_type(&self) -> NetType {
+        NetType::TCP
+    }
+
+    async fn write(&mut self, bytes: Bytes) -> Result<(), BytesIOError> {
+        self.stream.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    async fn read_timeout(&mut self, duration: Duration) -> Result<BytesMut, BytesIOError> {
+        match tokio::time::timeout(duration, self.read()).await {
+            Ok(data) => data,
+            Err(err) => Err(BytesIOError {
+                value: BytesIOErrorValue::TimeoutError(err),
+            }),
+        }
+    }
+
+    async fn read(&mut self) -> Result<BytesMut, BytesIOError> {
+        let mut buf = BytesMut::with_capacity(4096);
+        if self.stream.read_buf(&mut buf).await? == 0 {
+            return Err(BytesIOError {
+                value: BytesIOErrorValue::NoneReturn,
+            });
+        }
+        Ok(buf)
+    }
+}
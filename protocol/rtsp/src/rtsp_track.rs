@@ -3,12 +3,18 @@ use super::rtsp_channel::RtpChannel;
 use super::rtsp_codec::RtspCodecInfo;
 use super::rtsp_transport::RtspTransport;
 use crate::rtp::errors::UnPackerError;
+use crate::rtp::rtcp::RTCP_SR_INTERVAL;
+use crate::rtp::srtp::SrtpKey;
 use crate::rtsp_channel::TRtpFunc;
-use bytes::BytesMut;
 use bytesio::bytes_reader::BytesReader;
 use bytesio::bytesio::TNetIO;
 use std::sync::Arc;
+use std::time::Duration;
+use streamhub::statistics::RtpReceiveStatistics;
+use tokio::sync::mpsc;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
 #[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
 pub enum TrackType {
@@ -35,34 +41,52 @@ pub struct RtspTrack {
     pub transport: RtspTransport,
     pub uri: String,
     pub media_control: String,
+    //the SDES key of the peer for the RTP/SAVP transport, the received packets are
+    //unprotected with it
+    pub srtp_key: Option<SrtpKey>,
+    //the SDES key advertised to the peer, the sent packets are protected with it
+    pub local_srtp_key: Option<SrtpKey>,
 
     pub rtp_channel: Arc<Mutex<RtpChannel>>,
     pub rtcp_channel: Arc<Mutex<RtcpChannel>>,
+    //the udp receive loops are aborted when the track is dropped.
+    receive_loop_handles: Vec<JoinHandle<()>>,
+    //the last time receiving rtp/rtcp data, used to detect the dead peers.
+    last_active_time: Arc<Mutex<Instant>>,
 }
 
 impl RtspTrack {
     pub fn new(track_type: TrackType, codec_info: RtspCodecInfo, media_control: String) -> Self {
+        let clock_rate = codec_info.sample_rate;
         let rtp_channel = RtpChannel::new(codec_info);
+        let rtcp_channel = RtcpChannel::new(rtp_channel.get_ssrc(), clock_rate);
 
         RtspTrack {
             track_type,
             media_control,
+            srtp_key: None,
+            local_srtp_key: None,
             transport: RtspTransport::default(),
             uri: String::default(),
             rtp_channel: Arc::new(Mutex::new(rtp_channel)),
-            rtcp_channel: Arc::new(Mutex::default()),
+            rtcp_channel: Arc::new(Mutex::new(rtcp_channel)),
+            receive_loop_handles: Vec::new(),
+            last_active_time: Arc::new(Mutex::new(Instant::now())),
         }
     }
 
     pub async fn rtp_receive_loop(&mut self, mut rtp_io: Box<dyn TNetIO + Send + Sync>) {
+        self.rtp_channel.lock().await.enable_jitter_buffer();
         let rtp_channel_out = self.rtp_channel.clone();
-        tokio::spawn(async move {
-            let mut reader = BytesReader::new(BytesMut::new());
-            let mut rtp_channel_in = rtp_channel_out.lock().await;
+        let last_active_time = self.last_active_time.clone();
+        let handle = tokio::spawn(async move {
             loop {
                 match rtp_io.read().await {
                     Ok(data) => {
-                        reader.extend_from_slice(&data[..]);
+                        *last_active_time.lock().await = Instant::now();
+                        //one udp datagram is one rtp packet
+                        let mut reader = BytesReader::new(data);
+                        let mut rtp_channel_in = rtp_channel_out.lock().await;
                         if let Err(err) = rtp_channel_in.on_packet(&mut reader).await {
                             log::error!("rtp_receive_loop on_packet error: {}", err);
                         }
@@ -74,27 +98,59 @@ impl RtspTrack {
                 }
             }
         });
+        self.receive_loop_handles.push(handle);
     }
-    //send and receive rtcp data in a UDP channel
+    //send and receive rtcp data in a UDP channel, the sender reports are sent
+    //periodically if the track sends rtp packets, and the NACKs are sent when
+    //the received rtp packets are missing.
     pub async fn rtcp_receive_loop(&mut self, rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) {
         let rtcp_channel_out = self.rtcp_channel.clone();
+        let last_active_time = self.last_active_time.clone();
 
-        tokio::spawn(async move {
-            let mut reader = BytesReader::new(BytesMut::new());
-            let mut rtcp_channel_in = rtcp_channel_out.lock().await;
+        let (nack_sender, mut nack_receiver) = mpsc::unbounded_channel();
+        self.rtp_channel.lock().await.set_nack_sender(nack_sender);
+
+        let handle = tokio::spawn(async move {
+            let mut sr_interval = tokio::time::interval(Duration::from_secs(RTCP_SR_INTERVAL));
 
             loop {
-                let data = match rtcp_io.lock().await.read().await {
-                    Ok(data) => data,
-                    Err(err) => {
-                        log::error!("read error: {:?}", err);
-                        break;
+                let io = rtcp_io.clone();
+                tokio::select! {
+                    data = async move { io.lock().await.read().await } => {
+                        let data = match data {
+                            Ok(data) => data,
+                            Err(err) => {
+                                log::error!("read error: {:?}", err);
+                                break;
+                            }
+                        };
+                        *last_active_time.lock().await = Instant::now();
+                        let mut reader = BytesReader::new(data);
+                        rtcp_channel_out
+                            .lock()
+                            .await
+                            .on_rtcp(&mut reader, rtcp_io.clone())
+                            .await;
+                    }
+                    _ = sr_interval.tick() => {
+                        if let Err(err) = rtcp_channel_out.lock().await.send_sr(rtcp_io.clone()).await {
+                            log::error!("send sr error: {}", err);
+                        }
+                    }
+                    Some(lost_sequences) = nack_receiver.recv() => {
+                        if let Err(err) = rtcp_channel_out
+                            .lock()
+                            .await
+                            .send_nack(&lost_sequences, rtcp_io.clone())
+                            .await
+                        {
+                            log::error!("send nack error: {}", err);
+                        }
                     }
-                };
-                reader.extend_from_slice(&data[..]);
-                rtcp_channel_in.on_rtcp(&mut reader, rtcp_io.clone()).await;
+                }
             }
         });
+        self.receive_loop_handles.push(handle);
     }
 
     pub async fn set_transport(&mut self, transport: RtspTransport) {
@@ -111,6 +167,7 @@ impl RtspTrack {
     }
 
     pub async fn on_rtp(&mut self, reader: &mut BytesReader) -> Result<(), UnPackerError> {
+        *self.last_active_time.lock().await = Instant::now();
         self.rtp_channel.lock().await.on_packet(reader).await
     }
 
@@ -119,10 +176,38 @@ impl RtspTrack {
         reader: &mut BytesReader,
         io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
     ) {
+        *self.last_active_time.lock().await = Instant::now();
         self.rtcp_channel.lock().await.on_rtcp(reader, io).await;
     }
 
+    //send the sender report over the rtsp connection(the interleaved tcp transport)
+    pub async fn send_sr(&mut self, io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) {
+        if let Err(err) = self.rtcp_channel.lock().await.send_sr(io).await {
+            log::error!("send sr error: {}", err);
+        }
+    }
+
+    pub async fn get_receive_statistics(&self) -> RtpReceiveStatistics {
+        let mut statistics = self.rtp_channel.lock().await.get_receive_statistics();
+        let rtcp_channel = self.rtcp_channel.lock().await;
+        statistics.nack_count = rtcp_channel.get_nack_count();
+        statistics.jitter = rtcp_channel.get_jitter();
+        statistics
+    }
+
+    pub async fn last_active_time(&self) -> Instant {
+        *self.last_active_time.lock().await
+    }
+
     pub async fn create_packer(&mut self, io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>) {
         self.rtp_channel.lock().await.create_packer(io);
     }
 }
+
+impl Drop for RtspTrack {
+    fn drop(&mut self) {
+        for handle in &self.receive_loop_handles {
+            handle.abort();
+        }
+    }
+}
@@ -20,11 +20,17 @@ pub enum ProtocolType {
 pub struct RtspTransport {
     pub cast_type: CastType,
     pub protocol_type: ProtocolType,
+    //the RTP/SAVP profile
+    pub is_srtp: bool,
     pub interleaved: Option<[u8; 2]>,
     pub transport_mod: Option<String>,
     pub client_port: Option<[u16; 2]>,
     pub server_port: Option<[u16; 2]>,
     pub ssrc: Option<u32>,
+    //the multicast group address, ports and ttl
+    pub destination: Option<String>,
+    pub port: Option<[u16; 2]>,
+    pub ttl: Option<u32>,
 }
 
 impl Unmarshal for RtspTransport {
@@ -41,6 +47,14 @@ impl Unmarshal for RtspTransport {
                 "RTP/AVP/UDP" | "RTP/AVP" => {
                     rtsp_transport.protocol_type = ProtocolType::UDP;
                 }
+                "RTP/SAVP/TCP" => {
+                    rtsp_transport.protocol_type = ProtocolType::TCP;
+                    rtsp_transport.is_srtp = true;
+                }
+                "RTP/SAVP/UDP" | "RTP/SAVP" => {
+                    rtsp_transport.protocol_type = ProtocolType::UDP;
+                    rtsp_transport.is_srtp = true;
+                }
                 "unicast" => {
                     rtsp_transport.cast_type = CastType::Unicast;
                 }
@@ -94,6 +108,28 @@ impl Unmarshal for RtspTransport {
                         rtsp_transport.ssrc = Some(ssrc);
                     }
                 }
+                "destination" => {
+                    rtsp_transport.destination = Some(kv[1].to_string());
+                }
+                "port" => {
+                    let ports = scanf!(kv[1], '-', u16, u16);
+
+                    let mut multicast_ports: [u16; 2] = [0, 0];
+                    if let Some(port) = ports.0 {
+                        multicast_ports[0] = port;
+                        multicast_ports[1] = port + 1;
+                    }
+                    if let Some(port) = ports.1 {
+                        multicast_ports[1] = port;
+                    }
+
+                    rtsp_transport.port = Some(multicast_ports);
+                }
+                "ttl" => {
+                    if let Ok(ttl) = kv[1].parse::<u32>() {
+                        rtsp_transport.ttl = Some(ttl);
+                    }
+                }
 
                 _ => {}
             }
@@ -105,9 +141,11 @@ impl Unmarshal for RtspTransport {
 
 impl Marshal for RtspTransport {
     fn marshal(&self) -> String {
-        let protocol_type = match self.protocol_type {
-            ProtocolType::TCP => "RTP/AVP/TCP",
-            ProtocolType::UDP => "RTP/AVP/UDP",
+        let protocol_type = match (&self.protocol_type, self.is_srtp) {
+            (ProtocolType::TCP, false) => "RTP/AVP/TCP",
+            (ProtocolType::UDP, false) => "RTP/AVP/UDP",
+            (ProtocolType::TCP, true) => "RTP/SAVP/TCP",
+            (ProtocolType::UDP, true) => "RTP/SAVP/UDP",
         };
 
         let cast_type = match self.cast_type {
@@ -127,6 +165,24 @@ impl Marshal for RtspTransport {
             String::from("")
         };
 
+        let destination = if let Some(destination) = &self.destination {
+            format!("destination={destination};")
+        } else {
+            String::from("")
+        };
+
+        let port = if let Some(ports) = self.port {
+            format!("port={}-{};", ports[0], ports[1])
+        } else {
+            String::from("")
+        };
+
+        let ttl = if let Some(ttl) = self.ttl {
+            format!("ttl={ttl};")
+        } else {
+            String::from("")
+        };
+
         let interleaved = if let Some(interleaveds) = self.interleaved {
             format!("interleaved={}-{};", interleaveds[0], interleaveds[1])
         } else {
@@ -145,7 +201,9 @@ impl Marshal for RtspTransport {
             String::from("")
         };
 
-        format!("{protocol_type};{cast_type};{client_port}{server_port}{interleaved}{ssrc}{mode}")
+        format!(
+            "{protocol_type};{cast_type};{destination}{client_port}{server_port}{port}{ttl}{interleaved}{ssrc}{mode}"
+        )
     }
 }
 
@@ -155,6 +213,8 @@ mod tests {
     use crate::global_trait::Marshal;
     use crate::global_trait::Unmarshal;
 
+    use super::CastType;
+    use super::ProtocolType;
     use super::RtspTransport;
 
     #[test]
@@ -175,4 +235,34 @@ mod tests {
 
         println!("marshal reslut: {}", parser.marshal());
     }
+
+    #[test]
+    fn test_parse_multicast_transport() {
+        let transport = RtspTransport::unmarshal(
+            "RTP/AVP;multicast;destination=239.0.0.1;port=20000-20001;ttl=16",
+        )
+        .unwrap();
+
+        assert_eq!(transport.cast_type, CastType::Multicast);
+        assert_eq!(transport.protocol_type, ProtocolType::UDP);
+        assert_eq!(transport.destination.as_deref(), Some("239.0.0.1"));
+        assert_eq!(transport.port, Some([20000, 20001]));
+        assert_eq!(transport.ttl, Some(16));
+        assert_eq!(
+            transport.marshal(),
+            "RTP/AVP/UDP;multicast;destination=239.0.0.1;port=20000-20001;ttl=16;"
+        );
+    }
+
+    #[test]
+    fn test_parse_srtp_transport() {
+        let transport = RtspTransport::unmarshal("RTP/SAVP;unicast;client_port=8000-8001").unwrap();
+
+        assert_eq!(transport.protocol_type, ProtocolType::UDP);
+        assert!(transport.is_srtp);
+        assert_eq!(
+            transport.marshal(),
+            "RTP/SAVP/UDP;unicast;client_port=8000-8001;"
+        );
+    }
 }
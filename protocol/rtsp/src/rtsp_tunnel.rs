@@ -0,0 +1,433 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use bytes::{Bytes, BytesMut};
+use bytesio::bytesio::{NetType, TNetIO, TcpIO};
+use bytesio::bytesio_errors::{BytesIOError, BytesIOErrorValue};
+use futures::{SinkExt, StreamExt};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+//the GET connection is dropped if no POST connection pairs it in this duration
+const TUNNEL_PAIR_TIMEOUT: Duration = Duration::from_secs(30);
+const HTTP_HEADER_MAX_LENGTH: usize = 4096;
+const HTTP_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+const TUNNEL_GET_RESPONSE: &[u8] = b"HTTP/1.0 200 OK\r\n\
+    Content-Type: application/x-rtsp-tunnelled\r\n\
+    Cache-Control: no-store\r\n\
+    Pragma: no-cache\r\n\
+    Connection: close\r\n\r\n";
+const TUNNEL_CONFLICT_RESPONSE: &[u8] = b"HTTP/1.0 409 Conflict\r\nConnection: close\r\n\r\n";
+
+pub enum ConnectionType {
+    Rtsp,
+    //the server to client half of the http tunnel
+    HttpTunnelGet { cookie: String },
+    //the client to server half of the http tunnel
+    HttpTunnelPost { cookie: String },
+    WebSocket,
+}
+
+struct HttpHeader {
+    method: String,
+    //the keys are lowercase
+    headers: HashMap<String, String>,
+    length: usize,
+}
+
+fn parse_http_header(data: &[u8]) -> Option<HttpHeader> {
+    let length = data.windows(4).position(|window| window == b"\r\n\r\n")? + 4;
+    let header = std::str::from_utf8(&data[..length]).ok()?;
+
+    let mut lines = header.lines();
+    let method = lines.next()?.split(' ').next()?.to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    Some(HttpHeader {
+        method,
+        headers,
+        length,
+    })
+}
+
+//Tell the rtsp connections from the http tunnel and websocket ones by the first request,
+//the request is only peeked so that the rtsp session or websocket handshake can read it again.
+async fn peek_http_header(stream: &TcpStream) -> io::Result<Option<HttpHeader>> {
+    let mut buf = vec![0; HTTP_HEADER_MAX_LENGTH];
+    let peek = async {
+        loop {
+            let length = stream.peek(&mut buf).await?;
+            if length == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            let data = &buf[..length];
+            if length >= 5 && !data.starts_with(b"GET ") && !data.starts_with(b"POST ") {
+                return Ok(None);
+            }
+            if let Some(header) = parse_http_header(data) {
+                return Ok(Some(header));
+            }
+            if length == buf.len() {
+                return Ok(None);
+            }
+            //wait for the rest of the header
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+
+    match tokio::time::timeout(HTTP_HEADER_TIMEOUT, peek).await {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+    }
+}
+
+//Detect the connection type, the http header of the tunnel connections is consumed and
+//the GET connection is answered by the tunnel manager.
+pub async fn accept_connection(stream: &mut TcpStream) -> io::Result<ConnectionType> {
+    let header = match peek_http_header(stream).await? {
+        Some(header) => header,
+        None => return Ok(ConnectionType::Rtsp),
+    };
+
+    let is_websocket = header
+        .headers
+        .get("upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    if is_websocket {
+        return Ok(ConnectionType::WebSocket);
+    }
+
+    let cookie = match header.headers.get("x-sessioncookie") {
+        Some(cookie) => cookie.clone(),
+        None => {
+            stream
+                .write_all(b"HTTP/1.0 400 Bad Request\r\nConnection: close\r\n\r\n")
+                .await?;
+            return Err(io::Error::from(io::ErrorKind::InvalidData));
+        }
+    };
+
+    let mut data = vec![0; header.length];
+    stream.read_exact(&mut data).await?;
+
+    if header.method == "GET" {
+        Ok(ConnectionType::HttpTunnelGet { cookie })
+    } else {
+        Ok(ConnectionType::HttpTunnelPost { cookie })
+    }
+}
+
+enum HttpTunnel {
+    //waiting for the POST connection
+    Pending(TcpIO),
+    //the new POST connections are sent to the tunnel io
+    Connected(mpsc::UnboundedSender<TcpIO>),
+}
+
+//Pair the GET and POST connections of the QuickTime style http tunnel by the x-sessioncookie.
+#[derive(Clone, Default)]
+pub struct HttpTunnelManager {
+    tunnels: Arc<Mutex<HashMap<String, HttpTunnel>>>,
+}
+
+impl HttpTunnelManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //The cookie identifies the tunnel, the GET connection reusing a cookie is rejected.
+    pub async fn on_get(&self, cookie: String, mut stream: TcpStream) -> io::Result<()> {
+        let duplicate_cookie = || {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("duplicate x-sessioncookie {cookie}"),
+            )
+        };
+        if self.tunnels.lock().unwrap().contains_key(&cookie) {
+            stream.write_all(TUNNEL_CONFLICT_RESPONSE).await?;
+            return Err(duplicate_cookie());
+        }
+        stream.write_all(TUNNEL_GET_RESPONSE).await?;
+
+        //the cookie may be taken by another GET connection while responding
+        match self.tunnels.lock().unwrap().entry(cookie.clone()) {
+            Entry::Occupied(_) => return Err(duplicate_cookie()),
+            Entry::Vacant(entry) => {
+                entry.insert(HttpTunnel::Pending(TcpIO::new(stream)));
+            }
+        }
+
+        let tunnels = self.tunnels.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(TUNNEL_PAIR_TIMEOUT).await;
+            let mut tunnels = tunnels.lock().unwrap();
+            if let Some(HttpTunnel::Pending(_)) = tunnels.get(&cookie) {
+                log::info!("http tunnel {} is not paired, close it", cookie);
+                tunnels.remove(&cookie);
+            }
+        });
+        Ok(())
+    }
+
+    //Return the io of the tunnel when the first POST connection arrives, the following
+    //POST connections of the same tunnel are passed to the existing one.
+    pub fn on_post(&self, cookie: String, io: TcpIO) -> Option<HttpTunnelIO> {
+        let mut tunnels = self.tunnels.lock().unwrap();
+        match tunnels.remove(&cookie) {
+            Some(HttpTunnel::Pending(get_io)) => {
+                let (post_sender, post_receiver) = mpsc::unbounded_channel();
+                let _ = post_sender.send(io);
+                tunnels.insert(cookie.clone(), HttpTunnel::Connected(post_sender));
+
+                Some(HttpTunnelIO {
+                    cookie,
+                    get_io,
+                    post_io: None,
+                    post_receiver,
+                    base64_data: BytesMut::new(),
+                    manager: self.clone(),
+                })
+            }
+            Some(HttpTunnel::Connected(post_sender)) => {
+                if post_sender.send(io).is_ok() {
+                    tunnels.insert(cookie, HttpTunnel::Connected(post_sender));
+                }
+                None
+            }
+            None => {
+                log::error!("no GET connection found for the http tunnel {}", cookie);
+                None
+            }
+        }
+    }
+}
+
+//The rtsp responses and interleaved data are sent through the GET connection, and the
+//base64 encoded rtsp requests are received from the POST connection.
+pub struct HttpTunnelIO {
+    cookie: String,
+    get_io: TcpIO,
+    post_io: Option<TcpIO>,
+    post_receiver: mpsc::UnboundedReceiver<TcpIO>,
+    //the base64 data which is not decoded yet
+    base64_data: BytesMut,
+    manager: HttpTunnelManager,
+}
+
+//Decode the complete base64 data, the requests are encoded separately by the client,
+//so the padding may be in the middle of the data.
+fn decode_base64_data(base64_data: &mut BytesMut) -> Option<BytesMut> {
+    while !base64_data.is_empty() {
+        let length = match base64_data.iter().position(|c| *c == b'=') {
+            Some(padding_index) => (padding_index / 4 + 1) * 4,
+            None => base64_data.len() / 4 * 4,
+        };
+        if length == 0 || length > base64_data.len() {
+            return None;
+        }
+
+        let data = base64_data.split_to(length);
+        match general_purpose::STANDARD.decode(&data) {
+            Ok(data) => return Some(BytesMut::from(&data[..])),
+            Err(err) => log::error!("http tunnel decode error: {}", err),
+        }
+    }
+    None
+}
+
+impl Drop for HttpTunnelIO {
+    fn drop(&mut self) {
+        if let Ok(mut tunnels) = self.manager.tunnels.lock() {
+            tunnels.remove(&self.cookie);
+        }
+    }
+}
+
+#[async_trait]
+impl TNetIO for HttpTunnelIO {
+    fn get_net_type(&self) -> NetType {
+        NetType::TCP
+    }
+
+    async fn write(&mut self, bytes: Bytes) -> Result<(), BytesIOError> {
+        self.get_io.write(bytes).await
+    }
+
+    async fn read_timeout(&mut self, duration: Duration) -> Result<BytesMut, BytesIOError> {
+        match tokio::time::timeout(duration, self.read()).await {
+            Ok(data) => data,
+            Err(err) => Err(BytesIOError {
+                value: BytesIOErrorValue::TimeoutError(err),
+            }),
+        }
+    }
+
+    async fn read(&mut self) -> Result<BytesMut, BytesIOError> {
+        loop {
+            if let Some(data) = decode_base64_data(&mut self.base64_data) {
+                return Ok(data);
+            }
+
+            match &mut self.post_io {
+                Some(post_io) => match post_io.read().await {
+                    Ok(data) => {
+                        let data = data.iter().filter(|c| !c.is_ascii_whitespace());
+                        self.base64_data.extend(data);
+                    }
+                    //the client may close the POST connection and send the next requests with a new one
+                    Err(_) => self.post_io = None,
+                },
+                None => match self.post_receiver.recv().await {
+                    Some(post_io) => self.post_io = Some(post_io),
+                    None => {
+                        return Err(BytesIOError {
+                            value: BytesIOErrorValue::NoneReturn,
+                        })
+                    }
+                },
+            }
+        }
+    }
+}
+
+//Each websocket message carries rtsp messages or interleaved binary data.
+pub struct WebSocketIO {
+    stream: WebSocketStream<TcpStream>,
+}
+
+//echo the rtsp sub protocol if the client requests it
+#[allow(clippy::result_large_err)]
+fn echo_sub_protocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    let is_rtsp_protocol = request
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|protocols| protocols.to_str().ok())
+        .is_some_and(|protocols| protocols.split(',').any(|p| p.trim() == "rtsp"));
+    if is_rtsp_protocol {
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", "rtsp".parse().unwrap());
+    }
+    Ok(response)
+}
+
+impl WebSocketIO {
+    pub async fn accept(stream: TcpStream) -> Result<Self, BytesIOError> {
+        match tokio_tungstenite::accept_hdr_async(stream, echo_sub_protocol).await {
+            Ok(stream) => Ok(Self { stream }),
+            Err(err) => Err(to_bytesio_error(err)),
+        }
+    }
+}
+
+fn to_bytesio_error(err: tokio_tungstenite::tungstenite::Error) -> BytesIOError {
+    BytesIOError {
+        value: BytesIOErrorValue::IOError(io::Error::other(err)),
+    }
+}
+
+#[async_trait]
+impl TNetIO for WebSocketIO {
+    fn get_net_type(&self) -> NetType {
+        NetType::TCP
+    }
+
+    async fn write(&mut self, bytes: Bytes) -> Result<(), BytesIOError> {
+        self.stream
+            .send(Message::Binary(bytes.to_vec()))
+            .await
+            .map_err(to_bytesio_error)
+    }
+
+    async fn read_timeout(&mut self, duration: Duration) -> Result<BytesMut, BytesIOError> {
+        match tokio::time::timeout(duration, self.read()).await {
+            Ok(data) => data,
+            Err(err) => Err(BytesIOError {
+                value: BytesIOErrorValue::TimeoutError(err),
+            }),
+        }
+    }
+
+    async fn read(&mut self) -> Result<BytesMut, BytesIOError> {
+        loop {
+            match self.stream.next().await {
+                Some(Ok(Message::Binary(data))) => return Ok(BytesMut::from(&data[..])),
+                Some(Ok(Message::Text(data))) => return Ok(BytesMut::from(data.as_bytes())),
+                //the pong is replied by the websocket stream
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(BytesIOError {
+                        value: BytesIOErrorValue::NoneReturn,
+                    })
+                }
+                Some(Err(err)) => return Err(to_bytesio_error(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tunnel_header() {
+        let header = parse_http_header(
+            b"POST /live/test HTTP/1.0\r\nx-sessioncookie: 9b6f2e\r\n\
+            Content-Type: application/x-rtsp-tunnelled\r\n\r\nT1BUSU9OUyBydHNw",
+        )
+        .unwrap();
+        assert_eq!(header.method, "POST");
+        assert_eq!(header.headers.get("x-sessioncookie").unwrap(), "9b6f2e");
+        assert_eq!(header.length, 97);
+        assert!(parse_http_header(b"GET /live/test HTTP/1.0\r\n").is_none());
+    }
+
+    #[test]
+    fn test_decode_tunnel_data() {
+        //"OPTIONS" + "TEARDOWN" encoded separately and split by the network
+        let mut base64_data = BytesMut::from(&b"T1BUSU9O"[..]);
+        assert_eq!(decode_base64_data(&mut base64_data).unwrap(), "OPTION");
+        base64_data.extend_from_slice(b"Uw==VEVBUkRP");
+        assert_eq!(decode_base64_data(&mut base64_data).unwrap(), "S");
+        assert_eq!(decode_base64_data(&mut base64_data).unwrap(), "TEARDO");
+        base64_data.extend_from_slice(b"V0");
+        assert!(decode_base64_data(&mut base64_data).is_none());
+        base64_data.extend_from_slice(b"4=");
+        assert_eq!(decode_base64_data(&mut base64_data).unwrap(), "WN");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_tunnel_cookie() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let manager = HttpTunnelManager::new();
+
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            let mut client = TcpStream::connect(address).await.unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            let result = manager.on_get(String::from("9b6f2e"), server).await;
+
+            let mut response = vec![0; 1024];
+            let length = client.read(&mut response).await.unwrap();
+            response.truncate(length);
+            responses.push((result.is_ok(), response, client));
+        }
+        assert!(responses[0].0);
+        assert!(responses[0].1.starts_with(b"HTTP/1.0 200 OK"));
+        assert!(!responses[1].0);
+        assert_eq!(responses[1].1, TUNNEL_CONFLICT_RESPONSE);
+    }
+}
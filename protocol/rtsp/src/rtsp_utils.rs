@@ -22,8 +22,31 @@ pub fn print(title: &str, data: BytesMut) {
     println!("===========")
 }
 
+//Decode the %XX escaped characters, e.g. the user name and password of a url.
+pub fn percent_decode(data: &str) -> String {
+    let bytes = data.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%'
+            && idx + 2 < bytes.len()
+            && bytes[idx + 1].is_ascii_hexdigit()
+            && bytes[idx + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or_default();
+            decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+            idx += 3;
+            continue;
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 #[cfg(test)]
 mod tests {
+    use super::percent_decode;
 
     #[test]
     fn test_scanf() {
@@ -35,4 +58,12 @@ mod tests {
             println!("a:{a} b:{b} c:{c} ");
         }
     }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("p%40ss%3Aword"), "p@ss:word");
+        assert_eq!(percent_decode("%E4%B8%AD"), "\u{4e2d}");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
 }
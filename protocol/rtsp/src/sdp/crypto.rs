@@ -0,0 +1,90 @@
+use crate::global_trait::{Marshal, Unmarshal};
+use base64::{engine::general_purpose, Engine as _};
+
+//https://www.rfc-editor.org/rfc/rfc4568#section-9.1
+#[derive(Debug, Clone, Default)]
+pub struct Crypto {
+    pub tag: u32,
+    pub crypto_suite: String,
+    //the base64 encoded master key and salt
+    pub key: String,
+    //the optional lifetime and MKI which follow the key
+    pub key_extra: String,
+}
+
+impl Crypto {
+    pub fn new(tag: u32, crypto_suite: &str, master_key_salt: &[u8]) -> Self {
+        Self {
+            tag,
+            crypto_suite: crypto_suite.to_string(),
+            key: general_purpose::STANDARD.encode(master_key_salt),
+            key_extra: String::from(""),
+        }
+    }
+
+    pub fn get_master_key_salt(&self) -> Option<Vec<u8>> {
+        general_purpose::STANDARD.decode(&self.key).ok()
+    }
+}
+
+impl Unmarshal for Crypto {
+    // a=crypto:1 AES_CM_128_HMAC_SHA1_80 inline:PS1uQCVeeCFCanVmcjkpPywjNWhcYD0mXXtxaVBR|2^20|1:32
+    fn unmarshal(raw_data: &str) -> Option<Self> {
+        let mut crypto = Crypto::default();
+
+        let parts: Vec<&str> = raw_data.split_whitespace().collect();
+        crypto.tag = parts.first()?.parse::<u32>().ok()?;
+        crypto.crypto_suite = parts.get(1)?.to_string();
+
+        //only the first key of the inline key params is used
+        let key_params = parts.get(2)?.split(';').next()?.strip_prefix("inline:")?;
+        match key_params.split_once('|') {
+            Some((key, key_extra)) => {
+                crypto.key = key.to_string();
+                crypto.key_extra = key_extra.to_string();
+            }
+            None => {
+                crypto.key = key_params.to_string();
+            }
+        }
+
+        Some(crypto)
+    }
+}
+
+impl Marshal for Crypto {
+    fn marshal(&self) -> String {
+        let mut crypto = format!("{} {} inline:{}", self.tag, self.crypto_suite, self.key);
+        if !self.key_extra.is_empty() {
+            crypto = format!("{}|{}", crypto, self.key_extra);
+        }
+        crypto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::global_trait::{Marshal, Unmarshal};
+
+    use super::Crypto;
+
+    #[test]
+    fn test_marshal_unmarshal_crypto() {
+        let crypto = Crypto::unmarshal(
+            "1 AES_CM_128_HMAC_SHA1_80 inline:PS1uQCVeeCFCanVmcjkpPywjNWhcYD0mXXtxaVBR|2^20|1:32",
+        )
+        .unwrap();
+
+        assert_eq!(crypto.tag, 1);
+        assert_eq!(crypto.crypto_suite, "AES_CM_128_HMAC_SHA1_80");
+        assert_eq!(crypto.key_extra, "2^20|1:32");
+        assert_eq!(crypto.get_master_key_salt().unwrap().len(), 30);
+        assert_eq!(
+            crypto.marshal(),
+            "1 AES_CM_128_HMAC_SHA1_80 inline:PS1uQCVeeCFCanVmcjkpPywjNWhcYD0mXXtxaVBR|2^20|1:32"
+        );
+
+        assert!(Crypto::unmarshal("1 AES_CM_128_HMAC_SHA1_80").is_none());
+    }
+}
@@ -1,7 +1,9 @@
+pub mod crypto;
 pub mod fmtp;
 pub mod rtpmap;
 
 use crate::global_trait::{Marshal, Unmarshal};
+use crypto::Crypto;
 use rtpmap::RtpMap;
 use std::collections::HashMap;
 
@@ -60,11 +62,14 @@ m=audio 11704 RTP/AVP 96 97 98 0 8 18 101 99 100 */
 pub struct SdpMediaInfo {
     pub media_type: String,
     port: usize,
-    protocol: String,
+    //RTP/AVP or RTP/SAVP
+    pub protocol: String,
     fmts: Vec<u8>,
     bandwidth: Option<Bandwidth>,
     pub rtpmap: RtpMap,
     pub fmtp: Option<fmtp::Fmtp>,
+    //the SDES key of the SRTP
+    pub crypto: Option<Crypto>,
     pub attributes: HashMap<String, String>,
 }
 
@@ -125,6 +130,24 @@ impl Unmarshal for SdpMediaInfo {
             cur_param_idx += 1;
         }
 
+        //the static payload types may be offered without a=rtpmap
+        //https://www.rfc-editor.org/rfc/rfc3551#section-6
+        if let Some(fmt) = sdp_media.fmts.first() {
+            let encoding_name = match fmt {
+                0 => "PCMU",
+                8 => "PCMA",
+                _ => "",
+            };
+            if !encoding_name.is_empty() {
+                sdp_media.rtpmap = RtpMap {
+                    payload_type: *fmt as u16,
+                    encoding_name: encoding_name.to_string(),
+                    clock_rate: 8000,
+                    encoding_param: String::from(""),
+                };
+            }
+        }
+
         Some(sdp_media)
     }
 }
@@ -169,6 +192,10 @@ impl Marshal for SdpMediaInfo {
             sdp_media_info = format!("{}a=fmtp:{}", sdp_media_info, fmtp.marshal());
         }
 
+        if let Some(crypto) = &self.crypto {
+            sdp_media_info = format!("{}a=crypto:{}\r\n", sdp_media_info, crypto.marshal());
+        }
+
         for (k, v) in &self.attributes {
             sdp_media_info = format!("{sdp_media_info}a={k}:{v}\r\n");
         }
@@ -271,6 +298,13 @@ impl Unmarshal for Sdp {
                                         Fmtp::new(&cur_media.rtpmap.encoding_name, attr_value);
                                     continue;
                                 }
+                                "crypto" => {
+                                    //only the first key is used if several ones are offered
+                                    if cur_media.crypto.is_none() {
+                                        cur_media.crypto = Crypto::unmarshal(attr_value);
+                                    }
+                                    continue;
+                                }
                                 _ => {}
                             }
                         }
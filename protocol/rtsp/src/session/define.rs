@@ -28,6 +28,9 @@ pub mod rtsp_method_name {
     ];
 }
 
+//the default seconds to tear down the session if no request or rtp/rtcp data is received
+pub const SESSION_TIMEOUT: u64 = 60;
+
 pub enum SessionType {
     Client,
     Server,
@@ -8,6 +8,7 @@ use {
     std::str::Utf8Error,
     streamhub::errors::StreamHubError,
     tokio::sync::oneshot::error::RecvError,
+    xmpegts::errors::MpegTsError,
 };
 
 #[derive(Debug)]
@@ -41,6 +42,22 @@ pub enum SessionErrorValue {
     AuthError(#[cause] AuthError),
     #[fail(display = "Channel receive error")]
     ChannelRecvError,
+    #[fail(display = "invalid rtsp url: {}", _0)]
+    InvalidUrl(String),
+    #[fail(display = "rtsp response status error: {}", _0)]
+    ResponseStatusError(u16),
+    #[fail(display = "corrupted rtsp response")]
+    CorruptedResponse,
+    #[fail(display = "no sdp found in the describe response")]
+    SdpNotFound,
+    #[fail(display = "no supported track found in the sdp")]
+    NoSupportedTrack,
+    #[fail(display = "no multicast group is allocated for the stream: {}", _0)]
+    MulticastGroupNotFound(String),
+    #[fail(display = "no record found for the stream: {}", _0)]
+    RecordNotFound(String),
+    #[fail(display = "mpegts error: {}", _0)]
+    MpegTsError(#[cause] MpegTsError),
 }
 
 impl From<BytesIOError> for SessionError {
@@ -115,6 +132,14 @@ impl From<AuthError> for SessionError {
     }
 }
 
+impl From<MpegTsError> for SessionError {
+    fn from(error: MpegTsError) -> Self {
+        SessionError {
+            value: SessionErrorValue::MpegTsError(error),
+        }
+    }
+}
+
 impl fmt::Display for SessionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.value, f)
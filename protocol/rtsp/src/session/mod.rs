@@ -1,5 +1,9 @@
+pub mod client_session;
 pub mod define;
 pub mod errors;
+pub mod multicast;
+pub mod playback;
+pub mod rtmp2rtsp;
 use super::rtsp_codec;
 use crate::global_trait::Marshal;
 use crate::global_trait::Unmarshal;
@@ -12,16 +16,30 @@ use commonlib::http::HttpResponse as RtspResponse;
 use commonlib::http::Marshal as RtspMarshal;
 use commonlib::http::Unmarshal as RtspUnmarshal;
 
+use crate::rtp::rtcp::RTCP_SR_INTERVAL;
 use crate::rtp::RtpPacket;
 use crate::rtsp_range::RtspRange;
+use crate::rtsp_range::RtspRangeType;
 
+use crate::rtp::srtp::SrtpIO;
+use crate::rtp::srtp::SrtpKey;
+use crate::rtp::srtp::AES_CM_128_HMAC_SHA1_80;
+use crate::sdp::crypto::Crypto;
 use crate::sdp::fmtp::Fmtp;
+use crate::sdp::SdpMediaInfo;
 
+use crate::rtsp_codec::RtspCodecId;
 use crate::rtsp_codec::RtspCodecInfo;
+use crate::rtsp_sync::AvSynchronizer;
 use crate::rtsp_track::RtspTrack;
 use crate::rtsp_track::TrackType;
+use crate::rtsp_transport::CastType;
 use crate::rtsp_transport::ProtocolType;
 use crate::rtsp_transport::RtspTransport;
+use multicast::MulticastGroup;
+use multicast::MulticastManager;
+use playback::PlaybackConfig;
+use playback::RecordPlayback;
 
 use byteorder::BigEndian;
 use bytes::BytesMut;
@@ -33,6 +51,7 @@ use bytesio::bytesio::UdpIO;
 use errors::SessionError;
 use errors::SessionErrorValue;
 use http::StatusCode;
+use streamhub::define::AudioCodecType;
 use streamhub::define::DataSender;
 use streamhub::define::MediaInfo;
 use streamhub::define::VideoCodecType;
@@ -43,26 +62,28 @@ use super::sdp::Sdp;
 
 use async_trait::async_trait;
 use bytesio::bytesio::TNetIO;
-use bytesio::bytesio::TcpIO;
 use define::rtsp_method_name;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
 use tokio::sync::mpsc;
 
 use commonlib::auth::Auth;
+use std::time::Duration;
 use streamhub::{
     define::{
-        FrameData, Information, InformationSender, NotifyInfo, PublishType, PublisherInfo,
-        StreamHubEvent, StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler,
+        FrameData, FrameDataSender, Information, InformationSender, NotifyInfo, PublishType,
+        PublisherInfo, StatisticData, StatisticDataSender, StreamHubEvent, StreamHubEventSender,
+        SubFrameDataReceiver, SubscribeType, SubscriberInfo, TStreamHandler,
     },
     errors::{StreamHubError, StreamHubErrorValue},
-    statistics::StatisticsStream,
+    statistics::{RtpReceiveStatistics, StatisticsStream},
     stream::StreamIdentifier,
     utils::{RandomDigitCount, Uuid},
 };
-use tokio::net::TcpStream;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 pub struct RtspServerSession {
     io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
@@ -72,12 +93,33 @@ pub struct RtspServerSession {
     tracks: HashMap<TrackType, RtspTrack>,
     sdp: Sdp,
     pub session_id: Option<Uuid>,
-    session_type: define::SessionType,
 
     stream_handler: Arc<RtspStreamHandler>,
     event_producer: StreamHubEventSender,
 
     auth: Option<Auth>,
+
+    //the stream path of the DESCRIBE request
+    stream_path: String,
+    multicast_manager: Option<MulticastManager>,
+    //the multicast group joined by this session
+    multicast_group: Option<MulticastGroup>,
+    playback_config: Option<PlaybackConfig>,
+    //the record played back by this session
+    playback: Option<RecordPlayback>,
+    is_playing_back: bool,
+    //offer the SRTP keys to the players, only enabled over the TLS connection
+    enable_srtp: bool,
+    //tear down the session if no request or rtp/rtcp data is received in the seconds
+    session_timeout: u64,
+
+    is_published: bool,
+    is_subscribed: bool,
+    //the frames of the stream hub sent to the player after PLAY
+    frame_receiver: Option<SubFrameDataReceiver>,
+    statistic_data_sender: Option<StatisticDataSender>,
+    //the last time receiving a request or interleaved data
+    last_active_time: Instant,
 }
 
 pub struct InterleavedBinaryData {
@@ -111,9 +153,13 @@ impl InterleavedBinaryData {
 
 impl RtspServerSession {
     pub fn new(
-        stream: TcpStream,
+        net_io: Box<dyn TNetIO + Send + Sync>,
         event_producer: StreamHubEventSender,
         auth: Option<Auth>,
+        multicast_manager: Option<MulticastManager>,
+        playback_config: Option<PlaybackConfig>,
+        enable_srtp: bool,
+        session_timeout: u64,
     ) -> Self {
         // let remote_addr = if let Ok(addr) = stream.peer_addr() {
         //     log::info!("server session: {}", addr.to_string());
@@ -122,7 +168,6 @@ impl RtspServerSession {
         //     None
         // };
 
-        let net_io: Box<dyn TNetIO + Send + Sync> = Box::new(TcpIO::new(stream));
         let io = Arc::new(Mutex::new(net_io));
 
         Self {
@@ -132,44 +177,148 @@ impl RtspServerSession {
             tracks: HashMap::new(),
             sdp: Sdp::default(),
             session_id: None,
-            session_type: define::SessionType::Server,
             event_producer,
             stream_handler: Arc::new(RtspStreamHandler::new()),
             auth,
+            stream_path: String::from(""),
+            multicast_manager,
+            multicast_group: None,
+            playback_config,
+            playback: None,
+            is_playing_back: false,
+            enable_srtp,
+            session_timeout,
+            is_published: false,
+            is_subscribed: false,
+            frame_receiver: None,
+            statistic_data_sender: None,
+            last_active_time: Instant::now(),
         }
     }
 
     pub async fn run(&mut self) -> Result<(), SessionError> {
+        let session_timeout = Duration::from_secs(self.session_timeout);
+        //the interval panics if it is zero
+        let mut check_interval =
+            tokio::time::interval((session_timeout / 4).max(Duration::from_secs(1)));
+        let mut sr_interval = tokio::time::interval(Duration::from_secs(RTCP_SR_INTERVAL));
+        let mut frame_receiver: Option<SubFrameDataReceiver> = None;
+
         loop {
-            while self.reader.len() < 4 {
-                let data = self.io.lock().await.read().await?;
-                self.reader.extend_from_slice(&data[..]);
+            self.process_buffered_data().await?;
+
+            //the receiver is taken after PLAY and dropped after PAUSE/TEARDOWN
+            if let Some(receiver) = self.frame_receiver.take() {
+                frame_receiver = Some(receiver);
+            }
+            if !self.is_subscribed && !self.is_playing_back {
+                frame_receiver = None;
             }
 
-            if let Ok(data) = InterleavedBinaryData::new(&mut self.reader) {
-                match data {
-                    Some(a) => {
-                        if self.reader.len() < a.length as usize {
-                            let data = self.io.lock().await.read().await?;
-                            self.reader.extend_from_slice(&data[..]);
+            let io = self.io.clone();
+            tokio::select! {
+                data = async move { io.lock().await.read().await } => {
+                    self.reader.extend_from_slice(&data?[..]);
+                }
+                frame_data = async { frame_receiver.as_mut()?.recv().await }, if frame_receiver.is_some() => {
+                    match frame_data {
+                        Some(frame_data) => self.on_frame_data(frame_data).await?,
+                        //wait for the client to seek or tear down at the end of the record
+                        None if self.is_playing_back => {
+                            self.is_playing_back = false;
+                        }
+                        None => {
+                            self.leave_stream_hub().await;
+                            return Err(SessionError {
+                                value: SessionErrorValue::CannotReceiveFrameData,
+                            });
+                        }
+                    }
+                }
+                _ = sr_interval.tick() => {
+                    //the sender reports over udp are sent in the rtcp receive loops
+                    for track in self.tracks.values_mut() {
+                        if track.transport.interleaved.is_some() {
+                            track.send_sr(self.io.clone()).await;
                         }
-                        self.on_rtp_over_rtsp_message(a.channel_identifier, a.length as usize)
-                            .await?;
                     }
-                    None => {
-                        self.on_rtsp_message().await?;
+                    if let (true, Some(sender)) = (self.is_published, &self.statistic_data_sender) {
+                        send_rtp_receive_statistics(&self.tracks, sender).await;
+                    }
+                }
+                _ = check_interval.tick() => {
+                    if self.get_last_active_time().await.elapsed() > session_timeout {
+                        log::info!(
+                            "rtsp session {} of {} timeout, tear it down",
+                            self.session_id.map(|id| id.to_string()).unwrap_or_default(),
+                            self.stream_path
+                        );
+                        self.leave_stream_hub().await;
+                        return Ok(());
                     }
                 }
             }
         }
     }
 
+    //Handle the interleaved rtp/rtcp data and the whole rtsp messages in the buffer.
+    async fn process_buffered_data(&mut self) -> Result<(), SessionError> {
+        while self.reader.len() >= 4 {
+            if self.reader.get(0)? == 0x24 {
+                let length =
+                    u16::from_be_bytes([self.reader.get(2)?, self.reader.get(3)?]) as usize;
+                if self.reader.len() < 4 + length {
+                    break;
+                }
+                if let Ok(Some(data)) = InterleavedBinaryData::new(&mut self.reader) {
+                    self.on_rtp_over_rtsp_message(data.channel_identifier, data.length as usize)
+                        .await?;
+                }
+            } else {
+                let data = self.reader.get_remaining_bytes();
+                if !data.windows(4).any(|window| window == b"\r\n\r\n") {
+                    break;
+                }
+                self.on_rtsp_message().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_frame_data(&mut self, frame_data: FrameData) -> Result<(), SessionError> {
+        let (track_type, timestamp, data) = match frame_data {
+            FrameData::Audio { timestamp, data } => (TrackType::Audio, timestamp, data),
+            FrameData::Video { timestamp, data } => (TrackType::Video, timestamp, data),
+            _ => return Ok(()),
+        };
+
+        if let Some(track) = self.tracks.get_mut(&track_type) {
+            track
+                .rtp_channel
+                .lock()
+                .await
+                .on_frame(data, timestamp)
+                .await?;
+        }
+        Ok(())
+    }
+
+    //The session is alive if it receives requests(keepalive) or rtp/rtcp data of any track.
+    async fn get_last_active_time(&self) -> Instant {
+        let mut last_active_time = self.last_active_time;
+        for track in self.tracks.values() {
+            last_active_time = last_active_time.max(track.last_active_time().await);
+        }
+        last_active_time
+    }
+
     async fn on_rtp_over_rtsp_message(
         &mut self,
         channel_identifier: u8,
         length: usize,
     ) -> Result<(), SessionError> {
         let mut cur_reader = BytesReader::new(self.reader.read_bytes(length)?);
+        self.last_active_time = Instant::now();
 
         for track in self.tracks.values_mut() {
             if let Some(interleaveds) = track.transport.interleaved {
@@ -192,41 +341,52 @@ impl RtspServerSession {
         let rtsp_request: RtspRequest;
         let mut retry_count = 0;
         loop {
-            // TODO(all) : shoud check if have '\r\n\r\n' firstly.
             let data = self.reader.get_remaining_bytes();
-            if let Some(rtsp_request_data) = RtspRequest::unmarshal(std::str::from_utf8(&data)?) {
+            //only the message is consumed, the following requests or interleaved data
+            //are kept in the buffer
+            let header_end_idx = data
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .map_or(0, |idx| idx + 4);
+            if let Some(rtsp_request_data) =
+                RtspRequest::unmarshal(std::str::from_utf8(&data[..header_end_idx])?)
+            {
                 // TCP packet sticking issue, if have content_length in header.
                 // should check the body
-                if let Some(content_length) =
-                    rtsp_request_data.get_header(&String::from("Content-Length"))
-                {
-                    if let Ok(uint_num) = content_length.parse::<usize>() {
-                        if rtsp_request_data.body.is_none()
-                            || uint_num > rtsp_request_data.body.clone().unwrap().len()
-                        {
-                            if retry_count >= 5 {
-                                log::error!(
-                                    "corrupted rtsp message={}",
-                                    std::str::from_utf8(&data)?
-                                );
-                                return Ok(());
-                            }
-                            retry_count += 1;
-                            let data_recv = self.io.lock().await.read().await?;
-                            self.reader.extend_from_slice(&data_recv[..]);
-                            continue;
-                        }
+                let content_length = rtsp_request_data
+                    .get_header(&String::from("Content-Length"))
+                    .and_then(|content_length| content_length.parse::<usize>().ok())
+                    .unwrap_or_default();
+                if data.len() < header_end_idx + content_length {
+                    if retry_count >= 5 {
+                        log::error!("corrupted rtsp message={}", String::from_utf8_lossy(&data));
+                        self.reader.extract_remaining_bytes();
+                        return Ok(());
                     }
+                    retry_count += 1;
+                    let data_recv = self.io.lock().await.read().await?;
+                    self.reader.extend_from_slice(&data_recv[..]);
+                    continue;
+                }
+                let message = self.reader.read_bytes(header_end_idx + content_length)?;
+                match RtspRequest::unmarshal(std::str::from_utf8(&message)?) {
+                    Some(rtsp_request_data) => rtsp_request = rtsp_request_data,
+                    None => return Ok(()),
                 }
-                rtsp_request = rtsp_request_data;
-                self.reader.extract_remaining_bytes();
             } else {
-                log::error!("corrupted rtsp message={}", std::str::from_utf8(&data)?);
+                log::error!("corrupted rtsp message={}", String::from_utf8_lossy(&data));
+                self.reader.extract_remaining_bytes();
                 return Ok(());
             }
             break;
         }
 
+        self.last_active_time = Instant::now();
+        if !self.check_session_id(&rtsp_request) {
+            let response = Self::gen_rtsp_error_response(454, "Session Not Found", &rtsp_request);
+            return self.send_response(&response).await;
+        }
+
         match rtsp_request.method.as_str() {
             rtsp_method_name::OPTIONS => {
                 self.handle_options(&rtsp_request).await?;
@@ -245,22 +405,52 @@ impl RtspServerSession {
                     log::info!("handle_play error: {}", err);
                 }
             }
+            rtsp_method_name::PAUSE if self.playback.is_some() => {
+                self.is_playing_back = false;
+                let response = Self::gen_response(http::StatusCode::OK, &rtsp_request);
+                self.send_response(&response).await?;
+            }
             rtsp_method_name::RECORD => {
                 self.handle_record(&rtsp_request).await?;
             }
             rtsp_method_name::TEARDOWN => {
-                self.handle_teardown(&rtsp_request)?;
+                self.handle_teardown(&rtsp_request).await?;
+            }
+            //the empty GET_PARAMETER/SET_PARAMETER requests are used for keepalive
+            rtsp_method_name::GET_PARAMETER => {
+                let response = Self::gen_response(http::StatusCode::OK, &rtsp_request);
+                self.send_response(&response).await?;
+            }
+            rtsp_method_name::SET_PARAMETER => {
+                let response = if rtsp_request.body.as_deref().unwrap_or_default().is_empty() {
+                    Self::gen_response(http::StatusCode::OK, &rtsp_request)
+                } else {
+                    Self::gen_rtsp_error_response(451, "Parameter Not Understood", &rtsp_request)
+                };
+                self.send_response(&response).await?;
+            }
+            _ => {
+                let response = Self::gen_response(http::StatusCode::NOT_IMPLEMENTED, &rtsp_request);
+                self.send_response(&response).await?;
             }
-            rtsp_method_name::PAUSE => {}
-            rtsp_method_name::GET_PARAMETER => {}
-            rtsp_method_name::SET_PARAMETER => {}
-            rtsp_method_name::REDIRECT => {}
-
-            _ => {}
         }
         Ok(())
     }
 
+    //The requests after SETUP must carry the id of this session.
+    fn check_session_id(&self, rtsp_request: &RtspRequest) -> bool {
+        match (
+            &self.session_id,
+            rtsp_request.get_header(&"Session".to_string()),
+        ) {
+            (Some(session_id), Some(request_session)) => {
+                let request_session_id = request_session.split(';').next().unwrap_or_default();
+                request_session_id.trim() == session_id.to_string()
+            }
+            _ => true,
+        }
+    }
+
     async fn handle_options(&mut self, rtsp_request: &RtspRequest) -> Result<(), SessionError> {
         let status_code = http::StatusCode::OK;
         let mut response = Self::gen_response(status_code, rtsp_request);
@@ -277,6 +467,17 @@ impl RtspServerSession {
         // The sender is used for sending sdp information from the server session to client session
         // receiver is used to receive the sdp information
         let (sender, mut receiver) = mpsc::unbounded_channel();
+        self.stream_path = rtsp_request.uri.path.clone();
+
+        if let Some(playback_config) = &self.playback_config {
+            if let Some(record_stream_path) =
+                playback_config.get_record_stream_path(&self.stream_path)
+            {
+                return self
+                    .handle_playback_describe(rtsp_request, &record_stream_path)
+                    .await;
+            }
+        }
 
         let request_event = StreamHubEvent::Request {
             identifier: StreamIdentifier::Rtsp {
@@ -294,8 +495,14 @@ impl RtspServerSession {
         if let Some(Information::Sdp { data }) = receiver.recv().await {
             if let Some(sdp) = Sdp::unmarshal(&data) {
                 self.sdp = sdp;
+                self.set_srtp_keys();
                 //it can new tracks when get the sdp information;
                 self.new_tracks()?;
+                //the players have no way to advertise their keys in rtsp, so the offered
+                //key is used for both directions
+                for track in self.tracks.values_mut() {
+                    track.local_srtp_key = track.srtp_key.clone();
+                }
             }
         }
 
@@ -317,6 +524,12 @@ impl RtspServerSession {
             auth.authenticate(&stream_name, &rtsp_request.uri.query, false)?;
         }
 
+        self.stream_path = rtsp_request.uri.path.clone();
+        //the publisher is identified by the session id in the stream hub
+        if self.session_id.is_none() {
+            self.session_id = Some(Uuid::new(RandomDigitCount::Zero));
+        }
+
         if let Some(request_body) = &rtsp_request.body {
             if let Some(sdp) = Sdp::unmarshal(request_body) {
                 self.sdp = sdp.clone();
@@ -331,7 +544,7 @@ impl RtspServerSession {
 
         let publish_event = StreamHubEvent::Publish {
             identifier: StreamIdentifier::Rtsp {
-                stream_path: rtsp_request.uri.path.clone(),
+                stream_path: self.stream_path.clone(),
             },
             result_sender: event_result_sender,
             info: self.get_publisher_info(),
@@ -344,32 +557,19 @@ impl RtspServerSession {
             });
         }
 
-        let sender = event_result_receiver.await??.0.unwrap();
-
-        for track in self.tracks.values_mut() {
-            let sender_out = sender.clone();
-            let mut rtp_channel_guard = track.rtp_channel.lock().await;
-
-            rtp_channel_guard.on_frame_handler(Box::new(
-                move |msg: FrameData| -> Result<(), UnPackerError> {
-                    if let Err(err) = sender_out.send(msg) {
-                        log::error!("send frame error: {}", err);
-                    }
-                    Ok(())
-                },
-            ));
-
-            let rtcp_channel = Arc::clone(&track.rtcp_channel);
-            rtp_channel_guard.on_packet_for_rtcp_handler(Box::new(move |packet: RtpPacket| {
-                let rtcp_channel_in = Arc::clone(&rtcp_channel);
-                Box::pin(async move {
-                    rtcp_channel_in.lock().await.on_packet(packet);
-                })
-            }));
-        }
+        let result = event_result_receiver.await??;
+        set_frame_handlers(&mut self.tracks, result.0.unwrap()).await;
+        self.statistic_data_sender = result.2;
+        self.is_published = true;
 
         let status_code = http::StatusCode::OK;
-        let response = Self::gen_response(status_code, rtsp_request);
+        let mut response = Self::gen_response(status_code, rtsp_request);
+        if let Some(answer) = self.gen_srtp_answer() {
+            response.body = Some(answer.marshal());
+            response
+                .headers
+                .insert("Content-Type".to_string(), "application/sdp".to_string());
+        }
         self.send_response(&response).await?;
 
         Ok(())
@@ -379,7 +579,26 @@ impl RtspServerSession {
         let status_code = http::StatusCode::OK;
         let mut response = Self::gen_response(status_code, rtsp_request);
 
-        for track in self.tracks.values_mut() {
+        let is_multicast = rtsp_request
+            .get_header(&"Transport".to_string())
+            .and_then(|data| RtspTransport::unmarshal(data))
+            .is_some_and(|transport| transport.cast_type == CastType::Multicast);
+        let multicast_group = if is_multicast {
+            match self.join_multicast_group().await {
+                Some(group) => Some(group),
+                None => {
+                    log::error!("handle_setup: no multicast group for {}", self.stream_path);
+                    let response =
+                        Self::gen_rtsp_error_response(461, "Unsupported Transport", rtsp_request);
+                    self.send_response(&response).await?;
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+
+        for (track_type, track) in self.tracks.iter_mut() {
             if !rtsp_request.uri.marshal().contains(&track.media_control) {
                 continue;
             }
@@ -395,10 +614,41 @@ impl RtspServerSession {
                     let mut rtp_server_port: Option<u16> = None;
                     let mut rtcp_server_port: Option<u16> = None;
 
+                    //SRTP is only supported by the unicast udp transport with the SDES key
+                    let srtp_keys = if trans.is_srtp {
+                        match (&track.local_srtp_key, &track.srtp_key) {
+                            (Some(send_key), Some(recv_key))
+                                if trans.protocol_type == ProtocolType::UDP
+                                    && multicast_group.is_none() =>
+                            {
+                                Some((send_key.clone(), recv_key.clone()))
+                            }
+                            _ => {
+                                response = Self::gen_rtsp_error_response(
+                                    461,
+                                    "Unsupported Transport",
+                                    rtsp_request,
+                                );
+                                break;
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
                     match trans.protocol_type {
                         ProtocolType::TCP => {
                             track.create_packer(self.io.clone()).await;
                         }
+                        //the rtp packets are sent by the multicast sender of the stream
+                        ProtocolType::UDP if multicast_group.is_some() => {
+                            if let Some(group) = &multicast_group {
+                                trans.destination = Some(group.address.to_string());
+                                trans.port = Some(group.track_ports(track_type));
+                                trans.ttl = Some(group.ttl);
+                                trans.client_port = None;
+                            }
+                        }
                         ProtocolType::UDP => {
                             let (rtp_port, rtcp_port) =
                                 if let Some(client_ports) = trans.client_port {
@@ -412,7 +662,15 @@ impl RtspServerSession {
                             if let Some(rtp_io) = UdpIO::new(address.clone(), rtp_port, 0).await {
                                 rtp_server_port = rtp_io.get_local_port();
 
-                                let box_udp_io: Box<dyn TNetIO + Send + Sync> = Box::new(rtp_io);
+                                let box_udp_io: Box<dyn TNetIO + Send + Sync> = match &srtp_keys {
+                                    Some((send_key, recv_key)) => Box::new(SrtpIO::new(
+                                        Box::new(rtp_io),
+                                        send_key,
+                                        recv_key,
+                                        false,
+                                    )),
+                                    None => Box::new(rtp_io),
+                                };
                                 //if mode is empty then it is a player session.
                                 if trans.transport_mod.is_none() {
                                     track.create_packer(Arc::new(Mutex::new(box_udp_io))).await;
@@ -426,8 +684,16 @@ impl RtspServerSession {
                                     .await
                             {
                                 rtcp_server_port = rtcp_io.get_local_port();
-                                let box_rtcp_io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>> =
-                                    Arc::new(Mutex::new(Box::new(rtcp_io)));
+                                let box_rtcp_io: Box<dyn TNetIO + Send + Sync> = match &srtp_keys {
+                                    Some((send_key, recv_key)) => Box::new(SrtpIO::new(
+                                        Box::new(rtcp_io),
+                                        send_key,
+                                        recv_key,
+                                        true,
+                                    )),
+                                    None => Box::new(rtcp_io),
+                                };
+                                let box_rtcp_io = Arc::new(Mutex::new(box_rtcp_io));
                                 track.rtcp_receive_loop(box_rtcp_io).await;
                             }
                         }
@@ -447,10 +713,6 @@ impl RtspServerSession {
                     response
                         .headers
                         .insert("Transport".to_string(), new_transport_data);
-                    response
-                        .headers
-                        .insert("Session".to_string(), self.session_id.unwrap().to_string());
-
                     track.set_transport(trans).await;
                 }
             }
@@ -468,49 +730,22 @@ impl RtspServerSession {
             auth.authenticate(&stream_name, &rtsp_request.uri.query, true)?;
         }
 
-        for track in self.tracks.values_mut() {
-            let protocol_type = track.transport.protocol_type.clone();
-
-            match protocol_type {
-                ProtocolType::TCP => {
-                    let channel_identifer = if let Some(interleaveds) = track.transport.interleaved
-                    {
-                        interleaveds[0]
-                    } else {
-                        log::error!("handle_play:should not be here!!!");
-                        0
-                    };
+        if self.playback.is_some() {
+            return self.handle_playback_play(rtsp_request).await;
+        }
 
-                    track.rtp_channel.lock().await.on_packet_handler(Box::new(
-                        move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
-                            Box::pin(async move {
-                                let msg = packet.marshal()?;
-                                let mut bytes_writer = AsyncBytesWriter::new(io);
-                                bytes_writer.write_u8(0x24)?;
-                                bytes_writer.write_u8(channel_identifer)?;
-                                bytes_writer.write_u16::<BigEndian>(msg.len() as u16)?;
-                                bytes_writer.write(&msg)?;
-                                bytes_writer.flush().await?;
-                                Ok(())
-                            })
-                        },
-                    ));
-                }
-                ProtocolType::UDP => {
-                    track.rtp_channel.lock().await.on_packet_handler(Box::new(
-                        move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
-                            Box::pin(async move {
-                                let mut bytes_writer = AsyncBytesWriter::new(io);
-
-                                let msg = packet.marshal()?;
-                                bytes_writer.write(&msg)?;
-                                bytes_writer.flush().await?;
-                                Ok(())
-                            })
-                        },
-                    ));
-                }
+        if self.multicast_group.is_some() {
+            if let Some(multicast_manager) = &self.multicast_manager {
+                multicast_manager
+                    .start(&self.stream_path, &self.sdp, self.event_producer.clone())
+                    .await?;
             }
+            let response = Self::gen_response(http::StatusCode::OK, rtsp_request);
+            return self.send_response(&response).await;
+        }
+
+        for track in self.tracks.values_mut() {
+            set_packet_handler(track).await;
         }
 
         let status_code = http::StatusCode::OK;
@@ -518,13 +753,15 @@ impl RtspServerSession {
 
         self.send_response(&response).await?;
 
-        self.session_type = define::SessionType::Client;
+        if self.stream_path.is_empty() {
+            self.stream_path = rtsp_request.uri.path.clone();
+        }
 
         let (event_result_sender, event_result_receiver) = oneshot::channel();
 
         let subscribe_event = StreamHubEvent::Subscribe {
             identifier: StreamIdentifier::Rtsp {
-                stream_path: rtsp_request.uri.path.clone(),
+                stream_path: self.stream_path.clone(),
             },
             info: self.get_subscriber_info(),
             result_sender: event_result_sender,
@@ -536,161 +773,268 @@ impl RtspServerSession {
             });
         }
 
-        let mut receiver = event_result_receiver.await??.0.frame_receiver.unwrap();
+        //the frames are sent in the run loop, so the keepalive requests can be handled.
+        self.frame_receiver = event_result_receiver.await??.0.frame_receiver;
+        self.is_subscribed = true;
 
-        let mut retry_times = 0;
-        loop {
-            if let Some(frame_data) = receiver.recv().await {
-                match frame_data {
-                    FrameData::Audio {
-                        timestamp,
-                        mut data,
-                    } => {
-                        if let Some(audio_track) = self.tracks.get_mut(&TrackType::Audio) {
-                            audio_track
-                                .rtp_channel
-                                .lock()
-                                .await
-                                .on_frame(&mut data, timestamp)
-                                .await?;
-                        }
-                    }
-                    FrameData::Video {
-                        timestamp,
-                        mut data,
-                    } => {
-                        if let Some(video_track) = self.tracks.get_mut(&TrackType::Video) {
-                            video_track
-                                .rtp_channel
-                                .lock()
-                                .await
-                                .on_frame(&mut data, timestamp)
-                                .await?;
-                        }
-                    }
-                    _ => {}
-                }
-            } else {
-                retry_times += 1;
-                log::info!(
-                    "send_channel_data: no data receives ,retry {} times!",
-                    retry_times
-                );
+        Ok(())
+    }
 
-                if retry_times > 10 {
-                    return Err(SessionError {
-                        value: SessionErrorValue::CannotReceiveFrameData,
-                    });
-                }
+    async fn handle_playback_describe(
+        &mut self,
+        rtsp_request: &RtspRequest,
+        record_stream_path: &str,
+    ) -> Result<(), SessionError> {
+        let playback = match self.playback_config.as_ref() {
+            Some(playback_config) => {
+                RecordPlayback::open(playback_config, record_stream_path).await
+            }
+            None => return Ok(()),
+        };
+        let playback = match playback {
+            Ok(playback) => playback,
+            Err(err) => {
+                log::error!("handle_playback_describe: {}", err);
+                let response = Self::gen_response(http::StatusCode::NOT_FOUND, rtsp_request);
+                return self.send_response(&response).await;
             }
+        };
+
+        self.sdp = playback.get_sdp();
+        self.set_srtp_keys();
+        self.new_tracks()?;
+        self.playback = Some(playback);
+
+        let mut response = Self::gen_response(http::StatusCode::OK, rtsp_request);
+        response.body = Some(self.sdp.marshal());
+        response
+            .headers
+            .insert("Content-Type".to_string(), "application/sdp".to_string());
+        self.send_response(&response).await
+    }
+
+    //PLAY with the Range header seeks the record, and the one without Range resumes after PAUSE.
+    async fn handle_playback_play(
+        &mut self,
+        rtsp_request: &RtspRequest,
+    ) -> Result<(), SessionError> {
+        let playback = match &self.playback {
+            Some(playback) => playback,
+            None => return Ok(()),
+        };
+
+        let scale = match rtsp_request.get_header(&"Scale".to_string()) {
+            Some(scale) => scale.trim().parse::<f64>().unwrap_or_default(),
+            None => 1.0,
+        };
+        //rewinding is not supported
+        if scale <= 0.0 {
+            let response = Self::gen_rtsp_error_response(
+                456,
+                "Header Field Not Valid for Resource",
+                rtsp_request,
+            );
+            return self.send_response(&response).await;
         }
+
+        let position = match rtsp_request.get_header(&"Range".to_string()) {
+            Some(range) => {
+                RtspRange::unmarshal(range).and_then(|range| playback.get_seek_position(&range))
+            }
+            None => Some(playback.get_position()),
+        };
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let response = Self::gen_rtsp_error_response(457, "Invalid Range", rtsp_request);
+                return self.send_response(&response).await;
+            }
+        };
+
+        let duration = playback.get_duration();
+        let (frame_receiver, start_position) = playback.start(position, scale).await?;
+
+        let request_url = rtsp_request.uri.marshal();
+        let mut rtp_infos = Vec::new();
+        for track_type in [TrackType::Video, TrackType::Audio] {
+            if let Some(track) = self.tracks.get_mut(&track_type) {
+                set_packet_handler(track).await;
+
+                let seq_number = match track.rtp_channel.lock().await.get_seq_number() {
+                    Some(seq_number) => seq_number,
+                    None => continue,
+                };
+                let url = if track.media_control.starts_with("rtsp://") {
+                    track.media_control.clone()
+                } else {
+                    format!(
+                        "{}/{}",
+                        request_url.trim_end_matches('/'),
+                        track.media_control
+                    )
+                };
+                rtp_infos.push(format!(
+                    "url={};seq={};rtptime={}",
+                    url,
+                    seq_number,
+                    playback.get_rtp_timestamp(&track_type, start_position)
+                ));
+            }
+        }
+
+        let mut response = Self::gen_response(http::StatusCode::OK, rtsp_request);
+        let range = RtspRange::new(RtspRangeType::NPT, start_position, Some(duration));
+        response
+            .headers
+            .insert("Range".to_string(), range.marshal());
+        response
+            .headers
+            .insert("Scale".to_string(), format!("{scale:.1}"));
+        response
+            .headers
+            .insert("RTP-Info".to_string(), rtp_infos.join(","));
+        self.send_response(&response).await?;
+
+        //the frames are sent in the run loop after the response
+        self.frame_receiver = Some(frame_receiver);
+        self.is_playing_back = true;
+
+        Ok(())
     }
 
     async fn handle_record(&mut self, rtsp_request: &RtspRequest) -> Result<(), SessionError> {
+        let status_code = http::StatusCode::OK;
+        let mut response = Self::gen_response(status_code, rtsp_request);
         if let Some(range_str) = rtsp_request.headers.get(&String::from("Range")) {
             if let Some(range) = RtspRange::unmarshal(range_str) {
-                let status_code = http::StatusCode::OK;
-                let mut response = Self::gen_response(status_code, rtsp_request);
                 response
                     .headers
                     .insert(String::from("Range"), range.marshal());
-                response
-                    .headers
-                    .insert("Session".to_string(), self.session_id.unwrap().to_string());
-
-                self.send_response(&response).await?;
             }
         }
+        self.send_response(&response).await?;
 
         Ok(())
     }
 
-    fn handle_teardown(&mut self, rtsp_request: &RtspRequest) -> Result<(), SessionError> {
-        let identifier = StreamIdentifier::Rtsp {
-            stream_path: rtsp_request.uri.path.clone(),
-        };
+    async fn handle_teardown(&mut self, rtsp_request: &RtspRequest) -> Result<(), SessionError> {
+        self.is_playing_back = false;
+        self.leave_stream_hub().await;
 
-        let event = match self.session_type {
-            define::SessionType::Client => {
-                log::info!("handle_teardown: client");
+        let response = Self::gen_response(http::StatusCode::OK, rtsp_request);
+        self.send_response(&response).await
+    }
 
-                StreamHubEvent::UnSubscribe {
-                    identifier,
-                    info: self.get_subscriber_info(),
-                }
+    //Unsubscribe/unpublish the stream when the session is torn down, timed out or disconnected.
+    pub async fn leave_stream_hub(&mut self) {
+        //the multicast session does not subscribe the stream by itself
+        self.leave_multicast_group().await;
+
+        let identifier = StreamIdentifier::Rtsp {
+            stream_path: self.stream_path.clone(),
+        };
+        let event = if self.is_subscribed {
+            self.is_subscribed = false;
+            StreamHubEvent::UnSubscribe {
+                identifier,
+                info: self.get_subscriber_info(),
             }
-            define::SessionType::Server => {
-                log::info!("handle_teardown: server");
-                StreamHubEvent::UnPublish {
-                    identifier,
-                    info: self.get_publisher_info(),
-                }
+        } else if self.is_published {
+            self.is_published = false;
+            StreamHubEvent::UnPublish {
+                identifier,
+                info: self.get_publisher_info(),
             }
+        } else {
+            return;
         };
 
         let event_json_str = serde_json::to_string(&event).unwrap();
-
-        let rv = self.event_producer.send(event);
-        match rv {
+        match self.event_producer.send(event) {
             Err(err) => {
-                log::error!("handle_teardown: send event error: {err} for event: {event_json_str}");
-                Err(SessionError {
-                    value: SessionErrorValue::StreamHubEventSendErr,
-                })
+                log::error!(
+                    "leave_stream_hub: send event error: {err} for event: {event_json_str}"
+                );
             }
             Ok(()) => {
-                log::info!("handle_teardown: send event success: {event_json_str}");
-                Ok(())
+                log::info!("leave_stream_hub: send event success: {event_json_str}");
             }
         }
     }
 
-    fn new_tracks(&mut self) -> Result<(), SessionError> {
-        for media in &self.sdp.medias {
-            let media_control = if let Some(media_control_val) = media.attributes.get("control") {
-                media_control_val.clone()
-            } else {
-                String::from("")
-            };
+    async fn join_multicast_group(&mut self) -> Option<MulticastGroup> {
+        if self.multicast_group.is_none() {
+            self.multicast_group = self
+                .multicast_manager
+                .as_ref()?
+                .join(&self.stream_path)
+                .await;
+        }
+        self.multicast_group.clone()
+    }
 
-            let media_name = &media.media_type;
-            log::info!("media_name: {}", media_name);
-            match media_name.as_str() {
-                "audio" => {
-                    let codec_id = rtsp_codec::RTSP_CODEC_NAME_2_ID
-                        .get(&media.rtpmap.encoding_name.to_lowercase().as_str())
-                        .unwrap()
-                        .clone();
-                    let codec_info = RtspCodecInfo {
-                        codec_id,
-                        payload_type: media.rtpmap.payload_type as u8,
-                        sample_rate: media.rtpmap.clock_rate,
-                        channel_count: media.rtpmap.encoding_param.parse().unwrap(),
-                    };
+    async fn leave_multicast_group(&mut self) {
+        if self.multicast_group.take().is_some() {
+            if let Some(multicast_manager) = &self.multicast_manager {
+                multicast_manager.leave(&self.stream_path).await;
+            }
+        }
+    }
 
-                    log::info!("audio codec info: {:?}", codec_info);
+    fn new_tracks(&mut self) -> Result<(), SessionError> {
+        self.tracks = new_tracks(&self.sdp);
+        Ok(())
+    }
 
-                    let track = RtspTrack::new(TrackType::Audio, codec_info, media_control);
-                    self.tracks.insert(TrackType::Audio, track);
-                }
-                "video" => {
-                    let codec_id = rtsp_codec::RTSP_CODEC_NAME_2_ID
-                        .get(&media.rtpmap.encoding_name.to_lowercase().as_str())
-                        .unwrap()
-                        .clone();
-                    let codec_info = RtspCodecInfo {
-                        codec_id,
-                        payload_type: media.rtpmap.payload_type as u8,
-                        sample_rate: media.rtpmap.clock_rate,
-                        ..Default::default()
-                    };
-                    let track = RtspTrack::new(TrackType::Video, codec_info, media_control);
-                    self.tracks.insert(TrackType::Video, track);
+    //The keys of the publisher are not forwarded to the players, new SDES keys are
+    //offered instead if SRTP is enabled.
+    fn set_srtp_keys(&mut self) {
+        for media in &mut self.sdp.medias {
+            media.crypto = None;
+            media.protocol = String::from("RTP/AVP");
+            if !self.enable_srtp {
+                continue;
+            }
+            match SrtpKey::generate(AES_CM_128_HMAC_SHA1_80) {
+                Ok(key) => {
+                    media.crypto = Some(Crypto::new(1, key.crypto_suite(), key.master_key_salt()));
+                    media.protocol = String::from("RTP/SAVP");
                 }
-                _ => {}
+                Err(err) => log::error!("generate srtp key error: {}", err),
             }
         }
-        Ok(())
+    }
+
+    //The publisher protects the packets with its own key, the keys protecting the rtcp
+    //packets sent to it are answered with a=crypto in the ANNOUNCE response.
+    fn gen_srtp_answer(&mut self) -> Option<Sdp> {
+        let mut answer = self.sdp.clone();
+        let mut has_crypto = false;
+
+        for media in &mut answer.medias {
+            let track = match media.media_type.as_str() {
+                "audio" => self.tracks.get_mut(&TrackType::Audio),
+                "video" => self.tracks.get_mut(&TrackType::Video),
+                _ => None,
+            };
+            let local_key = track.and_then(|track| {
+                let peer_key = track.srtp_key.as_ref()?;
+                track.local_srtp_key = SrtpKey::generate(peer_key.crypto_suite()).ok();
+                track.local_srtp_key.clone()
+            });
+            media.crypto = match (&media.crypto, local_key) {
+                (Some(crypto), Some(key)) => {
+                    has_crypto = true;
+                    Some(Crypto::new(
+                        crypto.tag,
+                        key.crypto_suite(),
+                        key.master_key_salt(),
+                    ))
+                }
+                _ => None,
+            };
+        }
+        has_crypto.then_some(answer)
     }
 
     fn gen_response(status_code: StatusCode, rtsp_request: &RtspRequest) -> RtspResponse {
@@ -716,6 +1060,18 @@ impl RtspServerSession {
         response
     }
 
+    //The rtsp specific status codes which are not defined in http.
+    fn gen_rtsp_error_response(
+        status_code: u16,
+        reason_phrase: &str,
+        rtsp_request: &RtspRequest,
+    ) -> RtspResponse {
+        let mut response = Self::gen_response(http::StatusCode::OK, rtsp_request);
+        response.status_code = status_code;
+        response.reason_phrase = reason_phrase.to_string();
+        response
+    }
+
     fn get_subscriber_info(&mut self) -> SubscriberInfo {
         let id = if let Some(session_id) = &self.session_id {
             *session_id
@@ -727,6 +1083,7 @@ impl RtspServerSession {
             id,
             sub_type: SubscribeType::PlayerRtsp,
             sub_data_type: streamhub::define::SubDataType::Frame,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
                 remote_addr: String::from(""),
@@ -753,6 +1110,14 @@ impl RtspServerSession {
     }
 
     async fn send_response(&mut self, response: &RtspResponse) -> Result<(), SessionError> {
+        let mut response = response.clone();
+        if let Some(session_id) = self.session_id {
+            response.headers.insert(
+                "Session".to_string(),
+                format!("{};timeout={}", session_id, self.session_timeout),
+            );
+        }
+
         self.writer.write(response.marshal().as_bytes())?;
         self.writer.flush().await?;
 
@@ -760,6 +1125,196 @@ impl RtspServerSession {
     }
 }
 
+//The SDES key carried by the a=crypto attribute of the media.
+fn get_srtp_key(media: &SdpMediaInfo) -> Option<SrtpKey> {
+    let crypto = media.crypto.as_ref()?;
+    let master_key_salt = crypto.get_master_key_salt()?;
+    match SrtpKey::new(&crypto.crypto_suite, master_key_salt) {
+        Ok(key) => Some(key),
+        Err(err) => {
+            log::warn!("invalid srtp key of {}: {}", media.media_type, err);
+            None
+        }
+    }
+}
+
+//Create the audio/video tracks from the medias of the sdp, the medias with unsupported codecs are ignored.
+fn new_tracks(sdp: &Sdp) -> HashMap<TrackType, RtspTrack> {
+    let mut tracks = HashMap::new();
+    for media in &sdp.medias {
+        let media_control = if let Some(media_control_val) = media.attributes.get("control") {
+            media_control_val.clone()
+        } else {
+            String::from("")
+        };
+
+        let media_name = &media.media_type;
+        log::info!("media_name: {}", media_name);
+
+        let codec_id = match rtsp_codec::RTSP_CODEC_NAME_2_ID
+            .get(&media.rtpmap.encoding_name.to_lowercase().as_str())
+        {
+            Some(codec_id) => codec_id.clone(),
+            None => {
+                log::warn!("unsupported codec: {}", media.rtpmap.encoding_name);
+                continue;
+            }
+        };
+
+        match media_name.as_str() {
+            "audio" => {
+                let codec_info = RtspCodecInfo {
+                    codec_id,
+                    payload_type: media.rtpmap.payload_type as u8,
+                    sample_rate: media.rtpmap.clock_rate,
+                    channel_count: media.rtpmap.encoding_param.parse().unwrap_or(1),
+                };
+
+                log::info!("audio codec info: {:?}", codec_info);
+
+                let mut track = RtspTrack::new(TrackType::Audio, codec_info, media_control);
+                track.srtp_key = get_srtp_key(media);
+                tracks.insert(TrackType::Audio, track);
+            }
+            "video" => {
+                let codec_info = RtspCodecInfo {
+                    codec_id,
+                    payload_type: media.rtpmap.payload_type as u8,
+                    sample_rate: media.rtpmap.clock_rate,
+                    ..Default::default()
+                };
+                let mut track = RtspTrack::new(TrackType::Video, codec_info, media_control);
+                track.srtp_key = get_srtp_key(media);
+                tracks.insert(TrackType::Video, track);
+            }
+            _ => {}
+        }
+    }
+    tracks
+}
+
+//Send the frames unpacked from the received rtp packets to the stream hub,
+//the timestamps of the tracks are aligned by the received sender reports.
+async fn set_frame_handlers(tracks: &mut HashMap<TrackType, RtspTrack>, sender: FrameDataSender) {
+    let av_sync = Arc::new(StdMutex::new(AvSynchronizer::new()));
+
+    for (track_type, track) in tracks.iter_mut() {
+        track
+            .rtcp_channel
+            .lock()
+            .await
+            .set_av_synchronizer(track_type.clone(), av_sync.clone());
+
+        let sender_out = sender.clone();
+        let av_sync_in = av_sync.clone();
+        let track_type_in = track_type.clone();
+        let mut rtp_channel_guard = track.rtp_channel.lock().await;
+
+        rtp_channel_guard.on_frame_handler(Box::new(
+            move |msg: FrameData| -> Result<(), UnPackerError> {
+                let msg = match (msg, av_sync_in.lock()) {
+                    (FrameData::Audio { timestamp, data }, Ok(mut av_sync)) => FrameData::Audio {
+                        timestamp: av_sync.sync_timestamp(track_type_in.clone(), timestamp),
+                        data,
+                    },
+                    (FrameData::Video { timestamp, data }, Ok(mut av_sync)) => FrameData::Video {
+                        timestamp: av_sync.sync_timestamp(track_type_in.clone(), timestamp),
+                        data,
+                    },
+                    (msg, _) => msg,
+                };
+                if let Err(err) = sender_out.send(msg) {
+                    log::error!("send frame error: {}", err);
+                }
+                Ok(())
+            },
+        ));
+
+        let rtcp_channel = Arc::clone(&track.rtcp_channel);
+        rtp_channel_guard.on_packet_for_rtcp_handler(Box::new(move |packet: RtpPacket| {
+            let rtcp_channel_in = Arc::clone(&rtcp_channel);
+            Box::pin(async move {
+                rtcp_channel_in.lock().await.on_packet(packet);
+            })
+        }));
+    }
+}
+
+//Report the packet loss/jitter/recovery counters of the received rtp packets.
+async fn send_rtp_receive_statistics(
+    tracks: &HashMap<TrackType, RtspTrack>,
+    sender: &StatisticDataSender,
+) {
+    let mut statistics = RtpReceiveStatistics::default();
+    for track in tracks.values() {
+        let track_statistics = track.get_receive_statistics().await;
+        statistics.lost_packets += track_statistics.lost_packets;
+        statistics.recovered_packets += track_statistics.recovered_packets;
+        statistics.nack_count += track_statistics.nack_count;
+        statistics.dropped_frames += track_statistics.dropped_frames;
+        statistics.jitter = statistics.jitter.max(track_statistics.jitter);
+    }
+
+    if let Err(err) = sender.send(StatisticData::RtpReceive { statistics }) {
+        log::error!("send statistic_data err: {}", err);
+    }
+}
+
+//Send the rtp packets packed from the frames of the stream hub to the network.
+async fn set_packet_handler(track: &mut RtspTrack) {
+    let rtcp_channel = Arc::clone(&track.rtcp_channel);
+    track
+        .rtp_channel
+        .lock()
+        .await
+        .on_sent_packet_for_rtcp_handler(Box::new(move |packet: RtpPacket| {
+            let rtcp_channel_in = Arc::clone(&rtcp_channel);
+            Box::pin(async move {
+                rtcp_channel_in.lock().await.on_sent_packet(packet);
+            })
+        }));
+
+    match track.transport.protocol_type {
+        ProtocolType::TCP => {
+            let channel_identifer = if let Some(interleaveds) = track.transport.interleaved {
+                interleaveds[0]
+            } else {
+                log::error!("set_packet_handler:should not be here!!!");
+                0
+            };
+
+            track.rtp_channel.lock().await.on_packet_handler(Box::new(
+                move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
+                    Box::pin(async move {
+                        let msg = packet.marshal()?;
+                        let mut bytes_writer = AsyncBytesWriter::new(io);
+                        bytes_writer.write_u8(0x24)?;
+                        bytes_writer.write_u8(channel_identifer)?;
+                        bytes_writer.write_u16::<BigEndian>(msg.len() as u16)?;
+                        bytes_writer.write(&msg)?;
+                        bytes_writer.flush().await?;
+                        Ok(())
+                    })
+                },
+            ));
+        }
+        ProtocolType::UDP => {
+            track.rtp_channel.lock().await.on_packet_handler(Box::new(
+                move |io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>, packet: RtpPacket| {
+                    Box::pin(async move {
+                        let mut bytes_writer = AsyncBytesWriter::new(io);
+
+                        let msg = packet.marshal()?;
+                        bytes_writer.write(&msg)?;
+                        bytes_writer.flush().await?;
+                        Ok(())
+                    })
+                },
+            ));
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct RtspStreamHandler {
     sdp: Mutex<Sdp>,
@@ -782,6 +1337,7 @@ impl TStreamHandler for RtspStreamHandler {
         &self,
         data_sender: DataSender,
         sub_type: SubscribeType,
+        _start_mode: streamhub::define::SubscribeStartMode,
     ) -> Result<(), StreamHubError> {
         let sender = match data_sender {
             DataSender::Frame { sender } => sender,
@@ -798,8 +1354,27 @@ impl TStreamHandler for RtspStreamHandler {
                 let mut audio_clock_rate: u32 = 0;
 
                 let mut vcodec: VideoCodecType = VideoCodecType::H264;
+                let mut acodec: AudioCodecType = AudioCodecType::AAC;
 
                 for media in &sdp_info.medias {
+                    //the G.711/G.726/Opus payloads have no fmtp, the remuxer learns
+                    //the codec and the clock rate from the media info.
+                    if media.media_type == "audio" {
+                        let codec = match rtsp_codec::RTSP_CODEC_NAME_2_ID
+                            .get(&media.rtpmap.encoding_name.to_lowercase().as_str())
+                        {
+                            Some(RtspCodecId::G711A) => Some(AudioCodecType::G711A),
+                            Some(RtspCodecId::G711U) => Some(AudioCodecType::G711U),
+                            Some(RtspCodecId::G726) => Some(AudioCodecType::G726),
+                            Some(RtspCodecId::Opus) => Some(AudioCodecType::Opus),
+                            _ => None,
+                        };
+                        if let Some(codec) = codec {
+                            acodec = codec;
+                            audio_clock_rate = media.rtpmap.clock_rate;
+                        }
+                    }
+
                     let mut bytes_writer = BytesWriter::new();
                     if let Some(fmtp) = &media.fmtp {
                         match fmtp {
@@ -811,9 +1386,9 @@ impl TStreamHandler for RtspStreamHandler {
 
                                 let frame_data = FrameData::Video {
                                     timestamp: 0,
-                                    data: bytes_writer.extract_current_bytes(),
+                                    data: bytes_writer.extract_current_bytes().freeze(),
                                 };
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send sps/pps error: {}", err);
                                 }
                                 video_clock_rate = media.rtpmap.clock_rate;
@@ -828,9 +1403,9 @@ impl TStreamHandler for RtspStreamHandler {
 
                                 let frame_data = FrameData::Video {
                                     timestamp: 0,
-                                    data: bytes_writer.extract_current_bytes(),
+                                    data: bytes_writer.extract_current_bytes().freeze(),
                                 };
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send sps/pps/vps error: {}", err);
                                 }
 
@@ -839,10 +1414,10 @@ impl TStreamHandler for RtspStreamHandler {
                             Fmtp::Mpeg4(data) => {
                                 let frame_data = FrameData::Audio {
                                     timestamp: 0,
-                                    data: data.asc.clone(),
+                                    data: data.asc.clone().freeze(),
                                 };
 
-                                if let Err(err) = sender.send(frame_data) {
+                                if let Err(err) = sender.try_send(frame_data) {
                                     log::error!("send asc error: {}", err);
                                 }
 
@@ -852,12 +1427,13 @@ impl TStreamHandler for RtspStreamHandler {
                     }
                 }
 
-                if let Err(err) = sender.send(FrameData::MediaInfo {
+                if let Err(err) = sender.try_send(FrameData::MediaInfo {
                     media_info: MediaInfo {
                         audio_clock_rate,
                         video_clock_rate,
 
                         vcodec,
+                        acodec,
                     },
                 }) {
                     log::error!("send media info error: {}", err);
@@ -881,3 +1457,176 @@ impl TStreamHandler for RtspStreamHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytesio::bytesio::TcpIO;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    //the server session and the client side of its connection
+    async fn new_session(session_timeout: u64) -> (RtspServerSession, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let (event_producer, _) = mpsc::unbounded_channel();
+
+        let session = RtspServerSession::new(
+            Box::new(TcpIO::new(server)),
+            event_producer,
+            None,
+            None,
+            None,
+            false,
+            session_timeout,
+        );
+        (session, client)
+    }
+
+    async fn read_response(client: &mut TcpStream) -> RtspResponse {
+        let mut data = Vec::new();
+        while !data.windows(4).any(|window| window == b"\r\n\r\n") {
+            let mut buf = [0u8; 1024];
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            data.extend_from_slice(&buf[..n]);
+        }
+        RtspResponse::unmarshal(std::str::from_utf8(&data).unwrap()).unwrap()
+    }
+
+    fn new_request(method: &str, cseq: u32, session: Option<&str>, body: &str) -> String {
+        let mut request =
+            format!("{method} rtsp://127.0.0.1:554/live/test RTSP/1.0\r\nCSeq: {cseq}\r\n");
+        if let Some(session) = session {
+            request += &format!("Session: {session}\r\n");
+        }
+        if !body.is_empty() {
+            request += &format!("Content-Length: {}\r\n", body.len());
+        }
+        request + "\r\n" + body
+    }
+
+    #[tokio::test]
+    async fn test_check_session_id() {
+        let (mut session, _client) = new_session(define::SESSION_TIMEOUT).await;
+        let request = |session: Option<&str>| {
+            RtspRequest::unmarshal(&new_request("PLAY", 1, session, "")).unwrap()
+        };
+
+        //no session is set up yet
+        assert!(session.check_session_id(&request(Some("12345678"))));
+
+        let session_id = Uuid::new(RandomDigitCount::Zero);
+        session.session_id = Some(session_id);
+        assert!(session.check_session_id(&request(None)));
+        assert!(session.check_session_id(&request(Some(&session_id.to_string()))));
+        assert!(session.check_session_id(&request(Some(&format!("{session_id};timeout=60")))));
+        assert!(!session.check_session_id(&request(Some("12345678"))));
+    }
+
+    #[tokio::test]
+    async fn test_process_buffered_data() {
+        let (mut session, mut client) = new_session(define::SESSION_TIMEOUT).await;
+
+        //the rtp data of an unknown channel followed by a request and a partial one
+        let options = new_request("OPTIONS", 1, None, "");
+        let get_parameter = new_request("GET_PARAMETER", 2, None, "");
+        session
+            .reader
+            .extend_from_slice(&[0x24, 0x00, 0x00, 0x04, 0x80, 0x60, 0x00, 0x01]);
+        session.reader.extend_from_slice(options.as_bytes());
+        session
+            .reader
+            .extend_from_slice(&get_parameter.as_bytes()[..10]);
+        session.process_buffered_data().await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.headers.get("CSeq").unwrap(), "1");
+        assert_eq!(
+            &session.reader.get_remaining_bytes()[..],
+            &get_parameter.as_bytes()[..10]
+        );
+
+        //the partial interleaved data is kept until the whole packet is received
+        session
+            .reader
+            .extend_from_slice(&get_parameter.as_bytes()[10..]);
+        session
+            .reader
+            .extend_from_slice(&[0x24, 0x01, 0x00, 0x04, 0x80, 0xC8]);
+        session.process_buffered_data().await.unwrap();
+
+        let response = read_response(&mut client).await;
+        assert_eq!(response.headers.get("CSeq").unwrap(), "2");
+        assert_eq!(session.reader.len(), 6);
+
+        session.reader.extend_from_slice(&[0x00, 0x00]);
+        session.process_buffered_data().await.unwrap();
+        assert_eq!(session.reader.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_gen_srtp_answer() {
+        let (mut session, _client) = new_session(define::SESSION_TIMEOUT).await;
+        session.sdp = Sdp::unmarshal(
+            "v=0\r\n\
+            o=- 0 0 IN IP4 127.0.0.1\r\n\
+            s=camera\r\n\
+            t=0 0\r\n\
+            m=video 0 RTP/SAVP 96\r\n\
+            a=rtpmap:96 H264/90000\r\n\
+            a=control:streamid=0\r\n\
+            a=crypto:1 AES_CM_128_HMAC_SHA1_80 inline:PS1uQCVeeCFCanVmcjkpPywjNWhcYD0mXXtxaVBR\r\n",
+        )
+        .unwrap();
+        session.new_tracks().unwrap();
+
+        let answer = session.gen_srtp_answer().unwrap();
+        let track = &session.tracks[&TrackType::Video];
+        let peer_key = track.srtp_key.as_ref().unwrap();
+        let local_key = track.local_srtp_key.as_ref().unwrap();
+        assert_ne!(peer_key.master_key_salt(), local_key.master_key_salt());
+
+        let crypto = answer.medias[0].crypto.as_ref().unwrap();
+        assert_eq!(crypto.tag, 1);
+        assert_eq!(
+            crypto.get_master_key_salt().unwrap(),
+            local_key.master_key_salt()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_response() {
+        let (mut session, mut client) = new_session(30).await;
+        let session_id = Uuid::new(RandomDigitCount::Zero);
+        session.session_id = Some(session_id);
+        let session_header = session_id.to_string();
+
+        let get_parameter = new_request("GET_PARAMETER", 1, Some(&session_header), "");
+        let set_parameter = new_request("SET_PARAMETER", 2, Some(&session_header), "");
+        let set_parameter_with_body =
+            new_request("SET_PARAMETER", 3, Some(&session_header), "volume: 50\r\n");
+        let wrong_session = new_request("GET_PARAMETER", 4, Some("12345678"), "");
+
+        for (request, status_code) in [
+            (get_parameter, 200),
+            (set_parameter, 200),
+            (set_parameter_with_body, 451),
+            (wrong_session, 454),
+        ] {
+            session.reader.extend_from_slice(request.as_bytes());
+            session.process_buffered_data().await.unwrap();
+
+            let response = read_response(&mut client).await;
+            assert_eq!(response.status_code, status_code);
+            assert_eq!(
+                response.headers.get("Session").unwrap(),
+                &format!("{session_id};timeout=30")
+            );
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use super::errors::SessionError;
+use super::errors::SessionErrorValue;
+use super::new_tracks;
+use super::set_packet_handler;
+
+use crate::rtsp_track::RtspTrack;
+use crate::rtsp_track::TrackType;
+use crate::rtsp_transport::CastType;
+use crate::rtsp_transport::ProtocolType;
+use crate::rtsp_transport::RtspTransport;
+use crate::sdp::Sdp;
+
+use bytesio::bytesio::TNetIO;
+use bytesio::bytesio::UdpIO;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use streamhub::{
+    define::{
+        FrameData, NotifyInfo, StreamHubEvent, StreamHubEventSender, SubDataType,
+        SubFrameDataReceiver, SubscribeStartMode, SubscribeType, SubscriberInfo,
+    },
+    stream::StreamIdentifier,
+    utils::{RandomDigitCount, Uuid},
+};
+use tokio::sync::{oneshot, Mutex};
+
+//the rtp/rtcp ports of the video and audio tracks
+const PORTS_PER_GROUP: u16 = 4;
+
+#[derive(Debug, Clone)]
+pub struct MulticastConfig {
+    //the first address of the pool, e.g. 239.0.0.1
+    pub start_address: Ipv4Addr,
+    pub address_count: u32,
+    //the ports of the first group, the ports of the following groups are increased by 4
+    pub start_port: u16,
+    pub ttl: u32,
+}
+
+//The multicast group allocated for a stream.
+#[derive(Debug, Clone)]
+pub struct MulticastGroup {
+    pub address: Ipv4Addr,
+    pub port: u16,
+    pub ttl: u32,
+}
+
+impl MulticastGroup {
+    pub fn track_ports(&self, track_type: &TrackType) -> [u16; 2] {
+        let rtp_port = match track_type {
+            TrackType::Video => self.port,
+            _ => self.port + 2,
+        };
+        [rtp_port, rtp_port + 1]
+    }
+}
+
+struct MulticastStream {
+    index: u32,
+    group: MulticastGroup,
+    //the number of the sessions that set up the multicast transport
+    session_count: usize,
+    stop_sender: Option<oneshot::Sender<()>>,
+}
+
+//Allocate the multicast groups from the address pool and send one copy of
+//the rtp packets for each stream no matter how many viewers there are.
+#[derive(Clone)]
+pub struct MulticastManager {
+    config: MulticastConfig,
+    //key: stream path
+    streams: Arc<Mutex<HashMap<String, MulticastStream>>>,
+}
+
+impl MulticastManager {
+    pub fn new(config: MulticastConfig) -> Self {
+        Self {
+            config,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    //Return None if the address pool is exhausted.
+    pub async fn join(&self, stream_path: &str) -> Option<MulticastGroup> {
+        let mut streams = self.streams.lock().await;
+        if let Some(stream) = streams.get_mut(stream_path) {
+            stream.session_count += 1;
+            return Some(stream.group.clone());
+        }
+
+        let index = (0..self.config.address_count)
+            .find(|index| !streams.values().any(|stream| stream.index == *index))?;
+        let port = self
+            .config
+            .start_port
+            .checked_add((index as u16).checked_mul(PORTS_PER_GROUP)?)?;
+        let group = MulticastGroup {
+            address: Ipv4Addr::from(u32::from(self.config.start_address).checked_add(index)?),
+            port,
+            ttl: self.config.ttl,
+        };
+        log::info!(
+            "allocate multicast group {}:{} for stream {}",
+            group.address,
+            group.port,
+            stream_path
+        );
+
+        streams.insert(
+            stream_path.to_string(),
+            MulticastStream {
+                index,
+                group: group.clone(),
+                session_count: 1,
+                stop_sender: None,
+            },
+        );
+        Some(group)
+    }
+
+    //Start sending the stream to the multicast group if it is not being sent.
+    pub async fn start(
+        &self,
+        stream_path: &str,
+        sdp: &Sdp,
+        event_producer: StreamHubEventSender,
+    ) -> Result<(), SessionError> {
+        let mut streams = self.streams.lock().await;
+        let stream = match streams.get_mut(stream_path) {
+            Some(stream) => stream,
+            None => {
+                return Err(SessionError {
+                    value: SessionErrorValue::MulticastGroupNotFound(stream_path.to_string()),
+                });
+            }
+        };
+
+        //the sender exits when the stream is unpublished
+        if let Some(stop_sender) = &stream.stop_sender {
+            if !stop_sender.is_closed() {
+                return Ok(());
+            }
+        }
+
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        stream.stop_sender = Some(stop_sender);
+
+        let mut sender = MulticastSender::new(
+            stream.group.clone(),
+            stream_path.to_string(),
+            event_producer,
+        );
+        sender.create_tracks(sdp).await;
+        tokio::spawn(async move {
+            if let Err(err) = sender.run(stop_receiver).await {
+                log::error!("multicast sender run error: {}", err);
+            }
+            sender.unsubscribe_from_stream_hub();
+        });
+
+        Ok(())
+    }
+
+    pub async fn leave(&self, stream_path: &str) {
+        let mut streams = self.streams.lock().await;
+        if let Some(stream) = streams.get_mut(stream_path) {
+            stream.session_count = stream.session_count.saturating_sub(1);
+            if stream.session_count > 0 {
+                return;
+            }
+            if let Some(stream) = streams.remove(stream_path) {
+                log::info!(
+                    "release multicast group {}:{} of stream {}",
+                    stream.group.address,
+                    stream.group.port,
+                    stream_path
+                );
+                if let Some(stop_sender) = stream.stop_sender {
+                    let _ = stop_sender.send(());
+                }
+            }
+        }
+    }
+}
+
+struct MulticastSender {
+    group: MulticastGroup,
+    stream_path: String,
+    tracks: HashMap<TrackType, RtspTrack>,
+    subscriber_id: Uuid,
+    event_producer: StreamHubEventSender,
+    is_subscribed: bool,
+}
+
+impl MulticastSender {
+    fn new(
+        group: MulticastGroup,
+        stream_path: String,
+        event_producer: StreamHubEventSender,
+    ) -> Self {
+        Self {
+            group,
+            stream_path,
+            tracks: HashMap::new(),
+            subscriber_id: Uuid::new(RandomDigitCount::Zero),
+            event_producer,
+            is_subscribed: false,
+        }
+    }
+
+    async fn create_tracks(&mut self, sdp: &Sdp) {
+        self.tracks = new_tracks(sdp);
+
+        for (track_type, track) in self.tracks.iter_mut() {
+            let ports = self.group.track_ports(track_type);
+            let rtp_io = match UdpIO::new(self.group.address.to_string(), ports[0], 0).await {
+                Some(rtp_io) => rtp_io,
+                None => {
+                    log::error!("create multicast udp socket failed");
+                    continue;
+                }
+            };
+            if let Err(err) = rtp_io.set_multicast_ttl_v4(self.group.ttl) {
+                log::error!("set multicast ttl error: {}", err);
+            }
+
+            let box_rtp_io: Box<dyn TNetIO + Send + Sync> = Box::new(rtp_io);
+            track.create_packer(Arc::new(Mutex::new(box_rtp_io))).await;
+
+            //the sender reports are sent to the rtcp port of the group
+            if let Some(rtcp_io) = UdpIO::new(self.group.address.to_string(), ports[1], 0).await {
+                if let Err(err) = rtcp_io.set_multicast_ttl_v4(self.group.ttl) {
+                    log::error!("set multicast ttl error: {}", err);
+                }
+                let box_rtcp_io: Box<dyn TNetIO + Send + Sync> = Box::new(rtcp_io);
+                track
+                    .rtcp_receive_loop(Arc::new(Mutex::new(box_rtcp_io)))
+                    .await;
+            }
+            track
+                .set_transport(RtspTransport {
+                    cast_type: CastType::Multicast,
+                    protocol_type: ProtocolType::UDP,
+                    destination: Some(self.group.address.to_string()),
+                    port: Some(ports),
+                    ttl: Some(self.group.ttl),
+                    ..Default::default()
+                })
+                .await;
+            set_packet_handler(track).await;
+        }
+    }
+
+    async fn run(&mut self, mut stop_receiver: oneshot::Receiver<()>) -> Result<(), SessionError> {
+        let mut receiver = self.subscribe_from_stream_hub().await?;
+
+        loop {
+            tokio::select! {
+                frame_data = receiver.recv() => {
+                    let (track_type, timestamp, data) = match frame_data {
+                        Some(FrameData::Audio { timestamp, data }) => (TrackType::Audio, timestamp, data),
+                        Some(FrameData::Video { timestamp, data }) => (TrackType::Video, timestamp, data),
+                        Some(_) => continue,
+                        None => {
+                            log::info!("multicast sender: the stream {} is unpublished", self.stream_path);
+                            return Ok(());
+                        }
+                    };
+
+                    if let Some(track) = self.tracks.get_mut(&track_type) {
+                        track
+                            .rtp_channel
+                            .lock()
+                            .await
+                            .on_frame(data, timestamp)
+                            .await?;
+                    }
+                }
+                _ = &mut stop_receiver => {
+                    log::info!("multicast sender: stop sending the stream {}", self.stream_path);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn get_identifier(&self) -> StreamIdentifier {
+        StreamIdentifier::Rtsp {
+            stream_path: self.stream_path.clone(),
+        }
+    }
+
+    fn get_subscriber_info(&self) -> SubscriberInfo {
+        SubscriberInfo {
+            id: self.subscriber_id,
+            sub_type: SubscribeType::PlayerRtsp,
+            sub_data_type: SubDataType::Frame,
+            start_mode: SubscribeStartMode::default(),
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: format!("{}:{}", self.group.address, self.group.port),
+            },
+        }
+    }
+
+    async fn subscribe_from_stream_hub(&mut self) -> Result<SubFrameDataReceiver, SessionError> {
+        let (event_result_sender, event_result_receiver) = oneshot::channel();
+
+        let subscribe_event = StreamHubEvent::Subscribe {
+            identifier: self.get_identifier(),
+            info: self.get_subscriber_info(),
+            result_sender: event_result_sender,
+        };
+
+        if self.event_producer.send(subscribe_event).is_err() {
+            return Err(SessionError {
+                value: SessionErrorValue::StreamHubEventSendErr,
+            });
+        }
+
+        let receiver = event_result_receiver.await??.0.frame_receiver;
+        self.is_subscribed = true;
+
+        match receiver {
+            Some(receiver) => Ok(receiver),
+            None => Err(SessionError {
+                value: SessionErrorValue::CannotReceiveFrameData,
+            }),
+        }
+    }
+
+    fn unsubscribe_from_stream_hub(&mut self) {
+        if !self.is_subscribed {
+            return;
+        }
+        self.is_subscribed = false;
+
+        let unsubscribe_event = StreamHubEvent::UnSubscribe {
+            identifier: self.get_identifier(),
+            info: self.get_subscriber_info(),
+        };
+        if self.event_producer.send(unsubscribe_event).is_err() {
+            log::error!("multicast sender: send unsubscribe event error");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_multicast_group_allocation() {
+        let manager = MulticastManager::new(MulticastConfig {
+            start_address: Ipv4Addr::new(239, 0, 0, 1),
+            address_count: 2,
+            start_port: 20000,
+            ttl: 16,
+        });
+
+        let group = manager.join("/live/a").await.unwrap();
+        assert_eq!(group.address, Ipv4Addr::new(239, 0, 0, 1));
+        assert_eq!(group.track_ports(&TrackType::Audio), [20002, 20003]);
+
+        //the viewers of the same stream share the group
+        let group = manager.join("/live/a").await.unwrap();
+        assert_eq!(group.address, Ipv4Addr::new(239, 0, 0, 1));
+
+        let group = manager.join("/live/b").await.unwrap();
+        assert_eq!(group.address, Ipv4Addr::new(239, 0, 0, 2));
+        assert_eq!(group.port, 20004);
+        assert!(manager.join("/live/c").await.is_none());
+
+        //the group is released after all the sessions leave
+        manager.leave("/live/a").await;
+        assert!(manager.join("/live/c").await.is_none());
+        manager.leave("/live/a").await;
+        let group = manager.join("/live/c").await.unwrap();
+        assert_eq!(group.address, Ipv4Addr::new(239, 0, 0, 1));
+    }
+}
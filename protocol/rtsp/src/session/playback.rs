@@ -0,0 +1,584 @@
+use super::errors::SessionError;
+use super::errors::SessionErrorValue;
+
+use crate::global_trait::Unmarshal;
+use crate::rtp::utils::find_start_code;
+use crate::rtsp_range::RtspRange;
+use crate::rtsp_range::RtspRangeType;
+use crate::rtsp_track::TrackType;
+use crate::sdp::Sdp;
+
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use streamhub::define::{FrameData, SubFrameDataReceiver, SubFrameDataSender};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use xmpegts::define::epsi_stream_type;
+use xmpegts::demuxer::{TsDemuxer, TsFrame};
+
+const FRAME_CHANNEL_SIZE: usize = 64;
+//only the key frames are sent when fast forwarding with a big scale
+const KEY_FRAME_ONLY_SCALE: f64 = 4.0;
+const AAC_SAMPLES_PER_FRAME: i64 = 1024;
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+#[derive(Debug, Clone)]
+pub struct PlaybackConfig {
+    //rtsp://host:port/{path_prefix}/{app_name}/{stream_name} plays the record of app_name/stream_name
+    pub path_prefix: String,
+    //the root folder of the hls records
+    pub record_path: String,
+}
+
+impl PlaybackConfig {
+    //Return the recorded stream path if it is a playback request.
+    pub fn get_record_stream_path(&self, stream_path: &str) -> Option<String> {
+        let record_stream_path = stream_path
+            .trim_start_matches('/')
+            .strip_prefix(self.path_prefix.trim_matches('/'))?
+            .strip_prefix('/')?;
+        Some(record_stream_path.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RecordSegment {
+    path: String,
+    //the npt(ms) of the segment beginning
+    begin: i64,
+    duration: i64,
+    //the utc time(ms) of the segment beginning
+    program_date_time: Option<i64>,
+}
+
+struct PlaybackFrame {
+    track_type: TrackType,
+    is_key_frame: bool,
+    //the npt(ms) by the decoding time, used for pacing
+    npt: i64,
+    //the presentation time in 90kHz
+    pts: i64,
+    data: Bytes,
+}
+
+//Play back the hls record(vod m3u8 + ts files) of a stream.
+pub struct RecordPlayback {
+    segments: Vec<RecordSegment>,
+    //the total duration(ms)
+    duration: i64,
+    sdp: Sdp,
+    audio_clock_rate: u32,
+    //the npt(ms) of the last frame sent out, the PLAY after PAUSE resumes from here
+    position: Arc<AtomicI64>,
+}
+
+impl RecordPlayback {
+    pub async fn open(config: &PlaybackConfig, stream_path: &str) -> Result<Self, SessionError> {
+        let record_not_found = || SessionError {
+            value: SessionErrorValue::RecordNotFound(stream_path.to_string()),
+        };
+        //the names come from the request uri, the paths must not leave the record folder
+        let (app_name, stream_name) = stream_path
+            .split_once('/')
+            .filter(|(app_name, stream_name)| {
+                is_valid_path_part(app_name) && is_valid_path_part(stream_name)
+            })
+            .ok_or_else(record_not_found)?;
+
+        let m3u8_path = format!(
+            "{}/{app_name}/hls/{stream_name}/vod_{stream_name}.m3u8",
+            config.record_path
+        );
+        if !is_under_folder(&m3u8_path, &config.record_path).await {
+            log::error!("{} is not under the record path", m3u8_path);
+            return Err(record_not_found());
+        }
+        let m3u8_content = tokio::fs::read_to_string(&m3u8_path).await.map_err(|err| {
+            log::error!("read {} error: {}", m3u8_path, err);
+            record_not_found()
+        })?;
+        let ts_folder = format!("{}/{app_name}/{stream_name}/hls", config.record_path);
+        let segments = parse_vod_m3u8(&m3u8_content, &ts_folder);
+
+        let duration = match segments.last() {
+            Some(segment) => segment.begin + segment.duration,
+            None => return Err(record_not_found()),
+        };
+        let (sdp, audio_clock_rate) =
+            gen_sdp(&read_segment(&segments[0]).await?, duration).ok_or_else(record_not_found)?;
+
+        Ok(Self {
+            segments,
+            duration,
+            sdp,
+            audio_clock_rate,
+            position: Arc::new(AtomicI64::new(0)),
+        })
+    }
+
+    pub fn get_sdp(&self) -> Sdp {
+        self.sdp.clone()
+    }
+
+    pub fn get_duration(&self) -> i64 {
+        self.duration
+    }
+
+    pub fn get_position(&self) -> i64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    //The rtp timestamps of the record begin with 0.
+    pub fn get_rtp_timestamp(&self, track_type: &TrackType, position: i64) -> u32 {
+        match track_type {
+            TrackType::Video => (position * 90) as u32,
+            _ => (position * self.audio_clock_rate as i64 / 1000) as u32,
+        }
+    }
+
+    //Convert the range to the npt(ms), return None if it is out of the record.
+    pub fn get_seek_position(&self, range: &RtspRange) -> Option<i64> {
+        let position = match range.range_type {
+            RtspRangeType::NPT => range.begin,
+            //seek to the next segment if the time is not recorded
+            RtspRangeType::CLOCK => self.segments.iter().find_map(|segment| {
+                let begin_time = segment.program_date_time?;
+                (range.begin < begin_time + segment.duration)
+                    .then(|| segment.begin + (range.begin - begin_time).max(0))
+            })?,
+        };
+        (0..self.duration).contains(&position).then_some(position)
+    }
+
+    //Start sending the frames from the key frame before the position, return the frame
+    //receiver and the npt(ms) that the playback actually begins with.
+    pub async fn start(
+        &self,
+        position: i64,
+        scale: f64,
+    ) -> Result<(SubFrameDataReceiver, i64), SessionError> {
+        let index = self
+            .segments
+            .iter()
+            .rposition(|segment| segment.begin <= position)
+            .unwrap_or(0);
+        let ts_frames = read_segment(&self.segments[index]).await?;
+        let mut frames = gen_playback_frames(&self.segments[index], ts_frames);
+
+        let start_index = frames
+            .iter()
+            .rposition(|frame| frame.is_key_frame && frame.npt <= position)
+            .unwrap_or(0);
+        frames.drain(..start_index);
+        let start_position = frames.first().map_or(position, |frame| frame.npt);
+        self.position.store(start_position, Ordering::Relaxed);
+
+        let (sender, receiver) = mpsc::channel(FRAME_CHANNEL_SIZE);
+        let player = FramePlayer {
+            segments: self.segments[index + 1..].to_vec(),
+            start_position,
+            scale,
+            audio_clock_rate: self.audio_clock_rate,
+            position: self.position.clone(),
+            sender,
+        };
+        tokio::spawn(player.run(frames));
+
+        Ok((receiver, start_position))
+    }
+}
+
+struct FramePlayer {
+    segments: Vec<RecordSegment>,
+    start_position: i64,
+    scale: f64,
+    audio_clock_rate: u32,
+    position: Arc<AtomicI64>,
+    sender: SubFrameDataSender,
+}
+
+impl FramePlayer {
+    //The frames are sent by the scaled npt, it exits when the frame receiver is dropped(PAUSE/TEARDOWN).
+    async fn run(self, mut frames: Vec<PlaybackFrame>) {
+        let start_time = Instant::now();
+        let mut segments = self.segments.iter();
+
+        loop {
+            for frame in frames {
+                //the audio is muted when not playing with the normal speed
+                if self.scale != 1.0 && frame.track_type == TrackType::Audio {
+                    continue;
+                }
+                if self.scale >= KEY_FRAME_ONLY_SCALE && !frame.is_key_frame {
+                    continue;
+                }
+
+                let offset = ((frame.npt - self.start_position).max(0) as f64 / self.scale) as u64;
+                tokio::time::sleep_until(start_time + Duration::from_millis(offset)).await;
+
+                let frame_data = match frame.track_type {
+                    TrackType::Video => FrameData::Video {
+                        timestamp: frame.pts as u32,
+                        data: frame.data,
+                    },
+                    _ => FrameData::Audio {
+                        timestamp: (frame.pts * self.audio_clock_rate as i64 / 90000) as u32,
+                        data: frame.data,
+                    },
+                };
+                if self.sender.send(frame_data).await.is_err() {
+                    return;
+                }
+                self.position.store(frame.npt, Ordering::Relaxed);
+            }
+
+            let segment = match segments.next() {
+                Some(segment) => segment,
+                None => break,
+            };
+            frames = match read_segment(segment).await {
+                Ok(ts_frames) => gen_playback_frames(segment, ts_frames),
+                Err(err) => {
+                    log::error!("read segment {} error: {}", segment.path, err);
+                    break;
+                }
+            };
+        }
+        log::info!(
+            "playback ends at {}ms",
+            self.position.load(Ordering::Relaxed)
+        );
+    }
+}
+
+// #EXT-X-PROGRAM-DATE-TIME:2024-01-01T08:00:00.000Z
+// #EXTINF:10.000
+// 0.ts
+fn parse_vod_m3u8(content: &str, ts_folder: &str) -> Vec<RecordSegment> {
+    let mut segments = Vec::new();
+    let mut begin: i64 = 0;
+    let mut duration: Option<i64> = None;
+    let mut program_date_time: Option<i64> = None;
+
+    for line in content.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            program_date_time = chrono::DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|datetime| datetime.timestamp_millis());
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let value = value.split(',').next().unwrap_or_default();
+            duration = value
+                .parse::<f64>()
+                .ok()
+                .map(|seconds| (seconds * 1000.0) as i64);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            if let Some(duration) = duration.take() {
+                segments.push(RecordSegment {
+                    path: format!("{ts_folder}/{line}"),
+                    begin,
+                    duration,
+                    program_date_time: program_date_time.take(),
+                });
+                begin += duration;
+            }
+        }
+    }
+    segments
+}
+
+//The path part(app name or stream name) is a single folder/file name.
+fn is_valid_path_part(part: &str) -> bool {
+    !part.is_empty() && part != "." && part != ".." && !part.contains(['/', '\\'])
+}
+
+//Check that the path still lies in the folder after resolving the links and "..".
+async fn is_under_folder(path: &str, folder: &str) -> bool {
+    match (
+        tokio::fs::canonicalize(path).await,
+        tokio::fs::canonicalize(folder).await,
+    ) {
+        (Ok(path), Ok(folder)) => path.starts_with(folder),
+        _ => false,
+    }
+}
+
+async fn read_segment(segment: &RecordSegment) -> Result<Vec<TsFrame>, SessionError> {
+    let data = tokio::fs::read(&segment.path).await.map_err(|err| {
+        log::error!("read {} error: {}", segment.path, err);
+        SessionError {
+            value: SessionErrorValue::RecordNotFound(segment.path.clone()),
+        }
+    })?;
+
+    let mut demuxer = TsDemuxer::new();
+    let mut frames = demuxer.demux(&data)?;
+    frames.extend(demuxer.flush());
+    frames.sort_by_key(|frame| frame.dts);
+    Ok(frames)
+}
+
+//The timestamps are converted to the npt of the record.
+fn gen_playback_frames(segment: &RecordSegment, ts_frames: Vec<TsFrame>) -> Vec<PlaybackFrame> {
+    let mut frames = Vec::new();
+    let first_dts = match ts_frames.first() {
+        Some(frame) => frame.dts,
+        None => return frames,
+    };
+    let begin = segment.begin * 90;
+
+    for ts_frame in ts_frames {
+        let dts = begin + ts_frame.dts - first_dts;
+        let pts = begin + ts_frame.pts - first_dts;
+
+        match ts_frame.stream_type {
+            epsi_stream_type::PSI_STREAM_H264 | epsi_stream_type::PSI_STREAM_H265 => {
+                let is_h264 = ts_frame.stream_type == epsi_stream_type::PSI_STREAM_H264;
+                let is_key_frame = split_nalus(&ts_frame.data).iter().any(|nalu| {
+                    if is_h264 {
+                        nalu[0] & 0x1F == 5
+                    } else {
+                        (16..=21).contains(&((nalu[0] >> 1) & 0x3F))
+                    }
+                });
+                frames.push(PlaybackFrame {
+                    track_type: TrackType::Video,
+                    is_key_frame,
+                    npt: dts / 90,
+                    pts,
+                    data: ts_frame.data.freeze(),
+                });
+            }
+            epsi_stream_type::PSI_STREAM_AAC => {
+                //the rtp aac packer needs the raw aac frames without the adts headers
+                let mut offset: i64 = 0;
+                for (adts_header, raw_data) in split_adts_frames(&ts_frame.data) {
+                    let sample_rate = AAC_SAMPLE_RATES
+                        .get(((adts_header[2] >> 2) & 0x0F) as usize)
+                        .copied()
+                        .unwrap_or(44100) as i64;
+                    frames.push(PlaybackFrame {
+                        track_type: TrackType::Audio,
+                        is_key_frame: true,
+                        npt: (dts + offset) / 90,
+                        pts: pts + offset,
+                        data: Bytes::copy_from_slice(raw_data),
+                    });
+                    offset += AAC_SAMPLES_PER_FRAME * 90000 / sample_rate;
+                }
+            }
+            _ => {}
+        }
+    }
+    frames
+}
+
+fn split_nalus(data: &[u8]) -> Vec<&[u8]> {
+    let mut nalus = Vec::new();
+    let mut left = data;
+    while let Some(start) = find_start_code(left) {
+        left = &left[start + 3..];
+        let end = find_start_code(left).unwrap_or(left.len());
+        //remove the leading zero of the next 4 bytes start code
+        let mut nalu = &left[..end];
+        while let [data @ .., 0] = nalu {
+            nalu = data;
+        }
+        if !nalu.is_empty() {
+            nalus.push(nalu);
+        }
+        left = &left[end..];
+    }
+    nalus
+}
+
+//Return the adts headers and the raw aac data.
+fn split_adts_frames(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut frames = Vec::new();
+    let mut left = data;
+    while left.len() >= 7 && left[0] == 0xFF && left[1] & 0xF0 == 0xF0 {
+        //the crc is present if the protection absent is 0
+        let header_length = if left[1] & 0x01 == 0 { 9 } else { 7 };
+        let frame_length = ((left[3] as usize & 0x03) << 11)
+            | ((left[4] as usize) << 3)
+            | ((left[5] as usize) >> 5);
+        if frame_length <= header_length || frame_length > left.len() {
+            break;
+        }
+        frames.push((&left[..header_length], &left[header_length..frame_length]));
+        left = &left[frame_length..];
+    }
+    frames
+}
+
+//Generate the sdp from the parameter sets of the first key frame and the first adts header.
+fn gen_sdp(ts_frames: &[TsFrame], duration: i64) -> Option<(Sdp, u32)> {
+    let mut video_media: Option<String> = None;
+    let mut audio_media: Option<(String, u32)> = None;
+
+    for ts_frame in ts_frames {
+        match ts_frame.stream_type {
+            epsi_stream_type::PSI_STREAM_H264 if video_media.is_none() => {
+                let nalus = split_nalus(&ts_frame.data);
+                let sps = nalus.iter().find(|nalu| nalu[0] & 0x1F == 7);
+                let pps = nalus.iter().find(|nalu| nalu[0] & 0x1F == 8);
+                if let (Some(sps), Some(pps)) = (sps, pps) {
+                    if sps.len() < 4 {
+                        continue;
+                    }
+                    video_media = Some(format!(
+                        "m=video 0 RTP/AVP 96\r\n\
+                         a=rtpmap:96 H264/90000\r\n\
+                         a=fmtp:96 packetization-mode=1; sprop-parameter-sets={},{}; profile-level-id={}\r\n\
+                         a=control:streamid=0\r\n",
+                        general_purpose::STANDARD.encode(sps),
+                        general_purpose::STANDARD.encode(pps),
+                        hex::encode_upper(&sps[1..4])
+                    ));
+                }
+            }
+            epsi_stream_type::PSI_STREAM_H265 if video_media.is_none() => {
+                let nalus = split_nalus(&ts_frame.data);
+                let find_nalu = |nalu_type: u8| {
+                    nalus
+                        .iter()
+                        .find(|nalu| (nalu[0] >> 1) & 0x3F == nalu_type)
+                        .map(|nalu| general_purpose::STANDARD.encode(nalu))
+                };
+                if let (Some(vps), Some(sps), Some(pps)) =
+                    (find_nalu(32), find_nalu(33), find_nalu(34))
+                {
+                    video_media = Some(format!(
+                        "m=video 0 RTP/AVP 96\r\n\
+                         a=rtpmap:96 H265/90000\r\n\
+                         a=fmtp:96 sprop-vps={vps}; sprop-sps={sps}; sprop-pps={pps}\r\n\
+                         a=control:streamid=0\r\n"
+                    ));
+                }
+            }
+            epsi_stream_type::PSI_STREAM_AAC if audio_media.is_none() => {
+                if let Some((adts_header, _)) = split_adts_frames(&ts_frame.data).first() {
+                    let object_type = (adts_header[2] >> 6) + 1;
+                    let sample_rate_index = (adts_header[2] >> 2) & 0x0F;
+                    let channel_count = ((adts_header[2] & 0x01) << 2) | (adts_header[3] >> 6);
+                    let sample_rate = *AAC_SAMPLE_RATES.get(sample_rate_index as usize)?;
+                    //AudioSpecificConfig: object type(5 bits) + sample rate index(4 bits) + channel(4 bits)
+                    let config = ((object_type as u16) << 11)
+                        | ((sample_rate_index as u16) << 7)
+                        | ((channel_count as u16) << 3);
+                    audio_media = Some((
+                        format!(
+                            "m=audio 0 RTP/AVP 97\r\n\
+                             a=rtpmap:97 MPEG4-GENERIC/{sample_rate}/{channel_count}\r\n\
+                             a=fmtp:97 profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3; config={}\r\n\
+                             a=control:streamid=1\r\n",
+                            hex::encode_upper(config.to_be_bytes())
+                        ),
+                        sample_rate,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if video_media.is_none() && audio_media.is_none() {
+        return None;
+    }
+
+    let mut sdp = format!(
+        "v=0\r\n\
+         o=- 0 0 IN IP4 127.0.0.1\r\n\
+         s=Playback\r\n\
+         c=IN IP4 0.0.0.0\r\n\
+         t=0 0\r\n\
+         a=range:npt=0-{}.{:03}\r\n",
+        duration / 1000,
+        duration % 1000
+    );
+    let mut audio_clock_rate = 0;
+    if let Some(video_media) = video_media {
+        sdp += &video_media;
+    }
+    if let Some((audio_media, sample_rate)) = audio_media {
+        sdp += &audio_media;
+        audio_clock_rate = sample_rate;
+    }
+
+    Some((Sdp::unmarshal(&sdp)?, audio_clock_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek_record() {
+        let content = "#EXTM3U\n\
+            #EXT-X-VERSION:3\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-01-01T08:00:00.000Z\n\
+            #EXTINF:10.000\n\
+            0.ts\n\
+            #EXT-X-DISCONTINUITY\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-01-01T09:00:00.000Z\n\
+            #EXTINF:5.500\n\
+            1.ts\n\
+            #EXT-X-ENDLIST\n";
+        let segments = parse_vod_m3u8(content, "./live/test/hls");
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].path, "./live/test/hls/1.ts");
+        assert_eq!((segments[1].begin, segments[1].duration), (10000, 5500));
+
+        let playback = RecordPlayback {
+            segments,
+            duration: 15500,
+            sdp: Sdp::default(),
+            audio_clock_rate: 0,
+            position: Arc::new(AtomicI64::new(0)),
+        };
+        let seek = |range: &str| playback.get_seek_position(&RtspRange::unmarshal(range).unwrap());
+        assert_eq!(seek("npt=12.5-"), Some(12500));
+        assert_eq!(seek("npt=20-"), None);
+        assert_eq!(seek("clock=20240101T080003Z-"), Some(3000));
+        //the time between the segments is not recorded
+        assert_eq!(seek("clock=20240101T083000Z-"), Some(10000));
+        assert_eq!(seek("clock=20240101T100000Z-"), None);
+
+        let config = PlaybackConfig {
+            path_prefix: String::from("playback"),
+            record_path: String::from("."),
+        };
+        assert_eq!(
+            config.get_record_stream_path("playback/live/test"),
+            Some(String::from("live/test"))
+        );
+        assert_eq!(config.get_record_stream_path("live/test"), None);
+    }
+
+    #[tokio::test]
+    async fn test_record_path_traversal() {
+        assert!(is_valid_path_part("test"));
+        for part in ["", ".", "..", "../test", "live/test", "..\\test"] {
+            assert!(!is_valid_path_part(part));
+        }
+
+        let config = PlaybackConfig {
+            path_prefix: String::from("playback"),
+            record_path: String::from("."),
+        };
+        for stream_path in ["../etc", "live/..", "live/../../test", "live"] {
+            assert!(RecordPlayback::open(&config, stream_path).await.is_err());
+        }
+
+        let folder = std::env::temp_dir().join("xiu_record_path_traversal");
+        std::fs::create_dir_all(folder.join("record")).unwrap();
+        let folder_str = folder.to_str().unwrap();
+        assert!(is_under_folder(&format!("{folder_str}/record"), folder_str).await);
+        assert!(!is_under_folder(&format!("{folder_str}/record/../.."), folder_str).await);
+        assert!(!is_under_folder(&format!("{folder_str}/not_exist"), folder_str).await);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use {
+    crate::{global_trait::Unmarshal, sdp::Sdp},
+    base64::{engine::general_purpose, Engine as _},
+    bytes::{Bytes, BytesMut},
+    bytesio::bytes_reader::BytesReader,
+    streamhub::define::FrameData,
+    xflv::{
+        define::{aac_packet_type, avc_packet_type, AvcCodecId, SoundFormat},
+        mpeg4_avc::Mpeg4AvcProcessor,
+    },
+};
+
+const H264_PAYLOAD_TYPE: u8 = 96;
+const AAC_PAYLOAD_TYPE: u8 = 97;
+const VIDEO_CLOCK_RATE: u64 = 90000;
+//the sampling frequencies of the aac sampling frequency indexes
+const AAC_SAMPLING_FREQUENCIES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+//Convert the flv tags of a rtmp stream to the frames which can be packed into rtp:
+//the h264 nalus with start codes and the raw aac frames, the timestamps are converted
+//from milliseconds to the clock rates. The sdp is generated from the sequence headers.
+#[derive(Default)]
+pub struct Rtmp2RtspConverter {
+    avc_processor: Mpeg4AvcProcessor,
+    has_video: bool,
+    audio_specific_config: Option<BytesMut>,
+    audio_sample_rate: u32,
+    audio_channels: u8,
+}
+
+impl Rtmp2RtspConverter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Return the converted a/v frame, the sequence headers are saved for the sdp.
+    pub fn convert(&mut self, frame_data: FrameData) -> Option<FrameData> {
+        match frame_data {
+            FrameData::Video { timestamp, data } => self.convert_video(timestamp, data),
+            FrameData::Audio { timestamp, data } => self.convert_audio(timestamp, data),
+            _ => None,
+        }
+    }
+
+    fn convert_video(&mut self, timestamp: u32, data: Bytes) -> Option<FrameData> {
+        //frame type and codec id(1 byte) + avc packet type(1 byte) + composition time(3 bytes),
+        //only the h264 of the legacy flv is supported
+        if data.len() < 5 || data[0] & 0x80 != 0 || data[0] & 0x0F != AvcCodecId::H264 as u8 {
+            return None;
+        }
+
+        match data[1] {
+            avc_packet_type::AVC_SEQHDR => {
+                let mut reader = BytesReader::new(BytesMut::from(&data[5..]));
+                match self
+                    .avc_processor
+                    .decoder_configuration_record_load(&mut reader)
+                {
+                    Ok(_) => self.has_video = true,
+                    Err(err) => log::error!("rtmp2rtsp load avc sequence header error: {}", err),
+                }
+                None
+            }
+            avc_packet_type::AVC_NALU => {
+                let nalus = match self.avc_processor.h264_mp4toannexb(&data[5..]) {
+                    Ok(nalus) => nalus,
+                    Err(err) => {
+                        log::error!("rtmp2rtsp convert avc nalus error: {}", err);
+                        return None;
+                    }
+                };
+                //the signed 24 bits composition time
+                let composition_time =
+                    (i32::from_be_bytes([data[2], data[3], data[4], 0]) >> 8) as i64;
+                let pts = (timestamp as i64 + composition_time).max(0) as u64;
+
+                Some(FrameData::Video {
+                    timestamp: (pts * VIDEO_CLOCK_RATE / 1000) as u32,
+                    data: nalus.freeze(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn convert_audio(&mut self, timestamp: u32, data: Bytes) -> Option<FrameData> {
+        //sound format, rate, size and type(1 byte) + aac packet type(1 byte)
+        if data.len() < 2 || data[0] >> 4 != SoundFormat::AAC as u8 {
+            return None;
+        }
+
+        match data[1] {
+            aac_packet_type::AAC_SEQHDR => {
+                self.load_audio_specific_config(&data[2..]);
+                None
+            }
+            aac_packet_type::AAC_RAW if self.audio_specific_config.is_some() => {
+                Some(FrameData::Audio {
+                    timestamp: (timestamp as u64 * self.audio_sample_rate as u64 / 1000) as u32,
+                    data: data.slice(2..),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    //audio object type(5 bits) + sampling frequency index(4 bits) + channel configuration(4 bits)
+    fn load_audio_specific_config(&mut self, asc: &[u8]) {
+        if asc.len() < 2 {
+            return;
+        }
+        let index = (((asc[0] & 0x07) << 1) | (asc[1] >> 7)) as usize;
+        match AAC_SAMPLING_FREQUENCIES.get(index) {
+            Some(sample_rate) => {
+                self.audio_sample_rate = *sample_rate;
+                self.audio_channels = ((asc[1] >> 3) & 0x0F).max(1);
+                self.audio_specific_config = Some(BytesMut::from(asc));
+            }
+            None => log::error!(
+                "rtmp2rtsp unsupported aac sampling frequency index: {}",
+                index
+            ),
+        }
+    }
+
+    //Generate the sdp from the received sequence headers.
+    pub fn gen_sdp(&self) -> Option<Sdp> {
+        let mut medias = String::new();
+
+        let mpeg4_avc = &self.avc_processor.mpeg4_avc;
+        if let (true, Some(sps), Some(pps)) =
+            (self.has_video, mpeg4_avc.sps.first(), mpeg4_avc.pps.first())
+        {
+            if sps.data.len() >= 4 {
+                medias += &format!(
+                    "m=video 0 RTP/AVP {pt}\r\n\
+                     a=rtpmap:{pt} H264/90000\r\n\
+                     a=fmtp:{pt} packetization-mode=1; sprop-parameter-sets={},{}; profile-level-id={}\r\n",
+                    general_purpose::STANDARD.encode(&sps.data),
+                    general_purpose::STANDARD.encode(&pps.data),
+                    hex::encode_upper(&sps.data[1..4]),
+                    pt = H264_PAYLOAD_TYPE,
+                );
+            }
+        }
+
+        if let Some(asc) = &self.audio_specific_config {
+            medias += &format!(
+                "m=audio 0 RTP/AVP {pt}\r\n\
+                 a=rtpmap:{pt} MPEG4-GENERIC/{}/{}\r\n\
+                 a=fmtp:{pt} profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config={}\r\n",
+                self.audio_sample_rate,
+                self.audio_channels,
+                hex::encode_upper(asc),
+                pt = AAC_PAYLOAD_TYPE,
+            );
+        }
+
+        if medias.is_empty() {
+            return None;
+        }
+        Sdp::unmarshal(&format!(
+            "v=0\r\no=- 0 0 IN IP4 127.0.0.1\r\ns=xiu\r\nc=IN IP4 0.0.0.0\r\nt=0 0\r\n{medias}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtmp2rtsp_convert() {
+        let mut converter = Rtmp2RtspConverter::new();
+
+        //AVCDecoderConfigurationRecord with one sps and one pps
+        let sps = [
+            0x67, 0x64, 0x00, 0x1E, 0xAC, 0xD9, 0x40, 0xA0, 0x2F, 0xF9, 0x70, 0x11, 0x00, 0x00,
+            0x03, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x32, 0x0F, 0x16, 0x2D, 0x96,
+        ];
+        let pps = [0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0];
+        let mut video_seq_header = vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x64, 0x00, 0x1E];
+        video_seq_header.extend_from_slice(&[0xFF, 0xE1, 0x00, sps.len() as u8]);
+        video_seq_header.extend_from_slice(&sps);
+        video_seq_header.extend_from_slice(&[0x01, 0x00, pps.len() as u8]);
+        video_seq_header.extend_from_slice(&pps);
+        assert!(converter
+            .convert(FrameData::Video {
+                timestamp: 0,
+                data: Bytes::from(video_seq_header),
+            })
+            .is_none());
+
+        //AAC LC, 44100Hz, stereo
+        assert!(converter
+            .convert(FrameData::Audio {
+                timestamp: 0,
+                data: Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]),
+            })
+            .is_none());
+
+        let sdp = converter.gen_sdp().unwrap();
+        assert_eq!(sdp.medias.len(), 2);
+        assert_eq!(sdp.medias[0].rtpmap.encoding_name, "H264");
+        assert_eq!(sdp.medias[1].rtpmap.clock_rate, 44100);
+
+        //a non-idr nalu with the composition time of 40ms
+        let video = converter.convert(FrameData::Video {
+            timestamp: 1000,
+            data: Bytes::from_static(&[
+                0x27, 0x01, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x02, 0x41, 0x9A,
+            ]),
+        });
+        match video {
+            Some(FrameData::Video { timestamp, data }) => {
+                assert_eq!(timestamp, 1040 * 90);
+                assert_eq!(&data[..], &[0x00, 0x00, 0x00, 0x01, 0x41, 0x9A]);
+            }
+            _ => panic!("the video frame is not converted"),
+        }
+
+        let audio = converter.convert(FrameData::Audio {
+            timestamp: 1000,
+            data: Bytes::from_static(&[0xAF, 0x01, 0x21, 0x10]),
+        });
+        match audio {
+            Some(FrameData::Audio { timestamp, data }) => {
+                assert_eq!(timestamp, 44100);
+                assert_eq!(&data[..], &[0x21, 0x10]);
+            }
+            _ => panic!("the audio frame is not converted"),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::errors::{Aac2OpusError, Aac2OpusErrorValue};
+use audiopus::coder::Encoder as OpusEncoder;
+use fdk_aac::dec::{Decoder as AacDecoder, Transport};
+
+//the opus in webrtc is always 48khz stereo
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+const OPUS_CHANNELS: usize = 2;
+//the pcm samples(per channel) of a 20ms opus frame
+pub const OPUS_FRAME_SAMPLES: usize = 960;
+
+//https://wiki.multimedia.cx/index.php/ADTS
+const ADTS_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+//returns the sample rate and the channels of an adts frame
+fn parse_adts_header(adts: &[u8]) -> Option<(u32, usize)> {
+    if adts.len() < 7 || adts[0] != 0xFF || adts[1] & 0xF0 != 0xF0 {
+        return None;
+    }
+    let sample_rate = *ADTS_SAMPLE_RATES.get(((adts[2] >> 2) & 0x0F) as usize)?;
+    let channels = (((adts[2] & 0x01) << 2) | (adts[3] >> 6)) as usize;
+    Some((sample_rate, channels.max(1)))
+}
+
+//Convert the stereo pcm samples to 48khz by the linear interpolation.
+struct StereoResampler {
+    //the last input sample of the previous call
+    last_sample: (i16, i16),
+    //the position of the next output sample, 0 is the last sample and 1 is the first input sample
+    position: f64,
+}
+
+impl StereoResampler {
+    fn new() -> Self {
+        Self {
+            last_sample: (0, 0),
+            position: 1.,
+        }
+    }
+
+    fn resample(&mut self, samples: &[(i16, i16)], sample_rate: u32, output: &mut Vec<i16>) {
+        let step = sample_rate as f64 / OPUS_SAMPLE_RATE as f64;
+        let len = samples.len() as f64;
+
+        while self.position < len {
+            let index = self.position as usize;
+            let fraction = self.position - index as f64;
+            let first = if index == 0 {
+                self.last_sample
+            } else {
+                samples[index - 1]
+            };
+            let second = samples[index];
+
+            output.push(interpolate(first.0, second.0, fraction));
+            output.push(interpolate(first.1, second.1, fraction));
+            self.position += step;
+        }
+
+        if let Some(last_sample) = samples.last() {
+            self.last_sample = *last_sample;
+            self.position -= len;
+        }
+    }
+}
+
+fn interpolate(first: i16, second: i16, fraction: f64) -> i16 {
+    (first as f64 + (second as f64 - first as f64) * fraction) as i16
+}
+
+//Decode the aac(adts) frames to pcm and encode them to 48khz stereo opus,
+//it is the inverse of the Opus2AacTranscoder.
+pub struct Aac2OpusTranscoder {
+    decoder: AacDecoder,
+    encoder: OpusEncoder,
+    resampler: StereoResampler,
+    //the interleaved 48khz stereo pcm samples waiting for encoding
+    pcm_data: Vec<i16>,
+}
+
+impl Aac2OpusTranscoder {
+    pub fn new() -> Result<Self, Aac2OpusError> {
+        let encoder = OpusEncoder::new(
+            audiopus::SampleRate::Hz48000,
+            audiopus::Channels::Stereo,
+            audiopus::Application::Audio,
+        )?;
+
+        Ok(Aac2OpusTranscoder {
+            decoder: AacDecoder::new(Transport::Adts),
+            encoder,
+            resampler: StereoResampler::new(),
+            pcm_data: Vec::new(),
+        })
+    }
+
+    //returns the 20ms opus frames
+    pub fn transcode(&mut self, input: &[u8]) -> Result<Vec<Vec<u8>>, Aac2OpusError> {
+        let (sample_rate, channels) = parse_adts_header(input).ok_or(Aac2OpusError {
+            value: Aac2OpusErrorValue::InvalidAdtsHeader,
+        })?;
+
+        self.decoder.fill(input)?;
+        //the he-aac frame has 2048 samples per channel
+        let mut pcm_output: Vec<i16> = vec![0; 2048 * 8];
+        self.decoder.decode_frame(&mut pcm_output)?;
+        let pcm_output_len = self.decoder.decoded_frame_size();
+
+        let samples: Vec<(i16, i16)> = pcm_output[..pcm_output_len]
+            .chunks(channels)
+            .map(|sample| (sample[0], *sample.get(1).unwrap_or(&sample[0])))
+            .collect();
+        self.resampler
+            .resample(&samples, sample_rate, &mut self.pcm_data);
+
+        let mut opus_output: Vec<u8> = vec![0; 1500];
+        let mut result = Vec::new();
+        while self.pcm_data.len() >= OPUS_FRAME_SAMPLES * OPUS_CHANNELS {
+            let pcm = self.pcm_data.split_off(OPUS_FRAME_SAMPLES * OPUS_CHANNELS);
+            let output_size = self.encoder.encode(&self.pcm_data, &mut opus_output)?;
+            self.pcm_data = pcm;
+            result.push(opus_output[..output_size].to_vec());
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_adts_header, StereoResampler};
+
+    #[test]
+    fn test_parse_adts_header() {
+        //aac-lc 44100hz stereo
+        let adts = [0xFF, 0xF1, 0x50, 0x80, 0x2E, 0x7F, 0xFC];
+        assert_eq!(parse_adts_header(&adts), Some((44100, 2)));
+        assert_eq!(parse_adts_header(&adts[..6]), None);
+    }
+
+    #[test]
+    fn test_resample() {
+        let mut resampler = StereoResampler::new();
+        let mut output = Vec::new();
+
+        resampler.resample(&[(0, 0), (100, -100)], 24000, &mut output);
+        assert_eq!(output, vec![0, 0, 50, -50]);
+        //the samples between the calls are interpolated too
+        resampler.resample(&[(200, -200)], 24000, &mut output);
+        assert_eq!(output, vec![0, 0, 50, -50, 100, -100, 150, -150]);
+    }
+}
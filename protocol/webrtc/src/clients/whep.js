@@ -253,7 +253,6 @@ class WHEPClient extends EventTarget {
     }
 
     async trickle() {
-        return;
         //Clear timeout
         this.iceTrickeTimeout = null;
 
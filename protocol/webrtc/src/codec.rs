@@ -0,0 +1,285 @@
+use super::whip::parse_rtpmap;
+use bytes::{Bytes, BytesMut};
+use streamhub::define::VideoCodecType;
+use webrtc::api::media_engine::{
+    MediaEngine, MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+use webrtc::rtp::codecs::h264::H264Packet;
+use webrtc::rtp::codecs::h265::{H265Packet, H265Payload};
+use webrtc::rtp::packetizer::Depacketizer;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+};
+use webrtc::rtp_transceiver::RTCPFeedback;
+
+//H.265 is not in the default codecs of webrtc-rs
+pub const MIME_TYPE_H265: &str = "video/H265";
+
+//the video codecs which can be published, in the order of preference
+pub const VIDEO_CODECS: [VideoCodecType; 5] = [
+    VideoCodecType::H264,
+    VideoCodecType::H265,
+    VideoCodecType::VP8,
+    VideoCodecType::VP9,
+    VideoCodecType::AV1,
+];
+
+const VIDEO_RTCP_FEEDBACK: [(&str, &str); 4] = [
+    ("goog-remb", ""),
+    ("ccm", "fir"),
+    ("nack", ""),
+    ("nack", "pli"),
+];
+
+const ANNEXB_START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+//the codec of a rtpmap encoding name, e.g. H264 of "a=rtpmap:96 H264/90000"
+pub fn get_video_codec_type(name: &str) -> Option<VideoCodecType> {
+    match name.to_ascii_uppercase().as_str() {
+        "H264" => Some(VideoCodecType::H264),
+        "H265" => Some(VideoCodecType::H265),
+        "VP8" => Some(VideoCodecType::VP8),
+        "VP9" => Some(VideoCodecType::VP9),
+        //the old chrome versions use AV1X
+        "AV1" | "AV1X" => Some(VideoCodecType::AV1),
+        _ => None,
+    }
+}
+
+pub fn get_mime_type(codec: &VideoCodecType) -> &'static str {
+    match codec {
+        VideoCodecType::H264 => MIME_TYPE_H264,
+        VideoCodecType::H265 => MIME_TYPE_H265,
+        VideoCodecType::VP8 => MIME_TYPE_VP8,
+        VideoCodecType::VP9 => MIME_TYPE_VP9,
+        VideoCodecType::AV1 => MIME_TYPE_AV1,
+    }
+}
+
+//The video codecs in the rtpmaps of a sdp, in the order they are listed. The first
+//one of an answer is the codec sent by the offerer.
+pub fn get_sdp_video_codecs(sdp: &str) -> Vec<VideoCodecType> {
+    let mut codecs = Vec::new();
+    for line in sdp.lines().map(str::trim) {
+        if let Some(rtpmap) = line.strip_prefix("a=").filter(|a| a.starts_with("rtpmap:")) {
+            if let Some(codec) = parse_rtpmap(rtpmap)
+                .ok()
+                .and_then(|codec| get_video_codec_type(&codec.name))
+            {
+                if !codecs.contains(&codec) {
+                    codecs.push(codec);
+                }
+            }
+        }
+    }
+    codecs
+}
+
+//the payload types and the fmtp lines of a video codec
+fn get_codec_formats(codec: &VideoCodecType) -> Vec<(u8, &'static str)> {
+    match codec {
+        VideoCodecType::H264 => vec![
+            (
+                96,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+            ),
+            (
+                102,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f",
+            ),
+            (
+                104,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=4d001f",
+            ),
+            (
+                106,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=640032",
+            ),
+        ],
+        VideoCodecType::H265 => vec![(49, "")],
+        VideoCodecType::VP8 => vec![(98, "")],
+        VideoCodecType::VP9 => vec![(100, "profile-id=0")],
+        VideoCodecType::AV1 => vec![(41, "")],
+    }
+}
+
+//Register the opus audio and the video codecs, a whep session only registers the codec
+//of the stream so that the answer contains nothing else.
+pub fn register_codecs(
+    m: &mut MediaEngine,
+    vcodecs: &[VideoCodecType],
+) -> Result<(), webrtc::Error> {
+    m.register_codec(
+        RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                clock_rate: 48000,
+                channels: 2,
+                sdp_fmtp_line: "minptime=10;useinbandfec=1".to_owned(),
+                rtcp_feedback: vec![],
+            },
+            payload_type: 111,
+            ..Default::default()
+        },
+        RTPCodecType::Audio,
+    )?;
+
+    let rtcp_feedback: Vec<RTCPFeedback> = VIDEO_RTCP_FEEDBACK
+        .iter()
+        .map(|(typ, parameter)| RTCPFeedback {
+            typ: typ.to_string(),
+            parameter: parameter.to_string(),
+        })
+        .collect();
+    for vcodec in vcodecs {
+        for (payload_type, sdp_fmtp_line) in get_codec_formats(vcodec) {
+            m.register_codec(
+                RTCRtpCodecParameters {
+                    capability: RTCRtpCodecCapability {
+                        mime_type: get_mime_type(vcodec).to_owned(),
+                        clock_rate: 90000,
+                        channels: 0,
+                        sdp_fmtp_line: sdp_fmtp_line.to_owned(),
+                        rtcp_feedback: rtcp_feedback.clone(),
+                    },
+                    payload_type,
+                    ..Default::default()
+                },
+                RTPCodecType::Video,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+//The depacketizers of the video codecs which can be remuxed(H.264 and H.265), the
+//nal units are sent to the stream hub in Annex-B. The VP8/VP9/AV1 streams are only
+//forwarded as the rtp packets.
+pub enum VideoDepacketizer {
+    H264(H264Packet),
+    H265 {
+        packet: H265Packet,
+        //the nal unit of the fragmentation units received so far
+        fragments: BytesMut,
+    },
+}
+
+impl VideoDepacketizer {
+    pub fn new(codec: &VideoCodecType) -> Option<Self> {
+        match codec {
+            VideoCodecType::H264 => Some(Self::H264(H264Packet::default())),
+            VideoCodecType::H265 => Some(Self::H265 {
+                packet: H265Packet::default(),
+                fragments: BytesMut::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    //the complete nal units in the rtp payload
+    pub fn depacketize(&mut self, payload: &Bytes) -> Vec<Bytes> {
+        match self {
+            Self::H264(packet) => match packet.depacketize(payload) {
+                //the filler data is dropped
+                Ok(nalus) if nalus.len() > 4 && nalus[4] & 0x1F != 0x0C => vec![nalus],
+                _ => Vec::new(),
+            },
+            Self::H265 { packet, fragments } => {
+                if packet.depacketize(payload).is_err() {
+                    return Vec::new();
+                }
+                match packet.payload() {
+                    H265Payload::H265SingleNALUnitPacket(single) => {
+                        let mut nalu = BytesMut::from(&ANNEXB_START_CODE[..]);
+                        nalu.extend_from_slice(&single.payload_header().0.to_be_bytes());
+                        nalu.extend_from_slice(&single.payload());
+                        vec![nalu.freeze()]
+                    }
+                    H265Payload::H265AggregationPacket(aggregation) => aggregation
+                        .first_unit()
+                        .map(|unit| unit.nal_unit())
+                        .into_iter()
+                        .chain(aggregation.other_units().iter().map(|unit| unit.nal_unit()))
+                        .map(|nal_unit| {
+                            let mut nalu = BytesMut::from(&ANNEXB_START_CODE[..]);
+                            nalu.extend_from_slice(&nal_unit);
+                            nalu.freeze()
+                        })
+                        .collect(),
+                    H265Payload::H265FragmentationUnitPacket(fragment) => {
+                        let fu_header = fragment.fu_header();
+                        if fu_header.s() {
+                            //the nal unit type of the payload header is replaced by the fu type
+                            let header = (fragment.payload_header().0 & 0x81FF)
+                                | ((fu_header.fu_type() as u16) << 9);
+                            fragments.clear();
+                            fragments.extend_from_slice(&ANNEXB_START_CODE);
+                            fragments.extend_from_slice(&header.to_be_bytes());
+                        } else if fragments.is_empty() {
+                            //the start fragment is lost
+                            return Vec::new();
+                        }
+                        fragments.extend_from_slice(&fragment.payload());
+
+                        if fu_header.e() {
+                            vec![fragments.split().freeze()]
+                        } else {
+                            Vec::new()
+                        }
+                    }
+                    H265Payload::H265PACIPacket(_) => Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_sdp_video_codecs, VideoDepacketizer};
+    use bytes::Bytes;
+    use streamhub::define::VideoCodecType;
+
+    #[test]
+    fn test_get_sdp_video_codecs() {
+        let sdp = "m=video 9 UDP/TLS/RTP/SAVPF 96 97 45 49\r\n\
+                   a=rtpmap:96 VP8/90000\r\n\
+                   a=rtpmap:97 rtx/90000\r\n\
+                   a=rtpmap:45 AV1/90000\r\n\
+                   a=rtpmap:49 H265/90000\r\n";
+        assert_eq!(
+            get_sdp_video_codecs(sdp),
+            vec![
+                VideoCodecType::VP8,
+                VideoCodecType::AV1,
+                VideoCodecType::H265
+            ]
+        );
+    }
+
+    #[test]
+    fn test_h265_depacketizer() {
+        let mut depacketizer = VideoDepacketizer::new(&VideoCodecType::H265).unwrap();
+        //a IDR_W_RADL nal unit in two fragmentation units
+        let start = Bytes::from_static(&[0x62, 0x01, 0x93, 0xaf, 0x01]);
+        let end = Bytes::from_static(&[0x62, 0x01, 0x53, 0x02, 0x03]);
+        assert!(depacketizer.depacketize(&start).is_empty());
+        assert_eq!(
+            depacketizer.depacketize(&end),
+            vec![Bytes::from_static(&[
+                0x00, 0x00, 0x00, 0x01, 0x26, 0x01, 0xaf, 0x01, 0x02, 0x03
+            ])]
+        );
+        //the fragmentation unit without the start one is dropped
+        assert!(depacketizer.depacketize(&end).is_empty());
+
+        let single = Bytes::from_static(&[0x40, 0x01, 0x0c, 0x01, 0xff]);
+        assert_eq!(
+            depacketizer.depacketize(&single),
+            vec![Bytes::from_static(&[
+                0x00, 0x00, 0x00, 0x01, 0x40, 0x01, 0x0c, 0x01, 0xff
+            ])]
+        );
+        assert!(VideoDepacketizer::new(&VideoCodecType::VP8).is_none());
+    }
+}
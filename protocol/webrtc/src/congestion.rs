@@ -0,0 +1,114 @@
+use std::time::SystemTime;
+use webrtc::rtp::extension::abs_send_time_extension::unix2ntp;
+
+//the bitrate estimate grows at most 8% per second when the loss is low
+const ESTIMATE_INCREASE_RATIO: f64 = 1.08;
+const LOW_LOSS_RATIO: f64 = 0.02;
+const HIGH_LOSS_RATIO: f64 = 0.1;
+
+//Estimate the bandwidth of a viewer by the REMB/TWCC feedbacks, the loss based
+//estimate of GCC(https://datatracker.ietf.org/doc/html/draft-ietf-rmcat-gcc-02#section-6)
+//is used for the TWCC feedbacks.
+#[derive(Default)]
+pub struct BandwidthEstimator {
+    estimated_bitrate: Option<f64>,
+    received_packets: usize,
+    lost_packets: usize,
+    //the loss ratio in the last interval
+    loss_ratio: f64,
+}
+
+impl BandwidthEstimator {
+    //the max bitrate estimated by the viewer
+    pub fn on_remb(&mut self, bitrate: f64) {
+        self.estimated_bitrate = Some(bitrate);
+    }
+
+    //the received and lost packet counts in a TWCC feedback
+    pub fn on_twcc(&mut self, received: usize, lost: usize) {
+        self.received_packets += received;
+        self.lost_packets += lost;
+    }
+
+    //Update the estimate by the loss in the last interval, the estimate starts
+    //from the sending bitrate when the loss is high.
+    pub fn update(&mut self, sending_bitrate: f64) {
+        let total_packets = self.received_packets + self.lost_packets;
+        if total_packets == 0 {
+            return;
+        }
+        self.loss_ratio = self.lost_packets as f64 / total_packets as f64;
+        self.received_packets = 0;
+        self.lost_packets = 0;
+
+        if self.loss_ratio > HIGH_LOSS_RATIO {
+            let estimated_bitrate = self.estimated_bitrate.unwrap_or(sending_bitrate);
+            if estimated_bitrate > 0.0 {
+                self.estimated_bitrate = Some(estimated_bitrate * (1.0 - 0.5 * self.loss_ratio));
+            }
+        } else if self.loss_ratio < LOW_LOSS_RATIO {
+            if let Some(estimated_bitrate) = &mut self.estimated_bitrate {
+                *estimated_bitrate *= ESTIMATE_INCREASE_RATIO;
+            }
+        }
+    }
+
+    pub fn estimated_bitrate(&self) -> Option<f64> {
+        self.estimated_bitrate
+    }
+
+    //the viewer cannot receive the packets at the sending bitrate
+    pub fn is_congested(&self, sending_bitrate: f64) -> bool {
+        self.loss_ratio > HIGH_LOSS_RATIO
+            || self
+                .estimated_bitrate
+                .is_some_and(|estimated_bitrate| estimated_bitrate < sending_bitrate)
+    }
+}
+
+//The round trip time by a reception report(https://www.rfc-editor.org/rfc/rfc3550#section-6.4.1),
+//last_sender_report and delay are in the middle 32 bits of the NTP timestamp.
+pub fn get_rtt_ms(last_sender_report: u32, delay: u32, now: SystemTime) -> Option<f64> {
+    if last_sender_report == 0 {
+        return None;
+    }
+    let now = (unix2ntp(now) >> 16) as u32;
+    let rtt = now.checked_sub(last_sender_report)?.checked_sub(delay)?;
+    Some(rtt as f64 * 1000.0 / 65536.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_rtt_ms, BandwidthEstimator};
+    use std::time::{Duration, SystemTime};
+    use webrtc::rtp::extension::abs_send_time_extension::unix2ntp;
+
+    #[test]
+    fn test_bandwidth_estimator() {
+        let mut estimator = BandwidthEstimator::default();
+        estimator.on_twcc(100, 0);
+        estimator.update(1_000_000.0);
+        assert!(!estimator.is_congested(1_000_000.0));
+
+        //the estimate starts from the sending bitrate
+        estimator.on_twcc(80, 20);
+        estimator.update(1_000_000.0);
+        assert_eq!(estimator.estimated_bitrate(), Some(900_000.0));
+        assert!(estimator.is_congested(800_000.0));
+
+        estimator.on_remb(2_000_000.0);
+        estimator.on_twcc(100, 0);
+        estimator.update(1_000_000.0);
+        assert!(!estimator.is_congested(1_000_000.0));
+    }
+
+    #[test]
+    fn test_get_rtt_ms() {
+        let now = SystemTime::now();
+        //the sender report is sent 1.5s ago and the viewer delays it for 1s
+        let last_sender_report = (unix2ntp(now - Duration::from_millis(1500)) >> 16) as u32;
+        let rtt = get_rtt_ms(last_sender_report, 65536, now).unwrap();
+        assert!((rtt - 500.0).abs() < 1.0);
+        assert_eq!(get_rtt_ms(0, 0, now), None);
+    }
+}
@@ -1,9 +1,12 @@
 use {
     audiopus::error::Error as OpusError,
     failure::{Backtrace, Fail},
+    fdk_aac::dec::DecoderError as AacDecoderError,
     fdk_aac::enc::EncoderError as AacEncoderError,
     std::fmt,
+    std::io::Error as IOError,
     std::num::ParseIntError,
+    streamhub::define::VideoCodecType,
     webrtc::error::Error as RTCError,
     webrtc::util::Error as RTCUtilError,
 };
@@ -23,12 +26,22 @@ pub enum WebRTCErrorValue {
     ParseIntError(#[cause] ParseIntError),
     #[fail(display = "cannot get local description")]
     CanNotGetLocalDescription,
+    #[fail(display = "cannot get remote description")]
+    CanNotGetRemoteDescription,
+    #[fail(display = "the ice ufrag or pwd is missing")]
+    MissingIceCredentials,
     #[fail(display = "opus2aac error")]
     Opus2AacError,
     #[fail(display = "missing whitespace")]
     MissingWhitespace,
     #[fail(display = "missing colon")]
     MissingColon,
+    #[fail(display = "the offer does not support the video codec: {:?}", _0)]
+    UnsupportedVideoCodec(VideoCodecType),
+    #[fail(display = "io error: {}", _0)]
+    IOError(#[cause] IOError),
+    #[fail(display = "invalid udp port range: {}", _0)]
+    InvalidUdpPortRange(String),
 }
 
 impl From<RTCError> for WebRTCError {
@@ -47,6 +60,14 @@ impl From<RTCUtilError> for WebRTCError {
     }
 }
 
+impl From<IOError> for WebRTCError {
+    fn from(error: IOError) -> Self {
+        WebRTCError {
+            value: WebRTCErrorValue::IOError(error),
+        }
+    }
+}
+
 impl From<ParseIntError> for WebRTCError {
     fn from(error: ParseIntError) -> Self {
         WebRTCError {
@@ -98,6 +119,34 @@ impl From<AacEncoderError> for Opus2AacError {
     }
 }
 
+#[derive(Debug)]
+pub struct Aac2OpusError {
+    pub value: Aac2OpusErrorValue,
+}
+
+#[derive(Debug)]
+pub enum Aac2OpusErrorValue {
+    OpusError(OpusError),
+    AacDecoderError(AacDecoderError),
+    InvalidAdtsHeader,
+}
+
+impl From<OpusError> for Aac2OpusError {
+    fn from(error: OpusError) -> Self {
+        Aac2OpusError {
+            value: Aac2OpusErrorValue::OpusError(error),
+        }
+    }
+}
+
+impl From<AacDecoderError> for Aac2OpusError {
+    fn from(error: AacDecoderError) -> Self {
+        Aac2OpusError {
+            value: Aac2OpusErrorValue::AacDecoderError(error),
+        }
+    }
+}
+
 // impl fmt::Display for Opus2AacError {
 //     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 //         fmt::Display::fmt(&self.value, f)
@@ -0,0 +1,202 @@
+use super::errors::{WebRTCError, WebRTCErrorValue};
+use tokio::net::UdpSocket;
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::error::Error as RTCError;
+use webrtc::ice::udp_mux::{UDPMuxDefault, UDPMuxParams};
+use webrtc::ice::udp_network::{EphemeralUDP, UDPNetwork};
+use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+
+#[derive(Debug, Clone, Default)]
+pub struct IceServerConfig {
+    //stun:host:port or turn:host:port(only the turn over udp is supported)
+    pub urls: Vec<String>,
+    //the credentials of the turn servers
+    pub username: String,
+    pub credential: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct IceConfig {
+    pub ice_servers: Vec<IceServerConfig>,
+    //the public ips advertised in the host candidates instead of the local ones,
+    //used behind the 1:1 NAT(e.g. the cloud hosts or the kubernetes host ports)
+    pub nat_1to1_ips: Vec<String>,
+    //the min and max ports of the udp sockets gathered for each peer connection
+    pub udp_port_range: Option<(u16, u16)>,
+    //all the peer connections share one udp port, it overrides the port range
+    pub udp_mux_port: Option<u16>,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        Self {
+            ice_servers: vec![IceServerConfig {
+                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+                ..Default::default()
+            }],
+            nat_1to1_ips: Vec::new(),
+            udp_port_range: None,
+            udp_mux_port: None,
+        }
+    }
+}
+
+impl IceConfig {
+    //Both the min and max ports are required, a half configured range is rejected
+    //instead of being ignored silently.
+    pub fn set_udp_port_range(
+        &mut self,
+        port_min: Option<u16>,
+        port_max: Option<u16>,
+    ) -> Result<(), WebRTCError> {
+        self.udp_port_range = match (port_min, port_max) {
+            (Some(port_min), Some(port_max)) if port_min <= port_max => Some((port_min, port_max)),
+            (None, None) => None,
+            _ => {
+                return Err(WebRTCError {
+                    value: WebRTCErrorValue::InvalidUdpPortRange(format!(
+                        "udp_port_min {:?} and udp_port_max {:?}",
+                        port_min, port_max
+                    )),
+                })
+            }
+        };
+        Ok(())
+    }
+}
+
+//The ice settings shared by the whip and whep peer connections, the udp mux
+//socket is bound once when the server starts.
+#[derive(Clone)]
+pub struct IceContext {
+    ice_servers: Vec<RTCIceServer>,
+    setting_engine: SettingEngine,
+}
+
+impl IceContext {
+    pub async fn new(config: IceConfig) -> Result<Self, WebRTCError> {
+        let mut setting_engine = SettingEngine::default();
+
+        if !config.nat_1to1_ips.is_empty() {
+            setting_engine.set_nat_1to1_ips(config.nat_1to1_ips, RTCIceCandidateType::Host);
+        }
+
+        if let Some(port) = config.udp_mux_port {
+            if config.udp_port_range.is_some() {
+                log::warn!("the udp port range is ignored as the udp mux port is set");
+            }
+            let socket = UdpSocket::bind(("0.0.0.0", port)).await?;
+            log::info!("WebRTC ice udp mux listening on udp://0.0.0.0:{}", port);
+            let udp_mux = UDPMuxDefault::new(UDPMuxParams::new(socket));
+            setting_engine.set_udp_network(UDPNetwork::Muxed(udp_mux));
+        } else if let Some((port_min, port_max)) = config.udp_port_range {
+            let ephemeral_udp = EphemeralUDP::new(port_min, port_max).map_err(RTCError::from)?;
+            setting_engine.set_udp_network(UDPNetwork::Ephemeral(ephemeral_udp));
+        }
+
+        let ice_servers = config
+            .ice_servers
+            .into_iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls,
+                username: server.username,
+                credential: server.credential,
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Self {
+            ice_servers,
+            setting_engine,
+        })
+    }
+
+    pub fn setting_engine(&self) -> SettingEngine {
+        self.setting_engine.clone()
+    }
+
+    //https://www.rfc-editor.org/rfc/rfc9725#section-4.6
+    //the Link headers which tell the whip/whep clients the configured ice servers
+    pub fn ice_server_links(&self) -> Vec<String> {
+        let mut links = Vec::new();
+        for server in &self.ice_servers {
+            for url in &server.urls {
+                let mut link = format!("<{url}>; rel=\"ice-server\"");
+                if !server.username.is_empty() {
+                    link += &format!(
+                        "; username=\"{}\"; credential=\"{}\"; credential-type=\"password\"",
+                        server.username, server.credential
+                    );
+                }
+                links.push(link);
+            }
+        }
+        links
+    }
+
+    pub fn rtc_configuration(&self) -> RTCConfiguration {
+        RTCConfiguration {
+            ice_servers: self.ice_servers.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ice_server_links() {
+        let context = IceContext {
+            ice_servers: vec![
+                RTCIceServer {
+                    urls: vec![
+                        "stun:stun1.example.net".to_string(),
+                        "stun:stun2.example.net".to_string(),
+                    ],
+                    ..Default::default()
+                },
+                RTCIceServer {
+                    urls: vec!["turn:turn.example.net?transport=udp".to_string()],
+                    username: "user".to_string(),
+                    credential: "pass".to_string(),
+                    ..Default::default()
+                },
+            ],
+            setting_engine: SettingEngine::default(),
+        };
+
+        assert_eq!(
+            context.ice_server_links(),
+            vec![
+                "<stun:stun1.example.net>; rel=\"ice-server\"",
+                "<stun:stun2.example.net>; rel=\"ice-server\"",
+                "<turn:turn.example.net?transport=udp>; rel=\"ice-server\"; username=\"user\"; \
+                 credential=\"pass\"; credential-type=\"password\"",
+            ]
+        );
+        assert_eq!(context.rtc_configuration().ice_servers.len(), 2);
+    }
+
+    #[test]
+    fn test_gen_ice_config() {
+        let mut config = IceConfig::default();
+        assert_eq!(
+            config.ice_servers[0].urls,
+            vec!["stun:stun.l.google.com:19302"]
+        );
+
+        assert!(config.set_udp_port_range(None, None).is_ok());
+        assert_eq!(config.udp_port_range, None);
+        assert!(config.set_udp_port_range(Some(50000), Some(50100)).is_ok());
+        assert_eq!(config.udp_port_range, Some((50000, 50100)));
+        assert!(config.set_udp_port_range(Some(50000), Some(50000)).is_ok());
+
+        assert!(config.set_udp_port_range(Some(50000), None).is_err());
+        assert!(config.set_udp_port_range(None, Some(50100)).is_err());
+        assert!(config.set_udp_port_range(Some(50100), Some(50000)).is_err());
+    }
+}
@@ -1,8 +1,18 @@
+pub mod aac2opus;
+pub mod codec;
+pub mod congestion;
 pub mod errors;
+pub mod ice;
 // pub mod http;
 pub mod session;
+pub mod simulcast;
+pub mod statistics;
 pub mod webrtc;
 pub mod whep;
 pub mod whip;
 pub mod opus2aac;
+pub mod relay;
+pub mod remuxer;
 pub mod rtp_queue;
+pub mod tls;
+pub mod trickle;
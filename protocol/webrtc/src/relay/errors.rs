@@ -0,0 +1,86 @@
+use {
+    crate::{errors::WebRTCError, session::errors::SessionError},
+    failure::{Backtrace, Fail},
+    std::fmt,
+    tokio::sync::broadcast::error::RecvError,
+    webrtc::error::Error as RTCError,
+};
+
+#[derive(Debug)]
+pub struct ClientError {
+    pub value: ClientErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum ClientErrorValue {
+    #[fail(display = "session error: {}", _0)]
+    SessionError(#[cause] SessionError),
+    #[fail(display = "webrtc error: {}", _0)]
+    WebRTCError(#[cause] WebRTCError),
+    #[fail(display = "webrtc error: {}", _0)]
+    RTCError(#[cause] RTCError),
+    #[fail(display = "http request error: {}", _0)]
+    RequestError(#[cause] reqwest::Error),
+    #[fail(display = "unexpected http status: {}", _0)]
+    UnexpectedStatus(u16),
+    #[fail(display = "invalid resource url: {}", _0)]
+    InvalidResourceUrl(String),
+    #[fail(display = "receive broadcast event error: {}", _0)]
+    ReceiveError(RecvError),
+}
+
+impl From<SessionError> for ClientError {
+    fn from(error: SessionError) -> Self {
+        ClientError {
+            value: ClientErrorValue::SessionError(error),
+        }
+    }
+}
+
+impl From<WebRTCError> for ClientError {
+    fn from(error: WebRTCError) -> Self {
+        ClientError {
+            value: ClientErrorValue::WebRTCError(error),
+        }
+    }
+}
+
+impl From<RTCError> for ClientError {
+    fn from(error: RTCError) -> Self {
+        ClientError {
+            value: ClientErrorValue::RTCError(error),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(error: reqwest::Error) -> Self {
+        ClientError {
+            value: ClientErrorValue::RequestError(error),
+        }
+    }
+}
+
+impl From<RecvError> for ClientError {
+    fn from(error: RecvError) -> Self {
+        ClientError {
+            value: ClientErrorValue::ReceiveError(error),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl Fail for ClientError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.value.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.value.backtrace()
+    }
+}
@@ -0,0 +1,56 @@
+pub mod errors;
+pub mod pull_client;
+pub mod push_client;
+pub mod signaling;
+
+use {
+    tokio::sync::broadcast, webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState,
+};
+
+//the label of the data channel opened by the relay clients
+pub const DATA_CHANNEL_LABEL: &str = "messages";
+
+//The endpoint url of a hub stream, the {app_name} and {stream_name} in the configured
+//url are replaced, e.g. http://host:8900/whep?app={app_name}&stream={stream_name}.
+pub fn get_endpoint_url(url: &str, app_name: &str, stream_name: &str) -> String {
+    url.replace("{app_name}", app_name)
+        .replace("{stream_name}", stream_name)
+}
+
+//Wait until the peer connection to the remote server is disconnected or closed.
+pub(crate) async fn wait_for_disconnection(
+    state_receiver: &mut broadcast::Receiver<RTCPeerConnectionState>,
+) {
+    loop {
+        match state_receiver.recv().await {
+            Ok(
+                RTCPeerConnectionState::Disconnected
+                | RTCPeerConnectionState::Failed
+                | RTCPeerConnectionState::Closed,
+            ) => break,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_endpoint_url;
+
+    #[test]
+    fn test_get_endpoint_url() {
+        assert_eq!(
+            get_endpoint_url(
+                "http://127.0.0.1:8900/whep?app={app_name}&stream={stream_name}",
+                "live",
+                "test"
+            ),
+            "http://127.0.0.1:8900/whep?app=live&stream=test"
+        );
+        assert_eq!(
+            get_endpoint_url("https://example.com/whip/abc", "live", "test"),
+            "https://example.com/whip/abc"
+        );
+    }
+}
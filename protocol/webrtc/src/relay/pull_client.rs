@@ -0,0 +1,193 @@
+use {
+    super::{
+        errors::ClientError, get_endpoint_url, signaling::SignalingClient, wait_for_disconnection,
+        DATA_CHANNEL_LABEL,
+    },
+    crate::{
+        codec::{get_sdp_video_codecs, register_codecs, VIDEO_CODECS},
+        ice::{IceConfig, IceContext},
+        session::{WebRTCServerSession, WebRTCStreamHandler},
+        simulcast::is_layer_stream_name,
+        whip::{forward_data_messages, new_receiver_peer_connection, WhipSender},
+    },
+    std::{collections::HashMap, sync::Arc},
+    streamhub::{
+        define::{
+            BroadcastEvent, BroadcastEventReceiver, NotifyInfo, PubDataType, PublishType,
+            PublisherInfo, StreamHubEventSender,
+        },
+        stream::StreamIdentifier,
+        utils::{RandomDigitCount, Uuid},
+    },
+    tokio::sync::broadcast,
+    webrtc::{
+        api::media_engine::MediaEngine,
+        peer_connection::peer_connection_state::RTCPeerConnectionState,
+    },
+};
+
+//Pull the webrtc streams from a remote whep endpoint(e.g. another xiu) and publish
+//them to the stream hub when they are subscribed.
+pub struct WhepPullClient {
+    url: String,
+    token: Option<String>,
+    ice_config: IceConfig,
+    client_event_consumer: BroadcastEventReceiver,
+    channel_event_producer: StreamHubEventSender,
+}
+
+impl WhepPullClient {
+    pub fn new(
+        url: String,
+        token: Option<String>,
+        ice_config: IceConfig,
+        consumer: BroadcastEventReceiver,
+        producer: StreamHubEventSender,
+    ) -> Self {
+        Self {
+            url,
+            token,
+            ice_config,
+            client_event_consumer: consumer,
+            channel_event_producer: producer,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), ClientError> {
+        log::info!("whep pull client run, remote: {}", self.url);
+        //the udp mux port is bound by the whip/whep server
+        let ice_context = Arc::new(
+            IceContext::new(IceConfig {
+                udp_mux_port: None,
+                ..self.ice_config.clone()
+            })
+            .await?,
+        );
+
+        loop {
+            let event = self.client_event_consumer.recv().await?;
+
+            if let BroadcastEvent::Subscribe {
+                identifier:
+                    StreamIdentifier::WebRTC {
+                        app_name,
+                        stream_name,
+                    },
+            } = event
+            {
+                if is_layer_stream_name(&stream_name) {
+                    continue;
+                }
+
+                let url = get_endpoint_url(&self.url, &app_name, &stream_name);
+                log::info!(
+                    "whep pull client: pull {}/{} from {}",
+                    app_name,
+                    stream_name,
+                    url
+                );
+
+                let signaling = SignalingClient::new(url, self.token.clone());
+                let ice_context = ice_context.clone();
+                let producer = self.channel_event_producer.clone();
+                tokio::spawn(async move {
+                    if let Err(err) =
+                        pull(signaling, ice_context, app_name, stream_name, producer).await
+                    {
+                        log::error!("whep pull client session error: {}", err);
+                    }
+                });
+            }
+        }
+    }
+}
+
+async fn pull(
+    mut signaling: SignalingClient,
+    ice_context: Arc<IceContext>,
+    app_name: String,
+    stream_name: String,
+    producer: StreamHubEventSender,
+) -> Result<(), ClientError> {
+    let publisher_info = get_publisher_info(Uuid::new(RandomDigitCount::Zero));
+    let stream_handler = Arc::new(WebRTCStreamHandler::default());
+    let (sender, _) = WebRTCServerSession::publish_stream(
+        app_name.clone(),
+        stream_name.clone(),
+        publisher_info.clone(),
+        stream_handler.clone(),
+        &producer,
+    )
+    .await?;
+
+    let rv = receive(&mut signaling, &ice_context, sender, &stream_handler).await;
+
+    if let Err(err) = signaling.delete_resource().await {
+        log::error!("whep pull client delete resource error: {}", err);
+    }
+    WebRTCServerSession::unpublish_whip(app_name, stream_name, publisher_info, producer)?;
+    rv
+}
+
+async fn receive(
+    signaling: &mut SignalingClient,
+    ice_context: &IceContext,
+    sender: WhipSender,
+    stream_handler: &WebRTCStreamHandler,
+) -> Result<(), ClientError> {
+    let mut m = MediaEngine::default();
+    register_codecs(&mut m, &VIDEO_CODECS)?;
+    //the key frame requests of the local viewers are sent to the remote server by PLI
+    let peer_connection = new_receiver_peer_connection(
+        ice_context,
+        m,
+        sender.clone(),
+        HashMap::new(),
+        stream_handler.key_frame_request(),
+    )
+    .await?;
+
+    let (state_sender, mut state_receiver) = broadcast::channel(1);
+    peer_connection.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
+        log::info!("whep pull client peer connection state has changed: {s}");
+        let _ = state_sender.send(s);
+        Box::pin(async {})
+    }));
+
+    //receive the data messages of the remote stream
+    let rv = match peer_connection
+        .create_data_channel(DATA_CHANNEL_LABEL, None)
+        .await
+    {
+        Ok(data_channel) => {
+            forward_data_messages(&data_channel, sender);
+            signaling.negotiate(&peer_connection).await
+        }
+        Err(err) => Err(ClientError::from(err)),
+    };
+    if rv.is_ok() {
+        //the answer of a xiu whep endpoint only contains the codec of the stream
+        if let Some(answer) = peer_connection.remote_description().await {
+            if let Some(vcodec) = get_sdp_video_codecs(&answer.sdp).into_iter().next() {
+                stream_handler.set_video_codec(vcodec).await;
+            }
+        }
+        wait_for_disconnection(&mut state_receiver).await;
+    }
+    if let Err(err) = peer_connection.close().await {
+        log::error!("whep pull client close peer connection error: {}", err);
+    }
+    rv
+}
+
+fn get_publisher_info(id: Uuid) -> PublisherInfo {
+    PublisherInfo {
+        id,
+        pub_type: PublishType::RelayWebRTC,
+        pub_data_type: PubDataType::Both,
+        notify_info: NotifyInfo {
+            request_url: String::from(""),
+            remote_addr: String::from(""),
+        },
+    }
+}
@@ -0,0 +1,184 @@
+use {
+    super::{
+        errors::ClientError, get_endpoint_url, signaling::SignalingClient, wait_for_disconnection,
+        DATA_CHANNEL_LABEL,
+    },
+    crate::{
+        ice::{IceConfig, IceContext},
+        session::WebRTCServerSession,
+        simulcast::is_layer_stream_name,
+        whep::{new_sender_peer_connection, send_packets, WhepSubscriber},
+    },
+    std::sync::Arc,
+    streamhub::{
+        define::{
+            BroadcastEvent, BroadcastEventReceiver, NotifyInfo, StreamHubEventSender, SubDataType,
+            SubPacketDataReceiver, SubscribeStartMode, SubscribeType, SubscriberInfo,
+        },
+        stream::StreamIdentifier,
+        utils::{RandomDigitCount, Uuid},
+    },
+    tokio::sync::broadcast,
+};
+
+//Push the webrtc streams of the stream hub to a remote whip endpoint(e.g. Cloudflare
+//Stream, Janus or another xiu) when they are published.
+pub struct WhipPushClient {
+    url: String,
+    token: Option<String>,
+    ice_config: IceConfig,
+    client_event_consumer: BroadcastEventReceiver,
+    channel_event_producer: StreamHubEventSender,
+}
+
+impl WhipPushClient {
+    pub fn new(
+        url: String,
+        token: Option<String>,
+        ice_config: IceConfig,
+        consumer: BroadcastEventReceiver,
+        producer: StreamHubEventSender,
+    ) -> Self {
+        Self {
+            url,
+            token,
+            ice_config,
+            client_event_consumer: consumer,
+            channel_event_producer: producer,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), ClientError> {
+        log::info!("whip push client run, remote: {}", self.url);
+        //the udp mux port is bound by the whip/whep server
+        let ice_context = Arc::new(
+            IceContext::new(IceConfig {
+                udp_mux_port: None,
+                ..self.ice_config.clone()
+            })
+            .await?,
+        );
+
+        loop {
+            let val = self.client_event_consumer.recv().await?;
+
+            if let BroadcastEvent::Publish {
+                identifier:
+                    StreamIdentifier::WebRTC {
+                        app_name,
+                        stream_name,
+                    },
+            } = val
+            {
+                //the simulcast layers are pushed with the primary stream
+                if is_layer_stream_name(&stream_name) {
+                    continue;
+                }
+
+                let url = get_endpoint_url(&self.url, &app_name, &stream_name);
+                log::info!(
+                    "whip push client: push {}/{} to {}",
+                    app_name,
+                    stream_name,
+                    url
+                );
+
+                let signaling = SignalingClient::new(url, self.token.clone());
+                let ice_context = ice_context.clone();
+                let producer = self.channel_event_producer.clone();
+                tokio::spawn(async move {
+                    if let Err(err) =
+                        push(signaling, ice_context, app_name, stream_name, producer).await
+                    {
+                        log::error!("whip push client session error: {}", err);
+                    }
+                });
+            }
+        }
+    }
+}
+
+async fn push(
+    mut signaling: SignalingClient,
+    ice_context: Arc<IceContext>,
+    app_name: String,
+    stream_name: String,
+    producer: StreamHubEventSender,
+) -> Result<(), ClientError> {
+    let subscriber_info = get_subscriber_info(Uuid::new(RandomDigitCount::Zero));
+    let (receiver, statistic_sender) = WebRTCServerSession::subscribe_stream(
+        app_name.clone(),
+        stream_name.clone(),
+        subscriber_info.clone(),
+        &producer,
+    )
+    .await?;
+
+    let information =
+        WebRTCServerSession::query_information(&app_name, &stream_name, &producer).await;
+    //the remote server requests the key frames as a viewer
+    let subscriber = WhepSubscriber {
+        id: subscriber_info.id,
+        identifier: StreamIdentifier::WebRTC {
+            app_name: app_name.clone(),
+            stream_name: stream_name.clone(),
+        },
+        event_sender: producer.clone(),
+        statistic_sender,
+        vcodec: WebRTCServerSession::get_video_codec(&information),
+    };
+    let rv = send(&mut signaling, &ice_context, receiver, subscriber).await;
+
+    if let Err(err) = signaling.delete_resource().await {
+        log::error!("whip push client delete resource error: {}", err);
+    }
+    WebRTCServerSession::unsubscribe_whep(app_name, stream_name, subscriber_info, producer)?;
+    rv
+}
+
+async fn send(
+    signaling: &mut SignalingClient,
+    ice_context: &IceContext,
+    receiver: SubPacketDataReceiver,
+    subscriber: WhepSubscriber,
+) -> Result<(), ClientError> {
+    let (state_sender, mut state_receiver) = broadcast::channel(1);
+    let (peer_connection, mut tracks) =
+        new_sender_peer_connection(ice_context, &subscriber.vcodec, state_sender).await?;
+
+    //the data messages of the stream are sent on it
+    let rv = match peer_connection
+        .create_data_channel(DATA_CHANNEL_LABEL, None)
+        .await
+    {
+        Ok(data_channel) => {
+            tracks.add_data_channel(data_channel);
+            signaling.negotiate(&peer_connection).await
+        }
+        Err(err) => Err(ClientError::from(err)),
+    };
+    if rv.is_ok() {
+        //stop when the stream is unpublished or the remote server is gone
+        tokio::select! {
+            _ = send_packets(receiver, None, tracks, subscriber) => {}
+            _ = wait_for_disconnection(&mut state_receiver) => {}
+        }
+    }
+    if let Err(err) = peer_connection.close().await {
+        log::error!("whip push client close peer connection error: {}", err);
+    }
+    rv
+}
+
+fn get_subscriber_info(id: Uuid) -> SubscriberInfo {
+    SubscriberInfo {
+        id,
+        sub_type: SubscribeType::PublisherWebrtc,
+        sub_data_type: SubDataType::Packet,
+        start_mode: SubscribeStartMode::default(),
+        notify_info: NotifyInfo {
+            request_url: String::from(""),
+            remote_addr: String::from(""),
+        },
+    }
+}
@@ -0,0 +1,117 @@
+use {
+    super::errors::{ClientError, ClientErrorValue},
+    crate::errors::{WebRTCError, WebRTCErrorValue},
+    reqwest::{
+        header::{CONTENT_TYPE, LOCATION},
+        Client, RequestBuilder, Url,
+    },
+    std::time::Duration,
+    webrtc::peer_connection::{sdp::session_description::RTCSessionDescription, RTCPeerConnection},
+};
+
+const REQUEST_TIMEOUT: u64 = 10;
+
+//The http signaling of a whip/whep client(https://www.rfc-editor.org/rfc/rfc9725#section-4.2),
+//the offer is posted to the endpoint and the created resource is deleted when the session ends.
+pub struct SignalingClient {
+    client: Client,
+    endpoint: String,
+    //the bearer token of the endpoint
+    token: Option<String>,
+    resource_url: Option<Url>,
+}
+
+impl SignalingClient {
+    pub fn new(endpoint: String, token: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint,
+            token,
+            resource_url: None,
+        }
+    }
+
+    fn build_request(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.timeout(Duration::from_secs(REQUEST_TIMEOUT));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    //Send the offer of the peer connection after the ice gathering is complete
+    //and set the answer from the endpoint.
+    pub async fn negotiate(
+        &mut self,
+        peer_connection: &RTCPeerConnection,
+    ) -> Result<(), ClientError> {
+        let offer = peer_connection.create_offer(None).await?;
+        let mut gather_complete = peer_connection.gathering_complete_promise().await;
+        peer_connection.set_local_description(offer).await?;
+        let _ = gather_complete.recv().await;
+
+        let offer = match peer_connection.local_description().await {
+            Some(offer) => offer,
+            None => {
+                return Err(ClientError::from(WebRTCError {
+                    value: WebRTCErrorValue::CanNotGetLocalDescription,
+                }));
+            }
+        };
+
+        let answer = self.post_offer(offer.sdp).await?;
+        peer_connection
+            .set_remote_description(RTCSessionDescription::answer(answer)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn post_offer(&mut self, offer: String) -> Result<String, ClientError> {
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .header(CONTENT_TYPE, "application/sdp")
+            .body(offer);
+        let response = self.build_request(request).send().await?;
+        if !response.status().is_success() {
+            return Err(ClientError {
+                value: ClientErrorValue::UnexpectedStatus(response.status().as_u16()),
+            });
+        }
+
+        //the location may be relative to the endpoint
+        self.resource_url = match response.headers().get(LOCATION) {
+            Some(location) => {
+                let location = location.to_str().unwrap_or_default();
+                match response.url().join(location) {
+                    Ok(url) => Some(url),
+                    Err(_) => {
+                        return Err(ClientError {
+                            value: ClientErrorValue::InvalidResourceUrl(location.to_string()),
+                        });
+                    }
+                }
+            }
+            None => {
+                log::warn!("the endpoint {} returns no resource url", self.endpoint);
+                None
+            }
+        };
+
+        Ok(response.text().await?)
+    }
+
+    //terminate the session on the remote server
+    pub async fn delete_resource(&mut self) -> Result<(), ClientError> {
+        if let Some(resource_url) = self.resource_url.take() {
+            let request = self.client.delete(resource_url);
+            let response = self.build_request(request).send().await?;
+            if !response.status().is_success() {
+                return Err(ClientError {
+                    value: ClientErrorValue::UnexpectedStatus(response.status().as_u16()),
+                });
+            }
+        }
+        Ok(())
+    }
+}
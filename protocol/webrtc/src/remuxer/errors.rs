@@ -0,0 +1,94 @@
+use {
+    crate::errors::Aac2OpusError, failure::Fail, std::fmt, streamhub::errors::StreamHubError,
+    tokio::sync::broadcast::error::RecvError,
+    tokio::sync::oneshot::error::RecvError as OneshotRecvError, webrtc::rtp::Error as RtpError,
+    webrtc::util::Error as RTCUtilError, xflv::errors::FlvDemuxerError,
+};
+
+pub struct WebRTCRemuxerError {
+    pub value: WebRTCRemuxerErrorValue,
+}
+
+#[derive(Debug, Fail)]
+pub enum WebRTCRemuxerErrorValue {
+    #[fail(display = "receive error:{}", _0)]
+    RecvError(#[cause] RecvError),
+    #[fail(display = "flv demuxer error:{}", _0)]
+    FlvDemuxerError(#[cause] FlvDemuxerError),
+    #[fail(display = "rtp error:{}", _0)]
+    RtpError(#[cause] RtpError),
+    #[fail(display = "webrtc util error:{}", _0)]
+    RTCUtilError(#[cause] RTCUtilError),
+    #[fail(display = "stream hub event send error")]
+    StreamHubEventSendErr,
+    #[fail(display = "packet data send error")]
+    PacketDataSendErr,
+    #[fail(display = "event execute error: {}", _0)]
+    ChannelError(#[cause] StreamHubError),
+    #[fail(display = "tokio: oneshot receiver err: {}", _0)]
+    OneshotRecvError(#[cause] OneshotRecvError),
+    #[fail(display = "aac to opus transcode error: {:?}", _0)]
+    Aac2OpusError(Aac2OpusError),
+}
+
+impl From<RecvError> for WebRTCRemuxerError {
+    fn from(error: RecvError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::RecvError(error),
+        }
+    }
+}
+
+impl From<FlvDemuxerError> for WebRTCRemuxerError {
+    fn from(error: FlvDemuxerError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::FlvDemuxerError(error),
+        }
+    }
+}
+
+impl From<RtpError> for WebRTCRemuxerError {
+    fn from(error: RtpError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::RtpError(error),
+        }
+    }
+}
+
+impl From<RTCUtilError> for WebRTCRemuxerError {
+    fn from(error: RTCUtilError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::RTCUtilError(error),
+        }
+    }
+}
+
+impl From<StreamHubError> for WebRTCRemuxerError {
+    fn from(error: StreamHubError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::ChannelError(error),
+        }
+    }
+}
+
+impl From<OneshotRecvError> for WebRTCRemuxerError {
+    fn from(error: OneshotRecvError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::OneshotRecvError(error),
+        }
+    }
+}
+
+impl From<Aac2OpusError> for WebRTCRemuxerError {
+    fn from(error: Aac2OpusError) -> Self {
+        WebRTCRemuxerError {
+            value: WebRTCRemuxerErrorValue::Aac2OpusError(error),
+        }
+    }
+}
+
+impl fmt::Display for WebRTCRemuxerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
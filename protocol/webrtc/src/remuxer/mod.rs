@@ -0,0 +1,57 @@
+pub mod errors;
+pub mod rtmp2webrtc;
+
+use streamhub::{
+    define::{BroadcastEvent, BroadcastEventReceiver, StreamHubEventSender},
+    stream::StreamIdentifier,
+};
+
+use self::{errors::WebRTCRemuxerError, rtmp2webrtc::Rtmp2WebRTCRemuxerSession};
+
+//Receive publish event from stream hub and
+//remux from rtmp to webrtc(whep)
+pub struct WebRTCRemuxer {
+    receiver: BroadcastEventReceiver,
+    event_producer: StreamHubEventSender,
+}
+
+impl WebRTCRemuxer {
+    pub fn new(receiver: BroadcastEventReceiver, event_producer: StreamHubEventSender) -> Self {
+        Self {
+            receiver,
+            event_producer,
+        }
+    }
+    pub async fn run(&mut self) -> Result<(), WebRTCRemuxerError> {
+        log::info!("webrtc remuxer start...");
+
+        loop {
+            let val = self.receiver.recv().await?;
+            log::info!("{:?}", val);
+            match val {
+                //the rtsp streams are remuxed to rtmp first by the rtmp remuxer
+                BroadcastEvent::Publish {
+                    identifier:
+                        StreamIdentifier::Rtmp {
+                            app_name,
+                            stream_name,
+                        },
+                } => {
+                    let mut session = Rtmp2WebRTCRemuxerSession::new(
+                        app_name,
+                        stream_name,
+                        self.event_producer.clone(),
+                    );
+                    tokio::spawn(async move {
+                        if let Err(err) = session.run().await {
+                            log::error!("rtmp2webrtc session error: {}", err);
+                        }
+                    });
+                }
+                _ => {
+                    log::trace!("other infos...");
+                }
+            }
+        }
+    }
+}
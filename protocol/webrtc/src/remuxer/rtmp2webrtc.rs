@@ -0,0 +1,464 @@
+use super::errors::{WebRTCRemuxerError, WebRTCRemuxerErrorValue};
+use crate::aac2opus::{Aac2OpusTranscoder, OPUS_FRAME_SAMPLES, OPUS_SAMPLE_RATE};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use streamhub::{
+    define::{
+        DataSender, FrameData, InformationSender, NotifyInfo, PacketData, PacketDataSender,
+        PubDataType, PublishType, PublisherInfo, StreamHubEvent, StreamHubEventSender, SubDataType,
+        SubFrameDataReceiver, SubPacketDataSender, SubscribeStartMode, SubscribeType,
+        SubscriberInfo, TStreamHandler,
+    },
+    errors::{StreamHubError, StreamHubErrorValue},
+    statistics::StatisticsStream,
+    stream::StreamIdentifier,
+    utils::{RandomDigitCount, Uuid},
+};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::time::sleep;
+use webrtc::rtp::codecs::h264::H264Payloader;
+use webrtc::rtp::codecs::opus::OpusPayloader;
+use webrtc::rtp::packetizer::{new_packetizer, Packetizer};
+use webrtc::rtp::sequence::new_random_sequencer;
+use webrtc::util::Marshal;
+use xflv::define::frame_type;
+use xflv::demuxer::{FlvAudioTagDemuxer, FlvVideoTagDemuxer};
+
+const VIDEO_CLOCK_RATE: u32 = 90000;
+const RTP_MTU: usize = 1200;
+//stop caching the gop if it is too long
+const MAX_GOP_PACKETS: usize = 8192;
+
+//Cache the rtp packets since the latest key frame, so the whep players
+//joining later can start from the key frame.
+#[derive(Default)]
+pub struct Rtmp2WebRTCStreamHandler {
+    gop_packets: Mutex<Option<Vec<PacketData>>>,
+    //set when the first whep player subscribes the stream
+    has_subscriber: AtomicBool,
+}
+
+impl Rtmp2WebRTCStreamHandler {
+    fn has_subscriber(&self) -> bool {
+        self.has_subscriber.load(Ordering::Relaxed)
+    }
+
+    async fn start_gop(&self) {
+        *self.gop_packets.lock().await = Some(Vec::new());
+    }
+
+    async fn save_packet(&self, packet_data: &PacketData) {
+        if let Some(gop_packets) = &mut *self.gop_packets.lock().await {
+            if gop_packets.len() < MAX_GOP_PACKETS {
+                gop_packets.push(packet_data.clone());
+            }
+        }
+    }
+
+    //the audio packets are sent to the new subscribers only, the others have received them
+    async fn send_gop(
+        &self,
+        sender: &SubPacketDataSender,
+        video_only: bool,
+    ) -> Result<(), StreamHubError> {
+        if let Some(gop_packets) = &*self.gop_packets.lock().await {
+            for packet_data in gop_packets {
+                if video_only && !matches!(packet_data, PacketData::Video { .. }) {
+                    continue;
+                }
+                match sender.try_send(packet_data.clone()) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        log::warn!("send_gop: subscriber queue is full");
+                        break;
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        return Err(StreamHubError {
+                            value: StreamHubErrorValue::SendError,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TStreamHandler for Rtmp2WebRTCStreamHandler {
+    async fn send_prior_data(
+        &self,
+        data_sender: DataSender,
+        _sub_type: SubscribeType,
+        start_mode: SubscribeStartMode,
+    ) -> Result<(), StreamHubError> {
+        let sender = match data_sender {
+            DataSender::Packet { sender } => sender,
+            DataSender::Frame { sender: _ } => {
+                return Err(StreamHubError {
+                    value: StreamHubErrorValue::NotCorrectDataSenderType,
+                });
+            }
+        };
+        self.has_subscriber.store(true, Ordering::Relaxed);
+        if start_mode == SubscribeStartMode::NoCache {
+            return Ok(());
+        }
+
+        self.send_gop(&sender, false).await
+    }
+
+    async fn get_statistic_data(&self) -> Option<StatisticsStream> {
+        None
+    }
+
+    async fn send_information(&self, _sender: InformationSender) {}
+
+    //The rtmp publisher cannot be asked for a key frame, so the video of the gop since
+    //the latest key frame is replayed to the viewer, which rewrites the sequence numbers
+    //and the timestamps of the replayed packets.
+    async fn request_key_frame(&self, sender: Option<DataSender>) {
+        if let Some(DataSender::Packet { sender }) = sender {
+            if let Err(err) = self.send_gop(&sender, true).await {
+                log::error!("replay the gop error: {}", err);
+            }
+        }
+    }
+}
+
+//Subscribe the rtmp stream and publish it as a webrtc stream which can be
+//played by whep, the h264 is packed to rtp and the aac is transcoded to opus
+//after the first whep player subscribes.
+pub struct Rtmp2WebRTCRemuxerSession {
+    event_producer: StreamHubEventSender,
+    app_name: String,
+    stream_name: String,
+
+    //RTMP
+    data_receiver: SubFrameDataReceiver,
+    subscribe_id: Uuid,
+    video_demuxer: FlvVideoTagDemuxer,
+    audio_demuxer: FlvAudioTagDemuxer,
+
+    //WebRTC
+    packet_sender: Option<PacketDataSender>,
+    publish_id: Uuid,
+    stream_handler: Arc<Rtmp2WebRTCStreamHandler>,
+    video_packetizer: Box<dyn Packetizer + Send + Sync>,
+    audio_packetizer: Box<dyn Packetizer + Send + Sync>,
+    aac2opus_transcoder: Option<Aac2OpusTranscoder>,
+    //the rtp timestamp of the next opus frame
+    opus_timestamp: Option<u32>,
+}
+
+impl Rtmp2WebRTCRemuxerSession {
+    pub fn new(
+        app_name: String,
+        stream_name: String,
+        event_producer: StreamHubEventSender,
+    ) -> Self {
+        let (_, data_consumer) = mpsc::channel(1);
+
+        //the payload type and the ssrc are replaced by the tracks of the whep sessions
+        let video_packetizer = new_packetizer(
+            RTP_MTU,
+            0,
+            0,
+            Box::<H264Payloader>::default(),
+            Box::new(new_random_sequencer()),
+            VIDEO_CLOCK_RATE,
+        );
+        let audio_packetizer = new_packetizer(
+            RTP_MTU,
+            0,
+            0,
+            Box::new(OpusPayloader),
+            Box::new(new_random_sequencer()),
+            OPUS_SAMPLE_RATE,
+        );
+
+        Self {
+            event_producer,
+            app_name,
+            stream_name,
+            data_receiver: data_consumer,
+            subscribe_id: Uuid::new(RandomDigitCount::Four),
+            video_demuxer: FlvVideoTagDemuxer::new(),
+            audio_demuxer: FlvAudioTagDemuxer::new(),
+            packet_sender: None,
+            publish_id: Uuid::new(RandomDigitCount::Four),
+            stream_handler: Arc::new(Rtmp2WebRTCStreamHandler::default()),
+            video_packetizer: Box::new(video_packetizer),
+            audio_packetizer: Box::new(audio_packetizer),
+            aac2opus_transcoder: None,
+            opus_timestamp: None,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), WebRTCRemuxerError> {
+        if !self.publish_webrtc().await? {
+            return Ok(());
+        }
+        self.subscribe_rtmp().await?;
+        self.receive_rtmp_data().await?;
+
+        Ok(())
+    }
+
+    fn get_identifier(&self) -> StreamIdentifier {
+        StreamIdentifier::WebRTC {
+            app_name: self.app_name.clone(),
+            stream_name: self.stream_name.clone(),
+        }
+    }
+
+    fn get_publisher_info(&self) -> PublisherInfo {
+        PublisherInfo {
+            id: self.publish_id,
+            pub_type: PublishType::PushWebRTC,
+            pub_data_type: PubDataType::Packet,
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        }
+    }
+
+    fn get_subscriber_info(&self) -> SubscriberInfo {
+        SubscriberInfo {
+            id: self.subscribe_id,
+            sub_type: SubscribeType::PlayerWebrtc,
+            sub_data_type: SubDataType::Frame,
+            start_mode: SubscribeStartMode::default(),
+            notify_info: NotifyInfo {
+                request_url: String::from(""),
+                remote_addr: String::from(""),
+            },
+        }
+    }
+
+    //returns false if the webrtc stream exists, for example the rtmp stream is
+    //remuxed from a whip stream.
+    pub async fn publish_webrtc(&mut self) -> Result<bool, WebRTCRemuxerError> {
+        let (event_result_sender, event_result_receiver) = oneshot::channel();
+
+        let publish_event = StreamHubEvent::Publish {
+            identifier: self.get_identifier(),
+            result_sender: event_result_sender,
+            info: self.get_publisher_info(),
+            stream_handler: self.stream_handler.clone(),
+        };
+
+        if self.event_producer.send(publish_event).is_err() {
+            return Err(WebRTCRemuxerError {
+                value: WebRTCRemuxerErrorValue::StreamHubEventSendErr,
+            });
+        }
+
+        match event_result_receiver.await? {
+            Ok(result) => {
+                self.packet_sender = result.1;
+                Ok(true)
+            }
+            Err(StreamHubError {
+                value: StreamHubErrorValue::Exists,
+            }) => {
+                log::info!("the webrtc stream {} exists", self.get_identifier());
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn unpublish_webrtc(&mut self) -> Result<(), WebRTCRemuxerError> {
+        let unpublish_event = StreamHubEvent::UnPublish {
+            identifier: self.get_identifier(),
+            info: self.get_publisher_info(),
+        };
+
+        if self.event_producer.send(unpublish_event).is_err() {
+            return Err(WebRTCRemuxerError {
+                value: WebRTCRemuxerErrorValue::StreamHubEventSendErr,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn subscribe_rtmp(&mut self) -> Result<(), WebRTCRemuxerError> {
+        let (event_result_sender, event_result_receiver) = oneshot::channel();
+
+        let subscribe_event = StreamHubEvent::Subscribe {
+            identifier: StreamIdentifier::Rtmp {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+            },
+            info: self.get_subscriber_info(),
+            result_sender: event_result_sender,
+        };
+
+        if self.event_producer.send(subscribe_event).is_err() {
+            return Err(WebRTCRemuxerError {
+                value: WebRTCRemuxerErrorValue::StreamHubEventSendErr,
+            });
+        }
+
+        let receiver = event_result_receiver.await??.0;
+        self.data_receiver = receiver.frame_receiver.unwrap();
+        Ok(())
+    }
+
+    pub async fn unsubscribe_rtmp(&mut self) -> Result<(), WebRTCRemuxerError> {
+        let unsubscribe_event = StreamHubEvent::UnSubscribe {
+            identifier: StreamIdentifier::Rtmp {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+            },
+            info: self.get_subscriber_info(),
+        };
+        if let Err(err) = self.event_producer.send(unsubscribe_event) {
+            log::error!("unsubscribe_from_channels err {}", err);
+        }
+
+        Ok(())
+    }
+
+    pub async fn receive_rtmp_data(&mut self) -> Result<(), WebRTCRemuxerError> {
+        let mut retry_count = 0;
+        log::info!("begin receive rtmp data...");
+        loop {
+            if let Some(data) = self.data_receiver.recv().await {
+                match data {
+                    FrameData::Audio { timestamp, data } => {
+                        self.on_rtmp_audio(timestamp, data).await?
+                    }
+                    FrameData::Video { timestamp, data } => {
+                        self.on_rtmp_video(timestamp, data).await?
+                    }
+                    FrameData::DataMessage { timestamp, data } => {
+                        self.send_packet(PacketData::DataMessage { timestamp, data })
+                            .await?
+                    }
+                    _ => continue,
+                };
+                retry_count = 0;
+            } else {
+                sleep(Duration::from_millis(100)).await;
+                retry_count += 1;
+            }
+
+            if retry_count > 10 {
+                break;
+            }
+        }
+
+        self.unsubscribe_rtmp().await?;
+        self.unpublish_webrtc().await
+    }
+
+    async fn on_rtmp_video(
+        &mut self,
+        timestamp: u32,
+        data: Bytes,
+    ) -> Result<(), WebRTCRemuxerError> {
+        let video_data = match self.video_demuxer.demux(timestamp, data)? {
+            Some(video_data) => video_data,
+            //the sequence header or not h264
+            None => return Ok(()),
+        };
+
+        if video_data.frame_type == frame_type::KEY_FRAME {
+            self.stream_handler.start_gop().await;
+        }
+
+        let rtp_timestamp = (video_data.pts as u32).wrapping_mul(VIDEO_CLOCK_RATE / 1000);
+        let packets = self
+            .video_packetizer
+            .packetize(&video_data.data.freeze(), 0)?;
+
+        for mut packet in packets {
+            packet.header.timestamp = rtp_timestamp;
+            self.send_packet(PacketData::Video {
+                timestamp: rtp_timestamp,
+                data: packet.marshal()?,
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn on_rtmp_audio(
+        &mut self,
+        timestamp: u32,
+        data: Bytes,
+    ) -> Result<(), WebRTCRemuxerError> {
+        let audio_data = self.audio_demuxer.demux(timestamp, data)?;
+        //the sequence header or not aac
+        if !audio_data.has_data {
+            return Ok(());
+        }
+        //the transcoding is expensive, the streams without any whep player skip it
+        if !self.stream_handler.has_subscriber() {
+            return Ok(());
+        }
+
+        if self.aac2opus_transcoder.is_none() {
+            self.aac2opus_transcoder = Some(Aac2OpusTranscoder::new()?);
+        }
+        let opus_frames = match self
+            .aac2opus_transcoder
+            .as_mut()
+            .unwrap()
+            .transcode(&audio_data.data)
+        {
+            Ok(opus_frames) => opus_frames,
+            Err(err) => {
+                log::error!("aac2opus transcode error: {:?}", err);
+                return Ok(());
+            }
+        };
+
+        let mut opus_timestamp = *self
+            .opus_timestamp
+            .get_or_insert(timestamp.wrapping_mul(OPUS_SAMPLE_RATE / 1000));
+
+        for opus_frame in opus_frames {
+            let packets = self
+                .audio_packetizer
+                .packetize(&Bytes::from(opus_frame), OPUS_FRAME_SAMPLES as u32)?;
+
+            for mut packet in packets {
+                packet.header.timestamp = opus_timestamp;
+                self.send_packet(PacketData::Audio {
+                    timestamp: opus_timestamp,
+                    data: packet.marshal()?,
+                })
+                .await?;
+            }
+            opus_timestamp = opus_timestamp.wrapping_add(OPUS_FRAME_SAMPLES as u32);
+        }
+        self.opus_timestamp = Some(opus_timestamp);
+
+        Ok(())
+    }
+
+    async fn send_packet(&mut self, packet_data: PacketData) -> Result<(), WebRTCRemuxerError> {
+        //the data messages are not replayed to the new subscribers
+        if !matches!(packet_data, PacketData::DataMessage { .. }) {
+            self.stream_handler.save_packet(&packet_data).await;
+        }
+
+        if let Some(sender) = &self.packet_sender {
+            if sender.send(packet_data).is_err() {
+                return Err(WebRTCRemuxerError {
+                    value: WebRTCRemuxerErrorValue::PacketDataSendErr,
+                });
+            }
+        }
+        Ok(())
+    }
+}
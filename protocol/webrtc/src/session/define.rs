@@ -0,0 +1,17 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebRTCSessionType {
+    Whip,
+    Whep,
+}
+
+impl fmt::Display for WebRTCSessionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let session_type = match self {
+            WebRTCSessionType::Whip => "whip",
+            WebRTCSessionType::Whep => "whep",
+        };
+        write!(f, "{session_type}")
+    }
+}
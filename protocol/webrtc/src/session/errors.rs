@@ -1,3 +1,4 @@
+use crate::errors::WebRTCError;
 use streamhub::errors::StreamHubError;
 use {
     bytesio::bytes_errors::BytesReadError,
@@ -29,6 +30,8 @@ pub enum SessionErrorValue {
     ChannelError(#[cause] StreamHubError),
     #[fail(display = "webrtc error: {}", _0)]
     RTCError(#[cause] RTCError),
+    #[fail(display = "webrtc error: {}", _0)]
+    WebRTCError(#[cause] WebRTCError),
     #[fail(display = "tokio: oneshot receiver err: {}", _0)]
     RecvError(#[cause] RecvError),
     #[fail(display = "Auth err: {}", _0)]
@@ -1,8 +1,10 @@
+pub mod define;
 pub mod errors;
 use streamhub::{
     define::{
-        DataSender, InformationSender, NotifyInfo, PublishType, PublisherInfo, StreamHubEvent,
-        StreamHubEventSender, SubscribeType, SubscriberInfo, TStreamHandler,
+        DataSender, Information, InformationSender, NotifyInfo, PublishType, PublisherInfo,
+        StatisticData, StatisticDataSender, StreamHubEvent, StreamHubEventSender,
+        SubPacketDataReceiver, SubscribeType, SubscriberInfo, TStreamHandler, VideoCodecType,
     },
     errors::StreamHubError,
     statistics::StatisticsStream,
@@ -10,69 +12,77 @@ use streamhub::{
     utils::{RandomDigitCount, Uuid},
 };
 use tokio::sync::Mutex;
-use tokio::sync::{broadcast, oneshot};
-
-use bytesio::bytesio::TNetIO;
-use bytesio::bytesio::TcpIO;
-use std::io::Read;
-use std::{collections::HashMap, fs::File, sync::Arc};
-use tokio::net::TcpStream;
-
-use commonlib::define::http_method_name;
-use commonlib::http::{parse_content_length, HttpRequest, HttpResponse};
-
-use commonlib::http::Marshal as HttpMarshal;
-use commonlib::http::Unmarshal as HttpUnmarshal;
-
-use commonlib::auth::Auth;
-
-use super::whep::handle_whep;
-use super::whip::handle_whip;
+use tokio::sync::{broadcast, mpsc, oneshot, Notify};
+use tokio::time::{Duration, Instant};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::codec::get_sdp_video_codecs;
+use super::ice::IceContext;
+use super::simulcast::{get_layer_stream_name, parse_simulcast_rids};
+use super::statistics::report_statistics;
+use super::whep::{handle_whep, WhepSimulcast, WhepSubscriber};
+use super::whip::{handle_whip, WhipSender};
 use async_trait::async_trait;
+use define::WebRTCSessionType;
 
-use bytes::BytesMut;
-use bytesio::bytes_reader::BytesReader;
-use bytesio::bytes_writer::AsyncBytesWriter;
 use errors::SessionError;
 use errors::SessionErrorValue;
-use http::StatusCode;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::{sdp::session_description::RTCSessionDescription, RTCPeerConnection};
 
-pub struct WebRTCServerSession {
-    io: Arc<Mutex<Box<dyn TNetIO + Send + Sync>>>,
-    reader: BytesReader,
-    writer: AsyncBytesWriter,
+//the publisher is asked for a key frame at most once in the interval for all the viewers
+const PLI_INTERVAL: Duration = Duration::from_millis(500);
+//the time waiting for a disconnected peer connection to be reconnected(e.g. by an ice
+//restart) before it is closed
+const DISCONNECTED_TIMEOUT: Duration = Duration::from_secs(15);
 
+//A whip/whep resource(https://www.rfc-editor.org/rfc/rfc9725#section-4.2), it is created
+//by a POST request and kept for the later PATCH/DELETE requests.
+pub struct WebRTCServerSession {
     event_sender: StreamHubEventSender,
     stream_handler: Arc<WebRTCStreamHandler>,
 
-    pub session_id: Option<Uuid>,
-    pub http_request_data: Option<HttpRequest>,
+    pub session_id: Uuid,
+    pub session_type: WebRTCSessionType,
+    pub app_name: String,
+    pub stream_name: String,
     pub peer_connection: Option<Arc<RTCPeerConnection>>,
-
-    auth: Option<Auth>,
+    //the simulcast layer chosen by the whep viewer
+    pub simulcast_layer: Option<String>,
+    //the address of the http client which creates the session
+    pub remote_addr: String,
+    //the hub streams of the simulcast layers published or subscribed by this session
+    layer_streams: Vec<(String, Uuid)>,
+
+    ice_context: Arc<IceContext>,
+    //the server removes the session when its peer connection is closed
+    session_closed_sender: mpsc::UnboundedSender<Uuid>,
 }
 
 impl WebRTCServerSession {
     pub fn new(
-        stream: TcpStream,
+        session_type: WebRTCSessionType,
+        app_name: String,
+        stream_name: String,
         event_producer: StreamHubEventSender,
-        auth: Option<Auth>,
+        ice_context: Arc<IceContext>,
+        session_closed_sender: mpsc::UnboundedSender<Uuid>,
     ) -> Self {
-        let net_io: Box<dyn TNetIO + Send + Sync> = Box::new(TcpIO::new(stream));
-        let io = Arc::new(Mutex::new(net_io));
-
         Self {
-            io: io.clone(),
-            reader: BytesReader::new(BytesMut::default()),
-            writer: AsyncBytesWriter::new(io),
             event_sender: event_producer,
             stream_handler: Arc::new(WebRTCStreamHandler::default()),
-            session_id: None,
-            http_request_data: None,
+            session_id: Uuid::new(RandomDigitCount::Zero),
+            session_type,
+            app_name,
+            stream_name,
             peer_connection: None,
-            auth,
+            simulcast_layer: None,
+            remote_addr: String::new(),
+            layer_streams: Vec::new(),
+            ice_context,
+            session_closed_sender,
         }
     }
 
@@ -83,196 +93,125 @@ impl WebRTCServerSession {
         Ok(())
     }
 
+    //Publish or subscribe the stream by the sdp offer and return the answer.
     pub async fn run(
         &mut self,
-        uuid_2_sessions: Arc<Mutex<HashMap<Uuid, Arc<Mutex<WebRTCServerSession>>>>>,
-    ) -> Result<(), SessionError> {
-        while self.reader.len() < 4 {
-            let data = self.io.lock().await.read().await?;
-            self.reader.extend_from_slice(&data[..]);
-        }
-
-        let mut remaining_data = self.reader.get_remaining_bytes();
-
-        if let Some(content_length) = parse_content_length(std::str::from_utf8(&remaining_data)?) {
-            while remaining_data.len() < content_length as usize {
-                log::trace!(
-                    "content_length: {} {}",
-                    content_length,
-                    remaining_data.len()
-                );
-                let data = self.io.lock().await.read().await?;
-                self.reader.extend_from_slice(&data[..]);
-                remaining_data = self.reader.get_remaining_bytes();
-            }
+        offer: RTCSessionDescription,
+    ) -> Result<RTCSessionDescription, SessionError> {
+        match self.session_type {
+            WebRTCSessionType::Whip => self.publish_whip(offer).await,
+            WebRTCSessionType::Whep => self.subscribe_whep(offer).await,
         }
+    }
 
-        let request_data = self.reader.extract_remaining_bytes();
-
-        if let Some(http_request) = HttpRequest::unmarshal(std::str::from_utf8(&request_data)?) {
-            //POST /whip?app=live&stream=test HTTP/1.1
-            let eles: Vec<&str> = http_request.uri.path.splitn(2, '/').collect();
-            let pars_map = &http_request.query_pairs;
-
-            let request_method = http_request.method.as_str();
-            if request_method == http_method_name::GET {
-                let response = match http_request.uri.path.as_str() {
-                    "/" => Self::gen_file_response("./index.html"),
-                    "/whip.js" => Self::gen_file_response("./whip.js"),
-                    "/whep.js" => Self::gen_file_response("./whep.js"),
-                    _ => {
-                        log::warn!(
-                            "the http get path: {} is not supported.",
-                            http_request.uri.path
-                        );
-                        return Ok(());
-                    }
-                };
-
-                self.send_response(&response).await?;
-                return Ok(());
-            }
-
-            if eles.len() < 2 || pars_map.get("app").is_none() || pars_map.get("stream").is_none() {
-                log::error!(
-                    "WebRTCServerSession::run the http path is not correct: {}",
-                    http_request.uri.path
-                );
-
-                return Err(SessionError {
-                    value: errors::SessionErrorValue::HttpRequestPathError,
-                });
-            }
-
-            let t = eles[1];
-            let app_name = pars_map.get("app").unwrap().clone();
-            let stream_name = pars_map.get("stream").unwrap().clone();
+    //the simulcast layer streams are published or subscribed with their own ids
+    pub fn has_layer_stream(&self, id: &Uuid) -> bool {
+        self.layer_streams
+            .iter()
+            .any(|(_, stream_id)| stream_id == id)
+    }
 
-            log::info!("1:{},2:{},3:{}", t, app_name, stream_name);
+    //the hub streams are released when the peer connection is closed
+    pub async fn close_resource(&self) -> Result<(), SessionError> {
+        self.close_peer_connection().await
+    }
 
-            match request_method {
-                http_method_name::POST => {
-                    let sdp_data = if let Some(body) = http_request.body.as_ref() {
-                        body
-                    } else {
-                        return Err(SessionError {
-                            value: errors::SessionErrorValue::HttpRequestEmptySdp,
-                        });
-                    };
-                    self.session_id = Some(Uuid::new(RandomDigitCount::Zero));
-
-                    let path = format!(
-                        "{}?{}&session_id={}",
-                        http_request.uri.path,
-                        http_request.uri.query.as_ref().unwrap(),
-                        self.session_id.unwrap()
-                    );
-                    let offer = RTCSessionDescription::offer(sdp_data.clone())?;
-
-                    match t.to_lowercase().as_str() {
-                        "whip" => {
-                            if let Some(auth) = &self.auth {
-                                auth.authenticate(&stream_name, &http_request.uri.query, false)?;
-                            }
-                            self.publish_whip(app_name, stream_name, path, offer)
-                                .await?;
-                        }
-                        "whep" => {
-                            if let Some(auth) = &self.auth {
-                                auth.authenticate(&stream_name, &http_request.uri.query, true)?;
-                            }
-                            self.subscribe_whep(app_name, stream_name, path, offer)
-                                .await?;
-                        }
-                        _ => {
-                            log::error!(
-                                "current path: {}, method: {}",
-                                http_request.uri.path,
-                                t.to_lowercase()
-                            );
-                            return Err(SessionError {
-                                value: errors::SessionErrorValue::HttpRequestNotSupported,
-                            });
-                        }
-                    }
+    async fn publish_whip(
+        &mut self,
+        offer: RTCSessionDescription,
+    ) -> Result<RTCSessionDescription, SessionError> {
+        //the whep sessions query the offer to find the simulcast layers
+        self.stream_handler.set_sdp(offer.sdp.clone()).await;
+        let (sender, statistic_sender) = Self::publish_stream(
+            self.app_name.clone(),
+            self.stream_name.clone(),
+            Self::get_publisher_info(self.session_id, &self.remote_addr),
+            self.stream_handler.clone(),
+            &self.event_sender,
+        )
+        .await?;
+
+        //the first simulcast layer is published as the primary stream
+        let mut layer_senders = HashMap::new();
+        for rid in parse_simulcast_rids(&offer.sdp).into_iter().skip(1) {
+            let stream_name = get_layer_stream_name(&self.stream_name, &rid);
+            let publisher_id = Uuid::new(RandomDigitCount::Zero);
+            match Self::publish_stream(
+                self.app_name.clone(),
+                stream_name.clone(),
+                Self::get_publisher_info(publisher_id, &self.remote_addr),
+                Arc::new(WebRTCStreamHandler::default()),
+                &self.event_sender,
+            )
+            .await
+            {
+                Ok((layer_sender, _)) => {
+                    layer_senders.insert(rid, layer_sender);
+                    self.layer_streams.push((stream_name, publisher_id));
                 }
-                http_method_name::OPTIONS => {
-                    self.send_response(&Self::gen_response(http::StatusCode::OK))
-                        .await?
+                Err(err) => {
+                    self.unpublish_whip_streams()?;
+                    return Err(err);
                 }
-                http_method_name::PATCH => {}
-                http_method_name::DELETE => {
-                    if let Some(session_id) = pars_map.get("session_id") {
-                        if let Some(uuid) = Uuid::from_str2(session_id) {
-                            //stop the running session and delete it.
-                            let mut uuid_2_sessions_unlock = uuid_2_sessions.lock().await;
-                            if let Some(session) = uuid_2_sessions_unlock.get(&uuid) {
-                                if let Err(err) = session.lock().await.close_peer_connection().await
-                                {
-                                    log::error!("close peer connection failed: {}", err);
-                                } else {
-                                    log::info!("close peer connection successfully.");
-                                }
-                                uuid_2_sessions_unlock.remove(&uuid);
-                            } else {
-                                log::warn!("the session :{}  is not exited.", uuid);
-                            }
-                        }
-                    } else {
-                        log::error!(
-                            "the delete path does not contain session id: {}?{}",
-                            http_request.uri.path,
-                            http_request.uri.query.as_ref().unwrap()
-                        );
-                    }
-
-                    match t.to_lowercase().as_str() {
-                        "whip" => {
-                            Self::unpublish_whip(
-                                app_name,
-                                stream_name,
-                                self.get_publisher_info(),
-                                self.event_sender.clone(),
-                            )?;
-                        }
-                        "whep" => {}
-                        _ => {
-                            log::error!(
-                                "current path: {}, method: {}",
-                                http_request.uri.path,
-                                t.to_lowercase()
-                            );
-                            return Err(SessionError {
-                                value: errors::SessionErrorValue::HttpRequestNotSupported,
-                            });
-                        }
-                    }
+            }
+        }
 
-                    let status_code = http::StatusCode::OK;
-                    let response = Self::gen_response(status_code);
-                    self.send_response(&response).await?;
+        match handle_whip(
+            offer,
+            &self.ice_context,
+            sender,
+            layer_senders,
+            self.stream_handler.key_frame_request(),
+        )
+        .await
+        {
+            Ok((session_description, peer_connection)) => {
+                //the publisher sends the first video codec of the answer
+                let vcodec = get_sdp_video_codecs(&session_description.sdp)
+                    .into_iter()
+                    .next();
+                if let Some(vcodec) = &vcodec {
+                    self.stream_handler.set_video_codec(vcodec.clone()).await;
                 }
-                _ => {
-                    log::warn!(
-                        "WebRTCServerSession::unsupported method name: {}",
-                        http_request.method
-                    );
+
+                let (state_sender, state_receiver) = broadcast::channel(1);
+                peer_connection.on_peer_connection_state_change(Box::new(
+                    move |s: RTCPeerConnectionState| {
+                        log::info!("whip peer connection state has changed: {s}");
+                        let _ = state_sender.send(s);
+                        Box::pin(async {})
+                    },
+                ));
+                self.watch_peer_connection(peer_connection.clone(), state_receiver);
+
+                if let Some(statistic_sender) = statistic_sender {
+                    tokio::spawn(report_statistics(
+                        self.session_id,
+                        Arc::downgrade(&peer_connection),
+                        vcodec,
+                        true,
+                        statistic_sender,
+                    ));
                 }
+                self.peer_connection = Some(peer_connection);
+                Ok(session_description)
+            }
+            Err(err) => {
+                self.unpublish_whip_streams()?;
+                Err(SessionError {
+                    value: SessionErrorValue::WebRTCError(err),
+                })
             }
-
-            self.http_request_data = Some(http_request);
         }
-
-        Ok(())
     }
 
-    async fn publish_whip(
-        &mut self,
+    pub(crate) async fn publish_stream(
         app_name: String,
         stream_name: String,
-        path: String,
-        offer: RTCSessionDescription,
-    ) -> Result<(), SessionError> {
+        publisher_info: PublisherInfo,
+        stream_handler: Arc<WebRTCStreamHandler>,
+        event_sender: &StreamHubEventSender,
+    ) -> Result<(WhipSender, Option<StatisticDataSender>), SessionError> {
         let (event_result_sender, event_result_receiver) = oneshot::channel();
 
         let publish_event = StreamHubEvent::Publish {
@@ -281,44 +220,61 @@ impl WebRTCServerSession {
                 stream_name,
             },
             result_sender: event_result_sender,
-            info: self.get_publisher_info(),
-            stream_handler: self.stream_handler.clone(),
+            info: publisher_info.clone(),
+            stream_handler,
         };
 
-        if self.event_sender.send(publish_event).is_err() {
+        if event_sender.send(publish_event).is_err() {
             return Err(SessionError {
                 value: SessionErrorValue::StreamHubEventSendErr,
             });
         }
 
-        let sender = event_result_receiver.await??;
-
-        let response = match handle_whip(offer, sender.0, sender.1).await {
-            Ok((session_description, peer_connection)) => {
-                self.peer_connection = Some(peer_connection);
-
-                let status_code = http::StatusCode::CREATED;
-                let mut response = Self::gen_response(status_code);
-
-                response
-                    .headers
-                    .insert("Content-Type".to_string(), "application/sdp".to_string());
-                response.headers.insert("Location".to_string(), path);
-                response.body = Some(session_description.sdp);
-
-                response
-            }
-            Err(err) => {
-                log::error!("handle whip err: {}", err);
-                let status_code = http::StatusCode::SERVICE_UNAVAILABLE;
-                Self::gen_response(status_code)
+        match event_result_receiver.await?? {
+            (Some(frame_sender), Some(packet_sender), statistic_sender) => {
+                if let Some(sender) = &statistic_sender {
+                    let statistic_publisher = StatisticData::Publisher {
+                        id: publisher_info.id,
+                        remote_addr: publisher_info.notify_info.remote_addr,
+                        start_time: chrono::Local::now(),
+                    };
+                    if let Err(err) = sender.send(statistic_publisher) {
+                        log::error!("send statistic_publisher err: {}", err);
+                    }
+                }
+                Ok((
+                    WhipSender {
+                        frame_sender,
+                        packet_sender,
+                    },
+                    statistic_sender,
+                ))
             }
-        };
+            _ => Err(SessionError {
+                value: SessionErrorValue::StreamHubEventSendErr,
+            }),
+        }
+    }
 
-        self.send_response(&response).await
+    fn unpublish_whip_streams(&self) -> Result<(), SessionError> {
+        Self::unpublish_whip(
+            self.app_name.clone(),
+            self.stream_name.clone(),
+            Self::get_publisher_info(self.session_id, &self.remote_addr),
+            self.event_sender.clone(),
+        )?;
+        for (stream_name, publisher_id) in &self.layer_streams {
+            Self::unpublish_whip(
+                self.app_name.clone(),
+                stream_name.clone(),
+                Self::get_publisher_info(*publisher_id, &self.remote_addr),
+                self.event_sender.clone(),
+            )?;
+        }
+        Ok(())
     }
 
-    fn unpublish_whip(
+    pub(crate) fn unpublish_whip(
         app_name: String,
         stream_name: String,
         publish_info: PublisherInfo,
@@ -343,95 +299,273 @@ impl WebRTCServerSession {
 
     async fn subscribe_whep(
         &mut self,
-        app_name: String,
-        stream_name: String,
-        path: String,
         offer: RTCSessionDescription,
-    ) -> Result<(), SessionError> {
-        let subscriber_info = self.get_subscriber_info();
+    ) -> Result<RTCSessionDescription, SessionError> {
+        let (receiver, statistic_sender) = Self::subscribe_stream(
+            self.app_name.clone(),
+            self.stream_name.clone(),
+            Self::get_subscriber_info(self.session_id, &self.remote_addr),
+            &self.event_sender,
+        )
+        .await?;
+
+        //the other simulcast layers are subscribed from their own streams
+        let mut simulcast = None;
+        let information =
+            Self::query_information(&self.app_name, &self.stream_name, &self.event_sender).await;
+        let rids = information
+            .iter()
+            .find_map(|info| match info {
+                Information::Sdp { data } => Some(parse_simulcast_rids(data)),
+                _ => None,
+            })
+            .unwrap_or_default();
+        if rids.len() > 1 {
+            let mut receivers = Vec::new();
+            for rid in rids.iter().skip(1) {
+                let stream_name = get_layer_stream_name(&self.stream_name, rid);
+                let subscriber_id = Uuid::new(RandomDigitCount::Zero);
+                match Self::subscribe_stream(
+                    self.app_name.clone(),
+                    stream_name.clone(),
+                    Self::get_subscriber_info(subscriber_id, &self.remote_addr),
+                    &self.event_sender,
+                )
+                .await
+                {
+                    Ok((layer_receiver, _)) => {
+                        receivers.push(layer_receiver);
+                        self.layer_streams.push((stream_name, subscriber_id));
+                    }
+                    Err(err) => {
+                        self.unsubscribe_whep_streams()?;
+                        return Err(err);
+                    }
+                }
+            }
+            simulcast = Some(WhepSimulcast {
+                rids,
+                receivers,
+                forced_rid: self.simulcast_layer.clone(),
+            });
+        }
+
+        //the key frames of all the simulcast layers are requested by the primary stream
+        let vcodec = Self::get_video_codec(&information);
+        let subscriber = WhepSubscriber {
+            id: self.session_id,
+            identifier: StreamIdentifier::WebRTC {
+                app_name: self.app_name.clone(),
+                stream_name: self.stream_name.clone(),
+            },
+            event_sender: self.event_sender.clone(),
+            statistic_sender: statistic_sender.clone(),
+            vcodec: vcodec.clone(),
+        };
+        let (pc_state_sender, pc_state_receiver) = broadcast::channel(1);
+
+        let (session_description, peer_connection) = match handle_whep(
+            offer,
+            &self.ice_context,
+            receiver,
+            simulcast,
+            subscriber,
+            pc_state_sender,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                self.unsubscribe_whep_streams()?;
+                return Err(SessionError {
+                    value: SessionErrorValue::WebRTCError(err),
+                });
+            }
+        };
+
+        self.watch_peer_connection(peer_connection.clone(), pc_state_receiver);
+        if let Some(statistic_sender) = statistic_sender {
+            tokio::spawn(report_statistics(
+                self.session_id,
+                Arc::downgrade(&peer_connection),
+                Some(vcodec),
+                false,
+                statistic_sender,
+            ));
+        }
+
+        self.peer_connection = Some(peer_connection);
+        Ok(session_description)
+    }
+
+    //Close the peer connection when it is disconnected or failed. After it is closed, the
+    //hub streams are unpublished(whip) or unsubscribed(whep) and the session is removed.
+    fn watch_peer_connection(
+        &self,
+        peer_connection: Arc<RTCPeerConnection>,
+        mut state_receiver: broadcast::Receiver<RTCPeerConnectionState>,
+    ) {
+        let session_type = self.session_type;
+        let session_id = self.session_id;
+        let app_name = self.app_name.clone();
+        let remote_addr = self.remote_addr.clone();
+        let mut streams = vec![(self.stream_name.clone(), self.session_id)];
+        streams.extend(self.layer_streams.clone());
+        let event_sender = self.event_sender.clone();
+        let session_closed_sender = self.session_closed_sender.clone();
+
+        tokio::spawn(async move {
+            let mut disconnected_deadline: Option<Instant> = None;
+            loop {
+                let rv = match disconnected_deadline {
+                    Some(deadline) => tokio::time::timeout_at(deadline, state_receiver.recv())
+                        .await
+                        .unwrap_or_else(|_| {
+                            log::info!("{} session is not reconnected in time", session_type);
+                            Ok(RTCPeerConnectionState::Failed)
+                        }),
+                    None => state_receiver.recv().await,
+                };
+                let state = match rv {
+                    Ok(state) => state,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                match state {
+                    RTCPeerConnectionState::Disconnected => {
+                        disconnected_deadline
+                            .get_or_insert_with(|| Instant::now() + DISCONNECTED_TIMEOUT);
+                    }
+                    RTCPeerConnectionState::Failed => {
+                        disconnected_deadline = None;
+                        if let Err(err) = peer_connection.close().await {
+                            log::error!("peer connection close error: {}", err);
+                        }
+                    }
+                    RTCPeerConnectionState::Closed => {
+                        for (stream_name, id) in streams {
+                            let rv = match session_type {
+                                WebRTCSessionType::Whip => Self::unpublish_whip(
+                                    app_name.clone(),
+                                    stream_name,
+                                    Self::get_publisher_info(id, &remote_addr),
+                                    event_sender.clone(),
+                                ),
+                                WebRTCSessionType::Whep => Self::unsubscribe_whep(
+                                    app_name.clone(),
+                                    stream_name,
+                                    Self::get_subscriber_info(id, &remote_addr),
+                                    event_sender.clone(),
+                                ),
+                            };
+                            if let Err(err) = rv {
+                                log::error!("release the {} stream error: {}", session_type, err);
+                            }
+                        }
+                        let _ = session_closed_sender.send(session_id);
+                        break;
+                    }
+                    _ => disconnected_deadline = None,
+                }
+            }
+        });
+    }
+
+    //the sdp offer and the video codec of the whip publisher
+    pub(crate) async fn query_information(
+        app_name: &str,
+        stream_name: &str,
+        event_sender: &StreamHubEventSender,
+    ) -> Vec<Information> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let request_event = StreamHubEvent::Request {
+            identifier: StreamIdentifier::WebRTC {
+                app_name: app_name.to_string(),
+                stream_name: stream_name.to_string(),
+            },
+            sender,
+        };
+        let mut information = Vec::new();
+        if event_sender.send(request_event).is_err() {
+            return information;
+        }
+
+        //the sender is dropped after the stream handler sends the information
+        while let Some(info) = receiver.recv().await {
+            information.push(info);
+        }
+        information
+    }
 
+    //the streams remuxed from rtmp are h264
+    pub(crate) fn get_video_codec(information: &[Information]) -> VideoCodecType {
+        information
+            .iter()
+            .find_map(|info| match info {
+                Information::VideoCodec { codec } => Some(codec.clone()),
+                _ => None,
+            })
+            .unwrap_or(VideoCodecType::H264)
+    }
+
+    pub(crate) async fn subscribe_stream(
+        app_name: String,
+        stream_name: String,
+        subscriber_info: SubscriberInfo,
+        event_sender: &StreamHubEventSender,
+    ) -> Result<(SubPacketDataReceiver, Option<StatisticDataSender>), SessionError> {
         let (event_result_sender, event_result_receiver) = oneshot::channel();
 
         let subscribe_event = StreamHubEvent::Subscribe {
             identifier: StreamIdentifier::WebRTC {
-                app_name: app_name.clone(),
-                stream_name: stream_name.clone(),
+                app_name,
+                stream_name,
             },
             info: subscriber_info.clone(),
             result_sender: event_result_sender,
         };
 
-        if self.event_sender.send(subscribe_event).is_err() {
+        if event_sender.send(subscribe_event).is_err() {
             return Err(SessionError {
                 value: SessionErrorValue::StreamHubEventSendErr,
             });
         }
 
-        let receiver = event_result_receiver.await??.0.packet_receiver.unwrap();
-
-        let (pc_state_sender, mut pc_state_receiver) = broadcast::channel(1);
-
-        let response = match handle_whep(offer, receiver, pc_state_sender).await {
-            Ok((session_description, peer_connection)) => {
-                let pc_clone = peer_connection.clone();
-
-                let app_name_out = app_name.clone();
-                let stream_name_out = stream_name.clone();
-                let subscriber_info_out = subscriber_info.clone();
-                let sender_out = self.event_sender.clone();
-
-                tokio::spawn(async move {
-                    loop {
-                        if let Ok(state) = pc_state_receiver.recv().await {
-                            log::info!("state: {}", state);
-                            match state {
-                                RTCPeerConnectionState::Disconnected
-                                | RTCPeerConnectionState::Failed => {
-                                    if let Err(err) = pc_clone.close().await {
-                                        log::error!("peer connection close error: {}", err);
-                                    }
-                                }
-                                RTCPeerConnectionState::Closed => {
-                                    if let Err(err) = Self::unsubscribe_whep(
-                                        app_name_out,
-                                        stream_name_out,
-                                        subscriber_info_out,
-                                        sender_out,
-                                    ) {
-                                        log::error!("unsubscribe whep error: {}", err);
-                                    }
-                                    break;
-                                }
-                                _ => {}
-                            }
-                        } else {
-                            log::info!("recv");
-                        }
-                    }
-                });
+        let (data_receiver, statistic_sender) = event_result_receiver.await??;
+        if let Some(sender) = &statistic_sender {
+            let statistic_subscriber = StatisticData::Subscriber {
+                id: subscriber_info.id,
+                remote_addr: subscriber_info.notify_info.remote_addr,
+                sub_type: subscriber_info.sub_type,
+                start_time: chrono::Local::now(),
+            };
+            if let Err(err) = sender.send(statistic_subscriber) {
+                log::error!("send statistic_subscriber err: {}", err);
+            }
+        }
 
-                self.peer_connection = Some(peer_connection);
+        Ok((data_receiver.packet_receiver.unwrap(), statistic_sender))
+    }
 
-                let status_code = http::StatusCode::CREATED;
-                let mut response = Self::gen_response(status_code);
-                response
-                    .headers
-                    .insert("Content-Type".to_string(), "application/sdp".to_string());
-                response.headers.insert("Location".to_string(), path);
-                response.body = Some(session_description.sdp);
-                log::info!("before whep 1");
-                response
-            }
-            Err(err) => {
-                log::error!("handle whep err: {}", err);
-                let status_code = http::StatusCode::SERVICE_UNAVAILABLE;
-                Self::gen_response(status_code)
-            }
-        };
-        self.send_response(&response).await
+    fn unsubscribe_whep_streams(&self) -> Result<(), SessionError> {
+        Self::unsubscribe_whep(
+            self.app_name.clone(),
+            self.stream_name.clone(),
+            Self::get_subscriber_info(self.session_id, &self.remote_addr),
+            self.event_sender.clone(),
+        )?;
+        for (stream_name, subscriber_id) in &self.layer_streams {
+            Self::unsubscribe_whep(
+                self.app_name.clone(),
+                stream_name.clone(),
+                Self::get_subscriber_info(*subscriber_id, &self.remote_addr),
+                self.event_sender.clone(),
+            )?;
+        }
+        Ok(())
     }
 
-    fn unsubscribe_whep(
+    pub(crate) fn unsubscribe_whep(
         app_name: String,
         stream_name: String,
         subscriber_info: SubscriberInfo,
@@ -453,101 +587,54 @@ impl WebRTCServerSession {
         Ok(())
     }
 
-    fn get_subscriber_info(&self) -> SubscriberInfo {
-        let id = if let Some(session_id) = &self.session_id {
-            *session_id
-        } else {
-            Uuid::new(RandomDigitCount::Zero)
-        };
-
+    fn get_subscriber_info(id: Uuid, remote_addr: &str) -> SubscriberInfo {
         SubscriberInfo {
             id,
             sub_type: SubscribeType::PlayerWebrtc,
             sub_data_type: streamhub::define::SubDataType::Packet,
+            start_mode: streamhub::define::SubscribeStartMode::default(),
             notify_info: NotifyInfo {
                 request_url: String::from(""),
-                remote_addr: String::from(""),
+                remote_addr: remote_addr.to_string(),
             },
         }
     }
 
-    fn get_publisher_info(&self) -> PublisherInfo {
-        let id = if let Some(session_id) = &self.session_id {
-            *session_id
-        } else {
-            Uuid::new(RandomDigitCount::Zero)
-        };
-
+    fn get_publisher_info(id: Uuid, remote_addr: &str) -> PublisherInfo {
         PublisherInfo {
             id,
             pub_type: PublishType::PushWebRTC,
             pub_data_type: streamhub::define::PubDataType::Both,
             notify_info: NotifyInfo {
                 request_url: String::from(""),
-                remote_addr: String::from(""),
+                remote_addr: remote_addr.to_string(),
             },
         }
     }
-
-    fn gen_response(status_code: StatusCode) -> HttpResponse {
-        let reason_phrase = if let Some(reason) = status_code.canonical_reason() {
-            reason.to_string()
-        } else {
-            "".to_string()
-        };
-
-        let mut response = HttpResponse {
-            version: "HTTP/1.1".to_string(),
-            status_code: status_code.as_u16(),
-            reason_phrase,
-            ..Default::default()
-        };
-
-        response
-            .headers
-            .insert("Access-Control-Allow-Origin".to_owned(), "*".to_owned());
-        response.headers.insert(
-            "Access-Control-Allow-Headers".to_owned(),
-            "content-type".to_owned(),
-        );
-        response
-            .headers
-            .insert("Access-Control-Allow-Method".to_owned(), "POST".to_owned());
-        response
-    }
-
-    fn gen_file_response(file_path: &str) -> HttpResponse {
-        let mut response = Self::gen_response(http::StatusCode::OK);
-
-        let mut file = File::open(file_path).expect("Failed to open file");
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents)
-            .expect("Failed to read file");
-
-        let contents_str = String::from_utf8_lossy(&contents).to_string();
-
-        response
-            .headers
-            .insert("Content-Type".to_string(), "text/html".to_string());
-        response.body = Some(contents_str);
-
-        response
-    }
-
-    async fn send_response(&mut self, response: &HttpResponse) -> Result<(), SessionError> {
-        self.writer.write(response.marshal().as_bytes())?;
-        self.writer.flush().await?;
-        Ok(())
-    }
 }
 
 #[derive(Default)]
 pub struct WebRTCStreamHandler {
     sps: Mutex<Vec<u8>>,
     pps: Mutex<Vec<u8>>,
+    //the sdp offer of the whip publisher
+    sdp: Mutex<Option<String>>,
+    vcodec: Mutex<Option<VideoCodecType>>,
+    //notify the peer connection of the publisher to send a PLI
+    key_frame_request: Arc<Notify>,
+    last_key_frame_request: Mutex<Option<Instant>>,
 }
 
 impl WebRTCStreamHandler {
+    pub fn key_frame_request(&self) -> Arc<Notify> {
+        self.key_frame_request.clone()
+    }
+    pub async fn set_sdp(&self, sdp: String) {
+        *self.sdp.lock().await = Some(sdp);
+    }
+    pub async fn set_video_codec(&self, vcodec: VideoCodecType) {
+        *self.vcodec.lock().await = Some(vcodec);
+    }
     pub async fn set_sps(&self, sps: Vec<u8>) {
         *self.sps.lock().await = sps;
     }
@@ -562,6 +649,7 @@ impl TStreamHandler for WebRTCStreamHandler {
         &self,
         _data_sender: DataSender,
         _sub_type: SubscribeType,
+        _start_mode: streamhub::define::SubscribeStartMode,
     ) -> Result<(), StreamHubError> {
         Ok(())
     }
@@ -569,5 +657,25 @@ impl TStreamHandler for WebRTCStreamHandler {
         None
     }
 
-    async fn send_information(&self, _sender: InformationSender) {}
+    async fn send_information(&self, sender: InformationSender) {
+        if let Some(sdp) = self.sdp.lock().await.clone() {
+            if let Err(err) = sender.send(Information::Sdp { data: sdp }) {
+                log::error!("send sdp information error: {}", err);
+            }
+        }
+        if let Some(codec) = self.vcodec.lock().await.clone() {
+            if let Err(err) = sender.send(Information::VideoCodec { codec }) {
+                log::error!("send video codec information error: {}", err);
+            }
+        }
+    }
+
+    async fn request_key_frame(&self, _sender: Option<DataSender>) {
+        let mut last_key_frame_request = self.last_key_frame_request.lock().await;
+        if last_key_frame_request.is_some_and(|time| time.elapsed() < PLI_INTERVAL) {
+            return;
+        }
+        *last_key_frame_request = Some(Instant::now());
+        self.key_frame_request.notify_waiters();
+    }
 }
@@ -0,0 +1,293 @@
+use super::congestion::BandwidthEstimator;
+use streamhub::{define::VideoCodecType, queue::is_rtp_key_frame};
+
+//The rids of the simulcast layers sent by a whip publisher, e.g. a=simulcast:send h;m;l
+//(the paused layers are prefixed with ~). The layers are listed from high to low, the
+//first one is published as the primary stream.
+pub fn parse_simulcast_rids(sdp: &str) -> Vec<String> {
+    let mut rids = Vec::new();
+    for line in sdp.lines().map(str::trim) {
+        if let Some(layers) = line.strip_prefix("a=simulcast:send ") {
+            //the first rid of each alternative list is used
+            for layer in layers.split(' ').next().unwrap_or_default().split(';') {
+                let rid = layer.split(',').next().unwrap_or_default();
+                let rid = rid.trim_start_matches('~');
+                if !rid.is_empty() && !rids.iter().any(|value| value == rid) {
+                    rids.push(rid.to_string());
+                }
+            }
+        }
+    }
+    rids
+}
+
+//The hub stream of a simulcast layer except the primary one, e.g. test@m.
+pub fn get_layer_stream_name(stream_name: &str, rid: &str) -> String {
+    format!("{stream_name}@{rid}")
+}
+
+pub fn is_layer_stream_name(stream_name: &str) -> bool {
+    stream_name.contains('@')
+}
+
+struct Layer {
+    rid: String,
+    received_bytes: usize,
+    //the received bitrate in the last interval, bits per second
+    bitrate: f64,
+}
+
+//Choose the simulcast layer sent to a whep viewer by the bandwidth estimate
+//from the REMB/TWCC feedbacks, the layer is only switched at a key frame.
+pub struct LayerSelector {
+    layers: Vec<Layer>,
+    //the layer chosen by the layer query parameter
+    forced_layer: Option<usize>,
+    //the layer being sent to the viewer
+    current_layer: Option<usize>,
+    target_layer: usize,
+    estimator: BandwidthEstimator,
+    vcodec: VideoCodecType,
+}
+
+impl LayerSelector {
+    pub fn new(rids: Vec<String>, forced_rid: Option<String>, vcodec: VideoCodecType) -> Self {
+        let forced_layer = forced_rid.and_then(|forced_rid| {
+            let index = rids.iter().position(|rid| *rid == forced_rid);
+            if index.is_none() {
+                log::warn!("the simulcast layer: {} does not exist", forced_rid);
+            }
+            index
+        });
+
+        Self {
+            layers: rids
+                .into_iter()
+                .map(|rid| Layer {
+                    rid,
+                    received_bytes: 0,
+                    bitrate: 0.0,
+                })
+                .collect(),
+            forced_layer,
+            current_layer: None,
+            target_layer: forced_layer.unwrap_or(0),
+            estimator: BandwidthEstimator::default(),
+            vcodec,
+        }
+    }
+
+    pub fn current_rid(&self) -> Option<&str> {
+        self.current_layer
+            .map(|index| self.layers[index].rid.as_str())
+    }
+
+    //Return if the video packet of a layer should be sent, and if the
+    //current layer is switched to it.
+    pub fn on_video_packet(&mut self, layer: usize, packet: &[u8]) -> (bool, bool) {
+        if let Some(layer_value) = self.layers.get_mut(layer) {
+            layer_value.received_bytes += packet.len();
+        }
+
+        let mut switched = false;
+        if layer == self.target_layer
+            && self.current_layer != Some(layer)
+            && is_rtp_key_frame(&self.vcodec, packet)
+        {
+            if let Some(current_layer) = self.current_rid() {
+                log::info!(
+                    "switch the simulcast layer from {} to {}",
+                    current_layer,
+                    self.layers[layer].rid
+                );
+            }
+            self.current_layer = Some(layer);
+            switched = true;
+        }
+
+        (self.current_layer == Some(layer), switched)
+    }
+
+    //the max bitrate estimated by the viewer
+    pub fn on_remb(&mut self, bitrate: f64) {
+        self.estimator.on_remb(bitrate);
+    }
+
+    //the received and lost packet counts in a TWCC feedback
+    pub fn on_twcc(&mut self, received: usize, lost: usize) {
+        self.estimator.on_twcc(received, lost);
+    }
+
+    pub fn estimator(&self) -> &BandwidthEstimator {
+        &self.estimator
+    }
+
+    //Update the layer bitrates and choose the target layer, called every second.
+    pub fn on_interval(&mut self, elapsed_secs: f64) {
+        if elapsed_secs <= 0.0 {
+            return;
+        }
+        for layer in &mut self.layers {
+            layer.bitrate = layer.received_bytes as f64 * 8.0 / elapsed_secs;
+            layer.received_bytes = 0;
+        }
+        let sending_bitrate = self
+            .current_layer
+            .map(|index| self.layers[index].bitrate)
+            .unwrap_or_default();
+        self.estimator.update(sending_bitrate);
+
+        self.target_layer = match (self.forced_layer, self.estimator.estimated_bitrate()) {
+            (Some(forced_layer), _) => forced_layer,
+            (None, None) => 0,
+            //the layer with the highest bitrate under the estimate, or the lowest one
+            (None, Some(estimated_bitrate)) => {
+                let mut candidates: Vec<usize> = (0..self.layers.len())
+                    .filter(|index| self.layers[*index].bitrate > 0.0)
+                    .collect();
+                candidates
+                    .sort_by(|a, b| self.layers[*a].bitrate.total_cmp(&self.layers[*b].bitrate));
+                candidates
+                    .iter()
+                    .rev()
+                    .find(|index| self.layers[**index].bitrate <= estimated_bitrate)
+                    .or(candidates.first())
+                    .copied()
+                    .unwrap_or(self.target_layer)
+            }
+        };
+    }
+}
+
+//Rewrite the sequence numbers and timestamps of the simulcast layers into one continuous
+//rtp stream, as the layers have their own ssrcs, sequence numbers and timestamps.
+#[derive(Default)]
+pub struct RtpRewriter {
+    seq_offset: u16,
+    timestamp_offset: u32,
+    last_seq: Option<u16>,
+    last_timestamp: u32,
+}
+
+impl RtpRewriter {
+    //elapsed_ticks: the elapsed time since the last sent packet in the clock rate
+    pub fn rewrite(
+        &mut self,
+        seq: u16,
+        timestamp: u32,
+        switched: bool,
+        elapsed_ticks: u32,
+    ) -> (u16, u32) {
+        if switched {
+            if let Some(last_seq) = self.last_seq {
+                self.seq_offset = last_seq.wrapping_add(1).wrapping_sub(seq);
+                self.timestamp_offset = self
+                    .last_timestamp
+                    .wrapping_add(elapsed_ticks.max(1))
+                    .wrapping_sub(timestamp);
+            }
+        }
+
+        let seq = seq.wrapping_add(self.seq_offset);
+        let timestamp = timestamp.wrapping_add(self.timestamp_offset);
+        if self
+            .last_seq
+            .is_none_or(|last_seq| (seq.wrapping_sub(last_seq) as i16) > 0)
+        {
+            self.last_seq = Some(seq);
+            self.last_timestamp = timestamp;
+        }
+        (seq, timestamp)
+    }
+
+    //a packet is dropped, so the following sequence numbers are moved back by one
+    pub fn skip(&mut self) {
+        self.seq_offset = self.seq_offset.wrapping_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_simulcast_rids, LayerSelector, RtpRewriter};
+    use streamhub::define::VideoCodecType;
+
+    //a h264 rtp packet whose payload is an IDR nalu
+    fn gen_packet(key_frame: bool) -> Vec<u8> {
+        let mut packet = vec![0x80, 96, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1];
+        packet.extend_from_slice(if key_frame {
+            &[0x65, 0x88, 0x84]
+        } else {
+            &[0x41, 0x9a, 0x02]
+        });
+        packet
+    }
+
+    #[test]
+    fn test_parse_simulcast_rids() {
+        let sdp = "m=video 9 UDP/TLS/RTP/SAVPF 96\r\n\
+                   a=rid:h send\r\n\
+                   a=rid:m send\r\n\
+                   a=rid:l send\r\n\
+                   a=simulcast:send h;~m,m2;l\r\n";
+        assert_eq!(parse_simulcast_rids(sdp), vec!["h", "m", "l"]);
+        assert!(parse_simulcast_rids("m=video 9 UDP/TLS/RTP/SAVPF 96\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_layer_selector() {
+        let rids = vec!["h".to_string(), "l".to_string()];
+        let mut selector = LayerSelector::new(rids, None, VideoCodecType::H264);
+
+        //wait for the key frame of the high layer
+        assert_eq!(
+            selector.on_video_packet(0, &gen_packet(false)),
+            (false, false)
+        );
+        assert_eq!(
+            selector.on_video_packet(1, &gen_packet(true)),
+            (false, false)
+        );
+        assert_eq!(selector.on_video_packet(0, &gen_packet(true)), (true, true));
+        assert_eq!(selector.current_rid(), Some("h"));
+
+        //the loss makes the estimate lower than the high layer bitrate
+        selector.on_twcc(50, 50);
+        for _ in 0..100 {
+            selector.on_video_packet(0, &[0; 1000]);
+        }
+        for _ in 0..10 {
+            selector.on_video_packet(1, &[0; 1000]);
+        }
+        selector.on_interval(1.0);
+        assert_eq!(
+            selector.on_video_packet(1, &gen_packet(false)),
+            (false, false)
+        );
+        assert_eq!(selector.on_video_packet(1, &gen_packet(true)), (true, true));
+        assert_eq!(selector.current_rid(), Some("l"));
+
+        let mut selector = LayerSelector::new(
+            vec!["h".to_string(), "l".to_string()],
+            Some("l".to_string()),
+            VideoCodecType::H264,
+        );
+        assert_eq!(
+            selector.on_video_packet(0, &gen_packet(true)),
+            (false, false)
+        );
+        assert_eq!(selector.on_video_packet(1, &gen_packet(true)), (true, true));
+    }
+
+    #[test]
+    fn test_rtp_rewriter() {
+        let mut rewriter = RtpRewriter::default();
+        assert_eq!(rewriter.rewrite(100, 9000, true, 0), (100, 9000));
+        assert_eq!(rewriter.rewrite(101, 12000, false, 3000), (101, 12000));
+        //switch to another layer
+        assert_eq!(rewriter.rewrite(65535, 500, true, 3000), (102, 15000));
+        assert_eq!(rewriter.rewrite(0, 3500, false, 3000), (103, 18000));
+        //the dropped packet leaves no gap
+        rewriter.skip();
+        assert_eq!(rewriter.rewrite(2, 6500, false, 3000), (104, 21000));
+    }
+}
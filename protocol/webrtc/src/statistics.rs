@@ -0,0 +1,368 @@
+use std::sync::Weak;
+use streamhub::define::{StatisticData, StatisticDataSender, VideoCodecType};
+use streamhub::statistics::WebRTCStatistics;
+use streamhub::utils::Uuid;
+use tokio::time::{Duration, Instant};
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::stats::{ICECandidateStats, StatsReport, StatsReportType};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+//the rtp counters summed over all the tracks of a peer connection
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct RtpCounters {
+    bytes: u64,
+    //the packets sent by the remote publisher or by the server
+    packets: u64,
+    lost_packets: u64,
+}
+
+impl RtpCounters {
+    //The packets received from a publisher are compared with the ones in its sender
+    //reports, the packets lost by a viewer come from its receiver reports.
+    fn new(report: &StatsReport, is_publisher: bool) -> Self {
+        let mut counters = Self::default();
+        let mut received_packets = 0;
+        for stats in report.reports.values() {
+            match stats {
+                StatsReportType::InboundRTP(stats) if is_publisher => {
+                    counters.bytes += stats.bytes_received;
+                    received_packets += stats.packets_received;
+                }
+                StatsReportType::RemoteOutboundRTP(stats) if is_publisher => {
+                    counters.packets += stats.packets_sent;
+                }
+                StatsReportType::OutboundRTP(stats) if !is_publisher => {
+                    counters.bytes += stats.bytes_sent;
+                    counters.packets += stats.packets_sent;
+                }
+                StatsReportType::RemoteInboundRTP(stats) if !is_publisher => {
+                    counters.lost_packets += stats.packets_lost.max(0) as u64;
+                }
+                _ => {}
+            }
+        }
+        if is_publisher {
+            //the sender reports fall behind the received packets
+            counters.packets = counters.packets.max(received_packets);
+            counters.lost_packets = counters.packets - received_packets;
+        }
+        counters
+    }
+
+    //the packet loss rate in the interval since the last counters
+    fn packet_loss(&self, last: &RtpCounters) -> f64 {
+        let packets = self.packets.saturating_sub(last.packets);
+        if packets == 0 {
+            return 0.0;
+        }
+        let lost_packets = self.lost_packets.saturating_sub(last.lost_packets);
+        (lost_packets as f64 * 100.0 / packets as f64).min(100.0)
+    }
+}
+
+//the local and the remote candidates of the nominated candidate pair
+fn get_candidate_pair(report: &StatsReport) -> Option<(&ICECandidateStats, &ICECandidateStats)> {
+    report.reports.values().find_map(|stats| match stats {
+        StatsReportType::CandidatePair(pair) if pair.nominated => {
+            match (
+                report.reports.get(&pair.local_candidate_id),
+                report.reports.get(&pair.remote_candidate_id),
+            ) {
+                (
+                    Some(StatsReportType::LocalCandidate(local)),
+                    Some(StatsReportType::RemoteCandidate(remote)),
+                ) => Some((local, remote)),
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+fn format_candidate(candidate: &ICECandidateStats) -> String {
+    format!(
+        "{} {}:{}",
+        candidate.candidate_type, candidate.ip, candidate.port
+    )
+}
+
+//Report the statistics of a whip publisher or a whep viewer to the stream hub until
+//the peer connection is closed or the hub stream is unpublished.
+pub(crate) async fn report_statistics(
+    id: Uuid,
+    peer_connection: Weak<RTCPeerConnection>,
+    video_codec: Option<VideoCodecType>,
+    is_publisher: bool,
+    statistic_sender: StatisticDataSender,
+) {
+    let start_time = Instant::now();
+    let mut last_counters = RtpCounters::default();
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+    //the first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let report = match peer_connection.upgrade() {
+            Some(pc) if pc.connection_state() != RTCPeerConnectionState::Closed => {
+                pc.get_stats().await
+            }
+            _ => break,
+        };
+
+        let counters = RtpCounters::new(&report, is_publisher);
+        let bitrate = counters.bytes.saturating_sub(last_counters.bytes) * 8
+            / REPORT_INTERVAL.as_secs()
+            / 1000;
+
+        let mut statistics = WebRTCStatistics {
+            video_codec: video_codec.clone(),
+            bitrate: bitrate as usize,
+            packet_loss: counters.packet_loss(&last_counters),
+            duration: start_time.elapsed().as_secs(),
+            ..Default::default()
+        };
+        last_counters = counters;
+
+        let mut remote_addr = String::new();
+        if let Some((local, remote)) = get_candidate_pair(&report) {
+            statistics.local_candidate = format_candidate(local);
+            statistics.remote_candidate = format_candidate(remote);
+            remote_addr = format!("{}:{}", remote.ip, remote.port);
+        }
+
+        let statistic_data = StatisticData::WebRTC {
+            id,
+            remote_addr,
+            statistics,
+        };
+        if statistic_sender.send(statistic_data).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_candidate, get_candidate_pair, RtpCounters};
+    use std::collections::HashMap;
+    use tokio::time::Instant;
+    use webrtc::ice::candidate::{CandidatePairState, CandidateType};
+    use webrtc::ice::network_type::NetworkType;
+    use webrtc::stats::{
+        ICECandidatePairStats, ICECandidateStats, InboundRTPStats, OutboundRTPStats, RTCStatsType,
+        RemoteInboundRTPStats, RemoteOutboundRTPStats, StatsReport, StatsReportType,
+    };
+
+    fn gen_report(reports: Vec<StatsReportType>) -> StatsReport {
+        StatsReport {
+            reports: reports
+                .into_iter()
+                .enumerate()
+                .map(|(i, stats)| (i.to_string(), stats))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    fn gen_inbound(packets_received: u64, bytes_received: u64) -> StatsReportType {
+        StatsReportType::InboundRTP(InboundRTPStats {
+            timestamp: Instant::now(),
+            stats_type: RTCStatsType::InboundRTP,
+            id: String::new(),
+            ssrc: 1,
+            kind: "video",
+            packets_received,
+            track_identifier: String::new(),
+            mid: Default::default(),
+            last_packet_received_timestamp: None,
+            header_bytes_received: 0,
+            bytes_received,
+            nack_count: 0,
+            fir_count: None,
+            pli_count: None,
+        })
+    }
+
+    fn gen_remote_outbound(packets_sent: u64) -> StatsReportType {
+        StatsReportType::RemoteOutboundRTP(RemoteOutboundRTPStats {
+            timestamp: Instant::now(),
+            stats_type: RTCStatsType::RemoteOutboundRTP,
+            id: String::new(),
+            ssrc: 1,
+            kind: "video",
+            packets_sent,
+            bytes_sent: 0,
+            local_id: String::new(),
+            round_trip_time: None,
+            reports_sent: 1,
+            total_round_trip_time: 0.0,
+            round_trip_time_measurements: 0,
+        })
+    }
+
+    fn gen_outbound(packets_sent: u64, bytes_sent: u64) -> StatsReportType {
+        StatsReportType::OutboundRTP(OutboundRTPStats {
+            timestamp: Instant::now(),
+            stats_type: RTCStatsType::OutboundRTP,
+            id: String::new(),
+            ssrc: 1,
+            kind: "video",
+            packets_sent,
+            bytes_sent,
+            track_identifier: String::new(),
+            mid: Default::default(),
+            rid: None,
+            header_bytes_sent: 0,
+            nack_count: 0,
+            fir_count: None,
+            pli_count: None,
+        })
+    }
+
+    fn gen_remote_inbound(packets_lost: i64) -> StatsReportType {
+        StatsReportType::RemoteInboundRTP(RemoteInboundRTPStats {
+            timestamp: Instant::now(),
+            stats_type: RTCStatsType::RemoteInboundRTP,
+            id: String::new(),
+            ssrc: 1,
+            kind: "video",
+            packets_received: 0,
+            packets_lost,
+            local_id: String::new(),
+            round_trip_time: None,
+            total_round_trip_time: 0.0,
+            fraction_lost: 0.0,
+            round_trip_time_measurements: 0,
+        })
+    }
+
+    fn gen_candidate(id: &str, ip: &str, port: u16, is_local: bool) -> StatsReportType {
+        let candidate = ICECandidateStats {
+            timestamp: Instant::now(),
+            stats_type: if is_local {
+                RTCStatsType::LocalCandidate
+            } else {
+                RTCStatsType::RemoteCandidate
+            },
+            id: id.to_string(),
+            candidate_type: CandidateType::Host,
+            deleted: false,
+            ip: ip.to_string(),
+            network_type: NetworkType::Udp4,
+            port,
+            priority: 0,
+            relay_protocol: String::new(),
+            url: String::new(),
+        };
+        if is_local {
+            StatsReportType::LocalCandidate(candidate)
+        } else {
+            StatsReportType::RemoteCandidate(candidate)
+        }
+    }
+
+    fn gen_candidate_pair(nominated: bool) -> StatsReportType {
+        StatsReportType::CandidatePair(ICECandidatePairStats {
+            timestamp: Instant::now(),
+            stats_type: RTCStatsType::CandidatePair,
+            id: String::new(),
+            local_candidate_id: "local".to_string(),
+            remote_candidate_id: "remote".to_string(),
+            state: CandidatePairState::Succeeded,
+            nominated,
+            packets_sent: 0,
+            packets_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            last_packet_sent_timestamp: Instant::now(),
+            last_packet_received_timestamp: Instant::now(),
+            total_round_trip_time: 0.0,
+            current_round_trip_time: 0.0,
+            available_outgoing_bitrate: 0.0,
+            available_incoming_bitrate: 0.0,
+            requests_received: 0,
+            requests_sent: 0,
+            responses_received: 0,
+            responses_sent: 0,
+            consent_requests_sent: 0,
+            circuit_breaker_trigger_count: 0,
+            consent_expired_timestamp: Instant::now(),
+            first_request_timestamp: Instant::now(),
+            last_request_timestamp: Instant::now(),
+            retransmissions_sent: 0,
+        })
+    }
+
+    #[test]
+    fn test_publisher_packet_loss() {
+        //the sender report tells 1000 packets are sent and 950 of them are received
+        let report = gen_report(vec![gen_inbound(950, 100000), gen_remote_outbound(1000)]);
+        let counters = RtpCounters::new(&report, true);
+        assert_eq!(counters.bytes, 100000);
+        assert_eq!(counters.packets, 1000);
+        assert_eq!(counters.lost_packets, 50);
+        assert_eq!(counters.packet_loss(&RtpCounters::default()), 5.0);
+
+        //the sender report falls behind the received packets
+        let report = gen_report(vec![gen_inbound(1200, 120000), gen_remote_outbound(1000)]);
+        let last_counters = counters;
+        let counters = RtpCounters::new(&report, true);
+        assert_eq!(counters.packets, 1200);
+        assert_eq!(counters.lost_packets, 0);
+        assert_eq!(counters.packet_loss(&last_counters), 0.0);
+
+        //the loss is counted in the interval only
+        let report = gen_report(vec![gen_inbound(1390, 140000), gen_remote_outbound(1400)]);
+        assert_eq!(RtpCounters::new(&report, true).packet_loss(&counters), 5.0);
+    }
+
+    #[test]
+    fn test_viewer_packet_loss() {
+        //the inbound stats of the viewer are ignored
+        let report = gen_report(vec![
+            gen_outbound(1000, 100000),
+            gen_remote_inbound(100),
+            gen_inbound(10, 1000),
+        ]);
+        let last_counters = RtpCounters::new(&report, false);
+        assert_eq!(
+            last_counters,
+            RtpCounters {
+                bytes: 100000,
+                packets: 1000,
+                lost_packets: 100,
+            }
+        );
+        assert_eq!(last_counters.packet_loss(&RtpCounters::default()), 10.0);
+
+        //no packet is lost in the interval
+        let report = gen_report(vec![gen_outbound(2000, 200000), gen_remote_inbound(100)]);
+        let counters = RtpCounters::new(&report, false);
+        assert_eq!(counters.packet_loss(&last_counters), 0.0);
+        //no packet is sent in the interval
+        assert_eq!(counters.packet_loss(&counters), 0.0);
+    }
+
+    #[test]
+    fn test_get_candidate_pair() {
+        let local = gen_candidate("local", "10.0.0.1", 5000, true);
+        let remote = gen_candidate("remote", "192.168.1.2", 6000, false);
+        let mut report = StatsReport {
+            reports: HashMap::from([
+                ("local".to_string(), local),
+                ("remote".to_string(), remote),
+                ("pair".to_string(), gen_candidate_pair(false)),
+            ]),
+        };
+        assert!(get_candidate_pair(&report).is_none());
+
+        report
+            .reports
+            .insert("pair".to_string(), gen_candidate_pair(true));
+        let (local, remote) = get_candidate_pair(&report).unwrap();
+        assert_eq!(format_candidate(local), "host 10.0.0.1:5000");
+        assert_eq!(format_candidate(remote), "host 192.168.1.2:6000");
+    }
+}
@@ -0,0 +1,23 @@
+use std::io;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::TlsAcceptor;
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    //the https listening address of the whip/whep signaling
+    pub address: String,
+    //the PEM encoded certificate chain
+    pub cert_path: String,
+    //the PEM encoded PKCS#8 private key
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    pub fn new_acceptor(&self) -> io::Result<TlsAcceptor> {
+        let cert = std::fs::read(&self.cert_path)?;
+        let key = std::fs::read(&self.key_path)?;
+        let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(io::Error::other)?;
+        let acceptor = native_tls::TlsAcceptor::new(identity).map_err(io::Error::other)?;
+        Ok(TlsAcceptor::from(acceptor))
+    }
+}
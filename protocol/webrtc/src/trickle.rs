@@ -0,0 +1,211 @@
+use super::errors::{WebRTCError, WebRTCErrorValue};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+pub const SDP_FRAGMENT_CONTENT_TYPE: &str = "application/trickle-ice-sdpfrag";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SdpFragmentMedia {
+    //audio or video
+    pub kind: String,
+    pub mid: Option<String>,
+    //the values of the a=candidate lines, e.g. candidate:1 1 udp ...
+    pub candidates: Vec<String>,
+    pub end_of_candidates: bool,
+}
+
+//https://www.rfc-editor.org/rfc/rfc8840#section-9
+//The ice part of a sdp, it is also parsed from a full sdp to get the
+//ice credentials and the candidates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SdpFragment {
+    pub ice_ufrag: Option<String>,
+    pub ice_pwd: Option<String>,
+    pub medias: Vec<SdpFragmentMedia>,
+}
+
+impl SdpFragment {
+    pub fn unmarshal(data: &str) -> Self {
+        let mut fragment = SdpFragment::default();
+
+        for line in data.lines().map(str::trim) {
+            if let Some(media) = line.strip_prefix("m=") {
+                fragment.medias.push(SdpFragmentMedia {
+                    kind: media.split(' ').next().unwrap_or_default().to_string(),
+                    ..Default::default()
+                });
+                continue;
+            }
+
+            let attribute = match line.strip_prefix("a=") {
+                Some(attribute) => attribute,
+                None => continue,
+            };
+            //the credentials of the first media are used if they are not session level
+            if let Some(ice_ufrag) = attribute.strip_prefix("ice-ufrag:") {
+                fragment.ice_ufrag.get_or_insert(ice_ufrag.to_string());
+            } else if let Some(ice_pwd) = attribute.strip_prefix("ice-pwd:") {
+                fragment.ice_pwd.get_or_insert(ice_pwd.to_string());
+            } else if let Some(media) = fragment.medias.last_mut() {
+                if let Some(mid) = attribute.strip_prefix("mid:") {
+                    media.mid = Some(mid.to_string());
+                } else if attribute.starts_with("candidate:") {
+                    media.candidates.push(attribute.to_string());
+                } else if attribute == "end-of-candidates" {
+                    media.end_of_candidates = true;
+                }
+            }
+        }
+
+        fragment
+    }
+
+    pub fn marshal(&self) -> String {
+        let mut data = String::new();
+
+        if let Some(ice_ufrag) = &self.ice_ufrag {
+            data += &format!("a=ice-ufrag:{ice_ufrag}\r\n");
+        }
+        if let Some(ice_pwd) = &self.ice_pwd {
+            data += &format!("a=ice-pwd:{ice_pwd}\r\n");
+        }
+        for media in &self.medias {
+            data += &format!("m={} 9 RTP/AVP 0\r\n", media.kind);
+            if let Some(mid) = &media.mid {
+                data += &format!("a=mid:{mid}\r\n");
+            }
+            for candidate in &media.candidates {
+                data += &format!("a={candidate}\r\n");
+            }
+            if media.end_of_candidates {
+                data += "a=end-of-candidates\r\n";
+            }
+        }
+
+        data
+    }
+}
+
+//replace the ice credentials of all the medias in a sdp
+fn replace_ice_credentials(sdp: &str, ice_ufrag: &str, ice_pwd: &str) -> String {
+    let mut data = String::new();
+    for line in sdp.lines() {
+        if line.starts_with("a=ice-ufrag:") {
+            data += &format!("a=ice-ufrag:{ice_ufrag}\r\n");
+        } else if line.starts_with("a=ice-pwd:") {
+            data += &format!("a=ice-pwd:{ice_pwd}\r\n");
+        } else {
+            data += &format!("{line}\r\n");
+        }
+    }
+    data
+}
+
+//The etag of a whip/whep resource identifies its ice session, it changes after
+//the ice restarts.
+pub async fn get_etag(peer_connection: &RTCPeerConnection) -> Option<String> {
+    let local_description = peer_connection.local_description().await?;
+    let ice_ufrag = SdpFragment::unmarshal(&local_description.sdp).ice_ufrag?;
+    Some(format!("\"{ice_ufrag}\""))
+}
+
+//Apply a PATCH request(https://www.rfc-editor.org/rfc/rfc9725#section-4.3) to the peer connection:
+//the remote candidates are added and the ice is restarted if the remote credentials change,
+//the answer fragment is returned in the later case.
+pub async fn apply_sdp_fragment(
+    peer_connection: &RTCPeerConnection,
+    fragment: &SdpFragment,
+) -> Result<Option<SdpFragment>, WebRTCError> {
+    let remote_description = peer_connection
+        .remote_description()
+        .await
+        .ok_or(WebRTCError {
+            value: WebRTCErrorValue::CanNotGetRemoteDescription,
+        })?;
+    let remote_fragment = SdpFragment::unmarshal(&remote_description.sdp);
+
+    let mut answer_fragment = None;
+    if fragment.ice_ufrag.is_some() && fragment.ice_ufrag != remote_fragment.ice_ufrag {
+        let (ice_ufrag, ice_pwd) = match (&fragment.ice_ufrag, &fragment.ice_pwd) {
+            (Some(ice_ufrag), Some(ice_pwd)) => (ice_ufrag, ice_pwd),
+            _ => {
+                return Err(WebRTCError {
+                    value: WebRTCErrorValue::MissingIceCredentials,
+                });
+            }
+        };
+        log::info!("ice restart, remote ice ufrag: {}", ice_ufrag);
+
+        let offer = replace_ice_credentials(&remote_description.sdp, ice_ufrag, ice_pwd);
+        peer_connection
+            .set_remote_description(RTCSessionDescription::offer(offer)?)
+            .await?;
+
+        let answer = peer_connection.create_answer(None).await?;
+        let mut gather_complete = peer_connection.gathering_complete_promise().await;
+        peer_connection.set_local_description(answer).await?;
+        let _ = gather_complete.recv().await;
+
+        let local_description = peer_connection
+            .local_description()
+            .await
+            .ok_or(WebRTCError {
+                value: WebRTCErrorValue::CanNotGetLocalDescription,
+            })?;
+        answer_fragment = Some(SdpFragment::unmarshal(&local_description.sdp));
+    }
+
+    for (index, media) in fragment.medias.iter().enumerate() {
+        //find the media line of the candidates by the mid
+        let mline_index = remote_fragment
+            .medias
+            .iter()
+            .position(|remote_media| media.mid.is_some() && remote_media.mid == media.mid)
+            .unwrap_or(index);
+
+        for candidate in &media.candidates {
+            peer_connection
+                .add_ice_candidate(RTCIceCandidateInit {
+                    candidate: candidate.clone(),
+                    sdp_mid: media.mid.clone(),
+                    sdp_mline_index: Some(mline_index as u16),
+                    username_fragment: fragment.ice_ufrag.clone(),
+                })
+                .await?;
+        }
+    }
+
+    Ok(answer_fragment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replace_ice_credentials, SdpFragment};
+
+    #[test]
+    fn test_sdp_fragment() {
+        let data = "a=ice-ufrag:EsAw\r\n\
+                    a=ice-pwd:P2uYro0UCOQ4zxjKXaWCBui1\r\n\
+                    m=audio 9 RTP/AVP 0\r\n\
+                    a=mid:0\r\n\
+                    a=candidate:1387637174 1 udp 2122260223 192.0.2.1 61764 typ host generation 0 ufrag EsAw network-id 1\r\n\
+                    a=end-of-candidates\r\n";
+
+        let fragment = SdpFragment::unmarshal(data);
+        assert_eq!(fragment.ice_ufrag.as_deref(), Some("EsAw"));
+        assert_eq!(fragment.medias.len(), 1);
+        assert_eq!(fragment.medias[0].kind, "audio");
+        assert_eq!(fragment.medias[0].mid.as_deref(), Some("0"));
+        assert_eq!(fragment.medias[0].candidates.len(), 1);
+        assert!(fragment.medias[0].end_of_candidates);
+        assert_eq!(fragment.marshal(), data);
+
+        let sdp = "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=ice-ufrag:old\r\na=ice-pwd:old\r\n";
+        let sdp = replace_ice_credentials(sdp, "EsAw", "P2uYro0UCOQ4zxjKXaWCBui1");
+        assert_eq!(
+            SdpFragment::unmarshal(&sdp).ice_ufrag.as_deref(),
+            Some("EsAw")
+        );
+    }
+}
@@ -1,69 +1,560 @@
-use streamhub::define::StreamHubEventSender;
+use streamhub::define::{BroadcastEvent, BroadcastEventReceiver, StreamHubEventSender};
 
+use super::errors::{WebRTCError, WebRTCErrorValue};
+use super::ice::{IceConfig, IceContext};
+use super::session::define::WebRTCSessionType;
+use super::session::errors::SessionErrorValue;
 use super::session::WebRTCServerSession;
+use super::tls::TlsConfig;
+use super::trickle::{apply_sdp_fragment, get_etag, SdpFragment, SDP_FRAGMENT_CONTENT_TYPE};
 
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode, Uri},
+    middleware::map_response,
+    response::Response,
+    routing::{get, post},
+    Router,
+};
 use commonlib::auth::Auth;
-use commonlib::define::http_method_name;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use streamhub::utils::Uuid;
-use tokio::io::Error;
 use tokio::net::TcpListener;
-use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, Mutex};
+use tokio_native_tls::TlsAcceptor;
+use tower_service::Service;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+
+type WebRTCSessions = Arc<Mutex<HashMap<Uuid, Arc<Mutex<WebRTCServerSession>>>>>;
+
+#[derive(Clone)]
+struct WebRTCServerState {
+    event_producer: StreamHubEventSender,
+    auth: Option<Auth>,
+    ice_context: Arc<IceContext>,
+    uuid_2_sessions: WebRTCSessions,
+    session_closed_sender: mpsc::UnboundedSender<Uuid>,
+}
 
 pub struct WebRTCServer {
     address: String,
     event_producer: StreamHubEventSender,
-    uuid_2_sessions: Arc<Mutex<HashMap<Uuid, Arc<Mutex<WebRTCServerSession>>>>>,
+    //receive the clients kicked off by the api
+    client_event_consumer: BroadcastEventReceiver,
     auth: Option<Auth>,
+    ice_config: IceConfig,
+    tls_config: Option<TlsConfig>,
 }
 
 impl WebRTCServer {
-    pub fn new(address: String, event_producer: StreamHubEventSender, auth: Option<Auth>) -> Self {
+    pub fn new(
+        address: String,
+        event_producer: StreamHubEventSender,
+        client_event_consumer: BroadcastEventReceiver,
+        auth: Option<Auth>,
+        ice_config: IceConfig,
+        tls_config: Option<TlsConfig>,
+    ) -> Self {
         Self {
             address,
             event_producer,
-            uuid_2_sessions: Arc::new(Mutex::new(HashMap::new())),
+            client_event_consumer,
             auth,
+            ice_config,
+            tls_config,
         }
     }
 
-    pub async fn run(&mut self) -> Result<(), Error> {
-        let socket_addr: &SocketAddr = &self.address.parse().unwrap();
+    pub async fn run(&mut self) -> Result<(), WebRTCError> {
+        let (session_closed_sender, session_closed_receiver) = mpsc::unbounded_channel();
+        let state = WebRTCServerState {
+            event_producer: self.event_producer.clone(),
+            auth: self.auth.clone(),
+            ice_context: Arc::new(IceContext::new(self.ice_config.clone()).await?),
+            uuid_2_sessions: Arc::new(Mutex::new(HashMap::new())),
+            session_closed_sender,
+        };
+        tokio::spawn(remove_closed_sessions(
+            state.uuid_2_sessions.clone(),
+            session_closed_receiver,
+        ));
+        tokio::spawn(kick_off_sessions(
+            state.uuid_2_sessions.clone(),
+            self.client_event_consumer.resubscribe(),
+        ));
+
+        let app = Router::new()
+            //the demo pages under the clients folder
+            .route("/", get(|| serve_file("./index.html", "text/html")))
+            .route(
+                "/whip.js",
+                get(|| serve_file("./whip.js", "application/javascript")),
+            )
+            .route(
+                "/whep.js",
+                get(|| serve_file("./whep.js", "application/javascript")),
+            )
+            .route("/whip", post(post_whip).options(handle_options))
+            .route("/whep", post(post_whep).options(handle_options))
+            .route(
+                "/:session_type/resource/:session_id",
+                axum::routing::patch(patch_resource)
+                    .delete(delete_resource)
+                    .options(handle_options),
+            )
+            .layer(map_response(add_cors_headers))
+            .with_state(state);
+
+        if let Some(tls_config) = &self.tls_config {
+            let acceptor = tls_config.new_acceptor()?;
+            let socket_addr: SocketAddr = tls_config.address.parse().unwrap();
+            let listener = TcpListener::bind(socket_addr).await?;
+            log::info!("WebRTC server listening on https://{}", socket_addr);
+
+            tokio::spawn(serve_tls(listener, acceptor, app.clone()));
+        }
+
+        let socket_addr: SocketAddr = self.address.parse().unwrap();
         let listener = TcpListener::bind(socket_addr).await?;
+        log::info!("WebRTC server listening on http://{}", socket_addr);
 
-        log::info!("WebRTC server listening on tcp://{}", socket_addr);
-        loop {
-            let (tcp_stream, _) = listener.accept().await?;
-            let session = Arc::new(Mutex::new(WebRTCServerSession::new(
-                tcp_stream,
-                self.event_producer.clone(),
-                self.auth.clone(),
-            )));
-            let uuid_2_sessions = self.uuid_2_sessions.clone();
-            tokio::spawn(async move {
-                let mut session_unlock = session.lock().await;
-                if let Err(err) = session_unlock.run(uuid_2_sessions.clone()).await {
-                    log::error!("session run error, err: {}", err);
-                }
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await?;
+        Ok(())
+    }
+}
 
-                if let Some(http_request_data) = &session_unlock.http_request_data {
-                    let mut uuid_2_session_unlock = uuid_2_sessions.lock().await;
-
-                    match http_request_data.method.as_str() {
-                        http_method_name::POST => {
-                            if let Some(uuid) = session_unlock.session_id {
-                                uuid_2_session_unlock.insert(uuid, session.clone());
-                            }
-                        }
-                        http_method_name::OPTIONS => {}
-                        http_method_name::PATCH => {}
-                        http_method_name::DELETE => {}
-                        _ => {}
-                    }
+//the sessions are removed after their peer connections are closed
+async fn remove_closed_sessions(
+    uuid_2_sessions: WebRTCSessions,
+    mut session_closed_receiver: mpsc::UnboundedReceiver<Uuid>,
+) {
+    while let Some(session_id) = session_closed_receiver.recv().await {
+        if uuid_2_sessions.lock().await.remove(&session_id).is_some() {
+            log::info!("remove the closed session: {}", session_id);
+        }
+    }
+}
+
+//close the peer connections of the publishers and the viewers kicked off by the api
+async fn kick_off_sessions(
+    uuid_2_sessions: WebRTCSessions,
+    mut client_event_consumer: BroadcastEventReceiver,
+) {
+    loop {
+        let id = match client_event_consumer.recv().await {
+            Ok(BroadcastEvent::KickOffClient { id }) => id,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+        let mut session = uuid_2_sessions.lock().await.remove(&id);
+        if session.is_none() {
+            session = remove_layer_session(&uuid_2_sessions, &id).await;
+        }
+        if let Some(session) = session {
+            log::info!("kick off the session: {}", id);
+            if let Err(err) = session.lock().await.close_resource().await {
+                log::error!("close the kicked off session error: {}", err);
+            }
+        }
+    }
+}
+
+//remove the session which publishes or subscribes the simulcast layer stream of the id
+async fn remove_layer_session(
+    uuid_2_sessions: &WebRTCSessions,
+    id: &Uuid,
+) -> Option<Arc<Mutex<WebRTCServerSession>>> {
+    let sessions: Vec<(Uuid, Arc<Mutex<WebRTCServerSession>>)> = uuid_2_sessions
+        .lock()
+        .await
+        .iter()
+        .map(|(session_id, session)| (*session_id, session.clone()))
+        .collect();
+    for (session_id, session) in sessions {
+        if session.lock().await.has_layer_stream(id) {
+            return uuid_2_sessions.lock().await.remove(&session_id);
+        }
+    }
+    None
+}
+
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    loop {
+        let (tcp_stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                log::error!("https accept error: {}", err);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let tower_service = app.clone();
+        tokio::spawn(async move {
+            let tls_stream = match acceptor.accept(tcp_stream).await {
+                Ok(tls_stream) => tls_stream,
+                Err(err) => {
+                    log::error!("tls handshake with {} error: {}", remote_addr, err);
+                    return;
                 }
-            });
+            };
+
+            let hyper_service =
+                hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(remote_addr));
+                    tower_service.clone().call(request)
+                });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(tls_stream), hyper_service)
+                .await
+            {
+                log::error!("https connection with {} error: {}", remote_addr, err);
+            }
+        });
+    }
+}
+
+fn gen_response(status_code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status_code)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn add_cors_headers(mut response: Response<Body>) -> Response<Body> {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("Content-Type, Authorization, If-Match"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, PATCH, DELETE, OPTIONS"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static("Location, ETag, Link, Accept-Patch"),
+    );
+    response
+}
+
+async fn serve_file(file_path: &'static str, content_type: &'static str) -> Response<Body> {
+    match tokio::fs::read(file_path).await {
+        Ok(contents) => Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(contents))
+            .unwrap(),
+        Err(err) => {
+            log::warn!("read the file: {} error: {}", file_path, err);
+            gen_response(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+//the cors preflight of the browsers
+async fn handle_options() -> Response<Body> {
+    let mut response = gen_response(StatusCode::NO_CONTENT);
+    response
+        .headers_mut()
+        .insert("Accept-Post", HeaderValue::from_static("application/sdp"));
+    response
+}
+
+fn is_content_type(headers: &HeaderMap, content_type: &str) -> bool {
+    match headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        //application/sdp; charset=utf-8
+        Some(value) => value
+            .split(';')
+            .next()
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case(content_type)),
+        None => false,
+    }
+}
+
+//The token is carried by the Authorization header(Bearer <token>) or the query(token=xxx).
+fn authenticate(
+    auth: &Option<Auth>,
+    headers: &HeaderMap,
+    uri: &Uri,
+    stream_name: &String,
+    is_pull: bool,
+) -> bool {
+    if let Some(auth) = auth {
+        let authorization = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let query = uri.query().map(|query| query.to_string());
+        return auth
+            .authenticate_bearer(stream_name, authorization.as_ref(), &query, is_pull)
+            .is_ok();
+    }
+    true
+}
+
+//POST /whip?app=live&stream=test
+async fn post_whip(
+    State(state): State<WebRTCServerState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    Query(pars_map): Query<HashMap<String, String>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    handle_post(
+        state,
+        WebRTCSessionType::Whip,
+        remote_addr,
+        pars_map,
+        uri,
+        headers,
+        body,
+    )
+    .await
+}
+
+//POST /whep?app=live&stream=test
+async fn post_whep(
+    State(state): State<WebRTCServerState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    Query(pars_map): Query<HashMap<String, String>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    handle_post(
+        state,
+        WebRTCSessionType::Whep,
+        remote_addr,
+        pars_map,
+        uri,
+        headers,
+        body,
+    )
+    .await
+}
+
+async fn handle_post(
+    state: WebRTCServerState,
+    session_type: WebRTCSessionType,
+    remote_addr: SocketAddr,
+    pars_map: HashMap<String, String>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    let (app_name, stream_name) = match (pars_map.get("app"), pars_map.get("stream")) {
+        (Some(app_name), Some(stream_name)) => (app_name.clone(), stream_name.clone()),
+        _ => {
+            log::error!("the http path is not correct: {}", uri);
+            return gen_response(StatusCode::BAD_REQUEST);
         }
+    };
+
+    if !is_content_type(&headers, "application/sdp") {
+        return gen_response(StatusCode::UNSUPPORTED_MEDIA_TYPE);
     }
+
+    let is_pull = session_type == WebRTCSessionType::Whep;
+    if !authenticate(&state.auth, &headers, &uri, &stream_name, is_pull) {
+        return gen_response(StatusCode::UNAUTHORIZED);
+    }
+
+    let offer = match RTCSessionDescription::offer(body) {
+        Ok(offer) => offer,
+        Err(err) => {
+            log::error!("the sdp offer is not correct: {}", err);
+            return gen_response(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let mut session = WebRTCServerSession::new(
+        session_type,
+        app_name,
+        stream_name,
+        state.event_producer.clone(),
+        state.ice_context.clone(),
+        state.session_closed_sender.clone(),
+    );
+    //POST /whep?app=live&stream=test&layer=l forces a simulcast layer
+    session.simulcast_layer = pars_map.get("layer").cloned();
+    session.remote_addr = remote_addr.to_string();
+    let answer = match session.run(offer).await {
+        Ok(answer) => answer,
+        Err(err) => {
+            log::error!("handle {} err: {}", session_type, err);
+            //the viewer cannot receive the video codec of the publisher
+            if let SessionErrorValue::WebRTCError(WebRTCError {
+                value: WebRTCErrorValue::UnsupportedVideoCodec(_),
+            }) = err.value
+            {
+                return gen_response(StatusCode::NOT_ACCEPTABLE);
+            }
+            return gen_response(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+
+    //https://www.rfc-editor.org/rfc/rfc9725#section-4.2
+    let location = format!("/{}/resource/{}", session_type, session.session_id);
+    let mut builder = Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::CONTENT_TYPE, "application/sdp")
+        .header(header::LOCATION, location)
+        .header("Accept-Patch", SDP_FRAGMENT_CONTENT_TYPE);
+    if let Some(peer_connection) = &session.peer_connection {
+        if let Some(etag) = get_etag(peer_connection).await {
+            builder = builder.header(header::ETAG, etag);
+        }
+    }
+    for link in state.ice_context.ice_server_links() {
+        builder = builder.header(header::LINK, link);
+    }
+
+    //the peer connection may be closed before the session is saved
+    let is_closed = session
+        .peer_connection
+        .as_ref()
+        .is_some_and(|pc| pc.connection_state() == RTCPeerConnectionState::Closed);
+    if !is_closed {
+        state
+            .uuid_2_sessions
+            .lock()
+            .await
+            .insert(session.session_id, Arc::new(Mutex::new(session)));
+    }
+
+    builder.body(Body::from(answer.sdp)).unwrap()
+}
+
+async fn get_resource(
+    state: &WebRTCServerState,
+    session_id: &str,
+) -> Option<Arc<Mutex<WebRTCServerSession>>> {
+    let uuid = Uuid::from_str2(session_id)?;
+    let session = state.uuid_2_sessions.lock().await.get(&uuid).cloned();
+    if session.is_none() {
+        log::warn!("the session :{}  is not exited.", uuid);
+    }
+    session
+}
+
+//PATCH /whip/resource/{session_id}
+//the trickle ice and the ice restart: https://www.rfc-editor.org/rfc/rfc9725#section-4.3
+async fn patch_resource(
+    State(state): State<WebRTCServerState>,
+    Path((_, session_id)): Path<(String, String)>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response<Body> {
+    let session = match get_resource(&state, &session_id).await {
+        Some(session) => session,
+        None => return gen_response(StatusCode::NOT_FOUND),
+    };
+
+    if !is_content_type(&headers, SDP_FRAGMENT_CONTENT_TYPE) {
+        return gen_response(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let peer_connection = {
+        let session_unlock = session.lock().await;
+        let is_pull = session_unlock.session_type == WebRTCSessionType::Whep;
+        if !authenticate(
+            &state.auth,
+            &headers,
+            &uri,
+            &session_unlock.stream_name,
+            is_pull,
+        ) {
+            return gen_response(StatusCode::UNAUTHORIZED);
+        }
+        match &session_unlock.peer_connection {
+            Some(peer_connection) => peer_connection.clone(),
+            None => return gen_response(StatusCode::NOT_FOUND),
+        }
+    };
+
+    //"*" is used by the ice restarts, otherwise it should match the current ice session
+    if let Some(if_match) = headers
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        if if_match != "*" && Some(if_match.to_string()) != get_etag(&peer_connection).await {
+            return gen_response(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+
+    let fragment = SdpFragment::unmarshal(&body);
+    match apply_sdp_fragment(&peer_connection, &fragment).await {
+        Ok(Some(answer_fragment)) => {
+            let mut builder = Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, SDP_FRAGMENT_CONTENT_TYPE);
+            if let Some(etag) = get_etag(&peer_connection).await {
+                builder = builder.header(header::ETAG, etag);
+            }
+            builder.body(Body::from(answer_fragment.marshal())).unwrap()
+        }
+        Ok(None) => gen_response(StatusCode::NO_CONTENT),
+        Err(err) => {
+            log::error!("apply the sdp fragment error: {}", err);
+            gen_response(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+//DELETE /whip/resource/{session_id}
+async fn delete_resource(
+    State(state): State<WebRTCServerState>,
+    Path((_, session_id)): Path<(String, String)>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let session = match get_resource(&state, &session_id).await {
+        Some(session) => session,
+        None => return gen_response(StatusCode::NOT_FOUND),
+    };
+
+    let session_id = {
+        let session_unlock = session.lock().await;
+        let is_pull = session_unlock.session_type == WebRTCSessionType::Whep;
+        if !authenticate(
+            &state.auth,
+            &headers,
+            &uri,
+            &session_unlock.stream_name,
+            is_pull,
+        ) {
+            return gen_response(StatusCode::UNAUTHORIZED);
+        }
+        //stop the running session and delete it.
+        if let Err(err) = session_unlock.close_resource().await {
+            log::error!("close the resource failed: {}", err);
+        } else {
+            log::info!("close the resource successfully.");
+        }
+        session_unlock.session_id
+    };
+    state.uuid_2_sessions.lock().await.remove(&session_id);
+
+    gen_response(StatusCode::OK)
 }
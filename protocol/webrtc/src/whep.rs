@@ -1,41 +1,330 @@
+use super::codec::{get_mime_type, get_sdp_video_codecs, register_codecs};
+use super::congestion::{get_rtt_ms, BandwidthEstimator};
 use super::errors::WebRTCError;
 use super::errors::WebRTCErrorValue;
+use super::ice::IceContext;
 
+use super::simulcast::{LayerSelector, RtpRewriter};
+use bytes::Bytes;
 use std::sync::Arc;
+use std::time::SystemTime;
 use streamhub::define::PacketData;
-use streamhub::define::PacketDataReceiver;
+use streamhub::define::SubPacketDataReceiver;
+use streamhub::define::VideoCodecType;
+use streamhub::define::{StatisticData, StatisticDataSender, StreamHubEvent, StreamHubEventSender};
+use streamhub::queue::{is_rtp_key_frame, is_rtp_non_reference_frame};
+use streamhub::statistics::RtpSendStatistics;
+use streamhub::stream::StreamIdentifier;
+use streamhub::utils::Uuid;
 
-use webrtc::api::interceptor_registry::register_default_interceptors;
-use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264, MIME_TYPE_OPUS};
+use webrtc::api::interceptor_registry::{configure_nack, configure_rtcp_reports, configure_twcc};
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_OPUS};
 use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
-use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
-use webrtc::peer_connection::configuration::RTCConfiguration;
 
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{Duration, Instant};
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_cc::TransportLayerCc;
+use webrtc::rtp::packet::Packet;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 use webrtc::track::track_local::TrackLocal;
 use webrtc::track::track_local::TrackLocalWriter;
+use webrtc::util::Unmarshal;
 
 pub type Result<T> = std::result::Result<T, WebRTCError>;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 
+const VIDEO_CLOCK_RATE: u64 = 90000;
+//the viewers repeat the PLIs until the key frame arrives
+const KEY_FRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+//The simulcast layers of the whip publisher, the first layer is received
+//from the primary stream.
+pub struct WhepSimulcast {
+    pub rids: Vec<String>,
+    //the packet receivers of the other layers
+    pub receivers: Vec<SubPacketDataReceiver>,
+    //the layer chosen by the viewer, otherwise it is chosen by the bandwidth
+    pub forced_rid: Option<String>,
+}
+
+//the rtcp feedbacks from the viewer
+pub(crate) enum RtcpFeedback {
+    Remb(f64),
+    Twcc { received: usize, lost: usize },
+    //a PLI or FIR
+    KeyFrameRequest,
+    ReceiverReport { rtt: Option<f64>, fraction_lost: u8 },
+}
+
+//The hub stream sent to a viewer(a whep player or a remote whip server), the key frames
+//are requested from its publisher and the sending statistics are reported to it.
+pub struct WhepSubscriber {
+    pub id: Uuid,
+    pub identifier: StreamIdentifier,
+    pub event_sender: StreamHubEventSender,
+    pub statistic_sender: Option<StatisticDataSender>,
+    //the video codec of the publisher
+    pub vcodec: VideoCodecType,
+}
+
+//The congestion state and the statistics of a viewer, the non-reference frames are
+//dropped when the viewer cannot receive the sending bitrate.
+struct Viewer {
+    subscriber: WhepSubscriber,
+    statistics: RtpSendStatistics,
+    last_key_frame_request: Option<Instant>,
+    //a key frame is requested and not received yet
+    waiting_key_frame: bool,
+    //the latest sequence number of the received video packets
+    last_seq: Option<u16>,
+    //the gop replayed for the key frame request is being received
+    replaying: bool,
+    sent_bytes: usize,
+    sending_bitrate: f64,
+    congested: bool,
+}
+
+impl Viewer {
+    fn new(subscriber: WhepSubscriber) -> Self {
+        Self {
+            subscriber,
+            statistics: RtpSendStatistics::default(),
+            last_key_frame_request: None,
+            waiting_key_frame: false,
+            last_seq: None,
+            replaying: false,
+            sent_bytes: 0,
+            sending_bitrate: 0.0,
+            congested: false,
+        }
+    }
+
+    fn on_feedback(&mut self, feedback: RtcpFeedback) {
+        match feedback {
+            RtcpFeedback::KeyFrameRequest => self.request_key_frame(),
+            RtcpFeedback::ReceiverReport { rtt, fraction_lost } => {
+                if let Some(rtt) = rtt {
+                    self.statistics.rtt = rtt as u32;
+                }
+                self.statistics.packet_loss = fraction_lost as f64 * 100.0 / 256.0;
+            }
+            _ => {}
+        }
+    }
+
+    fn request_key_frame(&mut self) {
+        self.statistics.key_frame_requests += 1;
+        if self
+            .last_key_frame_request
+            .is_some_and(|time| time.elapsed() < KEY_FRAME_REQUEST_INTERVAL)
+        {
+            return;
+        }
+        self.last_key_frame_request = Some(Instant::now());
+        self.waiting_key_frame = true;
+
+        let request_event = StreamHubEvent::RequestKeyFrame {
+            identifier: self.subscriber.identifier.clone(),
+            id: self.subscriber.id,
+        };
+        if self.subscriber.event_sender.send(request_event).is_err() {
+            log::error!("send key frame request error: {}", self.subscriber.id);
+        }
+    }
+
+    //only the non-reference frames are dropped, so the others can still be decoded
+    fn should_drop(&mut self, packet: &[u8]) -> bool {
+        if self.congested && is_rtp_non_reference_frame(&self.subscriber.vcodec, packet) {
+            self.statistics.dropped_packets += 1;
+            return true;
+        }
+        self.sent_bytes += packet.len();
+        false
+    }
+
+    //Check a video packet against the received ones. The gop replayed for the key
+    //frame request(the rtmp gop cache) is older than the received packets, it is sent
+    //as a switched rtp stream with the fresh sequence numbers and timestamps, so is
+    //the live stream after it. Returns None for a packet received already, otherwise
+    //whether the packet is switched.
+    fn check_video_packet(&mut self, packet: &[u8]) -> Option<bool> {
+        let seq = match packet.get(2..4) {
+            Some(seq) => u16::from_be_bytes([seq[0], seq[1]]),
+            None => return Some(false),
+        };
+        let is_key_frame = is_rtp_key_frame(&self.subscriber.vcodec, packet);
+        let is_newer = self
+            .last_seq
+            .is_none_or(|last_seq| (seq.wrapping_sub(last_seq) as i16) > 0);
+
+        if is_newer {
+            if is_key_frame {
+                self.waiting_key_frame = false;
+            }
+            self.last_seq = Some(seq);
+            //the live packets continue after the replayed gop
+            let switched = self.replaying;
+            self.replaying = false;
+            return Some(switched);
+        }
+
+        if self.waiting_key_frame && is_key_frame {
+            self.waiting_key_frame = false;
+            self.replaying = true;
+            return Some(true);
+        }
+        //the older packets out of the replayed gop are the duplicates of its tail
+        self.replaying.then_some(false)
+    }
+
+    fn update_sending_bitrate(&mut self, elapsed_secs: f64) -> f64 {
+        if elapsed_secs > 0.0 {
+            self.sending_bitrate = self.sent_bytes as f64 * 8.0 / elapsed_secs;
+            self.sent_bytes = 0;
+        }
+        self.sending_bitrate
+    }
+
+    //update the congestion state by the estimate and report the statistics
+    fn update_congestion(&mut self, estimator: &BandwidthEstimator) {
+        let congested = estimator.is_congested(self.sending_bitrate);
+        if congested != self.congested {
+            log::info!(
+                "viewer {} congestion state has changed: {}",
+                self.subscriber.id,
+                congested
+            );
+            self.congested = congested;
+        }
+        self.statistics.estimated_bitrate =
+            estimator.estimated_bitrate().unwrap_or_default() as usize / 1000;
+
+        if let Some(sender) = &self.subscriber.statistic_sender {
+            let statistic_data = StatisticData::RtpSend {
+                id: self.subscriber.id,
+                statistics: self.statistics.clone(),
+            };
+            if let Err(err) = sender.send(statistic_data) {
+                log::error!("send rtp send statistics error: {}", err);
+            }
+        }
+    }
+}
+
+//The local tracks and the feedbacks of a peer connection sending a hub stream.
+pub(crate) struct SenderTracks {
+    video_track: Arc<TrackLocalStaticRTP>,
+    audio_track: Arc<TrackLocalStaticRTP>,
+    feedback_receiver: mpsc::UnboundedReceiver<RtcpFeedback>,
+    state_receiver: broadcast::Receiver<RTCPeerConnectionState>,
+    data_channels: DataChannels,
+}
+
+impl SenderTracks {
+    //a data channel opened by the local peer
+    pub(crate) fn add_data_channel(&mut self, data_channel: Arc<RTCDataChannel>) {
+        self.data_channels.channels.push(data_channel);
+    }
+}
+
+//The data channels the data messages of the hub stream are sent on, they are
+//opened by the viewer.
+pub(crate) struct DataChannels {
+    receiver: mpsc::UnboundedReceiver<Arc<RTCDataChannel>>,
+    channels: Vec<Arc<RTCDataChannel>>,
+}
+
+impl DataChannels {
+    async fn send(&mut self, data: &Bytes) {
+        while let Ok(data_channel) = self.receiver.try_recv() {
+            self.channels.push(data_channel);
+        }
+        self.channels
+            .retain(|data_channel| data_channel.ready_state() != RTCDataChannelState::Closed);
+
+        //the utf-8 messages are sent as text
+        let text = std::str::from_utf8(data).ok();
+        for data_channel in &self.channels {
+            if data_channel.ready_state() != RTCDataChannelState::Open {
+                continue;
+            }
+            let rv = match text {
+                Some(text) => data_channel.send_text(text.to_owned()).await,
+                None => data_channel.send(data).await,
+            };
+            if let Err(err) = rv {
+                log::error!("send data message error: {}", err);
+            }
+        }
+    }
+}
+
 pub async fn handle_whep(
     offer: RTCSessionDescription,
-    mut receiver: PacketDataReceiver,
+    ice_context: &IceContext,
+    receiver: SubPacketDataReceiver,
+    simulcast: Option<WhepSimulcast>,
+    subscriber: WhepSubscriber,
     state_sender: broadcast::Sender<RTCPeerConnectionState>,
 ) -> Result<(RTCSessionDescription, Arc<RTCPeerConnection>)> {
+    //the video is sent as it is published
+    let offer_codecs = get_sdp_video_codecs(&offer.sdp);
+    if !offer_codecs.is_empty() && !offer_codecs.contains(&subscriber.vcodec) {
+        return Err(WebRTCError {
+            value: WebRTCErrorValue::UnsupportedVideoCodec(subscriber.vcodec),
+        });
+    }
+
+    let (peer_connection, tracks) =
+        new_sender_peer_connection(ice_context, &subscriber.vcodec, state_sender).await?;
+
+    // Set the remote SessionDescription
+    peer_connection.set_remote_description(offer).await?;
+
+    // Create an answer
+    let answer = peer_connection.create_answer(None).await?;
+
+    // Create channel that is blocked until ICE Gathering is complete
+    let mut gather_complete = peer_connection.gathering_complete_promise().await;
+
+    // Sets the LocalDescription, and starts our UDP listeners
+    peer_connection.set_local_description(answer).await?;
+
+    // Block until ICE Gathering is complete, disabling trickle ICE
+    // we do this because we only can exchange one signaling message
+    // in a production application you should exchange ICE Candidates via OnICECandidate
+    let _ = gather_complete.recv().await;
+
+    tokio::spawn(send_packets(receiver, simulcast, tracks, subscriber));
+
+    get_answer(peer_connection).await
+}
+
+//Create a peer connection with the sendonly video and audio tracks, it is used
+//by the whep sessions and the whip push clients.
+pub(crate) async fn new_sender_peer_connection(
+    ice_context: &IceContext,
+    vcodec: &VideoCodecType,
+    state_sender: broadcast::Sender<RTCPeerConnectionState>,
+) -> Result<(Arc<RTCPeerConnection>, SenderTracks)> {
     // Everything below is the WebRTC-rs API! Thanks for using it ❤️.
 
     // Create a MediaEngine object to configure the supported codec
     let mut m = MediaEngine::default();
 
-    m.register_default_codecs()?;
+    register_codecs(&mut m, std::slice::from_ref(vcodec))?;
 
     // Create a InterceptorRegistry. This is the user configurable RTP/RTCP Pipeline.
     // This provides NACKs, RTCP Reports and other features. If you use `webrtc.NewPeerConnection`
@@ -43,23 +332,21 @@ pub async fn handle_whep(
     // for each PeerConnection.
     let mut registry = Registry::new();
 
-    // Use the default set of Interceptors
-    registry = register_default_interceptors(registry, &mut m)?;
+    // The TWCC sender is used instead of the receiver only one in the default
+    // interceptors, so that the viewers send the TWCC feedbacks.
+    registry = configure_nack(registry, &mut m);
+    registry = configure_rtcp_reports(registry);
+    registry = configure_twcc(registry, &mut m)?;
 
     // Create the API object with the MediaEngine
     let api = APIBuilder::new()
         .with_media_engine(m)
         .with_interceptor_registry(registry)
+        .with_setting_engine(ice_context.setting_engine())
         .build();
 
     // Prepare the configuration
-    let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-            ..Default::default()
-        }],
-        ..Default::default()
-    };
+    let config = ice_context.rtc_configuration();
 
     // Create a new RTCPeerConnection
     let peer_connection = Arc::new(api.new_peer_connection(config).await?);
@@ -67,7 +354,7 @@ pub async fn handle_whep(
     // Create Track that we send video back to browser on
     let video_track = Arc::new(TrackLocalStaticRTP::new(
         RTCRtpCodecCapability {
-            mime_type: MIME_TYPE_H264.to_owned(),
+            mime_type: get_mime_type(vcodec).to_owned(),
             ..Default::default()
         },
         "video".to_owned(),
@@ -96,12 +383,53 @@ pub async fn handle_whep(
     // Read incoming RTCP packets
     // Before these packets are returned they are processed by interceptors. For things
     // like NACK this needs to be called.
+    let (feedback_sender, feedback_receiver) = mpsc::unbounded_channel();
     tokio::spawn(async move {
         let mut rtcp_buf = vec![0u8; 1500];
-        while let Ok((_, _)) = rtp_sender.read(&mut rtcp_buf).await {}
+        while let Ok((packets, _)) = rtp_sender.read(&mut rtcp_buf).await {
+            for packet in packets {
+                let packet = packet.as_any();
+                let feedback =
+                    if let Some(remb) = packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+                        RtcpFeedback::Remb(remb.bitrate as f64)
+                    } else if let Some(twcc) = packet.downcast_ref::<TransportLayerCc>() {
+                        let received = twcc.recv_deltas.len();
+                        RtcpFeedback::Twcc {
+                            received,
+                            lost: (twcc.packet_status_count as usize).saturating_sub(received),
+                        }
+                    } else if packet.downcast_ref::<PictureLossIndication>().is_some()
+                        || packet.downcast_ref::<FullIntraRequest>().is_some()
+                    {
+                        RtcpFeedback::KeyFrameRequest
+                    } else if let Some(receiver_report) = packet.downcast_ref::<ReceiverReport>() {
+                        match receiver_report.reports.first() {
+                            Some(report) => RtcpFeedback::ReceiverReport {
+                                rtt: get_rtt_ms(
+                                    report.last_sender_report,
+                                    report.delay,
+                                    SystemTime::now(),
+                                ),
+                                fraction_lost: report.fraction_lost,
+                            },
+                            None => continue,
+                        }
+                    } else {
+                        continue;
+                    };
+                let _ = feedback_sender.send(feedback);
+            }
+        }
         Result::<()>::Ok(())
     });
 
+    let (data_channel_sender, data_channel_receiver) = mpsc::unbounded_channel();
+    peer_connection.on_data_channel(Box::new(move |data_channel: Arc<RTCDataChannel>| {
+        log::info!("receive the data channel: {}", data_channel.label());
+        let _ = data_channel_sender.send(data_channel);
+        Box::pin(async {})
+    }));
+
     // Set the handler for ICE connection state
     // This will notify you when the peer has connected/disconnected
     peer_connection.on_ice_connection_state_change(Box::new(
@@ -116,7 +444,7 @@ pub async fn handle_whep(
 
     // Set the handler for Peer connection state
     // This will notify you when the peer has connected/disconnected
-    let mut state_receiver = state_sender.subscribe();
+    let state_receiver = state_sender.subscribe();
     peer_connection.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
         log::info!("Peer Connection State has changed: {s}");
 
@@ -134,54 +462,162 @@ pub async fn handle_whep(
         Box::pin(async {})
     }));
 
-    // Set the remote SessionDescription
-    peer_connection.set_remote_description(offer).await?;
-
-    // Create an answer
-    let answer = peer_connection.create_answer(None).await?;
+    Ok((
+        peer_connection,
+        SenderTracks {
+            video_track,
+            audio_track,
+            feedback_receiver,
+            state_receiver,
+            data_channels: DataChannels {
+                receiver: data_channel_receiver,
+                channels: Vec::new(),
+            },
+        },
+    ))
+}
 
-    // Create channel that is blocked until ICE Gathering is complete
-    let mut gather_complete = peer_connection.gathering_complete_promise().await;
+//Send the packets of a hub stream to the peer until the peer connection is
+//closed or the stream is unpublished.
+pub(crate) async fn send_packets(
+    mut receiver: SubPacketDataReceiver,
+    simulcast: Option<WhepSimulcast>,
+    tracks: SenderTracks,
+    subscriber: WhepSubscriber,
+) {
+    let mut viewer = Viewer::new(subscriber);
+    if let Some(simulcast) = simulcast {
+        send_simulcast(receiver, simulcast, tracks, viewer).await;
+        return;
+    }
 
-    // Sets the LocalDescription, and starts our UDP listeners
-    peer_connection.set_local_description(answer).await?;
+    let SenderTracks {
+        video_track,
+        audio_track,
+        mut feedback_receiver,
+        mut state_receiver,
+        mut data_channels,
+    } = tracks;
 
-    // Block until ICE Gathering is complete, disabling trickle ICE
-    // we do this because we only can exchange one signaling message
-    // in a production application you should exchange ICE Candidates via OnICECandidate
-    let _ = gather_complete.recv().await;
+    let mut estimator = BandwidthEstimator::default();
+    let mut video_sender = VideoSender::new(video_track);
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut last_interval_time = Instant::now();
+    //the rtcp reader exits with the peer connection, stop polling the closed channel
+    let mut feedback_closed = false;
 
     // Read RTP packets forever and send them to the WebRTC Client
-    tokio::spawn(async move {
-        loop {
-            tokio::select! {
-                av_data = receiver.recv() =>{
-                    if let Some(data) = av_data {
-                        match data {
-                            PacketData::Video { timestamp: _, data } => {
-                                if let Err(err) = video_track.write(&data[..]).await {
-                                    log::error!("send video data error: {}", err);
-                                }
-                            }
-                            PacketData::Audio { timestamp: _, data } => {
-                                if let Err(err) = audio_track.write(&data[..]).await {
-                                    log::error!("send audio data error: {}", err);
-                                }
-                            }
+    loop {
+        tokio::select! {
+            av_data = receiver.recv() =>{
+                match av_data {
+                    Some(PacketData::Video { timestamp: _, data }) => {
+                        let switched = match viewer.check_video_packet(&data[..]) {
+                            Some(switched) => switched,
+                            None => continue,
+                        };
+                        if viewer.should_drop(&data[..]) {
+                            video_sender.skip(switched);
+                            continue;
                         }
+                        video_sender.send(&data[..], switched).await;
                     }
-                }
-                pc_state = state_receiver.recv() =>{
-                    if let Ok(state) = pc_state{
-                        if state == RTCPeerConnectionState::Closed {
-                            break;
+                    Some(PacketData::Audio { timestamp: _, data }) => {
+                        if let Err(err) = audio_track.write(&data[..]).await {
+                            log::error!("send audio data error: {}", err);
                         }
                     }
+                    Some(PacketData::DataMessage { timestamp: _, data }) => {
+                        data_channels.send(&data).await;
+                    }
+                    None => break,
+                }
+            }
+            feedback = feedback_receiver.recv(), if !feedback_closed => {
+                match feedback {
+                    Some(RtcpFeedback::Remb(bitrate)) => estimator.on_remb(bitrate),
+                    Some(RtcpFeedback::Twcc { received, lost }) => estimator.on_twcc(received, lost),
+                    Some(feedback) => viewer.on_feedback(feedback),
+                    None => feedback_closed = true,
+                }
+            }
+            _ = interval.tick() => {
+                let sending_bitrate =
+                    viewer.update_sending_bitrate(last_interval_time.elapsed().as_secs_f64());
+                last_interval_time = Instant::now();
+                estimator.update(sending_bitrate);
+                viewer.update_congestion(&estimator);
+            }
+            pc_state = state_receiver.recv() => {
+                match pc_state {
+                    //the state sender is dropped with the peer connection
+                    Ok(RTCPeerConnectionState::Closed)
+                    | Err(broadcast::error::RecvError::Closed) => break,
+                    _ => {}
                 }
             }
         }
-    });
+    }
+}
+
+//Rewrite the sequence numbers and the timestamps of the video packets and send them,
+//a switched packet starts another rtp stream(a simulcast layer or a replayed gop).
+struct VideoSender {
+    track: Arc<TrackLocalStaticRTP>,
+    rtp_rewriter: RtpRewriter,
+    last_sent_time: Instant,
+    //a switched packet is dropped, the next sent packet starts the rtp stream
+    switch_pending: bool,
+}
+
+impl VideoSender {
+    fn new(track: Arc<TrackLocalStaticRTP>) -> Self {
+        Self {
+            track,
+            rtp_rewriter: RtpRewriter::default(),
+            last_sent_time: Instant::now(),
+            switch_pending: false,
+        }
+    }
+
+    //the packet is dropped
+    fn skip(&mut self, switched: bool) {
+        self.switch_pending |= switched;
+        self.rtp_rewriter.skip();
+    }
+
+    async fn send(&mut self, data: &[u8], switched: bool) {
+        let mut packet = match Packet::unmarshal(&mut &data[..]) {
+            Ok(packet) => packet,
+            Err(err) => {
+                log::error!("unmarshal video packet error: {}", err);
+                return;
+            }
+        };
+
+        let switched = std::mem::take(&mut self.switch_pending) || switched;
+        let elapsed_ticks =
+            self.last_sent_time.elapsed().as_millis() as u64 * VIDEO_CLOCK_RATE / 1000;
+        self.last_sent_time = Instant::now();
+        (packet.header.sequence_number, packet.header.timestamp) = self.rtp_rewriter.rewrite(
+            packet.header.sequence_number,
+            packet.header.timestamp,
+            switched,
+            elapsed_ticks as u32,
+        );
+        //the header extension ids are negotiated by the publisher
+        packet.header.extension = false;
+        packet.header.extensions.clear();
 
+        if let Err(err) = self.track.write_rtp(&packet).await {
+            log::error!("send video data error: {}", err);
+        }
+    }
+}
+
+async fn get_answer(
+    peer_connection: Arc<RTCPeerConnection>,
+) -> Result<(RTCSessionDescription, Arc<RTCPeerConnection>)> {
     // Output the answer in base64 so we can paste it in browser
     if let Some(local_desc) = peer_connection.local_description().await {
         Ok((local_desc, peer_connection))
@@ -191,3 +627,176 @@ pub async fn handle_whep(
         })
     }
 }
+
+//Send the audio of the primary stream and the video of the layer chosen by the
+//LayerSelector to the viewer.
+async fn send_simulcast(
+    receiver: SubPacketDataReceiver,
+    simulcast: WhepSimulcast,
+    tracks: SenderTracks,
+    mut viewer: Viewer,
+) {
+    let SenderTracks {
+        video_track,
+        audio_track,
+        mut feedback_receiver,
+        mut state_receiver,
+        mut data_channels,
+    } = tracks;
+
+    //merge the packets of all the layers, the layer index is the index of the rid
+    let (layer_sender, mut layer_receiver) = mpsc::unbounded_channel();
+    let receivers = std::iter::once(receiver).chain(simulcast.receivers);
+    for (layer, mut receiver) in receivers.enumerate() {
+        let layer_sender = layer_sender.clone();
+        tokio::spawn(async move {
+            while let Some(packet) = receiver.recv().await {
+                if layer_sender.send((layer, packet)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(layer_sender);
+
+    let mut layer_selector = LayerSelector::new(
+        simulcast.rids,
+        simulcast.forced_rid,
+        viewer.subscriber.vcodec.clone(),
+    );
+    let mut video_sender = VideoSender::new(video_track);
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut last_interval_time = Instant::now();
+    //the rtcp reader exits with the peer connection, stop polling the closed channel
+    let mut feedback_closed = false;
+
+    loop {
+        tokio::select! {
+            layer_data = layer_receiver.recv() => {
+                let (layer, data) = match layer_data {
+                    Some(layer_data) => layer_data,
+                    None => break,
+                };
+                match data {
+                    PacketData::Video { timestamp: _, data } => {
+                        let (send, switched) = layer_selector.on_video_packet(layer, &data[..]);
+                        if !send {
+                            continue;
+                        }
+                        if viewer.should_drop(&data[..]) {
+                            video_sender.skip(switched);
+                            continue;
+                        }
+                        video_sender.send(&data[..], switched).await;
+                    }
+                    //the layer streams also carry the audio of the publisher
+                    PacketData::Audio { timestamp: _, data } => {
+                        if layer != 0 {
+                            continue;
+                        }
+                        if let Err(err) = audio_track.write(&data[..]).await {
+                            log::error!("send audio data error: {}", err);
+                        }
+                    }
+                    PacketData::DataMessage { timestamp: _, data } => {
+                        data_channels.send(&data).await;
+                    }
+                }
+            }
+            feedback = feedback_receiver.recv(), if !feedback_closed => {
+                match feedback {
+                    Some(RtcpFeedback::Remb(bitrate)) => layer_selector.on_remb(bitrate),
+                    Some(RtcpFeedback::Twcc { received, lost }) => {
+                        layer_selector.on_twcc(received, lost)
+                    }
+                    //the key frames of all the layers are requested by the primary stream
+                    Some(feedback) => viewer.on_feedback(feedback),
+                    None => feedback_closed = true,
+                }
+            }
+            _ = interval.tick() => {
+                let elapsed_secs = last_interval_time.elapsed().as_secs_f64();
+                last_interval_time = Instant::now();
+                layer_selector.on_interval(elapsed_secs);
+                viewer.update_sending_bitrate(elapsed_secs);
+                viewer.update_congestion(layer_selector.estimator());
+            }
+            pc_state = state_receiver.recv() => {
+                match pc_state {
+                    //the state sender is dropped with the peer connection
+                    Ok(RTCPeerConnectionState::Closed)
+                    | Err(broadcast::error::RecvError::Closed) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RtpRewriter, Viewer, WhepSubscriber};
+    use streamhub::define::VideoCodecType;
+    use streamhub::stream::StreamIdentifier;
+    use streamhub::utils::Uuid;
+    use tokio::sync::mpsc;
+
+    //a h264 rtp packet whose payload is an IDR or a non-IDR nalu
+    fn gen_packet(seq: u16, timestamp: u32, key_frame: bool) -> Vec<u8> {
+        let mut packet = vec![0x80, 96];
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 1]);
+        packet.extend_from_slice(if key_frame {
+            &[0x65, 0x88, 0x84]
+        } else {
+            &[0x41, 0x9a, 0x02]
+        });
+        packet
+    }
+
+    #[test]
+    fn test_replayed_gop() {
+        let (event_sender, _event_receiver) = mpsc::unbounded_channel();
+        let mut viewer = Viewer::new(WhepSubscriber {
+            id: Uuid::default(),
+            identifier: StreamIdentifier::WebRTC {
+                app_name: "live".to_string(),
+                stream_name: "test".to_string(),
+            },
+            event_sender,
+            statistic_sender: None,
+            vcodec: VideoCodecType::H264,
+        });
+        let mut rtp_rewriter = RtpRewriter::default();
+        let mut send = |viewer: &mut Viewer, seq: u16, key_frame: bool| {
+            let timestamp = seq as u32 * 3000;
+            let switched = viewer.check_video_packet(&gen_packet(seq, timestamp, key_frame))?;
+            Some(rtp_rewriter.rewrite(seq, timestamp, switched, 3000))
+        };
+
+        for seq in 100..105 {
+            assert_eq!(
+                send(&mut viewer, seq, false),
+                Some((seq, seq as u32 * 3000))
+            );
+        }
+
+        //the gop from 100 to 106 is replayed, 105 and 106 are not received yet
+        viewer.request_key_frame();
+        let mut sent = Vec::new();
+        for seq in 100..107 {
+            sent.push(send(&mut viewer, seq, seq == 100).unwrap());
+        }
+        //the duplicated live packets are dropped
+        assert_eq!(send(&mut viewer, 105, false), None);
+        assert_eq!(send(&mut viewer, 106, false), None);
+        sent.push(send(&mut viewer, 107, false).unwrap());
+
+        //the replayed packets continue the sent sequence numbers and timestamps
+        for (i, (seq, timestamp)) in sent.into_iter().enumerate() {
+            assert_eq!(seq, 105 + i as u16);
+            assert_eq!(timestamp, 315000 + i as u32 * 3000);
+        }
+    }
+}
@@ -1,32 +1,34 @@
 use crate::opus2aac::Opus2AacTranscoder;
 
+use super::codec::{get_video_codec_type, register_codecs, VideoDepacketizer, VIDEO_CODECS};
 use super::errors::WebRTCError;
 use super::errors::WebRTCErrorValue;
-use bytes::BytesMut;
+use super::ice::IceContext;
+use bytes::Bytes;
+use std::collections::HashMap;
 use std::sync::Arc;
 use streamhub::define::VideoCodecType;
-use streamhub::define::{FrameData, PacketData};
-use tokio::sync::mpsc::UnboundedSender;
+use streamhub::define::{FrameData, FrameDataSender, PacketData, PacketDataSender};
 use webrtc::rtp::codecs::opus::OpusPacket;
 
-use tokio::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::MediaEngine;
 use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
-use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
-use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
-use webrtc::rtp::codecs::h264::H264Packet;
 use webrtc::sdp::util::Codec;
 
 use super::rtp_queue::RtpQueue;
 use webrtc::rtp::packetizer::Depacketizer;
-use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_codec::{RTCRtpHeaderExtensionCapability, RTPCodecType};
 use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
 use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
 use webrtc::util::Marshal;
@@ -41,9 +43,36 @@ pub type Result<T> = std::result::Result<T, WebRTCError>;
 //     pub const NO_IDR_FRAME: u8 = 0x01; //0x41 B/P frame
 // }
 
-mod nal_payload_type {
-    pub const H264: u8 = 96;
-    pub const OPUS: u8 = 111;
+//the rtp header extensions which identify the simulcast layers
+const SIMULCAST_HEADER_EXTENSIONS: [&str; 3] = [
+    "urn:ietf:params:rtp-hdrext:sdes:mid",
+    "urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id",
+    "urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id",
+];
+
+//The hub senders of the primary stream or a simulcast layer stream.
+#[derive(Clone)]
+pub struct WhipSender {
+    pub frame_sender: FrameDataSender,
+    pub packet_sender: PacketDataSender,
+}
+
+impl WhipSender {
+    fn send_frame(senders: &[WhipSender], frame: FrameData) {
+        for sender in senders {
+            if let Err(err) = sender.frame_sender.send(frame.clone()) {
+                log::error!("send frame error: {}", err);
+            }
+        }
+    }
+
+    fn send_packet(senders: &[WhipSender], packet: PacketData) {
+        for sender in senders {
+            if let Err(err) = sender.packet_sender.send(packet.clone()) {
+                log::error!("send packet error: {}", err);
+            }
+        }
+    }
 }
 
 pub(crate) fn parse_rtpmap(rtpmap: &str) -> Result<Codec> {
@@ -88,13 +117,66 @@ pub(crate) fn parse_rtpmap(rtpmap: &str) -> Result<Codec> {
 
 pub async fn handle_whip(
     offer: RTCSessionDescription,
-    frame_sender: Option<UnboundedSender<FrameData>>,
-    packet_sender: Option<UnboundedSender<PacketData>>,
+    ice_context: &IceContext,
+    sender: WhipSender,
+    //the senders of the simulcast layers except the primary one, keyed by the rid
+    layer_senders: HashMap<String, WhipSender>,
+    key_frame_request: Arc<Notify>,
 ) -> Result<(RTCSessionDescription, Arc<RTCPeerConnection>)> {
     // Create a MediaEngine object to configure the supported codec
     let mut m = MediaEngine::default();
+    register_codecs(&mut m, &VIDEO_CODECS)?;
+
+    let peer_connection =
+        new_receiver_peer_connection(ice_context, m, sender, layer_senders, key_frame_request)
+            .await?;
+
+    // Set the remote SessionDescription
+    peer_connection.set_remote_description(offer).await?;
+
+    // Create an answer
+    let answer = peer_connection.create_answer(None).await?;
+
+    // Create channel that is blocked until ICE Gathering is complete
+    let mut gather_complete = peer_connection.gathering_complete_promise().await;
+
+    // Sets the LocalDescription, and starts our UDP listeners
+    peer_connection.set_local_description(answer).await?;
+
+    // Block until ICE Gathering is complete, disabling trickle ICE
+    // we do this because we only can exchange one signaling message
+    // in a production application you should exchange ICE Candidates via OnICECandidate
+    let _ = gather_complete.recv().await;
 
-    m.register_default_codecs()?;
+    // Output the answer in base64 so we can paste it in browser
+    if let Some(local_desc) = peer_connection.local_description().await {
+        Ok((local_desc, peer_connection))
+    } else {
+        Err(WebRTCError {
+            value: WebRTCErrorValue::CanNotGetLocalDescription,
+        })
+    }
+}
+
+//Create a peer connection which receives the audio and video tracks and sends them to
+//the stream hub, it is used by the whip server and the whep relay client. A PLI is sent
+//to the remote peer when the key_frame_request is notified.
+pub(crate) async fn new_receiver_peer_connection(
+    ice_context: &IceContext,
+    mut m: MediaEngine,
+    sender: WhipSender,
+    layer_senders: HashMap<String, WhipSender>,
+    key_frame_request: Arc<Notify>,
+) -> Result<Arc<RTCPeerConnection>> {
+    for extension in SIMULCAST_HEADER_EXTENSIONS {
+        m.register_header_extension(
+            RTCRtpHeaderExtensionCapability {
+                uri: extension.to_owned(),
+            },
+            RTPCodecType::Video,
+            None,
+        )?;
+    }
 
     // Create a InterceptorRegistry. This is the user configurable RTP/RTCP Pipeline.
     // This provides NACKs, RTCP Reports and other features. If you use `webrtc.NewPeerConnection`
@@ -109,16 +191,11 @@ pub async fn handle_whip(
     let api = APIBuilder::new()
         .with_media_engine(m)
         .with_interceptor_registry(registry)
+        .with_setting_engine(ice_context.setting_engine())
         .build();
 
     // Prepare the configuration
-    let config = RTCConfiguration {
-        ice_servers: vec![RTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-            ..Default::default()
-        }],
-        ..Default::default()
-    };
+    let config = ice_context.rtc_configuration();
 
     // Create a new RTCPeerConnection
     let peer_connection = Arc::new(api.new_peer_connection(config).await?);
@@ -143,7 +220,14 @@ pub async fn handle_whip(
         )
         .await?;
 
-    let offer_in = offer.clone();
+    // The messages of the data channels opened by the publisher are sent to the viewers
+    let data_sender = sender.clone();
+    peer_connection.on_data_channel(Box::new(move |data_channel: Arc<RTCDataChannel>| {
+        log::info!("receive the data channel: {}", data_channel.label());
+        forward_data_messages(&data_channel, data_sender.clone());
+        Box::pin(async {})
+    }));
+
     // Set a handler for when a new remote track starts, this handler will forward data to
     // our UDP listeners.
     // In your application this is where you would handle/process audio/video
@@ -152,6 +236,7 @@ pub async fn handle_whip(
         // Send a PLI on an interval so that the publisher is pushing a keyframe every rtcpPLIInterval
         let media_ssrc = track.ssrc();
         let pc2 = pc.clone();
+        let key_frame_request = key_frame_request.clone();
         tokio::spawn(async move {
             let mut result = Result::<usize>::Ok(0);
             while result.is_ok() {
@@ -159,25 +244,39 @@ pub async fn handle_whip(
                 tokio::pin!(timeout);
 
                 tokio::select! {
-                    _ = timeout.as_mut() =>{
-                        if let Some(pc) = pc2.upgrade(){
-                            result = pc.write_rtcp(&[Box::new(PictureLossIndication{
-                                sender_ssrc: 0,
-                                media_ssrc,
-                            })]).await.map_err(Into::into);
-                        }else{
-                            break;
-                        }
-                    }
+                    _ = timeout.as_mut() =>{}
+                    //a viewer lost the reference frames
+                    _ = key_frame_request.notified() =>{}
                 };
+                if let Some(pc) = pc2.upgrade() {
+                    result = pc
+                        .write_rtcp(&[Box::new(PictureLossIndication {
+                            sender_ssrc: 0,
+                            media_ssrc,
+                        })])
+                        .await
+                        .map_err(Into::into);
+                } else {
+                    break;
+                }
             }
         });
-        let packet_sender_clone = packet_sender.clone().unwrap();
-        let frame_sender_clone = frame_sender.clone().unwrap();
-        let offer_clone = offer_in.clone();
+        //the audio is sent to all the layer streams, and each video layer to its own stream
+        let senders = if track.kind() == RTPCodecType::Audio {
+            let mut senders: Vec<WhipSender> = layer_senders.values().cloned().collect();
+            senders.push(sender.clone());
+            senders
+        } else if let Some(layer_sender) = layer_senders.get(track.rid()) {
+            log::info!("receive the simulcast layer: {}", track.rid());
+            vec![layer_sender.clone()]
+        } else {
+            vec![sender.clone()]
+        };
+        let kind = track.kind();
+        let payload_type = track.payload_type();
+        let pc3 = pc.clone();
         tokio::spawn(async move {
             let mut b = vec![0u8; 3000];
-            let mut h264_packet = H264Packet::default();
             let mut opus_packet = OpusPacket;
 
             let mut video_codec = Codec::default();
@@ -191,43 +290,44 @@ pub async fn handle_whip(
             )
             .unwrap();
 
-            //111 OPUS/48000/2
-            //96 H264/90000
-            if let Ok(session_description) = offer_clone.unmarshal() {
+            //the codecs of the offer(whip) or the answer(whep relay)
+            let remote_description = match pc3.upgrade() {
+                Some(pc) => pc.remote_description().await,
+                None => None,
+            };
+            if let Some(Ok(session_description)) = remote_description.map(|sdp| sdp.unmarshal()) {
                 for m in session_description.media_descriptions {
                     for a in &m.attributes {
                         let attr = a.to_string();
                         if attr.starts_with("rtpmap:") {
                             if let Ok(codec) = parse_rtpmap(&attr) {
                                 log::info!("codec: {}", codec);
-                                match codec.name.as_str() {
-                                    "H264" => {
-                                        video_codec = codec;
-                                    }
-                                    "H265" => {
+                                if let Some(codec_type) = get_video_codec_type(&codec.name) {
+                                    //the video track uses the codec of its payload type, and the
+                                    //audio track takes the first one preferred by the offer
+                                    let is_track_codec = kind == RTPCodecType::Video
+                                        && codec.payload_type == payload_type;
+                                    if is_track_codec || video_codec.name.is_empty() {
                                         video_codec = codec;
-                                        vcodec = VideoCodecType::H265;
-                                    }
-                                    "OPUS" => {
-                                        audio_codec = codec;
-                                        let channels =
-                                            match audio_codec.encoding_parameters.as_str() {
-                                                "1" => audiopus::Channels::Mono,
-                                                "2" => audiopus::Channels::Stereo,
-                                                _ => audiopus::Channels::Stereo,
-                                            };
-
-                                        opus2aac_transcoder = Opus2AacTranscoder::new(
-                                            audio_codec.clock_rate as i32,
-                                            channels,
-                                            audio_codec.clock_rate,
-                                            fdk_aac::enc::ChannelMode::Stereo,
-                                        )
-                                        .unwrap();
-                                    }
-                                    _ => {
-                                        log::warn!("not supported codec: {}", codec);
+                                        vcodec = codec_type;
                                     }
+                                } else if codec.name.eq_ignore_ascii_case("opus") {
+                                    audio_codec = codec;
+                                    let channels = match audio_codec.encoding_parameters.as_str() {
+                                        "1" => audiopus::Channels::Mono,
+                                        "2" => audiopus::Channels::Stereo,
+                                        _ => audiopus::Channels::Stereo,
+                                    };
+
+                                    opus2aac_transcoder = Opus2AacTranscoder::new(
+                                        audio_codec.clock_rate as i32,
+                                        channels,
+                                        audio_codec.clock_rate,
+                                        fdk_aac::enc::ChannelMode::Stereo,
+                                    )
+                                    .unwrap();
+                                } else {
+                                    log::warn!("not supported codec: {}", codec);
                                 }
                             }
                         }
@@ -239,18 +339,19 @@ pub async fn handle_whip(
                 media_info: streamhub::define::MediaInfo {
                     audio_clock_rate: audio_codec.clock_rate,
                     video_clock_rate: video_codec.clock_rate,
-                    vcodec,
+                    vcodec: vcodec.clone(),
+                    //the opus audio is transcoded to aac
+                    acodec: streamhub::define::AudioCodecType::AAC,
                 },
             };
 
-            if let Err(err) = frame_sender_clone.send(media_info) {
-                log::error!("send media info error: {}", err);
-            } else {
-                log::info!("send media info suceess: {:?} {}", audio_codec, video_codec);
-            }
+            WhipSender::send_frame(&senders, media_info);
+            log::info!("send media info suceess: {:?} {}", audio_codec, video_codec);
 
-            let _sps_sent: bool = false;
-            let _pps_sent: bool = false;
+            let mut video_depacketizer = VideoDepacketizer::new(&vcodec);
+            if kind == RTPCodecType::Video && video_depacketizer.is_none() {
+                log::info!("the {:?} video is only sent as the rtp packets", vcodec);
+            }
             let mut aac_asc_sent: bool = false;
 
             let mut rtp_queue = RtpQueue::new(100);
@@ -258,71 +359,46 @@ pub async fn handle_whip(
             while let Ok((rtp_packet, _)) = track.read(&mut b).await {
                 let n = rtp_packet.marshal_to(&mut b)?;
 
-                match rtp_packet.header.payload_type {
-                    //video h264
-                    nal_payload_type::H264 => {
+                match kind {
+                    RTPCodecType::Video => {
                         let video_packet = PacketData::Video {
                             timestamp: rtp_packet.header.timestamp,
-                            data: BytesMut::from(&b[..n]),
+                            data: Bytes::copy_from_slice(&b[..n]),
                         };
-                        if let Err(err) = packet_sender_clone.send(video_packet) {
-                            log::error!("send video packet error: {}", err);
-                        }
+                        WhipSender::send_packet(&senders, video_packet);
 
+                        let depacketizer = match &mut video_depacketizer {
+                            Some(depacketizer) => depacketizer,
+                            None => continue,
+                        };
                         rtp_queue.write_queue(rtp_packet);
 
                         while let Some(rtp_packet_ordered) = rtp_queue.read_queue() {
-                            match h264_packet.depacketize(&rtp_packet_ordered.payload) {
-                                Ok(rv) => {
-                                    if !rv.is_empty() {
-                                        let byte_array = rv.to_vec();
-                                        let nal_type = byte_array[4] & 0x1F;
-
-                                        if nal_type != 0x0C {
-                                            let video_frame = FrameData::Video {
-                                                timestamp: rtp_packet_ordered.header.timestamp,
-                                                data: BytesMut::from(&byte_array[..]),
-                                            };
-
-                                            if let Err(err) = frame_sender_clone.send(video_frame) {
-                                                log::error!("send video frame error: {}", err);
-                                            } else {
-                                                // log::info!("send video frame suceess: {}", nal_type);
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(_err) => {
-                                    // log::error!("The h264 packet payload err:{}", err);
-                                    // let hex_string = hex::encode(b.to_vec());
-                                    // log::error!(
-                                    //     "The h264 packet payload err string :{}",
-                                    //     hex_string
-                                    // );
-                                }
+                            for nalu in depacketizer.depacketize(&rtp_packet_ordered.payload) {
+                                let video_frame = FrameData::Video {
+                                    timestamp: rtp_packet_ordered.header.timestamp,
+                                    data: nalu,
+                                };
+                                WhipSender::send_frame(&senders, video_frame);
                             }
                         }
                     }
-                    //aac 111(opus)
-                    nal_payload_type::OPUS => {
+                    //opus
+                    RTPCodecType::Audio => {
                         let audio_packet = PacketData::Audio {
                             timestamp: rtp_packet.header.timestamp,
-                            data: BytesMut::from(&b[..n]),
+                            data: Bytes::copy_from_slice(&b[..n]),
                         };
-                        if let Err(err) = packet_sender_clone.send(audio_packet) {
-                            log::error!("send audio packet error: {}", err);
-                        }
+                        WhipSender::send_packet(&senders, audio_packet);
 
                         if !aac_asc_sent {
                             if let Ok(aac) = Mpeg4Aac::new(2, 48000, 2) {
                                 if let Ok(asc) = aac.gen_audio_specific_config() {
                                     let audio_frame = FrameData::Audio {
                                         timestamp: 0,
-                                        data: asc,
+                                        data: asc.freeze(),
                                     };
-                                    if let Err(err) = frame_sender_clone.send(audio_frame) {
-                                        log::error!("send audio frame error: {}", err);
-                                    }
+                                    WhipSender::send_frame(&senders, audio_frame);
                                 }
                             }
                             aac_asc_sent = true;
@@ -338,16 +414,10 @@ pub async fn handle_whip(
                                             for data_val in data {
                                                 let audio_frame = FrameData::Audio {
                                                     timestamp: rtp_packet.header.timestamp,
-                                                    data: BytesMut::from(&data_val[..]),
+                                                    data: Bytes::from(data_val),
                                                 };
 
-                                                if let Err(err) =
-                                                    frame_sender_clone.send(audio_frame)
-                                                {
-                                                    log::error!("send audio frame error: {}", err);
-                                                } else {
-                                                    // log::info!("send aidop frame suceess");
-                                                }
+                                                WhipSender::send_frame(&senders, audio_frame);
                                             }
                                         }
                                         Err(err) => {
@@ -397,29 +467,29 @@ pub async fn handle_whip(
         Box::pin(async {})
     }));
 
-    // Set the remote SessionDescription
-    peer_connection.set_remote_description(offer).await?;
-
-    // Create an answer
-    let answer = peer_connection.create_answer(None).await?;
-
-    // Create channel that is blocked until ICE Gathering is complete
-    let mut gather_complete = peer_connection.gathering_complete_promise().await;
-
-    // Sets the LocalDescription, and starts our UDP listeners
-    peer_connection.set_local_description(answer).await?;
-
-    // Block until ICE Gathering is complete, disabling trickle ICE
-    // we do this because we only can exchange one signaling message
-    // in a production application you should exchange ICE Candidates via OnICECandidate
-    let _ = gather_complete.recv().await;
+    Ok(peer_connection)
+}
 
-    // Output the answer in base64 so we can paste it in browser
-    if let Some(local_desc) = peer_connection.local_description().await {
-        Ok((local_desc, peer_connection))
-    } else {
-        Err(WebRTCError {
-            value: WebRTCErrorValue::CanNotGetLocalDescription,
-        })
-    }
+//Send the messages received on the data channel to the stream hub.
+pub(crate) fn forward_data_messages(data_channel: &RTCDataChannel, sender: WhipSender) {
+    let start_time = Instant::now();
+    data_channel.on_message(Box::new(move |message: DataChannelMessage| {
+        let timestamp = start_time.elapsed().as_millis() as u32;
+        let senders = std::slice::from_ref(&sender);
+        WhipSender::send_frame(
+            senders,
+            FrameData::DataMessage {
+                timestamp,
+                data: message.data.clone(),
+            },
+        );
+        WhipSender::send_packet(
+            senders,
+            PacketData::DataMessage {
+                timestamp,
+                data: message.data,
+            },
+        );
+        Box::pin(async {})
+    }));
 }